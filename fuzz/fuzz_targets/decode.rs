@@ -0,0 +1,43 @@
+//! Decodes arbitrary bytes with every reader, which must agree with each
+//! other and never panic. Run it with `cargo fuzz run decode`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use xdcodec_rs::{
+    decode_ref, CodecReadExt, CodecWriteExt, Document, Error, IncrementalDecoder, Parser, Typed,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let e = (&data[..]).read_typed();
+    let _ = Parser::new(data).collect::<Vec<_>>();
+    let _ = decode_ref(data);
+    let _ = Document::new(data);
+    let _ = (&data[..]).read_raw();
+
+    // Fed a byte at a time, the incremental decoder ends up with the same
+    // value.
+    let mut dec = IncrementalDecoder::new();
+    let mut incremental = None;
+    for end in 0..=data.len() {
+        match dec.decode(&data[..end]) {
+            Ok((v, _)) => {
+                incremental = Some(v);
+                break;
+            }
+            Err(Error::Incomplete { .. }) => {}
+            Err(_) => break,
+        }
+    }
+    if let (Ok(e), Some(v)) = (&e, &incremental) {
+        assert!(same(e, v));
+    }
+});
+
+// NaN floats are not equal to themselves, and the entries of equal maps
+// may iterate in different orders.
+fn same(x: &Typed, y: &Typed) -> bool {
+    let mut a = Vec::new();
+    let mut b = Vec::new();
+    a.write_typed_canonical(x).is_ok() && b.write_typed_canonical(y).is_ok() && a == b
+}
@@ -0,0 +1,373 @@
+use crate::array::PackedArray;
+use crate::codec::{List, Map, Str, Typed};
+use crate::convert::{invalid_type, FromTyped};
+use crate::decimal::Decimal;
+use crate::error::Error;
+use crate::path::{Path, PathSegment};
+use crate::raw::RawValue;
+use crate::timestamp::Timestamp;
+use std::convert::TryFrom;
+
+macro_rules! impl_from {
+    ($variant:ident, $repr:ty; $($t:ty),*) => {
+        $(
+            impl From<$t> for Typed {
+                fn from(v: $t) -> Self {
+                    Typed::$variant(v as $repr)
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(Int, i64; i8, i16, i32, i64, isize);
+impl_from!(Uint, u64; u8, u16, u32, u64, usize);
+impl_from!(Float, f64; f32, f64);
+
+impl From<bool> for Typed {
+    fn from(b: bool) -> Self {
+        Typed::Bool(b)
+    }
+}
+
+impl From<Timestamp> for Typed {
+    fn from(ts: Timestamp) -> Self {
+        Typed::Timestamp(ts)
+    }
+}
+
+impl From<Decimal> for Typed {
+    fn from(d: Decimal) -> Self {
+        Typed::Decimal(d)
+    }
+}
+
+impl From<&str> for Typed {
+    fn from(s: &str) -> Self {
+        Typed::String(Str::from(s))
+    }
+}
+
+impl From<String> for Typed {
+    fn from(s: String) -> Self {
+        Typed::String(s.into())
+    }
+}
+
+#[cfg(feature = "smol_str")]
+impl From<smol_str::SmolStr> for Typed {
+    fn from(s: smol_str::SmolStr) -> Self {
+        Typed::String(s)
+    }
+}
+
+impl From<&[u8]> for Typed {
+    fn from(buf: &[u8]) -> Self {
+        Typed::Bytes(buf.to_vec().into())
+    }
+}
+
+impl From<Vec<u8>> for Typed {
+    fn from(buf: Vec<u8>) -> Self {
+        Typed::Bytes(buf.into())
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<bytes::Bytes> for Typed {
+    fn from(buf: bytes::Bytes) -> Self {
+        Typed::Bytes(buf)
+    }
+}
+
+impl From<List> for Typed {
+    fn from(l: List) -> Self {
+        Typed::List(l)
+    }
+}
+
+impl From<Map> for Typed {
+    fn from(m: Map) -> Self {
+        Typed::Map(m)
+    }
+}
+
+impl From<PackedArray> for Typed {
+    fn from(a: PackedArray) -> Self {
+        Typed::Array(a)
+    }
+}
+
+impl From<RawValue> for Typed {
+    fn from(raw: RawValue) -> Self {
+        Typed::Raw(raw)
+    }
+}
+
+/// None is converted into `Typed::Null`.
+impl<T: Into<Typed>> From<Option<T>> for Typed {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Typed::Null, Into::into)
+    }
+}
+
+// The scalars convert like `FromTyped::from_typed`.
+macro_rules! impl_try_from {
+    ($($t:ty),*) => {
+        $(
+            impl TryFrom<Typed> for $t {
+                type Error = Error;
+
+                fn try_from(e: Typed) -> Result<Self, Error> {
+                    <$t>::from_typed(&e).map_err(Error::from)
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64, bool);
+impl_try_from!(Timestamp, Decimal);
+
+impl TryFrom<Typed> for String {
+    type Error = Error;
+
+    fn try_from(e: Typed) -> Result<Self, Error> {
+        match e {
+            Typed::String(s) => Ok(s.into()),
+            _ => Err(invalid_type(&e, "string")),
+        }
+    }
+}
+
+impl TryFrom<Typed> for List {
+    type Error = Error;
+
+    fn try_from(e: Typed) -> Result<Self, Error> {
+        match e {
+            Typed::List(l) => Ok(l),
+            _ => Err(invalid_type(&e, "list")),
+        }
+    }
+}
+
+impl TryFrom<Typed> for Map {
+    type Error = Error;
+
+    fn try_from(e: Typed) -> Result<Self, Error> {
+        match e {
+            Typed::Map(m) => Ok(m),
+            _ => Err(invalid_type(&e, "map")),
+        }
+    }
+}
+
+/// The accessors return None if the value is of another type, they don't
+/// convert between types, see `as_number` for that, and `Typed::Raw` values
+/// are not decoded.
+impl Typed {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Typed::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Typed::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Typed::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_uint(&self) -> Option<u64> {
+        match self {
+            Typed::Uint(un) => Some(*un),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Typed::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<Timestamp> {
+        match self {
+            Typed::Timestamp(ts) => Some(*ts),
+            _ => None,
+        }
+    }
+
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Typed::Decimal(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Typed::Bytes(buf) => Some(&buf[..]),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Typed::String(s) => Some(&s[..]),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&PackedArray> {
+        match self {
+            Typed::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&List> {
+        match self {
+            Typed::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut List> {
+        match self {
+            Typed::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&Map> {
+        match self {
+            Typed::Map(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn as_map_mut(&mut self) -> Option<&mut Map> {
+        match self {
+            Typed::Map(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of `key` in a map.
+    pub fn get(&self, key: &str) -> Option<&Typed> {
+        self.as_map()?.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Typed> {
+        self.as_map_mut()?.get_mut(key)
+    }
+
+    /// Returns the element `i` of a list.
+    pub fn index(&self, i: usize) -> Option<&Typed> {
+        self.as_list()?.get(i)
+    }
+
+    pub fn index_mut(&mut self, i: usize) -> Option<&mut Typed> {
+        self.as_list_mut()?.get_mut(i)
+    }
+
+    /// Looks up the value at `path`.
+    pub fn get_path(&self, path: &Path) -> Option<&Typed> {
+        let mut e = self;
+        for seg in path.segments() {
+            e = match seg {
+                PathSegment::Key(k) => e.get(k)?,
+                PathSegment::Index(i) => e.index(*i)?,
+            };
+        }
+        Some(e)
+    }
+
+    pub fn get_path_mut(&mut self, path: &Path) -> Option<&mut Typed> {
+        let mut e = self;
+        for seg in path.segments() {
+            e = match seg {
+                PathSegment::Key(k) => e.get_mut(k)?,
+                PathSegment::Index(i) => e.index_mut(*i)?,
+            };
+        }
+        Some(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn from() {
+        assert_eq!(Typed::from(-1i8), Typed::Int(-1));
+        assert_eq!(Typed::from(1usize), Typed::Uint(1));
+        assert_eq!(Typed::from(0.5f32), Typed::Float(0.5));
+        assert_eq!(Typed::from(true), Typed::Bool(true));
+        assert_eq!(Typed::from("超"), Typed::String("超".into()));
+        assert_eq!(
+            Typed::from(b"xd".to_vec()),
+            Typed::Bytes(b"xd".to_vec().into())
+        );
+        assert_eq!(Typed::from(None::<i64>), Typed::Null);
+        assert_eq!(Typed::from(Some("x")), Typed::String("x".into()));
+        let l: List = vec![1u64.into(), "x".into()];
+        assert_eq!(
+            Typed::from(l),
+            Typed::List(vec![Typed::Uint(1), Typed::String("x".into())])
+        );
+    }
+
+    #[test]
+    fn try_from() {
+        assert_eq!(i32::try_from(Typed::Int(-1)).unwrap(), -1);
+        let err = u8::try_from(Typed::Uint(256)).unwrap_err();
+        assert!(matches!(err, Error::InvalidData(_)), "{:?}", err);
+        assert!(i64::try_from(Typed::Uint(1)).is_err());
+        let s: String = Typed::from("x").try_into().unwrap();
+        assert_eq!(s, "x");
+        assert!(String::try_from(Typed::Null).is_err());
+        assert!(List::try_from(Typed::List(vec![])).unwrap().is_empty());
+        assert!(Map::try_from(Typed::List(vec![])).is_err());
+        let ts = Timestamp::from_millis(1);
+        assert_eq!(Timestamp::try_from(Typed::from(ts)).unwrap(), ts);
+    }
+
+    #[test]
+    fn accessors() {
+        let mut user = Map::new();
+        user.insert("name".into(), "xd".into());
+        user.insert("age".into(), 3u8.into());
+        let mut m = Map::new();
+        m.insert("users".into(), Typed::List(vec![Typed::Null, user.into()]));
+        let mut e = Typed::from(m);
+
+        let path = Path::root().key("users").index(1).key("name");
+        assert_eq!(e.get_path(&path).and_then(Typed::as_str), Some("xd"));
+        let users = e.get("users").unwrap();
+        assert!(users.index(0).unwrap().is_null());
+        assert_eq!(
+            users.index(1).unwrap().get("age").unwrap().as_uint(),
+            Some(3)
+        );
+        assert_eq!(users.index(1).unwrap().get("age").unwrap().as_int(), None);
+        assert!(users.index(2).is_none());
+        assert!(e.get_path(&Path::root().index(0)).is_none());
+        assert_eq!(e.get_path(&Path::root()), Some(&e));
+
+        *e.get_path_mut(&path).unwrap() = Typed::Bool(false);
+        assert_eq!(e.get_path(&path).unwrap().as_bool(), Some(false));
+        e.get_mut("users").unwrap().as_list_mut().unwrap().clear();
+        assert_eq!(e.get("users").and_then(Typed::as_list), Some(&vec![]));
+    }
+}
@@ -0,0 +1,461 @@
+use crate::codec::{ByteBuf, Map, Str, Typed};
+use crate::convert::{invalid_type, FromTyped, ToTyped};
+use crate::error::{Error, Result};
+use crate::schema::{Describe, Field, Schema};
+use std::convert::TryInto;
+
+/// DType is the type of the elements of a `PackedArray`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+}
+
+const DTYPES: [(DType, &str, usize); 10] = [
+    (DType::I8, "i8", 1),
+    (DType::U8, "u8", 1),
+    (DType::I16, "i16", 2),
+    (DType::U16, "u16", 2),
+    (DType::I32, "i32", 4),
+    (DType::U32, "u32", 4),
+    (DType::I64, "i64", 8),
+    (DType::U64, "u64", 8),
+    (DType::F32, "f32", 4),
+    (DType::F64, "f64", 8),
+];
+
+impl DType {
+    /// Returns the name stored in the encoded array, e.g. "f64".
+    pub fn name(self) -> &'static str {
+        DTYPES[self as usize].1
+    }
+
+    /// Returns the size of an element in bytes.
+    pub fn size(self) -> usize {
+        DTYPES[self as usize].2
+    }
+
+    pub fn from_name(name: &str) -> Option<DType> {
+        DTYPES.iter().find(|d| d.1 == name).map(|d| d.0)
+    }
+
+    // The byte which stands for it in `Typed::Array`.
+    pub(crate) fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub(crate) fn from_code(code: u8) -> Option<DType> {
+        DTYPES.get(usize::from(code)).map(|d| d.0)
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Element is implemented by the primitive numbers a `PackedArray` holds.
+pub trait Element: Copy + sealed::Sealed {
+    const DTYPE: DType;
+
+    #[doc(hidden)]
+    fn extend_le(data: &[Self], buf: &mut Vec<u8>);
+
+    #[doc(hidden)]
+    fn from_le(buf: &[u8]) -> Vec<Self>;
+}
+
+macro_rules! impl_element {
+    ($($t:ty => $dtype:ident),*) => {
+        $(
+            impl sealed::Sealed for $t {}
+
+            impl Element for $t {
+                const DTYPE: DType = DType::$dtype;
+
+                fn extend_le(data: &[Self], buf: &mut Vec<u8>) {
+                    buf.reserve(std::mem::size_of_val(data));
+                    for x in data {
+                        buf.extend_from_slice(&x.to_le_bytes());
+                    }
+                }
+
+                fn from_le(buf: &[u8]) -> Vec<Self> {
+                    buf.chunks_exact(std::mem::size_of::<$t>())
+                        .map(|c| <$t>::from_le_bytes(c.try_into().unwrap()))
+                        .collect()
+                }
+            }
+        )*
+    };
+}
+
+impl_element!(
+    i8 => I8, u8 => U8, i16 => I16, u16 => U16, i32 => I32,
+    u32 => U32, i64 => I64, u64 => U64, f32 => F32, f64 => F64
+);
+
+/// PackedArray is a dense n-dimensional array of numbers whose elements are
+/// packed into a single `Typed::Bytes` in little endian and row-major order,
+/// rather than a list with a value per element. It is encoded as a map:
+///
+/// ```text
+/// {"dtype": "f64", "shape": [2, 3], "data": <48 bytes>}
+/// ```
+///
+/// or as a `Typed::Array` when converted with `Typed::from`, whose dtype
+/// and shape take a few bytes rather than a map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedArray {
+    dtype: DType,
+    shape: Vec<usize>,
+    data: ByteBuf,
+}
+
+fn num_elements(shape: &[usize]) -> Option<usize> {
+    shape.iter().try_fold(1usize, |n, d| n.checked_mul(*d))
+}
+
+impl PackedArray {
+    /// Packs `data`, whose length must be the product of `shape`.
+    pub fn new<T: Element>(shape: Vec<usize>, data: &[T]) -> Result<Self> {
+        let mut buf = Vec::new();
+        T::extend_le(data, &mut buf);
+        Self::from_bytes(T::DTYPE, shape, buf.into())
+    }
+
+    /// Wraps already packed elements.
+    pub fn from_bytes(dtype: DType, shape: Vec<usize>, data: ByteBuf) -> Result<Self> {
+        match num_elements(&shape).and_then(|n| n.checked_mul(dtype.size())) {
+            Some(n) if n == data.len() => Ok(PackedArray { dtype, shape, data }),
+            _ => Err(Error::InvalidInput(format!(
+                "{} bytes of {} don't match the shape {:?}",
+                data.len(),
+                dtype.name(),
+                shape
+            ))),
+        }
+    }
+
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.data.len() / self.dtype.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the packed elements.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Unpacks the elements in row-major order, `T` must match the dtype.
+    pub fn to_vec<T: Element>(&self) -> Result<Vec<T>> {
+        if T::DTYPE != self.dtype {
+            return Err(Error::InvalidData(format!(
+                "invalid dtype: expected {}, found {}",
+                T::DTYPE.name(),
+                self.dtype.name()
+            )));
+        }
+        Ok(T::from_le(&self.data))
+    }
+
+    #[cfg(any(feature = "ndarray", feature = "nalgebra"))]
+    fn check_ndim(&self, ndim: usize) -> Result<()> {
+        if self.shape.len() != ndim {
+            return Err(Error::InvalidData(format!(
+                "invalid shape: expected {} dimensions, found {:?}",
+                ndim, self.shape
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl ToTyped for PackedArray {
+    fn to_typed(&self) -> Typed {
+        let mut m = Map::new();
+        m.insert(
+            String::from("dtype"),
+            Typed::String(Str::from(self.dtype.name())),
+        );
+        m.insert(String::from("shape"), self.shape.to_typed());
+        m.insert(String::from("data"), Typed::Bytes(self.data.clone()));
+        Typed::Map(m)
+    }
+}
+
+impl Describe for PackedArray {
+    fn schema() -> Schema {
+        Schema::record(vec![
+            Field::required("dtype", Schema::String),
+            Field::required("shape", Schema::list(Schema::uint())),
+            Field::required("data", Schema::Bytes),
+        ])
+    }
+}
+
+impl FromTyped for PackedArray {
+    fn from_typed(e: &Typed) -> Result<Self> {
+        let m = match e {
+            Typed::Map(m) => m,
+            Typed::Array(a) => return Ok(a.clone()),
+            _ => return Err(invalid_type(e, "map")),
+        };
+        let field = |k: &str| {
+            m.get(k)
+                .ok_or_else(|| Error::InvalidData(format!("missing field: '{}'", k)))
+        };
+        let dtype = match field("dtype")? {
+            Typed::String(s) => DType::from_name(s)
+                .ok_or_else(|| Error::InvalidData(format!("unknown dtype: '{}'", s)))?,
+            e => return Err(invalid_type(e, "string")),
+        };
+        let shape = Vec::<usize>::from_typed(field("shape")?)?;
+        let data = match field("data")? {
+            Typed::Bytes(data) => data.clone(),
+            e => return Err(invalid_type(e, "bytes")),
+        };
+        Self::from_bytes(dtype, shape, data)
+            .map_err(|err| Error::InvalidData(err.to_string().to_string()))
+    }
+}
+
+#[cfg(feature = "ndarray")]
+mod ndarray_impls {
+    use super::*;
+    use ndarray::{Array, ArrayBase, Data, Dimension, IxDyn};
+
+    impl PackedArray {
+        pub fn from_ndarray<T, S, D>(a: &ArrayBase<S, D>) -> Self
+        where
+            T: Element,
+            S: Data<Elem = T>,
+            D: Dimension,
+        {
+            let mut buf = Vec::with_capacity(a.len() * T::DTYPE.size());
+            match a.as_slice() {
+                Some(data) => T::extend_le(data, &mut buf),
+                None => {
+                    for x in a.iter() {
+                        T::extend_le(std::slice::from_ref(x), &mut buf);
+                    }
+                }
+            }
+            PackedArray {
+                dtype: T::DTYPE,
+                shape: a.shape().to_vec(),
+                data: buf.into(),
+            }
+        }
+
+        /// Unpacks into an array of the dimension `D`, `ndarray::IxDyn`
+        /// takes any shape.
+        pub fn to_ndarray<T: Element, D: Dimension>(&self) -> Result<Array<T, D>> {
+            if let Some(ndim) = D::NDIM {
+                self.check_ndim(ndim)?;
+            }
+            Array::from_shape_vec(IxDyn(&self.shape), self.to_vec()?)
+                .and_then(|a| a.into_dimensionality())
+                .map_err(|err| Error::InvalidData(err.to_string().to_string()))
+        }
+    }
+
+    impl<T, S, D> ToTyped for ArrayBase<S, D>
+    where
+        T: Element,
+        S: Data<Elem = T>,
+        D: Dimension,
+    {
+        fn to_typed(&self) -> Typed {
+            PackedArray::from_ndarray(self).to_typed()
+        }
+    }
+
+    impl<T, S, D> Describe for ArrayBase<S, D>
+    where
+        T: Element,
+        S: Data<Elem = T>,
+        D: Dimension,
+    {
+        fn schema() -> Schema {
+            PackedArray::schema()
+        }
+    }
+
+    impl<T: Element, D: Dimension> FromTyped for Array<T, D> {
+        fn from_typed(e: &Typed) -> Result<Self> {
+            PackedArray::from_typed(e)?.to_ndarray()
+        }
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+mod nalgebra_impls {
+    use super::*;
+    use nalgebra::{DMatrix, DVector, Scalar};
+
+    impl PackedArray {
+        /// Packs a matrix with the shape `[nrows, ncols]`, the elements are
+        /// reordered from the column-major order of nalgebra.
+        pub fn from_dmatrix<T: Element + Scalar>(m: &DMatrix<T>) -> Self {
+            let mut buf = Vec::with_capacity(m.len() * T::DTYPE.size());
+            T::extend_le(m.transpose().as_slice(), &mut buf);
+            PackedArray {
+                dtype: T::DTYPE,
+                shape: vec![m.nrows(), m.ncols()],
+                data: buf.into(),
+            }
+        }
+
+        pub fn to_dmatrix<T: Element + Scalar>(&self) -> Result<DMatrix<T>> {
+            self.check_ndim(2)?;
+            let data = self.to_vec()?;
+            Ok(DMatrix::from_row_slice(self.shape[0], self.shape[1], &data))
+        }
+
+        /// Packs a vector with the shape `[len]`.
+        pub fn from_dvector<T: Element + Scalar>(v: &DVector<T>) -> Self {
+            let mut buf = Vec::with_capacity(v.len() * T::DTYPE.size());
+            T::extend_le(v.as_slice(), &mut buf);
+            PackedArray {
+                dtype: T::DTYPE,
+                shape: vec![v.len()],
+                data: buf.into(),
+            }
+        }
+
+        pub fn to_dvector<T: Element + Scalar>(&self) -> Result<DVector<T>> {
+            self.check_ndim(1)?;
+            Ok(DVector::from_vec(self.to_vec()?))
+        }
+    }
+
+    impl<T: Element + Scalar> ToTyped for DMatrix<T> {
+        fn to_typed(&self) -> Typed {
+            PackedArray::from_dmatrix(self).to_typed()
+        }
+    }
+
+    impl<T: Element + Scalar> Describe for DMatrix<T> {
+        fn schema() -> Schema {
+            PackedArray::schema()
+        }
+    }
+
+    impl<T: Element + Scalar> FromTyped for DMatrix<T> {
+        fn from_typed(e: &Typed) -> Result<Self> {
+            PackedArray::from_typed(e)?.to_dmatrix()
+        }
+    }
+
+    impl<T: Element + Scalar> ToTyped for DVector<T> {
+        fn to_typed(&self) -> Typed {
+            PackedArray::from_dvector(self).to_typed()
+        }
+    }
+
+    impl<T: Element + Scalar> Describe for DVector<T> {
+        fn schema() -> Schema {
+            PackedArray::schema()
+        }
+    }
+
+    impl<T: Element + Scalar> FromTyped for DVector<T> {
+        fn from_typed(e: &Typed) -> Result<Self> {
+            PackedArray::from_typed(e)?.to_dvector()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecReadExt, CodecWriteExt};
+
+    #[test]
+    fn packed() {
+        let a = PackedArray::new(vec![2, 3], &[1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_eq!(a.dtype(), DType::F64);
+        assert_eq!(a.len(), 6);
+        assert_eq!(&a.as_bytes()[8..16], &2.0f64.to_le_bytes());
+
+        let mut buf = Vec::new();
+        buf.write_typed(&a.to_typed()).unwrap();
+        let e = (&buf[..]).read_typed().unwrap();
+        let b = PackedArray::from_typed(&e).unwrap();
+        assert_eq!(b, a);
+        assert_eq!(b.to_vec::<f64>().unwrap()[5], 6.0);
+        assert!(b.to_vec::<f32>().is_err());
+
+        assert!(PackedArray::new(vec![2, 2], &[1i32, 2, 3]).is_err());
+        assert!(PackedArray::new(vec![usize::MAX, 2], &[0u8]).is_err());
+        assert!(PackedArray::new::<u8>(vec![0], &[]).unwrap().is_empty());
+        let mut m = match a.to_typed() {
+            Typed::Map(m) => m,
+            _ => unreachable!(),
+        };
+        m.insert(String::from("dtype"), Typed::String("f32".into()));
+        let err = PackedArray::from_typed(&Typed::Map(m)).unwrap_err();
+        assert!(matches!(err, Error::InvalidData(_)), "{:?}", err);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn ndarray() {
+        use ndarray::{array, Array2, Array3, ArrayD};
+
+        let a: Array2<i16> = array![[1, 2, 3], [4, 5, 6]];
+        let e = a.to_typed();
+        assert_eq!(Array2::<i16>::from_typed(&e).unwrap(), a);
+        assert_eq!(
+            ArrayD::<i16>::from_typed(&e).unwrap().shape(),
+            &[2usize, 3][..]
+        );
+        assert!(Array3::<i16>::from_typed(&e).is_err());
+        assert!(Array2::<u16>::from_typed(&e).is_err());
+
+        // Not in the standard layout.
+        let t = a.t();
+        let packed = PackedArray::from_ndarray(&t);
+        assert_eq!(packed.shape(), &[3, 2]);
+        assert_eq!(packed.to_vec::<i16>().unwrap(), vec![1, 4, 2, 5, 3, 6]);
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn nalgebra() {
+        use nalgebra::{DMatrix, DVector};
+
+        let m = DMatrix::from_row_slice(2, 3, &[1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let packed = PackedArray::from_dmatrix(&m);
+        assert_eq!(packed.shape(), &[2, 3]);
+        assert_eq!(
+            packed.to_vec::<f32>().unwrap(),
+            vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+        );
+        assert_eq!(DMatrix::<f32>::from_typed(&m.to_typed()).unwrap(), m);
+
+        let v = DVector::from_vec(vec![7u64, 8]);
+        assert_eq!(DVector::<u64>::from_typed(&v.to_typed()).unwrap(), v);
+        assert!(DVector::<f32>::from_typed(&m.to_typed()).is_err());
+    }
+}
@@ -0,0 +1,396 @@
+use crate::codec::{
+    base_tag, CodecReadExt, CodecWriteExt, List, Map, Typed, TYPE_ARRAY, TYPE_BYTES,
+    TYPE_BYTES_DEFLATE, TYPE_DECIMAL, TYPE_FALSE, TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT,
+    TYPE_INT_FIXED, TYPE_LIST, TYPE_LIST_V2, TYPE_MAP, TYPE_MAP_V2, TYPE_NULL, TYPE_STRING,
+    TYPE_STRING_DEFLATE, TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT, TYPE_UINT_FIXED,
+};
+use crate::error::{Error, Result};
+use crate::limits::{Budget, Limits};
+use crate::options::DecodeOptions;
+use crate::raw::RawValue;
+use crate::varint::{VarintReadExt, VarintWriteExt, MAX_VARINT_LEN};
+use std::future::Future;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+async fn read_byte<R: AsyncRead + Unpin + ?Sized>(r: &mut R, buf: &mut Vec<u8>) -> Result<u8> {
+    let b = r.read_u8().await?;
+    buf.push(b);
+    Ok(b)
+}
+
+async fn copy_uvarint<R: AsyncRead + Unpin + ?Sized>(r: &mut R, buf: &mut Vec<u8>) -> Result<u64> {
+    let start = buf.len();
+    for _ in 0..MAX_VARINT_LEN {
+        if read_byte(r, buf).await? < 0x80 {
+            return VarintReadExt::read_uvarint(&mut &buf[start..]);
+        }
+    }
+    Err(Error::VarintOverflow)
+}
+
+async fn copy_exact<R: AsyncRead + Unpin + ?Sized>(
+    r: &mut R,
+    buf: &mut Vec<u8>,
+    sz: u64,
+) -> Result<()> {
+    // Not allocated upfront, the length may be bogus.
+    let n = (&mut *r).take(sz).read_to_end(buf).await?;
+    if (n as u64) < sz {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(())
+}
+
+async fn copy_sized<R: AsyncRead + Unpin + ?Sized>(
+    r: &mut R,
+    buf: &mut Vec<u8>,
+    budget: &Budget,
+) -> Result<()> {
+    let sz = copy_uvarint(r, buf).await?;
+    budget.bytes(sz)?;
+    copy_exact(r, buf, sz).await
+}
+
+// Copies the next encoded value into `buf`, `tag` is the type of a value
+// whose tag was already consumed. The nesting is tracked with a stack, as
+// async functions can't recurse without boxing, and the value is rejected
+// as soon as it exceeds the limits of `budget`.
+async fn copy_typed<R: AsyncRead + Unpin + ?Sized>(
+    r: &mut R,
+    buf: &mut Vec<u8>,
+    mut tag: Option<u8>,
+    budget: &mut Budget,
+) -> Result<()> {
+    // Whether the container is a map and the number of elements left.
+    let mut stack: Vec<(bool, u64)> = Vec::new();
+    loop {
+        let t = match tag.take() {
+            Some(t) => t,
+            None => read_byte(r, buf).await?,
+        };
+        match t {
+            TYPE_NULL | TYPE_FALSE | TYPE_TRUE => {}
+            TYPE_INT | TYPE_UINT | TYPE_FLOAT => {
+                copy_uvarint(r, buf).await?;
+            }
+            TYPE_INT_FIXED | TYPE_UINT_FIXED | TYPE_FLOAT_FIXED => copy_exact(r, buf, 8).await?,
+            TYPE_TIMESTAMP | TYPE_DECIMAL => {
+                copy_uvarint(r, buf).await?;
+                copy_uvarint(r, buf).await?;
+            }
+            TYPE_BYTES | TYPE_STRING => copy_sized(r, buf, budget).await?,
+            TYPE_BYTES_DEFLATE | TYPE_STRING_DEFLATE => {
+                budget.bytes(copy_uvarint(r, buf).await?)?;
+                copy_sized(r, buf, budget).await?;
+            }
+            TYPE_ARRAY => {
+                read_byte(r, buf).await?;
+                for _ in 0..copy_uvarint(r, buf).await? {
+                    copy_uvarint(r, buf).await?;
+                }
+                copy_sized(r, buf, budget).await?;
+            }
+            TYPE_LIST | TYPE_MAP | TYPE_LIST_V2 | TYPE_MAP_V2 => {
+                let nelem = match t {
+                    TYPE_LIST_V2 | TYPE_MAP_V2 => copy_uvarint(r, buf).await?,
+                    _ => u64::from(read_byte(r, buf).await?),
+                };
+                budget.enter(nelem)?;
+                let is_map = base_tag(t) == TYPE_MAP;
+                if nelem > 0 {
+                    stack.push((is_map, nelem));
+                    if is_map {
+                        copy_sized(r, buf, budget).await?;
+                    }
+                    continue;
+                }
+                budget.leave();
+            }
+            _ => return Err(Error::UnknownType(t)),
+        }
+
+        // A value is complete, so are the containers it was the last
+        // element of.
+        loop {
+            match stack.last_mut() {
+                None => return Ok(()),
+                Some((_, 1)) => {
+                    stack.pop();
+                    budget.leave();
+                }
+                Some((is_map, nelem)) => {
+                    *nelem -= 1;
+                    if *is_map {
+                        copy_sized(r, buf, budget).await?;
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// AsyncCodecReadExt mirrors `CodecReadExt` for tokio's `AsyncRead`. Each
+/// value is read into memory before it is decoded, like
+/// `CodecReadExt::read_raw`.
+pub trait AsyncCodecReadExt: AsyncRead + Unpin {
+    fn read_uvarint(&mut self) -> impl Future<Output = Result<u64>> + Send + '_
+    where
+        Self: Send,
+    {
+        async move { copy_uvarint(self, &mut Vec::with_capacity(MAX_VARINT_LEN)).await }
+    }
+
+    fn read_varint(&mut self) -> impl Future<Output = Result<i64>> + Send + '_
+    where
+        Self: Send,
+    {
+        async move {
+            let mut buf = Vec::with_capacity(MAX_VARINT_LEN);
+            copy_uvarint(self, &mut buf).await?;
+            VarintReadExt::read_varint(&mut &buf[..])
+        }
+    }
+
+    fn read_sized(&mut self) -> impl Future<Output = Result<Vec<u8>>> + Send + '_
+    where
+        Self: Send,
+    {
+        async move {
+            let mut buf = Vec::new();
+            copy_sized(self, &mut buf, &Budget::new(&Limits::unlimited())).await?;
+            let n = VarintReadExt::read_uvarint(&mut &buf[..])?;
+            buf.drain(..buf.len() - n as usize);
+            Ok(buf)
+        }
+    }
+
+    fn read_raw(&mut self) -> impl Future<Output = Result<RawValue>> + Send + '_
+    where
+        Self: Send,
+    {
+        async move {
+            let mut buf = Vec::new();
+            let mut budget = Budget::new(&Limits::default());
+            copy_typed(self, &mut buf, None, &mut budget).await?;
+            Ok(RawValue::from_encoded(buf))
+        }
+    }
+
+    fn read_typed(&mut self) -> impl Future<Output = Result<Typed>> + Send + '_
+    where
+        Self: Send,
+    {
+        async move { self.read_raw().await?.decode() }
+    }
+
+    fn read_typed_with<'a>(
+        &'a mut self,
+        opts: &'a DecodeOptions,
+    ) -> impl Future<Output = Result<Typed>> + Send + 'a
+    where
+        Self: Send,
+    {
+        async move {
+            // The limits apply while the value is read, before it is all
+            // buffered.
+            let mut buf = Vec::new();
+            copy_typed(self, &mut buf, None, &mut Budget::new(&opts.limits)).await?;
+            CodecReadExt::read_typed_with(&mut &buf[..], opts)
+        }
+    }
+
+    fn read_list(&mut self) -> impl Future<Output = Result<List>> + Send + '_
+    where
+        Self: Send,
+    {
+        async move {
+            let mut buf = vec![TYPE_LIST];
+            let mut budget = Budget::new(&Limits::default());
+            copy_typed(self, &mut buf, Some(TYPE_LIST), &mut budget).await?;
+            CodecReadExt::read_list(&mut &buf[1..])
+        }
+    }
+
+    fn read_map(&mut self) -> impl Future<Output = Result<Map>> + Send + '_
+    where
+        Self: Send,
+    {
+        async move {
+            let mut buf = vec![TYPE_MAP];
+            let mut budget = Budget::new(&Limits::default());
+            copy_typed(self, &mut buf, Some(TYPE_MAP), &mut budget).await?;
+            CodecReadExt::read_map(&mut &buf[1..])
+        }
+    }
+}
+
+/// All types that implement `AsyncRead` get methods defined in
+/// `AsyncCodecReadExt` for free.
+impl<R: AsyncRead + Unpin + ?Sized> AsyncCodecReadExt for R {}
+
+/// AsyncCodecWriteExt mirrors `CodecWriteExt` for tokio's `AsyncWrite`, each
+/// value is encoded into memory and written at once.
+pub trait AsyncCodecWriteExt: AsyncWrite + Unpin {
+    fn write_uvarint(&mut self, un: u64) -> impl Future<Output = Result<()>> + Send + '_
+    where
+        Self: Send,
+    {
+        let mut buf = Vec::with_capacity(MAX_VARINT_LEN);
+        let res = VarintWriteExt::write_uvarint(&mut buf, un);
+        async move {
+            res?;
+            Ok(self.write_all(&buf).await?)
+        }
+    }
+
+    fn write_varint(&mut self, n: i64) -> impl Future<Output = Result<()>> + Send + '_
+    where
+        Self: Send,
+    {
+        let mut buf = Vec::with_capacity(MAX_VARINT_LEN);
+        let res = VarintWriteExt::write_varint(&mut buf, n);
+        async move {
+            res?;
+            Ok(self.write_all(&buf).await?)
+        }
+    }
+
+    fn write_sized<'a>(&'a mut self, data: &'a [u8]) -> impl Future<Output = Result<()>> + Send + 'a
+    where
+        Self: Send,
+    {
+        async move {
+            self.write_uvarint(data.len() as u64).await?;
+            Ok(self.write_all(data).await?)
+        }
+    }
+
+    fn write_typed(&mut self, e: &Typed) -> impl Future<Output = Result<()>> + Send + '_
+    where
+        Self: Send,
+    {
+        let mut buf = Vec::new();
+        let res = CodecWriteExt::write_typed(&mut buf, e);
+        async move {
+            res?;
+            Ok(self.write_all(&buf).await?)
+        }
+    }
+
+    fn write_list(&mut self, l: &List) -> impl Future<Output = Result<()>> + Send + '_
+    where
+        Self: Send,
+    {
+        let mut buf = Vec::new();
+        let res = CodecWriteExt::write_list(&mut buf, l);
+        async move {
+            res?;
+            Ok(self.write_all(&buf).await?)
+        }
+    }
+
+    fn write_map(&mut self, m: &Map) -> impl Future<Output = Result<()>> + Send + '_
+    where
+        Self: Send,
+    {
+        let mut buf = Vec::new();
+        let res = CodecWriteExt::write_map(&mut buf, m);
+        async move {
+            res?;
+            Ok(self.write_all(&buf).await?)
+        }
+    }
+}
+
+/// All types that implement `AsyncWrite` get methods defined in
+/// `AsyncCodecWriteExt` for free.
+impl<W: AsyncWrite + Unpin + ?Sized> AsyncCodecWriteExt for W {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn value() -> Typed {
+        let mut inner = Map::new();
+        inner.insert(String::from("k"), Typed::List(vec![]));
+        inner.insert(String::from("s"), Typed::String("超".into()));
+        let mut m = Map::new();
+        m.insert(
+            String::from("list"),
+            Typed::List(vec![
+                Typed::Map(inner),
+                Typed::Int(-300),
+                Typed::Map(Map::new()),
+                Typed::Float(0.5),
+            ]),
+        );
+        m.insert(String::from("b"), Typed::Bytes(vec![1u8, 2u8].into()));
+        Typed::Map(m)
+    }
+
+    #[tokio::test]
+    async fn roundtrip() {
+        let (mut w, mut r) = tokio::io::duplex(7);
+        let writer = tokio::spawn(async move {
+            w.write_typed(&value()).await.unwrap();
+            w.write_varint(-1).await.unwrap();
+            w.write_uvarint(u64::MAX).await.unwrap();
+            w.write_sized(b"xd").await.unwrap();
+            w.write_list(&vec![Typed::Uint(1)]).await.unwrap();
+            w.write_map(&Map::new()).await.unwrap();
+            w.write_typed(&value()).await.unwrap();
+        });
+
+        assert_eq!(r.read_typed().await.unwrap(), value());
+        assert_eq!(r.read_varint().await.unwrap(), -1);
+        assert_eq!(r.read_uvarint().await.unwrap(), u64::MAX);
+        assert_eq!(r.read_sized().await.unwrap(), b"xd");
+        assert_eq!(r.read_list().await.unwrap(), vec![Typed::Uint(1)]);
+        assert!(r.read_map().await.unwrap().is_empty());
+        let raw = r.read_raw().await.unwrap();
+        assert_eq!(raw.decode().unwrap(), value());
+        writer.await.unwrap();
+
+        let err = r.read_typed().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn invalid() {
+        let mut buf = Vec::new();
+        CodecWriteExt::write_typed(&mut buf, &value()).unwrap();
+        for n in 0..buf.len() {
+            assert!(AsyncCodecReadExt::read_typed(&mut &buf[..n]).await.is_err());
+        }
+        assert!(AsyncCodecReadExt::read_typed(&mut &b"x"[..]).await.is_err());
+        let mut r = &b"s\xff\xff\xff\xff\x0fx"[..];
+        assert!(AsyncCodecReadExt::read_typed(&mut r).await.is_err());
+        assert!(AsyncCodecReadExt::read_uvarint(&mut &[0xffu8; 11][..])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        // The length is rejected before the bytes are read.
+        let (mut w, mut r) = tokio::io::duplex(64);
+        w.write_all(b"s\xff\xff\xff\x7f").await.unwrap();
+        let opts = DecodeOptions::new().limits(Limits::new().max_bytes_len(8));
+        let err = r.read_typed_with(&opts).await.unwrap_err();
+        assert!(matches!(err, Error::TooLong(_)), "{:?}", err);
+
+        let mut deep = [b'l', 1u8].repeat(100_000);
+        deep.push(b'n');
+        let err = AsyncCodecReadExt::read_raw(&mut &deep[..])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::DepthExceeded(64)), "{:?}", err);
+        let opts = DecodeOptions::new().limits(Limits::new().max_depth(2));
+        let err = AsyncCodecReadExt::read_typed_with(&mut &deep[..], &opts)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::DepthExceeded(2)), "{:?}", err);
+    }
+}
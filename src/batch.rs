@@ -0,0 +1,253 @@
+use crate::codec::{CodecReadExt, CodecWriteExt, Typed};
+use crate::error::{Error, Result};
+use crate::options::DecodeOptions;
+use std::convert::TryInto;
+use std::ops::Range;
+
+const U32_LEN: usize = 4;
+
+fn read_u32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(buf[pos..pos + U32_LEN].try_into().unwrap())
+}
+
+fn too_large() -> Error {
+    Error::InvalidInput("the batch exceeds 4 GiB".into())
+}
+
+/// BatchBuilder encodes values one after another into a `Batch`.
+#[derive(Debug, Clone, Default)]
+pub struct BatchBuilder {
+    buf: Vec<u8>,
+    offsets: Vec<u32>,
+}
+
+impl BatchBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, e: &Typed) -> Result<()> {
+        let offset = self.buf.len();
+        self.buf.write_typed(e)?;
+        self.push_offset(offset)
+    }
+
+    /// Appends an already encoded value as is.
+    pub fn push_encoded(&mut self, encoded: &[u8]) -> Result<()> {
+        let offset = self.buf.len();
+        self.buf.extend_from_slice(encoded);
+        self.push_offset(offset)
+    }
+
+    fn push_offset(&mut self, offset: usize) -> Result<()> {
+        let table = (self.offsets.len() + 2) * U32_LEN;
+        if self.buf.len() + table > u32::MAX as usize {
+            self.buf.truncate(offset);
+            return Err(too_large());
+        }
+        self.offsets.push(offset as u32);
+        Ok(())
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Appends the offsets table.
+    pub fn finish(mut self) -> Batch {
+        for offset in self.offsets.iter() {
+            self.buf.extend_from_slice(&offset.to_le_bytes());
+        }
+        self.buf
+            .extend_from_slice(&(self.offsets.len() as u32).to_le_bytes());
+        Batch {
+            table: self.buf.len() - (self.offsets.len() + 1) * U32_LEN,
+            buf: self.buf,
+        }
+    }
+}
+
+/// Batch holds many encoded values in a single buffer, followed by a table
+/// of their offsets and their number, all little endian u32s:
+///
+/// ```text
+/// value 0 | value 1 | ... | offset 0 | offset 1 | ... | count
+/// ```
+///
+/// So any value is found in O(1) and a range of values can be copied into
+/// another batch without being decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Batch<B = Vec<u8>> {
+    buf: B,
+    // The position of the offsets table.
+    table: usize,
+}
+
+impl<B: AsRef<[u8]>> Batch<B> {
+    /// Wraps a buffer built by `BatchBuilder`, the offsets table is checked
+    /// but the values are not until they are decoded.
+    pub fn new(buf: B) -> Result<Self> {
+        let invalid = |msg: &str| Error::InvalidData(msg.to_owned().to_string());
+        let b = buf.as_ref();
+        if b.len() < U32_LEN {
+            return Err(invalid("the batch is too short"));
+        }
+        let n = read_u32(b, b.len() - U32_LEN) as usize;
+        let table = (n + 1)
+            .checked_mul(U32_LEN)
+            .and_then(|len| b.len().checked_sub(len))
+            .ok_or_else(|| invalid("the offsets table is truncated"))?;
+        let mut prev = 0;
+        for i in 0..n {
+            let offset = read_u32(b, table + i * U32_LEN) as usize;
+            if offset < prev || offset > table || (i == 0 && offset != 0) {
+                return Err(invalid("the offsets are out of order"));
+            }
+            prev = offset;
+        }
+        if n == 0 && table != 0 {
+            return Err(invalid("trailing bytes before the offsets table"));
+        }
+        Ok(Batch { buf, table })
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        (self.buf.as_ref().len() - self.table) / U32_LEN - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn offset(&self, i: usize) -> usize {
+        if i == self.len() {
+            return self.table;
+        }
+        read_u32(self.buf.as_ref(), self.table + i * U32_LEN) as usize
+    }
+
+    /// Returns the encoded bytes of the value `i`.
+    pub fn get(&self, i: usize) -> Option<&[u8]> {
+        if i >= self.len() {
+            return None;
+        }
+        Some(&self.buf.as_ref()[self.offset(i)..self.offset(i + 1)])
+    }
+
+    /// Decodes the value `i`.
+    pub fn decode(&self, i: usize) -> Result<Typed> {
+        self.decode_with(i, &DecodeOptions::default())
+    }
+
+    pub fn decode_with(&self, i: usize, opts: &DecodeOptions) -> Result<Typed> {
+        let mut encoded = self.get(i).ok_or_else(|| {
+            Error::InvalidInput(format!("index out of bounds: {} >= {}", i, self.len()))
+        })?;
+        let e = encoded.read_typed_with(opts)?;
+        if !encoded.is_empty() {
+            return Err(Error::TrailingBytes);
+        }
+        Ok(e)
+    }
+
+    /// Iterates over the encoded bytes of the values.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+
+    /// Copies the values in `range` into a new batch, they are not
+    /// re-encoded, only the offsets are rebased.
+    pub fn slice(&self, range: Range<usize>) -> Batch {
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        let mut builder = BatchBuilder::new();
+        if range.start < range.end {
+            let start = self.offset(range.start);
+            let end = self.offset(range.end);
+            builder
+                .buf
+                .extend_from_slice(&self.buf.as_ref()[start..end]);
+            builder
+                .offsets
+                .extend(range.map(|i| (self.offset(i) - start) as u32));
+        }
+        builder.finish()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf.as_ref()
+    }
+
+    pub fn into_inner(self) -> B {
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Vec<Typed> {
+        (0..10)
+            .map(|i| match i % 3 {
+                0 => Typed::Int(-i),
+                1 => Typed::String("x".repeat(i as usize).into()),
+                _ => Typed::List(vec![Typed::Uint(i as u64); i as usize]),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn access() {
+        let batch = batch_of(&values());
+        assert_eq!(batch.len(), 10);
+        let batch = Batch::new(batch.as_bytes()).unwrap();
+        for (i, e) in values().iter().enumerate() {
+            assert_eq!(&batch.decode(i).unwrap(), e);
+        }
+        assert!(batch.get(10).is_none());
+        assert!(batch.decode(10).is_err());
+        assert_eq!(batch.iter().count(), 10);
+
+        let sub = batch.slice(3..7);
+        assert_eq!(sub.len(), 4);
+        assert_eq!(sub, batch_of(&values()[3..7]));
+        assert!(Batch::new(sub.as_bytes()).is_ok());
+        assert_eq!(sub.get(0), batch.get(3));
+        assert_eq!(batch.slice(8..20).len(), 2);
+
+        let empty = BatchBuilder::new().finish();
+        assert_eq!(empty.as_bytes(), &[0u8; 4]);
+        assert!(Batch::new(empty.as_bytes()).unwrap().is_empty());
+    }
+
+    fn batch_of(values: &[Typed]) -> Batch {
+        let mut builder = BatchBuilder::new();
+        for e in values.iter() {
+            builder.push(e).unwrap();
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Batch::new(&[0u8; 3][..]).is_err());
+        assert!(Batch::new(&[1u8, 0, 0, 0][..]).is_err());
+        assert!(Batch::new(&[9u8, 0, 0, 0, 0][..]).is_err());
+
+        let mut builder = BatchBuilder::new();
+        builder.push(&Typed::Int(1)).unwrap();
+        builder.push_encoded(b"x").unwrap();
+        let batch = builder.finish();
+        assert!(batch.decode(1).is_err());
+        let mut buf = batch.into_inner();
+        // The offset of the second value points past the table.
+        buf[7] = 100;
+        assert!(Batch::new(&buf[..]).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! Inspects and converts encoded values, run `xdcodec help` for the usage.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+use xdcodec_rs::{testing, Codec, Config, Limits, Typed};
+
+const USAGE: &str = "\
+usage: xdcodec <command> [options] [file]
+
+Reads the file, or stdin if it is missing or `-`, and writes to stdout.
+
+commands:
+  print        prints the encoded value as a tree
+  to-json      converts the encoded value into JSON
+  from-json    encodes the JSON value, canonically with --canonical
+  validate     checks that the encoded value is within the limits
+  help         prints this
+
+options of validate, the defaults are those of `Limits::new`:
+  --max-bytes-len <n>
+  --max-depth <n>
+  --max-container-len <n>
+  --max-elements <n>";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Print,
+    ToJson,
+    FromJson { canonical: bool },
+    Validate(Limits),
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: Command,
+    file: Option<String>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let mut command = match args.next().as_deref() {
+        Some("print") => Command::Print,
+        Some("to-json") => Command::ToJson,
+        Some("from-json") => Command::FromJson { canonical: false },
+        Some("validate") => Command::Validate(Limits::new()),
+        Some("help") | Some("-h") | Some("--help") | None => Command::Help,
+        Some(other) => return Err(format!("unknown command: {}", other)),
+    };
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        match (&mut command, arg.as_str()) {
+            (Command::FromJson { canonical }, "--canonical") => *canonical = true,
+            (Command::Validate(limits), opt) if opt.starts_with("--") => {
+                let n = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| format!("{} needs a number", opt))?;
+                *limits = match opt {
+                    "--max-bytes-len" => limits.clone().max_bytes_len(n),
+                    "--max-depth" => limits.clone().max_depth(n),
+                    "--max-container-len" => limits.clone().max_container_len(n),
+                    "--max-elements" => limits.clone().max_elements(n),
+                    _ => return Err(format!("unknown option: {}", opt)),
+                };
+            }
+            (_, opt) if opt.starts_with("--") => {
+                return Err(format!("unknown option: {}", opt));
+            }
+            (_, path) if file.is_none() => file = Some(path.to_string()),
+            (_, path) => return Err(format!("unexpected argument: {}", path)),
+        }
+    }
+    Ok(Args { command, file })
+}
+
+fn read_input(file: Option<&str>) -> io::Result<Vec<u8>> {
+    match file {
+        None | Some("-") => {
+            let mut buf = Vec::new();
+            io::stdin().read_to_end(&mut buf)?;
+            Ok(buf)
+        }
+        Some(path) => fs::read(path),
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    if args.command == Command::Help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let input = read_input(args.file.as_deref()).map_err(|err| err.to_string())?;
+    let decode = |codec: &Codec| codec.decode(&input).map_err(|err| err.to_string());
+    let output = match args.command {
+        Command::Print => {
+            let e = decode(&Codec::default())?;
+            format!("{}\n", testing::render(&e)).into_bytes()
+        }
+        Command::ToJson => {
+            let v = decode(&Codec::default())?
+                .to_json()
+                .map_err(|err| err.to_string())?;
+            let s = serde_json::to_string_pretty(&v).map_err(|err| err.to_string())?;
+            format!("{}\n", s).into_bytes()
+        }
+        Command::FromJson { canonical } => {
+            let s = String::from_utf8(input).map_err(|err| err.to_string())?;
+            let e = Typed::from_json_str(&s).map_err(|err| err.to_string())?;
+            Codec::new(Config::new().canonical(canonical))
+                .encode(&e)
+                .map_err(|err| err.to_string())?
+        }
+        Command::Validate(limits) => {
+            decode(&Codec::new(Config::new().limits(limits)))?;
+            format!("ok, {} bytes\n", input.len()).into_bytes()
+        }
+        Command::Help => unreachable!(),
+    };
+    io::stdout()
+        .write_all(&output)
+        .map_err(|err| err.to_string())
+}
+
+fn main() {
+    let res = parse_args(env::args().skip(1)).and_then(run);
+    if let Err(err) = res {
+        eprintln!("xdcodec: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn args() {
+        assert_eq!(parse(&[]).unwrap().command, Command::Help);
+        let args = parse(&["from-json", "--canonical", "x.json"]).unwrap();
+        assert_eq!(args.command, Command::FromJson { canonical: true });
+        assert_eq!(args.file.as_deref(), Some("x.json"));
+        let args = parse(&["validate", "--max-depth", "3", "-"]).unwrap();
+        assert_eq!(args.command, Command::Validate(Limits::new().max_depth(3)));
+
+        assert!(parse(&["dump"]).is_err());
+        assert!(parse(&["print", "--canonical"]).is_err());
+        assert!(parse(&["validate", "--max-depth"]).is_err());
+        assert!(parse(&["validate", "--max-depth", "x"]).is_err());
+        assert!(parse(&["print", "a", "b"]).is_err());
+    }
+}
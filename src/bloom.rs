@@ -0,0 +1,213 @@
+use crate::codec::{CodecReadExt, CodecWriteExt, Type, Typed};
+use crate::error::{Error, Result};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+// The header is written before the value, its tag never collides with the
+// tags of values.
+pub(crate) const HEADER_KEY_BLOOM: Type = b'K';
+const BITS_PER_KEY: usize = 10;
+const NUM_HASHES: u8 = 7;
+
+/// KeyBloom is a bloom filter of the map keys in a document, it answers
+/// whether a document possibly contains a key without decoding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBloom {
+    bits: Vec<u8>,
+    hashes: u8,
+}
+
+// FNV-1a, it is stable across platforms and releases.
+fn fnv1a(key: &str) -> u64 {
+    let mut h = 0xcbf2_9ce4_8422_2325u64;
+    for b in key.as_bytes() {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
+
+// Raw values are decoded for their keys, the malformed ones have none.
+fn collect_keys<'a>(e: &'a Typed, keys: &mut Vec<Cow<'a, str>>) {
+    match e {
+        Typed::List(l) => l.iter().for_each(|e| collect_keys(e, keys)),
+        Typed::Map(m) => {
+            for (k, v) in m.iter() {
+                keys.push(Cow::Borrowed(k));
+                collect_keys(v, keys);
+            }
+        }
+        Typed::Raw(raw) => {
+            if let Ok(decoded) = raw.decode() {
+                let mut raw_keys = Vec::new();
+                collect_keys(&decoded, &mut raw_keys);
+                keys.extend(raw_keys.into_iter().map(|k| Cow::Owned(k.into_owned())));
+            }
+        }
+        _ => {}
+    }
+}
+
+impl KeyBloom {
+    /// Creates an empty filter sized for about `nkeys` keys.
+    pub fn with_capacity(nkeys: usize) -> Self {
+        let nbytes = (nkeys.max(1) * BITS_PER_KEY).div_ceil(8);
+        KeyBloom {
+            bits: vec![0u8; nbytes],
+            hashes: NUM_HASHES,
+        }
+    }
+
+    /// Builds a filter with all the map keys found in `e`, including the
+    /// keys of nested maps and of the maps inside `Typed::Raw` values.
+    pub fn from_typed(e: &Typed) -> Self {
+        let mut keys = Vec::new();
+        collect_keys(e, &mut keys);
+        let mut bloom = KeyBloom::with_capacity(keys.len());
+        keys.iter().for_each(|k| bloom.insert(k));
+        bloom
+    }
+
+    pub fn insert(&mut self, key: &str) {
+        for bit in self.positions(key) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Returns false if the key is definitely absent.
+    pub fn may_contain(&self, key: &str) -> bool {
+        self.positions(key)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn positions(&self, key: &str) -> impl Iterator<Item = usize> {
+        let h = fnv1a(key);
+        let (h1, h2) = (h & 0xffff_ffff, h >> 32);
+        let nbits = self.bits.len() as u64 * 8;
+        (0..self.hashes as u64).map(move |i| {
+            let h = h1.wrapping_add(i.wrapping_mul(h2));
+            (h % nbits) as usize
+        })
+    }
+}
+
+/// KeyBloomWriteExt writes documents prefixed with a `KeyBloom` header.
+pub trait KeyBloomWriteExt: Write {
+    fn write_typed_with_key_bloom(&mut self, e: &Typed) -> Result<()> {
+        let bloom = KeyBloom::from_typed(e);
+        self.write_u8(HEADER_KEY_BLOOM)?;
+        self.write_u8(bloom.hashes)?;
+        self.write_sized(&bloom.bits)?;
+        self.write_typed(e)
+    }
+}
+
+impl<W: Write + ?Sized> KeyBloomWriteExt for W {}
+
+/// KeyBloomReadExt reads documents which may be prefixed with a `KeyBloom`
+/// header.
+pub trait KeyBloomReadExt: Read {
+    /// Reads the header only, the value is left in the reader and can be
+    /// skipped with `read_raw` or decoded with `read_typed`.
+    fn read_key_bloom(&mut self) -> Result<KeyBloom> {
+        let t = self.read_u8()?;
+        if t != HEADER_KEY_BLOOM {
+            return Err(Error::InvalidData("no key bloom header".into()));
+        }
+        read_header(self)
+    }
+
+    /// Reads a document whose header is optional.
+    fn read_document(&mut self) -> Result<(Option<KeyBloom>, Typed)> {
+        let t = self.read_u8()?;
+        if t == HEADER_KEY_BLOOM {
+            let bloom = read_header(self)?;
+            return Ok((Some(bloom), self.read_typed()?));
+        }
+        let tag = [t];
+        let e = (&tag[..]).chain(self).read_typed()?;
+        Ok((None, e))
+    }
+}
+
+impl<R: Read + ?Sized> KeyBloomReadExt for R {}
+
+fn read_header<R: Read + ?Sized>(r: &mut R) -> Result<KeyBloom> {
+    let hashes = r.read_u8()?;
+    let bits = r.read_sized()?;
+    if bits.is_empty() || hashes == 0 {
+        return Err(Error::InvalidData("invalid key bloom".into()));
+    }
+    Ok(KeyBloom { bits, hashes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+    use crate::raw::RawValue;
+
+    fn doc() -> Typed {
+        let mut inner = Map::new();
+        inner.insert(String::from("nested"), Typed::Int(1));
+        let mut m = Map::new();
+        m.insert(String::from("user"), Typed::Map(inner));
+        m.insert(
+            String::from("events"),
+            Typed::List(vec![Typed::Map({
+                let mut m = Map::new();
+                m.insert(String::from("in-list"), Typed::Uint(2));
+                m
+            })]),
+        );
+        Typed::Map(m)
+    }
+
+    #[test]
+    fn contains() {
+        let bloom = KeyBloom::from_typed(&doc());
+        for k in ["user", "nested", "events", "in-list"].iter() {
+            assert!(bloom.may_contain(k), "{}", k);
+        }
+        let misses = (0..1000)
+            .filter(|i| bloom.may_contain(&format!("absent-{}", i)))
+            .count();
+        assert!(misses < 100, "{}", misses);
+    }
+
+    #[test]
+    fn raw_keys() {
+        let mut m = Map::new();
+        m.insert(
+            String::from("raw"),
+            Typed::Raw(RawValue::encode(&doc()).unwrap()),
+        );
+        let bloom = KeyBloom::from_typed(&Typed::Map(m));
+        for k in ["raw", "user", "nested", "events", "in-list"].iter() {
+            assert!(bloom.may_contain(k), "{}", k);
+        }
+    }
+
+    #[test]
+    fn header() {
+        let e = doc();
+        let mut buf = Vec::new();
+        buf.write_typed_with_key_bloom(&e).unwrap();
+
+        let mut r = &buf[..];
+        let bloom = r.read_key_bloom().unwrap();
+        assert!(bloom.may_contain("nested"));
+        assert_eq!(r.read_typed().unwrap(), e);
+
+        assert_eq!(
+            (&buf[..]).read_document().unwrap(),
+            (Some(bloom), e.clone())
+        );
+
+        let mut plain = Vec::new();
+        plain.write_typed(&e).unwrap();
+        assert_eq!((&plain[..]).read_document().unwrap(), (None, e));
+        assert!((&plain[..]).read_key_bloom().is_err());
+    }
+}
@@ -1,111 +1,230 @@
 extern crate byteorder;
 
+use crate::array::{DType, PackedArray};
+use crate::compress::read_deflated;
+use crate::config::FormatVersion;
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::limits::Budget;
+use crate::options::DecodeOptions;
+use crate::ordered_map::OrderedMap;
+use crate::raw::{self, RawValue};
+use crate::text::TextEncoding;
+use crate::timestamp::Timestamp;
 use crate::varint;
-use byteorder::{ReadBytesExt, WriteBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::collections::HashMap;
-use std::io::{Error, ErrorKind, Read, Result, Write};
-
-type Type = u8;
-const TYPE_INT: Type = 'i' as u8;
-const TYPE_UINT: Type = 'u' as u8;
-const TYPE_FLOAT: Type = 'f' as u8;
-const TYPE_BYTES: Type = 'b' as u8;
-const TYPE_STRING: Type = 's' as u8;
-const TYPE_LIST: Type = 'l' as u8;
-const TYPE_MAP: Type = 'm' as u8;
-const CONTAINER_CAPACITY: usize = 255;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+pub(crate) type Type = u8;
+pub(crate) const TYPE_INT: Type = b'i';
+pub(crate) const TYPE_UINT: Type = b'u';
+pub(crate) const TYPE_FLOAT: Type = b'f';
+pub(crate) const TYPE_BYTES: Type = b'b';
+pub(crate) const TYPE_STRING: Type = b's';
+pub(crate) const TYPE_LIST: Type = b'l';
+pub(crate) const TYPE_MAP: Type = b'm';
+pub(crate) const TYPE_NULL: Type = b'n';
+pub(crate) const TYPE_FALSE: Type = b'0';
+pub(crate) const TYPE_TRUE: Type = b'1';
+// The varint seconds and the uvarint nanoseconds.
+pub(crate) const TYPE_TIMESTAMP: Type = b't';
+// The varint mantissa and the uvarint scale.
+pub(crate) const TYPE_DECIMAL: Type = b'd';
+// Lists and maps whose numbers of elements are uvarints rather than a byte,
+// see `FormatVersion::V2`.
+pub(crate) const TYPE_LIST_V2: Type = b'L';
+pub(crate) const TYPE_MAP_V2: Type = b'M';
+// Compressed bytes and strings, the uvarint length of the value and the
+// sized deflate stream of it, see `compress`.
+pub(crate) const TYPE_BYTES_DEFLATE: Type = b'B';
+pub(crate) const TYPE_STRING_DEFLATE: Type = b'Z';
+// Numbers in 8 fixed bytes in little endian rather than varints, see
+// `Config::fixed_width`.
+pub(crate) const TYPE_INT_FIXED: Type = b'I';
+pub(crate) const TYPE_UINT_FIXED: Type = b'U';
+pub(crate) const TYPE_FLOAT_FIXED: Type = b'F';
+// Packed arrays, the dtype byte, the uvarint number of dimensions, the
+// uvarint dimensions and the sized elements in little endian.
+pub(crate) const TYPE_ARRAY: Type = b'a';
+pub(crate) const CONTAINER_CAPACITY: usize = 255;
+
+/// Returns the `FormatVersion::V1` tag of `t`, so that both versions of
+/// lists and maps are handled alike.
+pub(crate) fn base_tag(t: Type) -> Type {
+    match t {
+        TYPE_LIST_V2 => TYPE_LIST,
+        TYPE_MAP_V2 => TYPE_MAP,
+        _ => t,
+    }
+}
+
+/// Reads the number of elements of the list or map tagged `t`.
+pub(crate) fn read_container_len<R: Read + ?Sized>(r: &mut R, t: Type) -> Result<u64> {
+    match t {
+        TYPE_LIST_V2 | TYPE_MAP_V2 => Ok(varint::VarintReadExt::read_uvarint(r)?),
+        _ => Ok(r.read_u8()?.into()),
+    }
+}
+
+/// Returns the capacity to preallocate for `nelem` elements, which is
+/// bounded as the number may be bogus.
+pub(crate) fn container_capacity(nelem: u64) -> usize {
+    nelem.min(CONTAINER_CAPACITY as u64) as usize
+}
 
 pub type List = Vec<Typed>;
 pub type Map = HashMap<String, Typed>;
+/// RawKeyMap is a map whose keys are arbitrary bytes rather than text, see
+/// `CodecReadExt::read_raw_key_map`.
+pub type RawKeyMap = HashMap<Vec<u8>, Typed>;
+
+/// ByteBuf is the storage of `Typed::Bytes`, with the `bytes` feature it is
+/// a reference counted `bytes::Bytes`, so clones are cheap and values
+/// decoded by `read_typed_from_bytes` share the input buffer.
+#[cfg(not(feature = "bytes"))]
+pub type ByteBuf = Vec<u8>;
+#[cfg(feature = "bytes")]
+pub type ByteBuf = bytes::Bytes;
+
+/// Str is the storage of `Typed::String`, with the `smol_str` feature short
+/// strings are stored inline and long strings are reference counted, so
+/// decoding doesn't allocate for every short string and clones are cheap.
+#[cfg(not(feature = "smol_str"))]
+pub type Str = String;
+#[cfg(feature = "smol_str")]
+pub type Str = smol_str::SmolStr;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Typed {
+    Null,
+    Bool(bool),
     Int(i64),
     Uint(u64),
     Float(f64),
-    Bytes(Vec<u8>),
-    String(String),
+    Timestamp(Timestamp),
+    Decimal(Decimal),
+    Bytes(ByteBuf),
+    String(Str),
     List(List),
     Map(Map),
+    /// A dense array of numbers, whose elements are written at once rather
+    /// than one value each.
+    Array(PackedArray),
+    /// An already encoded value, it is written verbatim.
+    Raw(RawValue),
+}
+
+impl Typed {
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Typed::Null => "null",
+            Typed::Bool(_) => "bool",
+            Typed::Int(_) => "int",
+            Typed::Uint(_) => "uint",
+            Typed::Float(_) => "float",
+            Typed::Timestamp(_) => "timestamp",
+            Typed::Decimal(_) => "decimal",
+            Typed::Bytes(_) => "bytes",
+            Typed::String(_) => "string",
+            Typed::List(_) => "list",
+            Typed::Map(_) => "map",
+            Typed::Array(_) => "array",
+            Typed::Raw(_) => "raw",
+        }
+    }
 }
 
 pub trait CodecReadExt: ReadBytesExt + varint::VarintReadExt {
     fn read_sized(&mut self) -> Result<Vec<u8>> {
         let sz = self.read_uvarint()?;
-        println!("{}", sz);
-        let mut buf = vec![0u8; sz as usize];
-        self.read_exact(&mut buf)?;
-        Ok(buf)
+        read_exactly(self, sz)
     }
 
     fn read_typed(&mut self) -> Result<Typed> {
-        let t = self.read_u8()?;
-        match t {
-            TYPE_INT => {
-                let n = self.read_varint()?;
-                Ok(Typed::Int(n))
-            }
-            TYPE_UINT => {
-                let un = self.read_uvarint()?;
-                Ok(Typed::Uint(un))
-            }
-            TYPE_FLOAT => {
-                let un = self.read_uvarint()?;
-                Ok(Typed::Float(f64::from_bits(un)))
-            }
-            TYPE_BYTES => {
-                let bs = self.read_sized()?;
-                Ok(Typed::Bytes(bs))
-            }
-            TYPE_STRING => {
-                let buf = self.read_sized()?;
-                let s = String::from_utf8_lossy(&buf).to_string();
-                Ok(Typed::String(s))
-            }
-            TYPE_LIST => {
-                let l = self.read_list()?;
-                Ok(Typed::List(l))
-            }
-            TYPE_MAP => {
-                let m = self.read_map()?;
-                Ok(Typed::Map(m))
-            }
-            _ => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("unknown type: '{}'", t),
-            )),
-        }
+        self.read_typed_with(&DecodeOptions::default())
     }
 
-    fn read_list(&mut self) -> Result<List> {
-        let nelem = self.read_u8()?;
-        let mut l = List::with_capacity(nelem as usize);
-        if nelem == 0 {
-            return Ok(l);
-        }
+    /// Reads the next value as `opts` tell, it is rejected as soon as it
+    /// exceeds `DecodeOptions::limits`.
+    fn read_typed_with(&mut self, opts: &DecodeOptions) -> Result<Typed> {
+        let mut budget = Budget::new(&opts.limits);
+        read_typed_encoded(self, opts, opts.text_encoding, &mut budget)
+    }
 
-        for _ in 0..nelem {
-            let e = self.read_typed()?;
-            l.push(e);
-        }
+    /// Reads the next value without decoding it, the encoded bytes are kept
+    /// as is.
+    fn read_raw(&mut self) -> Result<RawValue> {
+        self.read_raw_with(&DecodeOptions::default())
+    }
 
-        Ok(l)
+    /// Reads the next value like `read_raw`, it is rejected as soon as it
+    /// exceeds `DecodeOptions::limits`.
+    fn read_raw_with(&mut self, opts: &DecodeOptions) -> Result<RawValue> {
+        let mut buf = Vec::new();
+        raw::copy_typed(self, &mut buf, &mut Budget::new(&opts.limits))?;
+        Ok(RawValue::from_encoded(buf))
+    }
+
+    fn read_list(&mut self) -> Result<List> {
+        self.read_list_with(&DecodeOptions::default())
+    }
+
+    fn read_list_with(&mut self, opts: &DecodeOptions) -> Result<List> {
+        let mut budget = Budget::new(&opts.limits);
+        read_list_encoded(self, opts, opts.text_encoding, TYPE_LIST, &mut budget)
     }
 
     fn read_map(&mut self) -> Result<Map> {
-        let nelem = self.read_u8()?;
-        let mut m = Map::new();
-        if nelem == 0 {
-            return Ok(m);
-        }
+        self.read_map_with(&DecodeOptions::default())
+    }
+
+    fn read_map_with(&mut self, opts: &DecodeOptions) -> Result<Map> {
+        let mut budget = Budget::new(&opts.limits);
+        read_map_encoded(self, opts, opts.text_encoding, TYPE_MAP, &mut budget)
+    }
+
+    /// Reads a map like `read_map`, keeping the entries in the order they
+    /// were written, the maps nested in it are still `Map`s.
+    fn read_ordered_map(&mut self) -> Result<OrderedMap> {
+        self.read_ordered_map_with(&DecodeOptions::default())
+    }
+
+    fn read_ordered_map_with(&mut self, opts: &DecodeOptions) -> Result<OrderedMap> {
+        let mut budget = Budget::new(&opts.limits);
+        let mut m = OrderedMap::new();
+        read_entries_encoded(
+            self,
+            opts,
+            opts.text_encoding,
+            TYPE_MAP,
+            &mut budget,
+            |k, v| {
+                m.insert(k, v);
+            },
+        )?;
+        Ok(m)
+    }
+
+    /// Reads a map like `read_map`, keeping the keys as the bytes they
+    /// were written as, so keys which are not valid UTF-8 survive. The maps
+    /// nested in it are still `Map`s.
+    fn read_raw_key_map(&mut self) -> Result<RawKeyMap> {
+        self.read_raw_key_map_with(&DecodeOptions::default())
+    }
 
+    fn read_raw_key_map_with(&mut self, opts: &DecodeOptions) -> Result<RawKeyMap> {
+        let mut budget = Budget::new(&opts.limits);
+        let nelem = read_container_len(self, TYPE_MAP)?;
+        budget.enter(nelem)?;
+        let mut m = RawKeyMap::with_capacity(container_capacity(nelem));
         for _ in 0..nelem {
-            let k = self.read_sized()?;
-            let k = String::from_utf8_lossy(&k).to_string();
-            let v = self.read_typed()?;
+            let k = read_sized_limited(self, &budget)?;
+            let v = read_typed_encoded(self, opts, opts.text_encoding, &mut budget)?;
             m.insert(k, v);
         }
-
+        budget.leave();
         Ok(m)
     }
 }
@@ -114,81 +233,490 @@ pub trait CodecReadExt: ReadBytesExt + varint::VarintReadExt {
 /// for free.
 impl<R: Read + ?Sized> CodecReadExt for R {}
 
+// Reads `sz` bytes, the buffer grows as they arrive rather than being
+// allocated upfront, as `sz` may be bogus.
+pub(crate) fn read_exactly<R: Read + ?Sized>(r: &mut R, sz: u64) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(sz.min(4096) as usize);
+    if (r.take(sz).read_to_end(&mut buf)? as u64) < sz {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(buf)
+}
+
+pub(crate) fn read_sized_limited<R: CodecReadExt + ?Sized>(
+    r: &mut R,
+    budget: &Budget,
+) -> Result<Vec<u8>> {
+    let sz = r.read_uvarint()?;
+    budget.bytes(sz)?;
+    read_exactly(r, sz)
+}
+
+// Strings are decoded with `enc`, it changes with the field encodings of
+// the map entries.
+fn read_typed_encoded<R: CodecReadExt + ?Sized>(
+    r: &mut R,
+    opts: &DecodeOptions,
+    enc: TextEncoding,
+    budget: &mut Budget,
+) -> Result<Typed> {
+    let t = r.read_u8()?;
+    match t {
+        TYPE_NULL => Ok(Typed::Null),
+        TYPE_FALSE => Ok(Typed::Bool(false)),
+        TYPE_TRUE => Ok(Typed::Bool(true)),
+        TYPE_INT => {
+            let n = r.read_varint()?;
+            Ok(Typed::Int(n))
+        }
+        TYPE_UINT => {
+            let un = r.read_uvarint()?;
+            Ok(Typed::Uint(un))
+        }
+        TYPE_FLOAT => {
+            let un = r.read_uvarint()?;
+            Ok(Typed::Float(f64::from_bits(un)))
+        }
+        TYPE_INT_FIXED => Ok(Typed::Int(r.read_i64::<LittleEndian>()?)),
+        TYPE_UINT_FIXED => Ok(Typed::Uint(r.read_u64::<LittleEndian>()?)),
+        TYPE_FLOAT_FIXED => Ok(Typed::Float(r.read_f64::<LittleEndian>()?)),
+        TYPE_TIMESTAMP => {
+            let secs = r.read_varint()?;
+            let ts = Timestamp::from_wire(secs, r.read_uvarint()?)?;
+            Ok(Typed::Timestamp(ts))
+        }
+        TYPE_DECIMAL => {
+            let mantissa = r.read_varint()?;
+            let d = Decimal::from_wire(mantissa, r.read_uvarint()?)?;
+            Ok(Typed::Decimal(d))
+        }
+        TYPE_BYTES => {
+            let bs = read_sized_limited(r, budget)?;
+            Ok(Typed::Bytes(bs.into()))
+        }
+        TYPE_STRING => {
+            let buf = read_sized_limited(r, budget)?;
+            let s = opts.string(opts.text(enc, &buf)?);
+            Ok(Typed::String(Str::from(s.as_ref())))
+        }
+        TYPE_BYTES_DEFLATE => {
+            let bs = read_deflated(r, budget)?;
+            Ok(Typed::Bytes(bs.into()))
+        }
+        TYPE_STRING_DEFLATE => {
+            let buf = read_deflated(r, budget)?;
+            let s = opts.string(opts.text(enc, &buf)?);
+            Ok(Typed::String(Str::from(s.as_ref())))
+        }
+        TYPE_ARRAY => Ok(Typed::Array(read_array(r, budget)?)),
+        TYPE_LIST | TYPE_LIST_V2 => {
+            let l = read_list_encoded(r, opts, enc, t, budget)?;
+            Ok(Typed::List(l))
+        }
+        TYPE_MAP | TYPE_MAP_V2 => {
+            let m = read_map_encoded(r, opts, enc, t, budget)?;
+            Ok(Typed::Map(m))
+        }
+        _ => Err(Error::UnknownType(t)),
+    }
+}
+
+/// Reads the rest of a packed array whose tag was already read.
+pub(crate) fn read_array<R: Read + ?Sized>(r: &mut R, budget: &Budget) -> Result<PackedArray> {
+    let (dtype, shape) = read_array_header(r)?;
+    let data = read_sized_limited(r, budget)?;
+    packed_array(dtype, shape, data.into())
+}
+
+/// Reads the dtype and the shape of a packed array, which are followed by
+/// the sized elements.
+pub(crate) fn read_array_header<R: Read + ?Sized>(r: &mut R) -> Result<(DType, Vec<usize>)> {
+    let code = r.read_u8()?;
+    let dtype = DType::from_code(code)
+        .ok_or_else(|| Error::InvalidData(format!("unknown dtype: {}", code)))?;
+    let ndim = varint::VarintReadExt::read_uvarint(r)?;
+    let mut shape = Vec::with_capacity(ndim.min(8) as usize);
+    for _ in 0..ndim {
+        let dim = varint::VarintReadExt::read_uvarint(r)?;
+        shape.push(usize::try_from(dim).map_err(|_| Error::TooLong(dim))?);
+    }
+    Ok((dtype, shape))
+}
+
+pub(crate) fn packed_array(dtype: DType, shape: Vec<usize>, data: ByteBuf) -> Result<PackedArray> {
+    PackedArray::from_bytes(dtype, shape, data).map_err(|err| Error::InvalidData(err.to_string()))
+}
+
+// The number of elements is read as the container tagged `t` has it.
+pub(crate) fn read_list_encoded<R: CodecReadExt + ?Sized>(
+    r: &mut R,
+    opts: &DecodeOptions,
+    enc: TextEncoding,
+    t: Type,
+    budget: &mut Budget,
+) -> Result<List> {
+    let nelem = read_container_len(r, t)?;
+    budget.enter(nelem)?;
+    let mut l = List::with_capacity(container_capacity(nelem));
+    for _ in 0..nelem {
+        let e = read_typed_encoded(r, opts, enc, budget)?;
+        l.push(e);
+    }
+    budget.leave();
+
+    Ok(l)
+}
+
+pub(crate) fn read_map_encoded<R: CodecReadExt + ?Sized>(
+    r: &mut R,
+    opts: &DecodeOptions,
+    enc: TextEncoding,
+    t: Type,
+    budget: &mut Budget,
+) -> Result<Map> {
+    let mut m = Map::new();
+    read_entries_encoded(r, opts, enc, t, budget, |k, v| {
+        m.insert(k, v);
+    })?;
+    Ok(m)
+}
+
+// Reads the entries of a map into `insert`, so they can be collected into
+// other maps than `Map`.
+pub(crate) fn read_entries_encoded<R, F>(
+    r: &mut R,
+    opts: &DecodeOptions,
+    enc: TextEncoding,
+    t: Type,
+    budget: &mut Budget,
+    mut insert: F,
+) -> Result<()>
+where
+    R: CodecReadExt + ?Sized,
+    F: FnMut(String, Typed),
+{
+    let nelem = read_container_len(r, t)?;
+    budget.enter(nelem)?;
+    for _ in 0..nelem {
+        let k = read_sized_limited(r, budget)?;
+        let k = opts.key(opts.text(enc, &k)?);
+        let field_enc = opts.field_encodings.get(&k).copied().unwrap_or(enc);
+        let v = read_typed_encoded(r, opts, field_enc, budget)?;
+        insert(k, v);
+    }
+    budget.leave();
+
+    Ok(())
+}
+
 pub trait CodecWriteExt: WriteBytesExt + varint::VarintWriteExt {
     fn write_sized(&mut self, buf: &[u8]) -> Result<()> {
         self.write_uvarint(buf.len() as u64)?;
-        self.write_all(buf)
+        Ok(self.write_all(buf)?)
     }
 
     fn write_typed(&mut self, e: &Typed) -> Result<()> {
+        self.write_typed_version(e, FormatVersion::V1)
+    }
+
+    /// Writes `e` in the format of `version`, the values written by
+    /// `write_typed` are `FormatVersion::V1`.
+    fn write_typed_version(&mut self, e: &Typed, version: FormatVersion) -> Result<()> {
         match e {
+            Typed::Null => Ok(self.write_u8(TYPE_NULL)?),
+            Typed::Bool(false) => Ok(self.write_u8(TYPE_FALSE)?),
+            Typed::Bool(true) => Ok(self.write_u8(TYPE_TRUE)?),
             Typed::Int(n) => {
                 self.write_u8(TYPE_INT)?;
-                self.write_varint(*n)
+                Ok(self.write_varint(*n)?)
             }
             Typed::Uint(un) => {
                 self.write_u8(TYPE_UINT)?;
-                self.write_uvarint(*un)
+                Ok(self.write_uvarint(*un)?)
             }
             Typed::Float(f) => {
                 self.write_u8(TYPE_FLOAT)?;
-                self.write_uvarint(f.to_bits())
+                Ok(self.write_uvarint(f.to_bits())?)
+            }
+            Typed::Timestamp(ts) => {
+                self.write_u8(TYPE_TIMESTAMP)?;
+                self.write_varint(ts.secs())?;
+                Ok(self.write_uvarint(ts.subsec_nanos().into())?)
+            }
+            Typed::Decimal(d) => {
+                self.write_u8(TYPE_DECIMAL)?;
+                self.write_varint(d.mantissa)?;
+                Ok(self.write_uvarint(d.scale.into())?)
             }
             Typed::Bytes(buf) => {
                 self.write_u8(TYPE_BYTES)?;
-                self.write_sized(&buf)
+                self.write_sized(buf)
             }
             Typed::String(s) => {
                 self.write_u8(TYPE_STRING)?;
-                let buf = Vec::from(s.clone());
-                self.write_sized(&buf)
+                self.write_sized(s.as_bytes())
             }
             Typed::List(l) => {
-                self.write_u8(TYPE_LIST)?;
-                self.write_list(l)
+                self.write_u8(version.list_tag())?;
+                write_elements(self, l, version)
             }
             Typed::Map(m) => {
-                self.write_u8(TYPE_MAP)?;
-                self.write_map(m)
+                self.write_u8(version.map_tag())?;
+                write_entries(self, m, version)
             }
+            Typed::Array(a) => {
+                self.write_u8(TYPE_ARRAY)?;
+                write_array(self, a)
+            }
+            Typed::Raw(raw) => Ok(self.write_all(raw.as_bytes())?),
         }
     }
 
+    /// Writes `e` with the map entries in key order, so equal values are
+    /// always encoded into the same bytes, see `Config::canonical`.
+    fn write_typed_canonical(&mut self, e: &Typed) -> Result<()> {
+        write_canonical(self, e, FormatVersion::V1)
+    }
+
     fn write_list(&mut self, l: &List) -> Result<()> {
-        let nelem = l.len();
-        if nelem >= CONTAINER_CAPACITY {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                format!("containers can only contain 255 elements"),
-            ));
+        self.write_list_iter(l)
+    }
+
+    fn write_map(&mut self, m: &Map) -> Result<()> {
+        self.write_map_iter(m)
+    }
+
+    /// Writes the entries in their order, like `write_map`.
+    fn write_ordered_map(&mut self, m: &OrderedMap) -> Result<()> {
+        self.write_map_iter(m.iter())
+    }
+
+    /// Writes the entries like `write_map`, the keys as they are.
+    fn write_raw_key_map(&mut self, m: &RawKeyMap) -> Result<()> {
+        let nelem = write_container_len(self, m.len(), FormatVersion::V1)?;
+        for (k, v) in m.iter() {
+            self.write_sized(k)?;
+            self.write_typed(v)?;
         }
+        check_container_len(nelem, m.len())
+    }
+
+    /// Writes the elements like `write_list`, without collecting them into a
+    /// `List` first.
+    fn write_list_iter<'a, I>(&mut self, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a Typed>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        write_elements(self, iter, FormatVersion::V1)
+    }
+
+    /// Writes the entries like `write_map`, without collecting them into a
+    /// `Map` first, so they can come from a `BTreeMap`, a `Vec` and so on.
+    fn write_map_iter<'a, K, I>(&mut self, iter: I) -> Result<()>
+    where
+        K: AsRef<str>,
+        I: IntoIterator<Item = (K, &'a Typed)>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        write_entries(self, iter, FormatVersion::V1)
+    }
+}
+
+pub(crate) fn write_elements<'a, W, I>(w: &mut W, iter: I, version: FormatVersion) -> Result<()>
+where
+    W: Write + ?Sized,
+    I: IntoIterator<Item = &'a Typed>,
+    I::IntoIter: ExactSizeIterator,
+{
+    let iter = iter.into_iter();
+    let nelem = write_container_len(w, iter.len(), version)?;
+    let mut n = 0;
+    for e in iter {
+        w.write_typed_version(e, version)?;
+        n += 1;
+    }
+    check_container_len(nelem, n)
+}
+
+pub(crate) fn write_entries<'a, W, K, I>(w: &mut W, iter: I, version: FormatVersion) -> Result<()>
+where
+    W: Write + ?Sized,
+    K: AsRef<str>,
+    I: IntoIterator<Item = (K, &'a Typed)>,
+    I::IntoIter: ExactSizeIterator,
+{
+    let iter = iter.into_iter();
+    let nelem = write_container_len(w, iter.len(), version)?;
+    let mut n = 0;
+    for (k, v) in iter {
+        w.write_sized(k.as_ref().as_bytes())?;
+        w.write_typed_version(v, version)?;
+        n += 1;
+    }
+    check_container_len(nelem, n)
+}
+
+pub(crate) fn write_array<W: Write + ?Sized>(w: &mut W, a: &PackedArray) -> Result<()> {
+    w.write_u8(a.dtype().code())?;
+    varint::VarintWriteExt::write_uvarint(w, a.shape().len() as u64)?;
+    for dim in a.shape() {
+        varint::VarintWriteExt::write_uvarint(w, *dim as u64)?;
+    }
+    w.write_sized(a.as_bytes())
+}
 
-        self.write_u8(nelem as u8)?;
-        for e in l.iter() {
-            self.write_typed(e)?;
+/// Writes `e` in 8 fixed bytes if it is a number whose varint would take
+/// more, returns whether it was written.
+pub(crate) fn write_fixed_width<W: Write + ?Sized>(w: &mut W, e: &Typed) -> Result<bool> {
+    // The uvarints of the numbers from it on take at least 9 bytes.
+    const MIN: u64 = 1 << 56;
+    match e {
+        Typed::Int(n) if ((n << 1) ^ (n >> 63)) as u64 >= MIN => {
+            w.write_u8(TYPE_INT_FIXED)?;
+            w.write_i64::<LittleEndian>(*n)?;
+        }
+        Typed::Uint(un) if *un >= MIN => {
+            w.write_u8(TYPE_UINT_FIXED)?;
+            w.write_u64::<LittleEndian>(*un)?;
         }
-        Ok(())
+        Typed::Float(f) if f.to_bits() >= MIN => {
+            w.write_u8(TYPE_FLOAT_FIXED)?;
+            w.write_f64::<LittleEndian>(*f)?;
+        }
+        _ => return Ok(false),
     }
+    Ok(true)
+}
 
-    fn write_map(&mut self, m: &Map) -> Result<()> {
-        let nelem = m.len();
-        if nelem >= CONTAINER_CAPACITY {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                format!("containers can only contain 255 elements"),
-            ));
+pub(crate) fn write_container_len<W: Write + ?Sized>(
+    w: &mut W,
+    nelem: usize,
+    version: FormatVersion,
+) -> Result<usize> {
+    if version == FormatVersion::V2 {
+        varint::VarintWriteExt::write_uvarint(w, nelem as u64)?;
+        return Ok(nelem);
+    }
+    if nelem >= CONTAINER_CAPACITY {
+        return Err(Error::ContainerTooLarge(nelem as u64));
+    }
+    w.write_u8(nelem as u8)?;
+    Ok(nelem)
+}
+
+// An `ExactSizeIterator` which lies about its length would corrupt the
+// output silently otherwise.
+fn check_container_len(expected: usize, actual: usize) -> Result<()> {
+    if expected != actual {
+        return Err(Error::InvalidInput(format!(
+            "iterator yielded {} elements, expected {}",
+            actual, expected
+        )));
+    }
+    Ok(())
+}
+
+/// Writes `e` with the map entries in key order, so equal values are always
+/// encoded into the same bytes. `Typed::Raw` values are decoded and written
+/// in the same way.
+pub(crate) fn write_canonical<W: Write + ?Sized>(
+    w: &mut W,
+    e: &Typed,
+    version: FormatVersion,
+) -> Result<()> {
+    match e {
+        Typed::List(l) => {
+            w.write_u8(version.list_tag())?;
+            write_canonical_elements(w, l, version)
         }
+        Typed::Map(m) => {
+            w.write_u8(version.map_tag())?;
+            write_canonical_entries(w, m, version)
+        }
+        Typed::Raw(raw) => write_canonical(w, &raw.decode()?, version),
+        _ => w.write_typed(e),
+    }
+}
 
-        self.write_u8(nelem as u8)?;
-        for (k, v) in m.iter() {
-            let buf = Vec::from(k.clone());
-            self.write_sized(&buf)?;
-            self.write_typed(v)?;
+pub(crate) fn write_canonical_elements<W: Write + ?Sized>(
+    w: &mut W,
+    l: &List,
+    version: FormatVersion,
+) -> Result<()> {
+    write_container_len(w, l.len(), version)?;
+    l.iter().try_for_each(|e| write_canonical(w, e, version))
+}
+
+pub(crate) fn write_canonical_entries<W: Write + ?Sized>(
+    w: &mut W,
+    m: &Map,
+    version: FormatVersion,
+) -> Result<()> {
+    write_container_len(w, m.len(), version)?;
+    let mut entries: Vec<_> = m.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (k, v) in entries {
+        w.write_sized(k.as_bytes())?;
+        write_canonical(w, v, version)?;
+    }
+    Ok(())
+}
+
+/// Encodes `e` like `CodecWriteExt::write_typed`.
+pub fn encode(e: &Typed) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    encode_into(e, &mut buf)?;
+    Ok(buf)
+}
+
+/// Appends the encoded `e` to `buf`, which is grown at most once. Nothing is
+/// appended if it fails.
+pub fn encode_into(e: &Typed, buf: &mut Vec<u8>) -> Result<()> {
+    let start = buf.len();
+    buf.reserve(encoded_len(e));
+    buf.write_typed(e).inspect_err(|_| buf.truncate(start))
+}
+
+/// Returns the number of bytes `encode` writes without encoding `e`. The
+/// lists and maps which are too large to encode count as if they weren't.
+pub fn encoded_len(e: &Typed) -> usize {
+    1 + match e {
+        Typed::Null | Typed::Bool(_) => 0,
+        Typed::Int(n) => varint::varint_len(*n),
+        Typed::Uint(un) => varint::uvarint_len(*un),
+        Typed::Float(f) => varint::uvarint_len(f.to_bits()),
+        Typed::Timestamp(ts) => {
+            varint::varint_len(ts.secs()) + varint::uvarint_len(ts.subsec_nanos().into())
         }
-        Ok(())
+        Typed::Decimal(d) => varint::varint_len(d.mantissa) + varint::uvarint_len(d.scale.into()),
+        Typed::Bytes(buf) => sized_len(buf.len()),
+        Typed::String(s) => sized_len(s.len()),
+        Typed::List(l) => 1 + l.iter().map(encoded_len).sum::<usize>(),
+        Typed::Map(m) => {
+            1 + m
+                .iter()
+                .map(|(k, v)| sized_len(k.len()) + encoded_len(v))
+                .sum::<usize>()
+        }
+        Typed::Array(a) => {
+            1 + varint::uvarint_len(a.shape().len() as u64)
+                + a.shape()
+                    .iter()
+                    .map(|dim| varint::uvarint_len(*dim as u64))
+                    .sum::<usize>()
+                + sized_len(a.as_bytes().len())
+        }
+        // Raw values are written as they are, their tag included, which
+        // may be missing if they were not validated.
+        Typed::Raw(raw) => return raw.as_bytes().len(),
     }
 }
 
+fn sized_len(n: usize) -> usize {
+    varint::uvarint_len(n as u64) + n
+}
+
 /// All types that implement `Write` get methods defined in `CodecWriteExt`
 /// for free.
 impl<W: Write + ?Sized> CodecWriteExt for W {}
@@ -196,50 +724,104 @@ impl<W: Write + ?Sized> CodecWriteExt for W {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::keys::KeyMode;
+    use crate::limits::Limits;
     use std::io;
     use std::io::prelude::*;
+    use std::io::ErrorKind;
 
     #[test]
     fn sized_bytes() {
         let mut buf = io::Cursor::new(Vec::new());
-        for s in ["a", "ab", "abc", "abcd", "abcde", "abcdef"].into_iter() {
+        for s in ["a", "ab", "abc", "abcd", "abcde", "abcdef"].iter() {
             buf.seek(io::SeekFrom::Start(0)).unwrap();
             assert!(buf.write_sized(s.as_bytes()).is_ok());
             buf.seek(io::SeekFrom::Start(0)).unwrap();
             match buf.read_sized() {
                 Ok(bs) => assert_eq!(&bs[..], s.as_bytes()),
-                Err(err) => assert!(false, "{}", err),
+                Err(err) => panic!("{}", err),
             }
         }
     }
 
+    #[test]
+    fn header_tags() {
+        // The headers written before values are told apart by their tags.
+        let headers = [
+            crate::bloom::HEADER_KEY_BLOOM,
+            crate::registry::HEADER_SCHEMA,
+        ];
+        for &h in headers.iter() {
+            let buf = [h, 0];
+            assert!(matches!((&buf[..]).read_typed(), Err(Error::UnknownType(t)) if t == h));
+            assert!(matches!((&buf[..]).read_raw(), Err(Error::UnknownType(t)) if t == h));
+        }
+    }
+
+    #[cfg(feature = "smol_str")]
+    #[test]
+    fn inline_strings() {
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::String("short".into())).unwrap();
+        match (&buf[..]).read_typed() {
+            Ok(Typed::String(s)) => assert!(!s.is_heap_allocated()),
+            v => panic!("unexpected: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn key_mode() {
+        let mut inner = Map::new();
+        inner.insert(String::from("Content_Type"), Typed::Int(1));
+        let mut m = Map::new();
+        m.insert(String::from("Headers"), Typed::Map(inner));
+        let mut buf = Vec::new();
+        buf.write_map(&m).unwrap();
+
+        assert_eq!((&buf[..]).read_map().unwrap(), m);
+
+        let opts = DecodeOptions::new().key_mode(KeyMode::Lowercase);
+        let mread = (&buf[..]).read_map_with(&opts).unwrap();
+        match mread.get("headers") {
+            Some(Typed::Map(inner)) => assert!(inner.contains_key("content_type")),
+            v => panic!("unexpected: {:?}", v),
+        }
+
+        let opts = DecodeOptions::new().key_mode(KeyMode::Normalize);
+        let mread = (&buf[..]).read_map_with(&opts).unwrap();
+        match mread.get("headers") {
+            Some(Typed::Map(inner)) => assert!(inner.contains_key("content-type")),
+            v => panic!("unexpected: {:?}", v),
+        }
+    }
+
     #[test]
     fn list() {
         let mut buf = io::Cursor::new(Vec::new());
         let mut m = Map::new();
-        m.insert(String::from("hi"), Typed::String(String::from("hello")));
+        m.insert(String::from("hi"), Typed::String("hello".into()));
         let l = vec![
             Typed::Int(0),
-            Typed::Int(i8::max_value() as i64),
-            Typed::Int(i16::max_value() as i64),
-            Typed::Int(i32::max_value() as i64),
-            Typed::Int(i64::max_value()),
-            Typed::Uint(u8::max_value() as u64),
-            Typed::Uint(u16::max_value() as u64),
-            Typed::Uint(u32::max_value() as u64),
-            Typed::Uint(u64::max_value()),
+            Typed::Int(i8::MAX as i64),
+            Typed::Int(i16::MAX as i64),
+            Typed::Int(i32::MAX as i64),
+            Typed::Int(i64::MAX),
+            Typed::Uint(u8::MAX as u64),
+            Typed::Uint(u16::MAX as u64),
+            Typed::Uint(u32::MAX as u64),
+            Typed::Uint(u64::MAX),
             Typed::Float(0.0),
             Typed::Float(12345.1231445),
-            Typed::Bytes(vec![0u8, 1u8, 128u8, 255u8]),
-            Typed::String(String::from("")),
-            Typed::String(String::from("hello")),
-            Typed::String(String::from("超")),
+            Typed::Bytes(vec![0u8, 1u8, 128u8, 255u8].into()),
+            Typed::String("".into()),
+            Typed::String("hello".into()),
+            Typed::String("超".into()),
             Typed::List(vec![
                 Typed::Int(123),
                 Typed::Uint(456),
                 Typed::Float(789.123),
-                Typed::Bytes(vec![]),
-                Typed::String(String::from("dumb")),
+                Typed::Bytes(vec![].into()),
+                Typed::String("dumb".into()),
                 Typed::Map(m.clone()),
             ]),
             Typed::Map(m.clone()),
@@ -249,8 +831,64 @@ mod tests {
         buf.seek(io::SeekFrom::Start(0)).unwrap();
         match buf.read_list() {
             Ok(lread) => assert_eq!(lread, l),
-            Err(err) => assert!(false, "{}", err),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn null_and_bool() {
+        for (e, encoded) in [
+            (Typed::Null, b"n"),
+            (Typed::Bool(false), b"0"),
+            (Typed::Bool(true), b"1"),
+        ]
+        .iter()
+        {
+            let mut buf = Vec::new();
+            buf.write_typed(e).unwrap();
+            assert_eq!(&buf[..], &encoded[..]);
+            assert_eq!(&(&buf[..]).read_typed().unwrap(), e);
         }
+
+        let mut m = Map::new();
+        m.insert(String::from("deleted"), Typed::Null);
+        m.insert(String::from("ok"), Typed::Bool(true));
+        let l = vec![Typed::Map(m), Typed::Bool(false), Typed::Null];
+        let mut buf = Vec::new();
+        buf.write_list(&l).unwrap();
+        assert_eq!((&buf[..]).read_list().unwrap(), l);
+
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::List(l)).unwrap();
+        let raw = (&buf[..]).read_raw().unwrap();
+        assert_eq!(raw.as_bytes(), &buf[..]);
+    }
+
+    #[test]
+    fn timestamp_and_decimal() {
+        let ts = Timestamp::from_millis(-1);
+        let d = Decimal::new(-1230, 2);
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::Timestamp(ts)).unwrap();
+        assert_eq!(buf, b"t\x01\xc0\x8f\xae\xdc\x03");
+        assert_eq!((&buf[..]).read_typed().unwrap(), Typed::Timestamp(ts));
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::Decimal(d)).unwrap();
+        assert_eq!(buf, b"d\x9b\x13\x02");
+        assert_eq!((&buf[..]).read_typed().unwrap(), Typed::Decimal(d));
+
+        let l = vec![Typed::Timestamp(ts), Typed::Decimal(d)];
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::List(l.clone())).unwrap();
+        let raw = (&buf[..]).read_raw().unwrap();
+        assert_eq!(raw.decode().unwrap(), Typed::List(l));
+
+        // The nanoseconds must be less than a second.
+        let err = (&b"t\x00\x80\x94\xeb\xdc\x03"[..])
+            .read_typed()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!((&b"d\x00\x80\x80\x80\x80\x10"[..]).read_typed().is_err());
     }
 
     #[test]
@@ -258,58 +896,40 @@ mod tests {
         let mut buf = io::Cursor::new(Vec::new());
         let mut m = Map::new();
         m.insert(String::from("0"), Typed::Int(0));
-        m.insert(String::from("01"), Typed::Int(i8::max_value() as i64));
-        m.insert(String::from("012"), Typed::Int(i16::max_value() as i64));
-        m.insert(String::from("0123"), Typed::Int(i32::max_value() as i64));
-        m.insert(String::from("01234"), Typed::Int(i64::max_value()));
-        m.insert(String::from("012345"), Typed::Uint(u8::max_value() as u64));
-        m.insert(
-            String::from("0123456"),
-            Typed::Uint(u16::max_value() as u64),
-        );
-        m.insert(
-            String::from("01234567"),
-            Typed::Uint(u32::max_value() as u64),
-        );
-        m.insert(String::from("012345678"), Typed::Uint(u64::max_value()));
+        m.insert(String::from("01"), Typed::Int(i8::MAX as i64));
+        m.insert(String::from("012"), Typed::Int(i16::MAX as i64));
+        m.insert(String::from("0123"), Typed::Int(i32::MAX as i64));
+        m.insert(String::from("01234"), Typed::Int(i64::MAX));
+        m.insert(String::from("012345"), Typed::Uint(u8::MAX as u64));
+        m.insert(String::from("0123456"), Typed::Uint(u16::MAX as u64));
+        m.insert(String::from("01234567"), Typed::Uint(u32::MAX as u64));
+        m.insert(String::from("012345678"), Typed::Uint(u64::MAX));
         m.insert(String::from("0123456789"), Typed::Float(0.0));
         m.insert(String::from("9012345678"), Typed::Float(54321.54321));
         m.insert(
             String::from("8901234567"),
-            Typed::Bytes(vec![0u8, 1u8, 128u8, 255u8]),
-        );
-        m.insert(String::from("7890123456"), Typed::String(String::from("")));
-        m.insert(
-            String::from("6789012345"),
-            Typed::String(String::from("hello")),
-        );
-        m.insert(
-            String::from("5678901234"),
-            Typed::String(String::from("超")),
+            Typed::Bytes(vec![0u8, 1u8, 128u8, 255u8].into()),
         );
+        m.insert(String::from("7890123456"), Typed::String("".into()));
+        m.insert(String::from("6789012345"), Typed::String("hello".into()));
+        m.insert(String::from("5678901234"), Typed::String("超".into()));
         let mut m2 = Map::new();
         m2.insert(String::from("0sa"), Typed::Int(0));
-        m2.insert(
-            String::from("012sfdasf345"),
-            Typed::Uint(u8::max_value() as u64),
-        );
+        m2.insert(String::from("012sfdasf345"), Typed::Uint(u8::MAX as u64));
         m2.insert(String::from("901234567sas8"), Typed::Float(54321.54321));
         m2.insert(
             String::from("8901234lj567"),
-            Typed::Bytes(vec![0u8, 1u8, 128u8, 255u8]),
-        );
-        m2.insert(
-            String::from("678901230945"),
-            Typed::String(String::from("world")),
+            Typed::Bytes(vec![0u8, 1u8, 128u8, 255u8].into()),
         );
+        m2.insert(String::from("678901230945"), Typed::String("world".into()));
         m.insert(
             String::from("list"),
             Typed::List(vec![
                 Typed::Int(123),
                 Typed::Uint(456),
                 Typed::Float(789.123),
-                Typed::Bytes(vec![]),
-                Typed::String(String::from("dumb")),
+                Typed::Bytes(vec![].into()),
+                Typed::String("dumb".into()),
                 Typed::Map(m2.clone()),
             ]),
         );
@@ -319,7 +939,225 @@ mod tests {
         buf.seek(io::SeekFrom::Start(0)).unwrap();
         match buf.read_map() {
             Ok(mread) => assert_eq!(mread, m),
-            Err(err) => assert!(false, "{}", err),
+            Err(err) => panic!("{}", err),
         }
     }
+
+    #[test]
+    fn iter_writers() {
+        let mut bm = std::collections::BTreeMap::new();
+        bm.insert("a", Typed::Int(1));
+        bm.insert("b", Typed::String("x".into()));
+        let mut buf = Vec::new();
+        buf.write_map_iter(&bm).unwrap();
+        let m = (&buf[..]).read_map().unwrap();
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.get("b"), Some(&Typed::String("x".into())));
+
+        let pairs = [(String::from("c"), Typed::Uint(3))];
+        let mut buf = Vec::new();
+        buf.write_map_iter(pairs.iter().map(|(k, v)| (k, v)))
+            .unwrap();
+        assert_eq!(
+            (&buf[..]).read_map().unwrap().get("c"),
+            Some(&Typed::Uint(3))
+        );
+
+        let l = [Typed::Int(1), Typed::Float(2.0)];
+        let mut buf = Vec::new();
+        buf.write_list_iter(&l).unwrap();
+        assert_eq!((&buf[..]).read_list().unwrap(), l.to_vec());
+
+        let l = vec![Typed::Int(0); CONTAINER_CAPACITY];
+        assert!(Vec::new().write_list_iter(&l).is_err());
+    }
+
+    #[test]
+    fn limits() {
+        // The lengths are checked before anything is allocated.
+        let huge = b"s\xff\xff\xff\xff\xff\xff\xff\xff\x7f";
+        let err = (&huge[..]).read_typed().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let opts = DecodeOptions::new().limits(Limits::unlimited());
+        let err = (&huge[..]).read_typed_with(&opts).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        let mut deep = [b'l', 1u8].repeat(100_000);
+        deep.extend_from_slice(b"i\x00");
+        assert!((&deep[..]).read_typed().is_err());
+        assert!((&deep[deep.len() - 12..]).read_typed().is_ok());
+
+        let l = vec![Typed::List(vec![Typed::Int(1); 100]); 100];
+        let mut buf = Vec::new();
+        buf.write_list(&l).unwrap();
+        assert_eq!((&buf[..]).read_list().unwrap(), l);
+        let opts = DecodeOptions::new().limits(Limits::new().max_elements(10_099));
+        assert!((&buf[..]).read_list_with(&opts).is_err());
+        let opts = DecodeOptions::new().limits(Limits::new().max_container_len(99));
+        assert!((&buf[..]).read_list_with(&opts).is_err());
+        let opts = DecodeOptions::new().limits(Limits::new().max_bytes_len(0));
+        assert!((&b"m\x01\x01ki\x00"[..]).read_map_with(&opts).is_err());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        // Every reader rejects the nesting past the limits rather than
+        // overflowing the stack.
+        let mut deep = [b'l', 1u8].repeat(1_000_000);
+        deep.push(b'n');
+        let err = (&deep[..]).read_raw().unwrap_err();
+        assert!(matches!(err, Error::DepthExceeded(64)), "{:?}", err);
+        let err = crate::typed_ref::decode_ref(&deep).unwrap_err();
+        assert!(matches!(err, Error::DepthExceeded(64)), "{:?}", err);
+        let err = crate::document::Document::new(&deep[..]).unwrap_err();
+        assert!(matches!(err, Error::DepthExceeded(64)), "{:?}", err);
+        let err = crate::delta::delta(&deep, b"n").unwrap_err();
+        assert!(err.to_string().contains("depth"), "{}", err);
+
+        let shallow = &deep[deep.len() - 21..];
+        let opts = DecodeOptions::new().limits(Limits::new().max_depth(9));
+        assert!((&shallow[..]).read_raw_with(&opts).is_err());
+        assert!(crate::typed_ref::decode_ref_with(shallow, &opts.limits).is_err());
+        assert!(crate::document::Document::with_limits(shallow, &opts.limits).is_err());
+        assert!(crate::delta::delta_with(shallow, shallow, &opts.limits).is_err());
+        let opts = DecodeOptions::new().limits(Limits::new().max_depth(10));
+        assert!((&shallow[..]).read_raw_with(&opts).is_ok());
+        assert!(crate::typed_ref::decode_ref_with(shallow, &opts.limits).is_ok());
+        assert!(crate::delta::delta_with(shallow, shallow, &opts.limits).is_ok());
+
+        // The document is checked without recursion, so trusted input may
+        // nest deeper.
+        let doc = crate::document::Document::with_limits(&deep[..], &Limits::unlimited());
+        assert!(doc.is_ok());
+    }
+
+    #[test]
+    fn format_v2() {
+        let mut m = Map::new();
+        m.insert(String::from("k"), Typed::List(vec![Typed::Uint(1); 1000]));
+        let e = Typed::List(vec![Typed::Map(m), Typed::String("s".into())]);
+        assert!(Vec::new().write_typed(&e).is_err());
+
+        let mut buf = Vec::new();
+        buf.write_typed_version(&e, FormatVersion::V2).unwrap();
+        assert_eq!(&buf[..7], b"L\x02M\x01\x01kL");
+        assert_eq!((&buf[..]).read_typed().unwrap(), e);
+        assert_eq!((&buf[..]).read_raw().unwrap().decode().unwrap(), e);
+
+        // Small values are the same in both versions, apart from the tags,
+        // and the versions can be nested.
+        let mut buf = Vec::new();
+        buf.write_typed_version(&Typed::List(vec![Typed::Int(1)]), FormatVersion::V2)
+            .unwrap();
+        assert_eq!(buf, b"L\x01i\x02");
+        let nested = b"l\x02L\x01i\x02m\x00";
+        assert_eq!(
+            (&nested[..]).read_typed().unwrap(),
+            Typed::List(vec![
+                Typed::List(vec![Typed::Int(1)]),
+                Typed::Map(Map::new())
+            ])
+        );
+
+        // A bogus number of elements doesn't preallocate.
+        let opts = DecodeOptions::new().limits(Limits::unlimited());
+        let mut r = &b"L\xff\xff\xff\xff\x0f"[..];
+        let err = r.read_typed_with(&opts).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn raw_keys() {
+        let mut m = RawKeyMap::new();
+        m.insert(vec![0xff, 0x00], Typed::Int(1));
+        m.insert(b"k".to_vec(), Typed::List(vec![Typed::Null]));
+        let mut buf = Vec::new();
+        buf.write_raw_key_map(&m).unwrap();
+        assert_eq!((&buf[..]).read_raw_key_map().unwrap(), m);
+
+        // The lossy keys don't round trip, and strict ones fail.
+        let lossy = (&buf[..]).read_map().unwrap();
+        assert_eq!(lossy.get("\u{fffd}\0"), Some(&Typed::Int(1)));
+        let opts = DecodeOptions::new().strict_utf8(true);
+        assert!((&buf[..]).read_map_with(&opts).is_err());
+        assert_eq!((&buf[..]).read_raw_key_map_with(&opts).unwrap(), m);
+
+        let opts = DecodeOptions::new().limits(Limits::new().max_bytes_len(1));
+        assert!(matches!(
+            (&buf[..]).read_raw_key_map_with(&opts),
+            Err(Error::TooLong(2))
+        ));
+    }
+
+    #[test]
+    fn arrays_and_fixed_width() {
+        let a = PackedArray::new(vec![2, 2], &[1.5f32, -2.0, 0.0, 4.0]).unwrap();
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::from(a.clone())).unwrap();
+        assert_eq!(&buf[..5], b"a\x08\x02\x02\x02");
+        let e = (&buf[..]).read_typed().unwrap();
+        assert_eq!(e.as_array(), Some(&a));
+        assert_eq!((&buf[..]).read_raw().unwrap().as_bytes(), &buf[..]);
+        assert_eq!(crate::typed_ref::decode_ref(&buf).unwrap().to_typed(), e);
+        let doc = crate::document::Document::new(&buf[..]).unwrap();
+        assert_eq!(doc.root().as_array(), Some(a.clone()));
+        #[cfg(feature = "bytes")]
+        assert_eq!(
+            crate::read_typed_from_bytes(&mut buf.clone().into()).unwrap(),
+            e
+        );
+        let opts = DecodeOptions::new().limits(Limits::new().max_bytes_len(15));
+        assert!(matches!(
+            (&buf[..]).read_typed_with(&opts),
+            Err(Error::TooLong(16))
+        ));
+        assert!((&b"a\x0a\x00\x00"[..]).read_typed().is_err());
+
+        let mut buf = b"F".to_vec();
+        buf.extend_from_slice(&0.1f64.to_le_bytes());
+        let e = (&buf[..]).read_typed().unwrap();
+        assert_eq!(e, Typed::Float(0.1));
+        assert_eq!((&buf[..]).read_raw().unwrap().as_bytes(), &buf[..]);
+        assert_eq!(crate::typed_ref::decode_ref(&buf).unwrap().to_typed(), e);
+        let doc = crate::document::Document::new(&buf[..]).unwrap();
+        assert_eq!(doc.root().as_float(), Some(0.1));
+        let mut buf = b"I".to_vec();
+        buf.extend_from_slice(&i64::MIN.to_le_bytes());
+        assert_eq!((&buf[..]).read_typed().unwrap(), Typed::Int(i64::MIN));
+        assert!((&buf[..8]).read_typed().unwrap_err().is_eof());
+    }
+
+    #[test]
+    fn encoded_len() {
+        let raw = RawValue::encode(&Typed::String("raw".into())).unwrap();
+        let a = PackedArray::new(vec![3], &[1u16, 2, 3]).unwrap();
+        let e = crate::typed!({
+            "n": [null, true, -1, i64::MIN, 0u64, u64::MAX, 0.5, -0.0],
+            "ts": Timestamp::new(-1, 999_999_999).unwrap(),
+            "d": Decimal::new(-12345, 2),
+            "s": "x".repeat(200),
+            "b": vec![0u8; 128],
+            "a": a,
+            "raw": raw,
+            "": {},
+        });
+        let mut buf = b"xd".to_vec();
+        super::encode_into(&e, &mut buf).unwrap();
+        assert_eq!(&buf[2..], &super::encode(&e).unwrap()[..]);
+        assert_eq!(super::encoded_len(&e), buf.len() - 2);
+
+        let empty = Typed::Raw(RawValue::from_encoded(Vec::new()));
+        assert_eq!(super::encoded_len(&empty), 0);
+        let e = Typed::List(vec![empty]);
+        assert_eq!(super::encoded_len(&e), super::encode(&e).unwrap().len());
+
+        let e = Typed::List(vec![Typed::Null; CONTAINER_CAPACITY]);
+        assert_eq!(super::encoded_len(&e), CONTAINER_CAPACITY + 2);
+        let n = buf.len();
+        assert!(matches!(
+            super::encode_into(&e, &mut buf),
+            Err(Error::ContainerTooLarge(_))
+        ));
+        assert_eq!(buf.len(), n);
+    }
 }
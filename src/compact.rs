@@ -0,0 +1,87 @@
+use crate::codec::{ByteBuf, Map, Str, Typed};
+
+#[cfg(not(feature = "bytes"))]
+fn shrink_bytes(buf: &mut ByteBuf) {
+    buf.shrink_to_fit();
+}
+
+// `bytes::Bytes` is an immutable view, there is nothing to shrink.
+#[cfg(feature = "bytes")]
+fn shrink_bytes(_: &mut ByteBuf) {}
+
+#[cfg(not(feature = "smol_str"))]
+fn shrink_str(s: &mut Str) {
+    s.shrink_to_fit();
+}
+
+// `smol_str::SmolStr` is either inline or exactly sized.
+#[cfg(feature = "smol_str")]
+fn shrink_str(_: &mut Str) {}
+
+impl Typed {
+    /// Recursively releases the spare capacity of lists, maps, strings and
+    /// bytes, it is useful for values which are kept around for a long time
+    /// after being built or mutated.
+    pub fn compact(&mut self) {
+        match self {
+            Typed::Bytes(buf) => shrink_bytes(buf),
+            Typed::String(s) => shrink_str(s),
+            Typed::List(l) => {
+                l.iter_mut().for_each(Typed::compact);
+                l.shrink_to_fit();
+            }
+            Typed::Map(m) => {
+                // Rebuilding the map also shrinks the keys, which can't be
+                // mutated in place.
+                *m = m
+                    .drain()
+                    .map(|(mut k, mut v)| {
+                        k.shrink_to_fit();
+                        v.compact();
+                        (k, v)
+                    })
+                    .collect::<Map>();
+            }
+            Typed::Raw(raw) => raw.shrink_to_fit(),
+            Typed::Null
+            | Typed::Bool(_)
+            | Typed::Int(_)
+            | Typed::Uint(_)
+            | Typed::Float(_)
+            | Typed::Timestamp(_)
+            | Typed::Decimal(_)
+            | Typed::Array(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact() {
+        let mut l = Vec::with_capacity(64);
+        l.push(Typed::Int(1));
+        let mut s = String::with_capacity(64);
+        s.push('a');
+        l.push(Typed::String(s.into()));
+        let mut m = Map::with_capacity(64);
+        m.insert(String::from("k"), Typed::List(l));
+        let mut e = Typed::Map(m);
+        let expected = e.clone();
+
+        e.compact();
+        assert_eq!(e, expected);
+        match &e {
+            Typed::Map(m) => {
+                assert!(m.capacity() < 64);
+                match m.get("k") {
+                    Some(Typed::List(l)) => assert_eq!(l.capacity(), 2),
+                    v => panic!("unexpected: {:?}", v),
+                }
+            }
+            v => panic!("unexpected: {:?}", v),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Compression of large `Typed::Bytes` and `Typed::String` values, see
+//! `Config::compression`.
+//!
+//! A compressed value has a tag of its own, `B` for bytes and `Z` for
+//! strings, followed by the uvarint length of the value and the sized raw
+//! deflate stream of it. The readers decompress the values transparently,
+//! which needs the `flate2` feature, the length is checked against the
+//! limits before anything is decompressed.
+
+use crate::codec::read_sized_limited;
+use crate::error::{Error, Result};
+use crate::limits::Budget;
+use crate::varint::VarintReadExt;
+use std::io::Read;
+
+#[cfg(feature = "flate2")]
+pub(crate) use self::deflate::write_deflated;
+#[cfg(feature = "flate2")]
+pub use self::deflate::Compression;
+
+/// Reads the rest of a compressed value whose tag was already read.
+pub(crate) fn read_deflated<R: Read + ?Sized>(r: &mut R, budget: &Budget) -> Result<Vec<u8>> {
+    let len = r.read_uvarint()?;
+    budget.bytes(len)?;
+    let stream = read_sized_limited(r, budget)?;
+    inflate(&stream, len)
+}
+
+/// Decompresses the deflate `stream` of a value of `len` bytes.
+#[cfg(feature = "flate2")]
+pub(crate) fn inflate(stream: &[u8], len: u64) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len.min(1 << 20) as usize);
+    flate2::read::DeflateDecoder::new(stream)
+        .take(len.saturating_add(1))
+        .read_to_end(&mut buf)
+        .map_err(|err| Error::InvalidData(format!("invalid deflate stream: {}", err)))?;
+    if buf.len() as u64 != len {
+        return Err(Error::InvalidData(format!(
+            "compressed value of {} bytes, expected {}",
+            buf.len(),
+            len
+        )));
+    }
+    Ok(buf)
+}
+
+#[cfg(not(feature = "flate2"))]
+pub(crate) fn inflate(_stream: &[u8], _len: u64) -> Result<Vec<u8>> {
+    Err(Error::InvalidData(
+        "compressed values need the `flate2` feature".into(),
+    ))
+}
+
+#[cfg(feature = "flate2")]
+mod deflate {
+    use crate::codec::{CodecWriteExt, Typed, TYPE_BYTES_DEFLATE, TYPE_STRING_DEFLATE};
+    use crate::error::Result;
+    use crate::varint::VarintWriteExt;
+    use byteorder::WriteBytesExt;
+    use std::io::Write;
+
+    /// Compression tells which `Typed::Bytes` and `Typed::String` values are
+    /// compressed, those at least `threshold` bytes long, as long as they
+    /// shrink.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Compression {
+        pub threshold: usize,
+        /// The deflate level, from 0 to 9.
+        pub level: u32,
+    }
+
+    impl Default for Compression {
+        fn default() -> Self {
+            Compression {
+                threshold: 4096,
+                level: 6,
+            }
+        }
+    }
+
+    impl Compression {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn threshold(mut self, n: usize) -> Self {
+            self.threshold = n;
+            self
+        }
+
+        pub fn level(mut self, level: u32) -> Self {
+            self.level = level.min(9);
+            self
+        }
+
+        // Returns the deflate stream of `buf` if it is worth it.
+        fn deflate(&self, buf: &[u8]) -> Result<Option<Vec<u8>>> {
+            if buf.len() < self.threshold {
+                return Ok(None);
+            }
+            let mut enc = flate2::write::DeflateEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(self.level),
+            );
+            enc.write_all(buf)?;
+            let stream = enc.finish()?;
+            // The length of the value takes at most 10 bytes.
+            Ok(Some(stream).filter(|stream| stream.len() + 10 < buf.len()))
+        }
+    }
+
+    /// Writes `e` compressed if it is bytes or a string which `c` tells
+    /// to compress, returns whether it was written.
+    pub(crate) fn write_deflated<W: Write + ?Sized>(
+        w: &mut W,
+        e: &Typed,
+        c: &Compression,
+    ) -> Result<bool> {
+        let (t, buf) = match e {
+            Typed::Bytes(buf) => (TYPE_BYTES_DEFLATE, &buf[..]),
+            Typed::String(s) => (TYPE_STRING_DEFLATE, s.as_bytes()),
+            _ => return Ok(false),
+        };
+        match c.deflate(buf)? {
+            Some(stream) => {
+                w.write_u8(t)?;
+                w.write_uvarint(buf.len() as u64)?;
+                w.write_sized(&stream)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::CodecReadExt;
+    #[cfg(feature = "flate2")]
+    use crate::{
+        codec::{CodecWriteExt, Map, Typed},
+        config::{Codec, Config},
+        encoder::Decoder,
+        error::Error,
+        limits::Limits,
+        parser::Parser,
+        typed_ref::decode_ref,
+        Compression,
+    };
+
+    // "abc" in a stored deflate block.
+    const STORED: &[u8] = b"Z\x03\x08\x01\x03\x00\xfc\xffabc";
+
+    #[test]
+    fn stored() {
+        let res = (&STORED[..]).read_typed();
+        #[cfg(feature = "flate2")]
+        assert_eq!(res.unwrap(), Typed::String("abc".into()));
+        #[cfg(not(feature = "flate2"))]
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "compressed values need the `flate2` feature"
+        );
+        // Skipping it doesn't need the feature.
+        let raw = (&STORED[..]).read_raw().unwrap();
+        assert_eq!(raw.as_bytes(), STORED);
+    }
+
+    #[cfg(feature = "flate2")]
+    fn value() -> Typed {
+        let mut m = Map::new();
+        m.insert(
+            "json".into(),
+            Typed::String("{\"k\": 1}, ".repeat(100).into()),
+        );
+        m.insert("blob".into(), Typed::Bytes(vec![7u8; 1000].into()));
+        m.insert("short".into(), Typed::String("v".repeat(10).into()));
+        // Incompressible bytes are written as they are.
+        let noise: Vec<u8> = (0..1000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        m.insert(
+            "noise".into(),
+            Typed::List(vec![Typed::Bytes(noise.into())]),
+        );
+        Typed::Map(m)
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn roundtrip() {
+        let codec = Codec::new(
+            Config::new()
+                .canonical(true)
+                .compression(Compression::new().threshold(64)),
+        );
+        let buf = codec.encode(&value()).unwrap();
+        let mut plain = Vec::new();
+        plain.write_typed_canonical(&value()).unwrap();
+        assert!(buf.len() < plain.len() - 1000, "{}", buf.len());
+        assert_eq!(codec.encode(&value()).unwrap(), buf);
+
+        assert_eq!(Codec::default().decode(&buf).unwrap(), value());
+        #[cfg(feature = "bytes")]
+        assert_eq!(
+            crate::read_typed_from_bytes(&mut buf.clone().into()).unwrap(),
+            value()
+        );
+        let raw = (&buf[..]).read_raw().unwrap();
+        assert_eq!(raw.as_bytes(), &buf[..]);
+        assert_eq!(Parser::new(&buf[..]).count(), 1 + 4 * 2 + 2 + 1);
+        let err = decode_ref(&buf).unwrap_err();
+        assert_eq!(err.to_string(), "compressed values can't be borrowed");
+
+        let mut enc = codec.encoder(Vec::new());
+        enc.encode(&value()).unwrap();
+        let framed = enc.into_inner();
+        assert_eq!(codec.decoder(&framed[..]).decode().unwrap(), value());
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn malformed() {
+        let codec = Codec::new(Config::new().limits(Limits::new().max_bytes_len(2)));
+        assert!(matches!(codec.decode(STORED), Err(Error::TooLong(3))));
+
+        let mut buf = STORED.to_vec();
+        buf[1] = 4;
+        let err = Codec::default().decode(&buf).unwrap_err();
+        assert_eq!(err.to_string(), "compressed value of 3 bytes, expected 4");
+        buf[1] = 2;
+        assert!(Codec::default().decode(&buf).is_err());
+        buf[3] = 0x07;
+        let err = Codec::default().decode(&buf).unwrap_err();
+        assert!(
+            err.to_string().starts_with("invalid deflate stream"),
+            "{}",
+            err
+        );
+
+        // A length over the limit is rejected before anything is inflated,
+        // whether the message is traced or not.
+        let mut bomb = vec![STORED[0]];
+        bomb.extend_from_slice(b"\x80\x80\x80\x80\x04");
+        bomb.extend_from_slice(&STORED[2..]);
+        let mut framed = Vec::new();
+        framed.write_sized(&bomb).unwrap();
+        for trace in [false, true].iter() {
+            let mut dec = Decoder::new(&framed[..]).trace(*trace);
+            assert!(matches!(dec.decode(), Err(Error::TooLong(0x4000_0000))));
+        }
+    }
+}
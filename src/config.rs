@@ -0,0 +1,455 @@
+use crate::codec::{
+    read_list_encoded, read_map_encoded, write_canonical, write_canonical_elements,
+    write_canonical_entries, write_container_len, write_elements, write_entries, write_fixed_width,
+    CodecReadExt, CodecWriteExt,
+};
+use crate::codec::{List, Map, Type, Typed, TYPE_LIST, TYPE_LIST_V2, TYPE_MAP, TYPE_MAP_V2};
+#[cfg(feature = "flate2")]
+use crate::compress::{write_deflated, Compression};
+use crate::encoder::{Decoder, Encoder};
+use crate::error::{Error, Result};
+use crate::limits::{Budget, Limits};
+use crate::options::DecodeOptions;
+use byteorder::WriteBytesExt;
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+/// FormatVersion is the revision of the wire format values are written in.
+/// Readers accept both, as the containers of each version have tags of
+/// their own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FormatVersion {
+    /// Lists and maps hold at most 254 elements, their numbers are a byte.
+    #[default]
+    V1,
+    /// The numbers of elements of lists and maps are uvarints, so they are
+    /// not limited. Readers which predate it can't decode them.
+    V2,
+}
+
+impl FormatVersion {
+    pub(crate) fn list_tag(self) -> Type {
+        match self {
+            FormatVersion::V1 => TYPE_LIST,
+            FormatVersion::V2 => TYPE_LIST_V2,
+        }
+    }
+
+    pub(crate) fn map_tag(self) -> Type {
+        match self {
+            FormatVersion::V1 => TYPE_MAP,
+            FormatVersion::V2 => TYPE_MAP_V2,
+        }
+    }
+}
+
+/// Config collects the settings of a `Codec`, so they are chosen once
+/// rather than at every call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Whether map entries are written in key order, so equal values are
+    /// always encoded into the same bytes.
+    pub canonical: bool,
+    /// The format values are written in, see `FormatVersion`.
+    pub format_version: FormatVersion,
+    /// Whether the messages of `Codec::encoder` and `Codec::decoder` carry
+    /// a CRC32 checksum, see `Crc32`.
+    pub checksum: bool,
+    /// Which bytes and strings are compressed, see `Compression`.
+    #[cfg(feature = "flate2")]
+    pub compression: Option<Compression>,
+    /// Whether the ints, uints and floats whose varints would take more
+    /// than 8 bytes, e.g. most floats and large random numbers, are written
+    /// in 8 fixed bytes instead. Readers which predate it can't decode them.
+    pub fixed_width: bool,
+    /// Whether strings and map keys are NFC normalized before they are
+    /// written, like `Typed::normalize_unicode` does, `Typed::Raw` values
+    /// are written as they are.
+    #[cfg(feature = "unicode-normalization")]
+    pub nfc: bool,
+    pub decode_options: DecodeOptions,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// See `DecodeOptions::limits`.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.decode_options.limits = limits;
+        self
+    }
+
+    pub fn canonical(mut self, yes: bool) -> Self {
+        self.canonical = yes;
+        self
+    }
+
+    pub fn format_version(mut self, version: FormatVersion) -> Self {
+        self.format_version = version;
+        self
+    }
+
+    pub fn checksum(mut self, yes: bool) -> Self {
+        self.checksum = yes;
+        self
+    }
+
+    #[cfg(feature = "flate2")]
+    pub fn compression(mut self, c: Compression) -> Self {
+        self.compression = Some(c);
+        self
+    }
+
+    pub fn fixed_width(mut self, yes: bool) -> Self {
+        self.fixed_width = yes;
+        self
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    pub fn nfc(mut self, yes: bool) -> Self {
+        self.nfc = yes;
+        self
+    }
+
+    /// See `DecodeOptions::strict_utf8`.
+    pub fn strict_utf8(mut self, yes: bool) -> Self {
+        self.decode_options.strict_utf8 = yes;
+        self
+    }
+
+    pub fn decode_options(mut self, opts: DecodeOptions) -> Self {
+        self.decode_options = opts;
+        self
+    }
+}
+
+/// Codec encodes and decodes values as configured, it mirrors
+/// `CodecWriteExt` and `CodecReadExt`, which behave like a default codec.
+/// The lists and maps read and written without a tag
+/// by `read_list`, `write_list` and so on have the numbers of elements of
+/// `Config::format_version`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Codec {
+    config: Config,
+}
+
+impl Codec {
+    pub fn new(config: Config) -> Self {
+        Codec { config }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn write_typed<W: Write + ?Sized>(&self, w: &mut W, e: &Typed) -> Result<()> {
+        write_configured(w, e, &self.config)
+    }
+
+    pub fn write_list<W: Write + ?Sized>(&self, w: &mut W, l: &List) -> Result<()> {
+        let version = self.config.format_version;
+        if !self.config.is_plain() {
+            return write_configured_elements(w, l, &self.config);
+        }
+        if self.config.canonical {
+            write_canonical_elements(w, l, version)
+        } else {
+            write_elements(w, l, version)
+        }
+    }
+
+    pub fn write_map<W: Write + ?Sized>(&self, w: &mut W, m: &Map) -> Result<()> {
+        let version = self.config.format_version;
+        if !self.config.is_plain() {
+            return write_configured_entries(w, m, &self.config);
+        }
+        if self.config.canonical {
+            write_canonical_entries(w, m, version)
+        } else {
+            write_entries(w, m, version)
+        }
+    }
+
+    pub fn read_typed<R: Read + ?Sized>(&self, r: &mut R) -> Result<Typed> {
+        r.read_typed_with(&self.config.decode_options)
+    }
+
+    pub fn read_list<R: Read + ?Sized>(&self, r: &mut R) -> Result<List> {
+        let opts = &self.config.decode_options;
+        let t = self.config.format_version.list_tag();
+        let mut budget = Budget::new(&opts.limits);
+        read_list_encoded(r, opts, opts.text_encoding, t, &mut budget)
+    }
+
+    pub fn read_map<R: Read + ?Sized>(&self, r: &mut R) -> Result<Map> {
+        let opts = &self.config.decode_options;
+        let t = self.config.format_version.map_tag();
+        let mut budget = Budget::new(&opts.limits);
+        read_map_encoded(r, opts, opts.text_encoding, t, &mut budget)
+    }
+
+    /// Encodes a single value.
+    pub fn encode(&self, e: &Typed) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_typed(&mut buf, e)?;
+        Ok(buf)
+    }
+
+    /// Decodes a single value, which must span the whole buffer.
+    pub fn decode(&self, mut buf: &[u8]) -> Result<Typed> {
+        let e = self.read_typed(&mut buf)?;
+        if !buf.is_empty() {
+            return Err(Error::TrailingBytes);
+        }
+        Ok(e)
+    }
+
+    /// Returns an encoder of length prefixed messages, see
+    /// `Encoder::with_config`.
+    pub fn encoder<W: Write>(&self, writer: W) -> Encoder<W> {
+        Encoder::with_config(writer, &self.config)
+    }
+
+    pub fn decoder<R: Read>(&self, reader: R) -> Decoder<R> {
+        Decoder::with_config(reader, &self.config)
+    }
+}
+
+impl Config {
+    // Whether values are written by the plain or canonical writers of
+    // `codec`, which are faster than `write_configured`.
+    fn is_plain(&self) -> bool {
+        #[cfg(feature = "flate2")]
+        if self.compression.is_some() {
+            return false;
+        }
+        #[cfg(feature = "unicode-normalization")]
+        if self.nfc {
+            return false;
+        }
+        !self.fixed_width
+    }
+}
+
+/// Writes `e` as `config` tells, like `Codec::write_typed`.
+pub(crate) fn write_configured<W: Write + ?Sized>(
+    w: &mut W,
+    e: &Typed,
+    config: &Config,
+) -> Result<()> {
+    let version = config.format_version;
+    if config.is_plain() {
+        return if config.canonical {
+            write_canonical(w, e, version)
+        } else {
+            w.write_typed_version(e, version)
+        };
+    }
+    // Strings are normalized before they are compressed.
+    #[cfg(feature = "unicode-normalization")]
+    if let (true, Typed::String(s)) = (config.nfc, e) {
+        if let Some(n) = crate::unicode::nfc(s) {
+            return write_configured(w, &Typed::String(n.into()), config);
+        }
+    }
+    #[cfg(feature = "flate2")]
+    if let Some(c) = &config.compression {
+        if write_deflated(w, e, c)? {
+            return Ok(());
+        }
+    }
+    if config.fixed_width && write_fixed_width(w, e)? {
+        return Ok(());
+    }
+    match e {
+        Typed::List(l) => {
+            w.write_u8(version.list_tag())?;
+            write_configured_elements(w, l, config)
+        }
+        Typed::Map(m) => {
+            w.write_u8(version.map_tag())?;
+            write_configured_entries(w, m, config)
+        }
+        Typed::Raw(raw) if config.canonical => write_configured(w, &raw.decode()?, config),
+        _ => w.write_typed_version(e, version),
+    }
+}
+
+fn write_configured_elements<W: Write + ?Sized>(
+    w: &mut W,
+    l: &List,
+    config: &Config,
+) -> Result<()> {
+    write_container_len(w, l.len(), config.format_version)?;
+    l.iter().try_for_each(|e| write_configured(w, e, config))
+}
+
+fn write_configured_entries<W: Write + ?Sized>(w: &mut W, m: &Map, config: &Config) -> Result<()> {
+    write_container_len(w, m.len(), config.format_version)?;
+    let mut entries: Vec<_> = m
+        .iter()
+        .map(|(k, v)| (configured_key(k, config), v))
+        .collect();
+    if config.canonical {
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+    for (k, v) in entries {
+        w.write_sized(k.as_bytes())?;
+        write_configured(w, v, config)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "unicode-normalization")]
+fn configured_key<'a>(k: &'a str, config: &Config) -> Cow<'a, str> {
+    if config.nfc {
+        if let Some(n) = crate::unicode::nfc(k) {
+            return Cow::Owned(n);
+        }
+    }
+    Cow::Borrowed(k)
+}
+
+#[cfg(not(feature = "unicode-normalization"))]
+fn configured_key<'a>(k: &'a str, _config: &Config) -> Cow<'a, str> {
+    Cow::Borrowed(k)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value() -> Typed {
+        let mut m = Map::new();
+        for k in ["c", "a", "b", "d"].iter() {
+            m.insert(k.to_string(), Typed::List(vec![Typed::String("v".into())]));
+        }
+        Typed::Map(m)
+    }
+
+    #[test]
+    fn canonical() {
+        let codec = Codec::new(Config::new().canonical(true));
+        let buf = codec.encode(&value()).unwrap();
+        assert_eq!(&buf[..5], b"m\x04\x01al");
+        assert_eq!(codec.decode(&buf).unwrap(), value());
+        assert_eq!(Codec::default().decode(&buf).unwrap(), value());
+        for _ in 0..10 {
+            assert_eq!(codec.encode(&value().clone()).unwrap(), buf);
+        }
+        let mut plain = Vec::new();
+        plain.write_typed_canonical(&value()).unwrap();
+        assert_eq!(plain, buf);
+
+        let mut enc = codec.encoder(Vec::new());
+        enc.encode(&value()).unwrap();
+        let framed = enc.into_inner();
+        assert_eq!(&framed[1..], &buf[..]);
+    }
+
+    #[test]
+    fn limits() {
+        let codec = Codec::new(Config::new().limits(Limits::new().max_depth(1)));
+        let buf = Codec::default().encode(&value()).unwrap();
+        let err = codec.decode(&buf).unwrap_err();
+        assert!(matches!(err, Error::DepthExceeded(1)), "{:?}", err);
+        assert!(codec.read_map(&mut &buf[1..]).is_err());
+
+        let codec = Codec::new(Config::new().limits(Limits::new().max_bytes_len(0)));
+        assert!(codec.decode(b"s\x00").is_ok());
+        assert!(matches!(codec.decode(b"s\x01x"), Err(Error::TooLong(1))));
+        assert!(matches!(
+            codec.decode(b"s\x00\x00"),
+            Err(Error::TrailingBytes)
+        ));
+
+        let mut dec = codec.decoder(&b"\x03s\x01x"[..]);
+        assert!(dec.decode().is_err());
+    }
+
+    #[test]
+    fn strict_utf8() {
+        let buf = b"m\x01\x01ks\x02\xc3\x28";
+        assert!(Codec::default().decode(buf).is_ok());
+        let codec = Codec::new(Config::new().strict_utf8(true));
+        let err = codec.decode(buf).unwrap_err();
+        assert!(matches!(err, Error::InvalidUtf8(_)), "{:?}", err);
+        assert!(codec.decode(b"m\x01\x01\xffi\x00").is_err());
+        assert!(codec.decode(b"s\x02\xc3\xa9").is_ok());
+    }
+
+    #[test]
+    fn format_version() {
+        let l = vec![Typed::Int(0); 300];
+        let codec = Codec::new(Config::new().format_version(FormatVersion::V2));
+        let buf = codec.encode(&Typed::List(l.clone())).unwrap();
+        assert_eq!(&buf[..3], b"L\xac\x02");
+        assert_eq!(
+            Codec::default().decode(&buf).unwrap(),
+            Typed::List(l.clone())
+        );
+        assert!(Codec::default().encode(&Typed::List(l.clone())).is_err());
+
+        let mut buf = Vec::new();
+        codec.write_list(&mut buf, &l).unwrap();
+        assert_eq!(codec.read_list(&mut &buf[..]).unwrap(), l);
+        let mut enc = codec.encoder(Vec::new());
+        enc.encode(&value()).unwrap();
+        let framed = enc.into_inner();
+        assert_eq!(framed[1], b'M');
+        assert_eq!(codec.decoder(&framed[..]).decode().unwrap(), value());
+
+        let codec = Codec::new(
+            Config::new()
+                .format_version(FormatVersion::V2)
+                .canonical(true),
+        );
+        let buf = codec.encode(&value()).unwrap();
+        assert_eq!(&buf[..5], b"M\x04\x01aL");
+        let mut buf = Vec::new();
+        codec.write_map(&mut buf, &Map::new()).unwrap();
+        assert!(codec.read_map(&mut &buf[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn checksum() {
+        let codec = Codec::new(Config::new().checksum(true));
+        let mut enc = codec.encoder(Vec::new());
+        enc.encode(&value()).unwrap();
+        let mut buf = enc.into_inner();
+        assert_eq!(codec.decoder(&buf[..]).decode().unwrap(), value());
+        assert_eq!(
+            buf.len(),
+            Codec::default().encode(&value()).unwrap().len() + 5
+        );
+
+        let n = buf.len();
+        buf[n - 1] ^= 1;
+        assert!(codec.decoder(&buf[..]).decode().is_err());
+    }
+
+    #[test]
+    fn fixed_width() {
+        let codec = Codec::new(Config::new().fixed_width(true).canonical(true));
+        let buf = codec.encode(&Typed::Float(0.1)).unwrap();
+        assert_eq!(buf[0], b'F');
+        assert_eq!(buf.len(), 9);
+        assert_eq!(codec.encode(&Typed::Uint(1)).unwrap(), b"u\x01");
+        assert_eq!(codec.encode(&Typed::Float(1.0)).unwrap().len(), 9);
+        assert_eq!(codec.encode(&Typed::Float(0.0)).unwrap(), b"f\x00");
+
+        let e = Typed::List(vec![Typed::Uint(u64::MAX), value()]);
+        let buf = codec.encode(&e).unwrap();
+        assert_eq!(&buf[..3], b"l\x02U");
+        assert_eq!(Codec::default().decode(&buf).unwrap(), e);
+        let mut plain = Vec::new();
+        plain.write_typed_canonical(&e).unwrap();
+        assert_eq!(buf.len() + 2, plain.len());
+        let mut enc = codec.encoder(Vec::new());
+        enc.encode(&e).unwrap();
+        assert_eq!(&enc.into_inner()[1..], &buf[..]);
+    }
+}
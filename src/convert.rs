@@ -0,0 +1,595 @@
+use crate::codec::{CodecReadExt, CodecWriteExt, List, Map, Str, Typed};
+use crate::error::{Error, Result};
+use crate::options::DecodeOptions;
+use crate::raw::RawValue;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+/// ToTyped converts a Rust value into `Typed`.
+pub trait ToTyped {
+    fn to_typed(&self) -> Typed;
+}
+
+/// FromTyped converts a `Typed` back into a Rust value.
+pub trait FromTyped: Sized {
+    fn from_typed(e: &Typed) -> Result<Self>;
+
+    /// Like `from_typed` but also accepts sloppy representations, e.g.
+    /// numbers stored in strings, integers for floats and 0/1 for booleans.
+    fn from_typed_lenient(e: &Typed) -> Result<Self> {
+        Self::from_typed(e)
+    }
+}
+
+/// ToMap converts a struct into a `Map`, it can be derived with
+/// `#[derive(ToMap)]` when the `derive` feature is enabled.
+pub trait ToMap {
+    fn to_map(&self) -> Map;
+}
+
+/// FromMap builds a struct from a `Map`, it can be derived with
+/// `#[derive(FromMap)]` when the `derive` feature is enabled.
+pub trait FromMap: Sized {
+    fn from_map(m: &Map) -> Result<Self>;
+
+    /// Like `from_map` but converts the fields with
+    /// `FromTyped::from_typed_lenient`.
+    fn from_map_lenient(m: &Map) -> Result<Self> {
+        Self::from_map(m)
+    }
+}
+
+/// XdEncode writes a struct as an encoded map, it can be derived with
+/// `#[derive(ToMap, XdEncode)]` when the `derive` feature is enabled.
+pub trait XdEncode: ToMap {
+    fn encode<W: Write>(&self, mut w: W) -> Result<()> {
+        w.write_typed(&Typed::Map(self.to_map()))
+    }
+}
+
+/// XdDecode reads a struct from an encoded map, it can be derived with
+/// `#[derive(FromMap, XdDecode)]` when the `derive` feature is enabled.
+pub trait XdDecode: FromMap {
+    fn decode<R: Read>(r: R) -> Result<Self> {
+        Self::decode_with(r, &DecodeOptions::default())
+    }
+
+    fn decode_with<R: Read>(mut r: R, opts: &DecodeOptions) -> Result<Self> {
+        match r.read_typed_with(opts)? {
+            Typed::Map(m) => Ok(Self::from_map(&m)?),
+            e => Err(invalid_type(&e, "map")),
+        }
+    }
+}
+
+pub(crate) fn invalid_type(e: &Typed, expected: &str) -> Error {
+    Error::InvalidData(format!(
+        "invalid type: expected {}, found {}",
+        expected,
+        e.type_name()
+    ))
+}
+
+pub(crate) fn out_of_range(e: &Typed, target: &str) -> Error {
+    Error::InvalidData(format!("{:?} is out of range for {}", e, target))
+}
+
+pub(crate) fn cannot_coerce(e: &Typed, target: &str) -> Error {
+    Error::InvalidData(format!("cannot coerce {:?} into {}", e, target))
+}
+
+fn integral(f: f64) -> Option<i128> {
+    if f.is_finite() && f.fract() == 0.0 && f.abs() < i128::MAX as f64 {
+        Some(f as i128)
+    } else {
+        None
+    }
+}
+
+macro_rules! impl_integer {
+    ($variant:ident, $repr:ty, $name:expr; $($t:ty),*) => {
+        $(
+            impl ToTyped for $t {
+                fn to_typed(&self) -> Typed {
+                    Typed::$variant(*self as $repr)
+                }
+            }
+
+            impl FromTyped for $t {
+                fn from_typed(e: &Typed) -> Result<Self> {
+                    match e {
+                        Typed::$variant(n) => {
+                            <$t>::try_from(*n).map_err(|_| out_of_range(e, stringify!($t)))
+                        }
+                        _ => Err(invalid_type(e, $name)),
+                    }
+                }
+
+                fn from_typed_lenient(e: &Typed) -> Result<Self> {
+                    let n = match e {
+                        Typed::Int(n) => <$t>::try_from(*n).ok(),
+                        Typed::Uint(un) => <$t>::try_from(*un).ok(),
+                        Typed::Float(f) => integral(*f).and_then(|n| <$t>::try_from(n).ok()),
+                        Typed::String(s) => s.trim().parse::<$t>().ok(),
+                        _ => return Err(invalid_type(e, $name)),
+                    };
+                    n.ok_or_else(|| cannot_coerce(e, stringify!($t)))
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(Int, i64, "int"; i8, i16, i32, i64, isize);
+impl_integer!(Uint, u64, "uint"; u8, u16, u32, u64, usize);
+
+impl ToTyped for f64 {
+    fn to_typed(&self) -> Typed {
+        Typed::Float(*self)
+    }
+}
+
+impl FromTyped for f64 {
+    fn from_typed(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::Float(f) => Ok(*f),
+            _ => Err(invalid_type(e, "float")),
+        }
+    }
+
+    fn from_typed_lenient(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::Float(f) => Ok(*f),
+            Typed::Int(n) => Ok(*n as f64),
+            Typed::Uint(un) => Ok(*un as f64),
+            Typed::String(s) => s.trim().parse().map_err(|_| cannot_coerce(e, "f64")),
+            _ => Err(invalid_type(e, "float")),
+        }
+    }
+}
+
+impl ToTyped for f32 {
+    fn to_typed(&self) -> Typed {
+        Typed::Float(*self as f64)
+    }
+}
+
+impl FromTyped for f32 {
+    fn from_typed(e: &Typed) -> Result<Self> {
+        f64::from_typed(e).map(|f| f as f32)
+    }
+
+    fn from_typed_lenient(e: &Typed) -> Result<Self> {
+        f64::from_typed_lenient(e).map(|f| f as f32)
+    }
+}
+
+impl ToTyped for bool {
+    fn to_typed(&self) -> Typed {
+        Typed::Bool(*self)
+    }
+}
+
+// Before `Typed::Bool`, booleans were stored as 0 or 1, which are still
+// accepted.
+impl FromTyped for bool {
+    fn from_typed(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::Bool(b) => Ok(*b),
+            Typed::Uint(0) => Ok(false),
+            Typed::Uint(1) => Ok(true),
+            Typed::Uint(_) => Err(out_of_range(e, "bool")),
+            _ => Err(invalid_type(e, "bool")),
+        }
+    }
+
+    fn from_typed_lenient(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::Bool(b) => Ok(*b),
+            Typed::Int(0) | Typed::Uint(0) => Ok(false),
+            Typed::Int(1) | Typed::Uint(1) => Ok(true),
+            Typed::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" => Ok(true),
+                "false" | "0" => Ok(false),
+                _ => Err(cannot_coerce(e, "bool")),
+            },
+            _ => Err(cannot_coerce(e, "bool")),
+        }
+    }
+}
+
+impl ToTyped for String {
+    fn to_typed(&self) -> Typed {
+        Typed::String(Str::from(self.as_str()))
+    }
+}
+
+impl FromTyped for String {
+    fn from_typed(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::String(s) => Ok(s.to_string()),
+            _ => Err(invalid_type(e, "string")),
+        }
+    }
+
+    fn from_typed_lenient(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::String(s) => Ok(s.to_string()),
+            Typed::Int(n) => Ok(n.to_string()),
+            Typed::Uint(un) => Ok(un.to_string()),
+            Typed::Float(f) => Ok(f.to_string()),
+            Typed::Bytes(buf) => {
+                String::from_utf8(buf.to_vec()).map_err(|_| cannot_coerce(e, "string"))
+            }
+            _ => Err(invalid_type(e, "string")),
+        }
+    }
+}
+
+impl ToTyped for Typed {
+    fn to_typed(&self) -> Typed {
+        self.clone()
+    }
+}
+
+impl FromTyped for Typed {
+    fn from_typed(e: &Typed) -> Result<Self> {
+        Ok(e.clone())
+    }
+}
+
+impl ToTyped for RawValue {
+    fn to_typed(&self) -> Typed {
+        Typed::Raw(self.clone())
+    }
+}
+
+impl<T: ToTyped> ToTyped for Vec<T> {
+    fn to_typed(&self) -> Typed {
+        Typed::List(self.iter().map(ToTyped::to_typed).collect::<List>())
+    }
+}
+
+impl<T: FromTyped> FromTyped for Vec<T> {
+    fn from_typed(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::List(l) => l.iter().map(T::from_typed).collect(),
+            _ => Err(invalid_type(e, "list")),
+        }
+    }
+
+    fn from_typed_lenient(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::List(l) => l.iter().map(T::from_typed_lenient).collect(),
+            _ => Err(invalid_type(e, "list")),
+        }
+    }
+}
+
+impl<T: ToTyped> ToTyped for HashMap<String, T> {
+    fn to_typed(&self) -> Typed {
+        Typed::Map(
+            self.iter()
+                .map(|(k, v)| (k.clone(), v.to_typed()))
+                .collect::<Map>(),
+        )
+    }
+}
+
+impl<T: FromTyped> FromTyped for HashMap<String, T> {
+    fn from_typed(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::Map(m) => m
+                .iter()
+                .map(|(k, v)| T::from_typed(v).map(|v| (k.clone(), v)))
+                .collect(),
+            _ => Err(invalid_type(e, "map")),
+        }
+    }
+
+    fn from_typed_lenient(e: &Typed) -> Result<Self> {
+        match e {
+            Typed::Map(m) => m
+                .iter()
+                .map(|(k, v)| T::from_typed_lenient(v).map(|v| (k.clone(), v)))
+                .collect(),
+            _ => Err(invalid_type(e, "map")),
+        }
+    }
+}
+
+// Helpers used by the code generated from `#[derive(FromMap)]`.
+
+#[doc(hidden)]
+pub fn from_field<T: FromTyped>(m: &Map, key: &str) -> Result<T> {
+    field(m, key, T::from_typed)
+}
+
+#[doc(hidden)]
+pub fn from_field_lenient<T: FromTyped>(m: &Map, key: &str) -> Result<T> {
+    field(m, key, T::from_typed_lenient)
+}
+
+#[doc(hidden)]
+pub fn from_field_or_default<T: FromTyped + Default>(m: &Map, key: &str) -> Result<T> {
+    optional_field(m, key, T::from_typed).map(Option::unwrap_or_default)
+}
+
+#[doc(hidden)]
+pub fn from_field_or_default_lenient<T: FromTyped + Default>(m: &Map, key: &str) -> Result<T> {
+    optional_field(m, key, T::from_typed_lenient).map(Option::unwrap_or_default)
+}
+
+#[doc(hidden)]
+pub fn from_optional_field<T: FromTyped>(m: &Map, key: &str) -> Result<Option<T>> {
+    optional_field(m, key, T::from_typed)
+}
+
+#[doc(hidden)]
+pub fn from_optional_field_lenient<T: FromTyped>(m: &Map, key: &str) -> Result<Option<T>> {
+    optional_field(m, key, T::from_typed_lenient)
+}
+
+#[doc(hidden)]
+pub fn from_map_value<T: FromMap>(e: &Typed) -> Result<T> {
+    match e {
+        Typed::Map(m) => T::from_map(m),
+        _ => Err(invalid_type(e, "map")),
+    }
+}
+
+#[doc(hidden)]
+pub fn from_map_value_lenient<T: FromMap>(e: &Typed) -> Result<T> {
+    match e {
+        Typed::Map(m) => T::from_map_lenient(m),
+        _ => Err(invalid_type(e, "map")),
+    }
+}
+
+#[doc(hidden)]
+pub fn extend_extra(m: &mut Map, extra: &Map) {
+    m.extend(extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+}
+
+#[doc(hidden)]
+pub fn extra_fields(m: &Map, known: &[&str]) -> Map {
+    m.iter()
+        .filter(|(k, _)| !known.contains(&k.as_str()))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+fn field<T>(m: &Map, key: &str, conv: fn(&Typed) -> Result<T>) -> Result<T> {
+    match m.get(key) {
+        Some(v) => from_value(v, key, conv),
+        None => Err(Error::InvalidData(format!("missing field: '{}'", key))),
+    }
+}
+
+fn optional_field<T>(m: &Map, key: &str, conv: fn(&Typed) -> Result<T>) -> Result<Option<T>> {
+    match m.get(key) {
+        Some(Typed::Null) | None => Ok(None),
+        Some(v) => from_value(v, key, conv).map(Some),
+    }
+}
+
+fn from_value<T>(v: &Typed, key: &str, conv: fn(&Typed) -> Result<T>) -> Result<T> {
+    conv(v).map_err(|err| Error::InvalidData(format!("invalid field '{}': {}", key, err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{Describe, Field, Schema};
+    use xdcodec_derive::{Describe, FromMap, ToMap, XdDecode, XdEncode};
+
+    #[derive(Debug, PartialEq, ToMap, FromMap, Describe)]
+    struct Endpoint {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Debug, PartialEq, ToMap, FromMap, Describe)]
+    struct Config {
+        name: String,
+        weight: f64,
+        retries: i32,
+        tags: Vec<String>,
+        primary: Endpoint,
+        fallback: Option<Endpoint>,
+    }
+
+    #[derive(Debug, PartialEq, ToMap, FromMap, Describe)]
+    struct Versioned {
+        version: u32,
+        #[xd(extra)]
+        unknown: Map,
+    }
+
+    #[derive(Debug, PartialEq, ToMap, FromMap, XdEncode, XdDecode, Describe)]
+    struct Message {
+        #[xd(rename = "type")]
+        kind: String,
+        #[xd(default)]
+        retries: u32,
+        #[xd(skip)]
+        cached: Vec<u8>,
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(u8::from_typed(&Typed::Uint(255)).unwrap(), 255);
+        assert!(u8::from_typed(&Typed::Uint(256)).is_err());
+        assert!(i8::from_typed(&Typed::Uint(1)).is_err());
+        assert_eq!(i16::from_typed(&(-3i16).to_typed()).unwrap(), -3);
+        assert_eq!(
+            Vec::<u32>::from_typed(&vec![1u32, 2u32].to_typed()).unwrap(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn derived_roundtrip() {
+        let c = Config {
+            name: String::from("svc"),
+            weight: 0.5,
+            retries: -1,
+            tags: vec![String::from("a"), String::from("b")],
+            primary: Endpoint {
+                host: String::from("127.0.0.1"),
+                port: 8080,
+            },
+            fallback: None,
+        };
+
+        let m = c.to_map();
+        assert!(!m.contains_key("fallback"));
+        assert_eq!(m.get("retries"), Some(&Typed::Int(-1)));
+        assert_eq!(Config::from_map(&m).unwrap(), c);
+    }
+
+    #[test]
+    fn derived_schema() {
+        let endpoint = Schema::record(vec![
+            Field::required("host", Schema::String),
+            Field::required("port", Schema::uint()),
+        ]);
+        assert_eq!(Endpoint::schema(), endpoint);
+        assert_eq!(
+            Config::schema(),
+            Schema::record(vec![
+                Field::required("name", Schema::String),
+                Field::required("weight", Schema::float()),
+                Field::required("retries", Schema::int()),
+                Field::required("tags", Schema::list(Schema::String)),
+                Field::required("primary", endpoint.clone()),
+                Field::optional("fallback", endpoint),
+            ])
+        );
+        assert_eq!(
+            Versioned::schema(),
+            Schema::record(vec![Field::required("version", Schema::uint())])
+        );
+
+        let c = Config {
+            name: String::from("svc"),
+            weight: 0.5,
+            retries: -1,
+            tags: Vec::new(),
+            primary: Endpoint {
+                host: String::from("h"),
+                port: 80,
+            },
+            fallback: None,
+        };
+        assert!(Config::schema().validate(&c.to_typed()).is_ok());
+        assert!(Config::schema()
+            .to_typescript("Config")
+            .contains("  fallback?: {\n    host: string;\n"));
+    }
+
+    #[test]
+    fn lenient() {
+        assert_eq!(u8::from_typed_lenient(&Typed::Int(7)).unwrap(), 7);
+        assert_eq!(i32::from_typed_lenient(&Typed::Float(-2.0)).unwrap(), -2);
+        assert!(i32::from_typed_lenient(&Typed::Float(2.5)).is_err());
+        assert!(u8::from_typed_lenient(&Typed::Int(-1)).is_err());
+        assert_eq!(f64::from_typed_lenient(&Typed::Uint(3)).unwrap(), 3.0);
+        assert!(bool::from_typed_lenient(&Typed::Int(1)).unwrap());
+        assert!(!bool::from_typed_lenient(&Typed::String("False".into())).unwrap());
+        assert!(bool::from_typed_lenient(&Typed::Int(2)).is_err());
+        assert!(bool::from_typed(&Typed::Int(1)).is_err());
+    }
+
+    #[test]
+    fn derived_lenient() {
+        let mut primary = Map::new();
+        primary.insert(String::from("host"), Typed::String("h".into()));
+        primary.insert(String::from("port"), Typed::String(" 80 ".into()));
+        let mut m = Map::new();
+        m.insert(String::from("name"), Typed::String("svc".into()));
+        m.insert(String::from("weight"), Typed::Int(1));
+        m.insert(String::from("retries"), Typed::Uint(3));
+        m.insert(
+            String::from("tags"),
+            Typed::List(vec![Typed::Int(1), Typed::Float(0.5)]),
+        );
+        m.insert(String::from("primary"), Typed::Map(primary));
+
+        assert!(Config::from_map(&m).is_err());
+        let c = Config::from_map_lenient(&m).unwrap();
+        assert_eq!(c.weight, 1.0);
+        assert_eq!(c.retries, 3);
+        assert_eq!(c.tags, vec![String::from("1"), String::from("0.5")]);
+        assert_eq!(c.primary.port, 80);
+    }
+
+    #[test]
+    fn derived_errors() {
+        let mut m = Endpoint {
+            host: String::from("localhost"),
+            port: 80,
+        }
+        .to_map();
+        m.insert(String::from("port"), Typed::Uint(65536));
+        let err = Endpoint::from_map(&m).unwrap_err();
+        assert!(err.to_string().contains("'port'"), "{}", err);
+
+        m.remove("host");
+        let err = Endpoint::from_map(&m).unwrap_err();
+        assert_eq!(err.to_string(), "missing field: 'host'");
+    }
+
+    #[test]
+    fn derived_extra() {
+        let mut m = Map::new();
+        m.insert(String::from("version"), Typed::Uint(2));
+        m.insert(String::from("added-in-v3"), Typed::String("x".into()));
+        let v = Versioned::from_map(&m).unwrap();
+        assert_eq!(v.version, 2);
+        assert_eq!(v.unknown.len(), 1);
+        assert_eq!(v.to_map(), m);
+
+        let mut v = Versioned::from_map_lenient(&m).unwrap();
+        v.unknown
+            .insert(String::from("version"), Typed::String("shadowed".into()));
+        assert_eq!(v.to_map().get("version"), Some(&Typed::Uint(2)));
+    }
+
+    #[test]
+    fn derived_attributes() {
+        let msg = Message {
+            kind: String::from("ping"),
+            retries: 3,
+            cached: vec![1],
+        };
+        let m = msg.to_map();
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.get("type"), Some(&Typed::String("ping".into())));
+
+        let mut buf = Vec::new();
+        msg.encode(&mut buf).unwrap();
+        let decoded = Message::decode(&buf[..]).unwrap();
+        assert_eq!(decoded.retries, 3);
+        assert!(decoded.cached.is_empty());
+
+        let mut m = Map::new();
+        m.insert(String::from("type"), Typed::String("pong".into()));
+        m.insert(String::from("cached"), Typed::Bytes(vec![1].into()));
+        let msg = Message::from_map(&m).unwrap();
+        assert_eq!((msg.retries, msg.cached.len()), (0, 0));
+        m.insert(String::from("retries"), Typed::Int(-1));
+        assert!(Message::from_map(&m).is_err());
+
+        assert_eq!(
+            Message::schema(),
+            Schema::record(vec![
+                Field::required("type", Schema::String),
+                Field::optional("retries", Schema::uint()),
+            ])
+        );
+        let err = Message::decode(&b"i\x00"[..]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid type: expected map, found int");
+    }
+}
@@ -0,0 +1,271 @@
+use crate::codec::Typed;
+use crate::path::{Path, PathSegment};
+use std::mem;
+
+fn child<'a>(e: &'a Typed, seg: &PathSegment) -> Option<&'a Typed> {
+    match (e, seg) {
+        (Typed::List(l), PathSegment::Index(i)) => l.get(*i),
+        (Typed::Map(m), PathSegment::Key(k)) => m.get(k),
+        _ => None,
+    }
+}
+
+fn child_mut<'a>(e: &'a mut Typed, seg: &PathSegment) -> Option<&'a mut Typed> {
+    match (e, seg) {
+        (Typed::List(l), PathSegment::Index(i)) => l.get_mut(*i),
+        (Typed::Map(m), PathSegment::Key(k)) => m.get_mut(k),
+        _ => None,
+    }
+}
+
+/// Cursor points at a value nested in a document, it moves between values
+/// and edits the one in focus in place.
+///
+/// The cursor only keeps the path of the focus, so it can move freely while
+/// holding the only mutable borrow of the document.
+pub struct Cursor<'a> {
+    root: &'a mut Typed,
+    path: Path,
+}
+
+impl<'a> Cursor<'a> {
+    /// Creates a cursor focused on `root`.
+    pub fn new(root: &'a mut Typed) -> Self {
+        Cursor {
+            root,
+            path: Path::root(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.path.is_root()
+    }
+
+    pub fn focus(&self) -> &Typed {
+        self.path
+            .segments()
+            .iter()
+            .fold(&*self.root, |e, seg| child(e, seg).unwrap())
+    }
+
+    pub fn focus_mut(&mut self) -> &mut Typed {
+        self.path
+            .segments()
+            .iter()
+            .fold(&mut *self.root, |e, seg| child_mut(e, seg).unwrap())
+    }
+
+    fn parent(&self) -> Option<&Typed> {
+        let segs = self.path.segments();
+        let (_, segs) = segs.split_last()?;
+        Some(
+            segs.iter()
+                .fold(&*self.root, |e, seg| child(e, seg).unwrap()),
+        )
+    }
+
+    fn parent_mut(&mut self) -> Option<&mut Typed> {
+        let segs = self.path.segments();
+        let (_, segs) = segs.split_last()?;
+        Some(
+            segs.iter()
+                .fold(&mut *self.root, |e, seg| child_mut(e, seg).unwrap()),
+        )
+    }
+
+    /// Moves into the element `i` of the list in focus, returns false and
+    /// stays if there is no such element.
+    pub fn down_index(&mut self, i: usize) -> bool {
+        self.down(PathSegment::Index(i))
+    }
+
+    /// Moves into the entry `key` of the map in focus, returns false and
+    /// stays if there is no such entry.
+    pub fn down_key(&mut self, key: &str) -> bool {
+        self.down(PathSegment::Key(key.to_string()))
+    }
+
+    fn down(&mut self, seg: PathSegment) -> bool {
+        if child(self.focus(), &seg).is_none() {
+            return false;
+        }
+        self.path.push(seg);
+        true
+    }
+
+    /// Moves to the list or map containing the focus, returns false at the
+    /// root.
+    pub fn up(&mut self) -> bool {
+        self.path.pop().is_some()
+    }
+
+    /// Moves back to the root.
+    pub fn top(&mut self) {
+        self.path = Path::root();
+    }
+
+    /// Moves to the next element of the parent list, or the entry with the
+    /// next key in order of the parent map.
+    pub fn next_sibling(&mut self) -> bool {
+        self.sibling(true)
+    }
+
+    /// Moves to the previous element of the parent list, or the entry with
+    /// the previous key in order of the parent map.
+    pub fn prev_sibling(&mut self) -> bool {
+        self.sibling(false)
+    }
+
+    fn sibling(&mut self, next: bool) -> bool {
+        let seg = match (self.parent(), self.path.segments().last()) {
+            (Some(Typed::List(l)), Some(PathSegment::Index(i))) => {
+                let i = if next {
+                    i.checked_add(1)
+                } else {
+                    i.checked_sub(1)
+                };
+                match i {
+                    Some(i) if i < l.len() => PathSegment::Index(i),
+                    _ => return false,
+                }
+            }
+            (Some(Typed::Map(m)), Some(PathSegment::Key(k))) => {
+                let keys = m
+                    .keys()
+                    .filter(|key| if next { *key > k } else { *key < k });
+                let key = if next { keys.min() } else { keys.max() };
+                match key {
+                    Some(key) => PathSegment::Key(key.clone()),
+                    None => return false,
+                }
+            }
+            _ => return false,
+        };
+        self.path.pop();
+        self.path.push(seg);
+        true
+    }
+
+    /// Replaces the value in focus, returns the previous one.
+    pub fn replace(&mut self, e: Typed) -> Typed {
+        mem::replace(self.focus_mut(), e)
+    }
+
+    /// Removes the value in focus from its parent and moves to the parent,
+    /// the root can't be removed.
+    pub fn remove(&mut self) -> Option<Typed> {
+        let seg = self.path.segments().last()?.clone();
+        let removed = match (self.parent_mut()?, &seg) {
+            (Typed::List(l), PathSegment::Index(i)) => l.remove(*i),
+            (Typed::Map(m), PathSegment::Key(k)) => m.remove(k)?,
+            _ => return None,
+        };
+        self.path.pop();
+        Some(removed)
+    }
+
+    /// Inserts an entry into the map in focus, returns the previous value.
+    /// Nothing is inserted if the focus is not a map.
+    pub fn insert(&mut self, key: &str, e: Typed) -> Option<Typed> {
+        match self.focus_mut() {
+            Typed::Map(m) => m.insert(key.to_string(), e),
+            _ => None,
+        }
+    }
+
+    /// Appends an element to the list in focus, returns false if the focus is
+    /// not a list.
+    pub fn push(&mut self, e: Typed) -> bool {
+        match self.focus_mut() {
+            Typed::List(l) => {
+                l.push(e);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+
+    fn doc() -> Typed {
+        let mut user = Map::new();
+        user.insert(String::from("name"), Typed::String("xd".into()));
+        user.insert(String::from("age"), Typed::Uint(18));
+        user.insert(
+            String::from("tags"),
+            Typed::List(vec![Typed::Int(1), Typed::Int(2), Typed::Int(3)]),
+        );
+        let mut m = Map::new();
+        m.insert(String::from("user"), Typed::Map(user));
+        Typed::Map(m)
+    }
+
+    #[test]
+    fn navigate() {
+        let mut e = doc();
+        let mut c = Cursor::new(&mut e);
+        assert!(!c.down_key("missing"));
+        assert!(c.down_key("user"));
+        assert!(c.down_key("age"));
+        assert!(!c.prev_sibling());
+        assert!(c.next_sibling());
+        assert_eq!(c.focus(), &Typed::String("xd".into()));
+        assert!(c.next_sibling());
+        assert!(!c.next_sibling());
+        assert!(c.down_index(1));
+        assert_eq!(c.path().to_string(), "user.tags[1]");
+        assert!(c.next_sibling());
+        assert_eq!(c.focus(), &Typed::Int(3));
+        assert!(!c.next_sibling());
+        assert!(c.prev_sibling() && c.prev_sibling());
+        assert!(!c.prev_sibling());
+        assert!(!c.down_index(0));
+        assert!(c.up() && c.up() && c.up());
+        assert!(!c.up());
+        assert!(c.is_root());
+    }
+
+    #[test]
+    fn edit() {
+        let mut e = doc();
+        let mut c = Cursor::new(&mut e);
+        c.down_key("user");
+        c.down_key("tags");
+        c.down_index(0);
+        assert_eq!(c.replace(Typed::Int(10)), Typed::Int(1));
+        c.next_sibling();
+        assert_eq!(c.remove(), Some(Typed::Int(2)));
+        assert_eq!(c.path().to_string(), "user.tags");
+        assert!(c.push(Typed::Int(4)));
+        c.up();
+        assert_eq!(c.insert("age", Typed::Uint(19)), Some(Typed::Uint(18)));
+        c.down_key("name");
+        assert!(!c.push(Typed::Int(0)));
+        c.top();
+        assert_eq!(c.remove(), None);
+
+        let mut expected = doc();
+        match &mut expected {
+            Typed::Map(m) => match m.get_mut("user") {
+                Some(Typed::Map(user)) => {
+                    user.insert(String::from("age"), Typed::Uint(19));
+                    user.insert(
+                        String::from("tags"),
+                        Typed::List(vec![Typed::Int(10), Typed::Int(3), Typed::Int(4)]),
+                    );
+                }
+                v => panic!("unexpected: {:?}", v),
+            },
+            v => panic!("unexpected: {:?}", v),
+        }
+        assert_eq!(e, expected);
+    }
+}
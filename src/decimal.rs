@@ -0,0 +1,176 @@
+use crate::codec::Typed;
+use crate::convert::{cannot_coerce, invalid_type, FromTyped, ToTyped};
+use crate::error::Error;
+use crate::schema::{Describe, Schema};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Decimal is an exact decimal number, `mantissa * 10^-scale`, e.g. 12.30
+/// is `Decimal::new(1230, 2)`. The scale is kept, so 12.3 and 12.30 are not
+/// equal, see `normalize`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    pub mantissa: i64,
+    pub scale: u32,
+}
+
+impl Decimal {
+    pub fn new(mantissa: i64, scale: u32) -> Self {
+        Decimal { mantissa, scale }
+    }
+
+    /// Removes the trailing zeros of the fraction, e.g. 12.30 becomes 12.3.
+    pub fn normalize(self) -> Self {
+        let mut d = self;
+        while d.scale > 0 && d.mantissa % 10 == 0 {
+            d.mantissa /= 10;
+            d.scale -= 1;
+        }
+        d
+    }
+
+    /// Returns the nearest float, which may not be exact.
+    pub fn to_f64(self) -> f64 {
+        // Scales past 400 underflow to zero anyway.
+        self.mantissa as f64 / 10f64.powi(self.scale.min(400) as i32)
+    }
+
+    pub(crate) fn from_wire(mantissa: i64, scale: u64) -> crate::Result<Self> {
+        let scale = u32::try_from(scale)
+            .map_err(|_| Error::InvalidData(format!("invalid decimal scale: {}", scale)))?;
+        Ok(Decimal { mantissa, scale })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.mantissa < 0 {
+            f.write_str("-")?;
+        }
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        if scale == 0 {
+            f.write_str(&digits)
+        } else if digits.len() > scale {
+            let (int, frac) = digits.split_at(digits.len() - scale);
+            write!(f, "{}.{}", int, frac)
+        } else {
+            write!(f, "0.{}{}", "0".repeat(scale - digits.len()), digits)
+        }
+    }
+}
+
+fn parse_decimal(s: &str) -> Option<Decimal> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (int, frac) = match s.find('.') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    };
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if int.is_empty() || !all_digits(int) || !all_digits(frac) || s.ends_with('.') {
+        return None;
+    }
+    let magnitude: u64 = format!("{}{}", int, frac).parse().ok()?;
+    let mantissa = if negative {
+        0i64.checked_sub_unsigned(magnitude)?
+    } else {
+        i64::try_from(magnitude).ok()?
+    };
+    Some(Decimal::new(mantissa, u32::try_from(frac.len()).ok()?))
+}
+
+/// Parses a decimal like "-12.30", the scale is the number of digits of the
+/// fraction. Exponents are not supported.
+impl FromStr for Decimal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        parse_decimal(s).ok_or_else(|| Error::InvalidData(format!("invalid decimal: {:?}", s)))
+    }
+}
+
+impl Describe for Decimal {
+    fn schema() -> Schema {
+        Schema::Decimal
+    }
+}
+
+impl ToTyped for Decimal {
+    fn to_typed(&self) -> Typed {
+        Typed::Decimal(*self)
+    }
+}
+
+/// The lenient conversion also accepts ints, uints which fit an i64 and
+/// strings.
+impl FromTyped for Decimal {
+    fn from_typed(e: &Typed) -> crate::Result<Self> {
+        match e {
+            Typed::Decimal(d) => Ok(*d),
+            _ => Err(invalid_type(e, "decimal")),
+        }
+    }
+
+    fn from_typed_lenient(e: &Typed) -> crate::Result<Self> {
+        let d = match e {
+            Typed::Decimal(d) => Some(*d),
+            Typed::Int(n) => Some(Decimal::new(*n, 0)),
+            Typed::Uint(un) => i64::try_from(*un).ok().map(|n| Decimal::new(n, 0)),
+            Typed::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        d.ok_or_else(|| cannot_coerce(e, "decimal"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text() {
+        for (d, s) in [
+            (Decimal::new(1230, 2), "12.30"),
+            (Decimal::new(-5, 3), "-0.005"),
+            (Decimal::new(-12, 2), "-0.12"),
+            (Decimal::new(7, 0), "7"),
+            (Decimal::new(0, 1), "0.0"),
+            (Decimal::new(i64::MIN, 18), "-9.223372036854775808"),
+        ]
+        .iter()
+        {
+            assert_eq!(d.to_string(), *s);
+            assert_eq!(s.parse::<Decimal>().ok(), Some(*d));
+        }
+        assert_eq!("+.5".parse::<Decimal>().ok(), None);
+        assert_eq!("+0.5".parse::<Decimal>().ok(), Some(Decimal::new(5, 1)));
+        for s in ["", "-", "1.", "1e3", "1.2.3", "9223372036854775808"].iter() {
+            assert!(s.parse::<Decimal>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let d = Decimal::new(1230, 2);
+        assert_eq!(d.normalize(), Decimal::new(123, 1));
+        assert_eq!(Decimal::new(0, 3).normalize(), Decimal::new(0, 0));
+        assert_eq!(d.to_f64(), 12.3);
+        assert!(Decimal::from_wire(1, u64::MAX).is_err());
+
+        assert_eq!(Decimal::from_typed(&d.to_typed()).unwrap(), d);
+        assert!(Decimal::from_typed(&Typed::Int(1)).is_err());
+        assert_eq!(
+            Decimal::from_typed_lenient(&Typed::String("12.30".into())).unwrap(),
+            d
+        );
+        assert_eq!(
+            Decimal::from_typed_lenient(&Typed::Uint(3)).unwrap(),
+            Decimal::new(3, 0)
+        );
+        assert!(Decimal::from_typed_lenient(&Typed::Uint(u64::MAX)).is_err());
+    }
+}
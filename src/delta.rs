@@ -0,0 +1,322 @@
+use crate::codec::{
+    base_tag, container_capacity, CodecReadExt, CodecWriteExt, TYPE_LIST, TYPE_MAP,
+};
+use crate::error::{Error, Result};
+use crate::layer::crc32;
+use crate::limits::{Budget, Limits};
+use crate::slice::SliceReader;
+use crate::varint::{VarintReadExt, VarintWriteExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::ops::Range;
+
+const OP_COPY: u8 = b'c';
+const OP_INSERT: u8 = b'i';
+
+// Copying fewer bytes costs more than inserting them.
+const MIN_COPY: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Copy { offset: u64, len: u64 },
+    Insert(Vec<u8>),
+}
+
+/// Delta turns an encoded document into another one, see `delta`. It only
+/// applies to the exact document it was made from, which is checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    old_len: u64,
+    old_crc: u32,
+    ops: Vec<Op>,
+}
+
+impl Delta {
+    /// Encodes the delta: the length and the CRC32 of the old document
+    /// followed by the operations, either `'c'` with the offset and length
+    /// of bytes copied from the old document, or `'i'` with bytes inserted.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_uvarint(self.old_len).unwrap();
+        buf.write_u32::<LittleEndian>(self.old_crc).unwrap();
+        for op in self.ops.iter() {
+            match op {
+                Op::Copy { offset, len } => {
+                    buf.push(OP_COPY);
+                    buf.write_uvarint(*offset).unwrap();
+                    buf.write_uvarint(*len).unwrap();
+                }
+                Op::Insert(bytes) => {
+                    buf.push(OP_INSERT);
+                    buf.write_sized(bytes).unwrap();
+                }
+            }
+        }
+        buf
+    }
+
+    pub fn from_bytes(mut buf: &[u8]) -> Result<Delta> {
+        let old_len = buf.read_uvarint()?;
+        let old_crc = buf.read_u32::<LittleEndian>()?;
+        let mut ops = Vec::new();
+        while !buf.is_empty() {
+            let op = match buf.read_u8()? {
+                OP_COPY => Op::Copy {
+                    offset: buf.read_uvarint()?,
+                    len: buf.read_uvarint()?,
+                },
+                OP_INSERT => Op::Insert(buf.read_sized()?),
+                op => {
+                    return Err(Error::InvalidData(format!(
+                        "unknown delta operation: '{}'",
+                        op
+                    )))
+                }
+            };
+            ops.push(op);
+        }
+        Ok(Delta {
+            old_len,
+            old_crc,
+            ops,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    ops: Vec<Op>,
+}
+
+impl Builder {
+    fn copy(&mut self, old: &[u8], r: Range<usize>) {
+        if r.len() < MIN_COPY {
+            return self.insert(&old[r]);
+        }
+        if let Some(Op::Copy { offset, len }) = self.ops.last_mut() {
+            if *offset + *len == r.start as u64 {
+                *len += r.len() as u64;
+                return;
+            }
+        }
+        self.ops.push(Op::Copy {
+            offset: r.start as u64,
+            len: r.len() as u64,
+        });
+    }
+
+    fn insert(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        if let Some(Op::Insert(buf)) = self.ops.last_mut() {
+            return buf.extend_from_slice(bytes);
+        }
+        self.ops.push(Op::Insert(bytes.to_vec()));
+    }
+}
+
+struct Entry {
+    // The encoded key of a map entry.
+    key: Option<Range<usize>>,
+    value: Range<usize>,
+}
+
+// Returns the end of the tag and the number of elements of the list or map
+// at `start`, and its entries, the offsets are relative to `buf`.
+fn entries(buf: &[u8], start: usize, is_map: bool) -> Result<(usize, Vec<Entry>)> {
+    let mut r = SliceReader::new(&buf[start..]);
+    let t = r.read_u8()?;
+    let n = r.read_container_len(t)?;
+    let header_end = start + r.offset();
+    let mut entries = Vec::with_capacity(container_capacity(n));
+    for _ in 0..n {
+        let key = if is_map {
+            let key_start = start + r.offset();
+            r.read_sized()?;
+            Some(key_start..start + r.offset())
+        } else {
+            None
+        };
+        let value_start = start + r.offset();
+        r.skip_value()?;
+        entries.push(Entry {
+            key,
+            value: value_start..start + r.offset(),
+        });
+    }
+    Ok((header_end, entries))
+}
+
+fn diff(old: &[u8], o: Range<usize>, new: &[u8], n: Range<usize>, b: &mut Builder) -> Result<()> {
+    if old[o.clone()] == new[n.clone()] {
+        b.copy(old, o);
+        return Ok(());
+    }
+    let t = new[n.start];
+    if old[o.start] != t || (base_tag(t) != TYPE_LIST && base_tag(t) != TYPE_MAP) {
+        b.insert(&new[n]);
+        return Ok(());
+    }
+
+    let is_map = base_tag(t) == TYPE_MAP;
+    let (_, old_entries) = entries(old, o.start, is_map)?;
+    let (header_end, new_entries) = entries(new, n.start, is_map)?;
+    // The tag and the number of elements.
+    b.insert(&new[n.start..header_end]);
+    for (i, e) in new_entries.into_iter().enumerate() {
+        let matched = match &e.key {
+            Some(key) => old_entries.iter().find(|oe| {
+                oe.key
+                    .as_ref()
+                    .is_some_and(|k| old[k.clone()] == new[key.clone()])
+            }),
+            None => old_entries.get(i),
+        };
+        match matched {
+            Some(oe) => {
+                if let Some(key) = &oe.key {
+                    b.copy(old, key.clone());
+                }
+                diff(old, oe.value.clone(), new, e.value, b)?;
+            }
+            None => {
+                let start = e.key.map_or(e.value.start, |k| k.start);
+                b.insert(&new[start..e.value.end]);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn value_range(buf: &[u8], limits: &Limits) -> Result<Range<usize>> {
+    let mut r = SliceReader::new(buf);
+    r.skip_value_with(&mut Budget::new(limits))?;
+    if !r.remaining().is_empty() {
+        return Err(Error::TrailingBytes);
+    }
+    Ok(0..buf.len())
+}
+
+/// Computes the delta which turns the encoded document `old` into `new`.
+/// The documents are aligned by their structure, the map entries by their
+/// keys and the list elements by their indexes, so the unchanged values are
+/// copied from `old` rather than sent. Documents which exceed the default
+/// `Limits` are rejected.
+pub fn delta(old: &[u8], new: &[u8]) -> Result<Delta> {
+    delta_with(old, new, &Limits::default())
+}
+
+/// Computes the delta like `delta`, documents which exceed `limits` are
+/// rejected. The documents are walked recursively, so `Limits::max_depth`
+/// bounds the stack it takes.
+pub fn delta_with(old: &[u8], new: &[u8], limits: &Limits) -> Result<Delta> {
+    let mut b = Builder::default();
+    let (o, n) = (value_range(old, limits)?, value_range(new, limits)?);
+    diff(old, o, new, n, &mut b)?;
+    Ok(Delta {
+        old_len: old.len() as u64,
+        old_crc: crc32(old),
+        ops: b.ops,
+    })
+}
+
+/// Applies the delta to the document it was made from.
+pub fn apply_delta(old: &[u8], d: &Delta) -> Result<Vec<u8>> {
+    if old.len() as u64 != d.old_len || crc32(old) != d.old_crc {
+        return Err(Error::InvalidInput(
+            "the delta was made from another document".into(),
+        ));
+    }
+    let mut buf = Vec::new();
+    for op in d.ops.iter() {
+        match op {
+            Op::Copy { offset, len } => {
+                let r = offset
+                    .checked_add(*len)
+                    .filter(|end| *end <= old.len() as u64)
+                    .map(|end| *offset as usize..end as usize)
+                    .ok_or_else(|| Error::InvalidData("copy out of the old document".into()))?;
+                buf.extend_from_slice(&old[r]);
+            }
+            Op::Insert(bytes) => buf.extend_from_slice(bytes),
+        }
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{Map, Typed};
+    use crate::config::FormatVersion;
+
+    fn encode(e: &Typed) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_typed(e).unwrap();
+        buf
+    }
+
+    fn doc(n: usize, extra: bool) -> Typed {
+        let mut m = Map::new();
+        for i in 0..20 {
+            m.insert(format!("key-{}", i), Typed::String("x".repeat(64).into()));
+        }
+        m.insert(
+            String::from("list"),
+            Typed::List((0..n).map(|i| Typed::Uint(i as u64 * 1000)).collect()),
+        );
+        if extra {
+            m.insert(String::from("extra"), Typed::Int(-1));
+        }
+        Typed::Map(m)
+    }
+
+    #[test]
+    fn roundtrip() {
+        let old = encode(&doc(5, false));
+        for new in [doc(5, true), doc(6, false), doc(2, true), Typed::Int(1)].iter() {
+            let new = encode(new);
+            let d = Delta::from_bytes(&delta(&old, &new).unwrap().to_bytes()).unwrap();
+            assert_eq!(apply_delta(&old, &d).unwrap(), new);
+        }
+
+        // Only the changes are inserted.
+        let new = encode(&doc(6, true));
+        let d = delta(&old, &new).unwrap();
+        assert!(
+            d.to_bytes().len() * 10 < new.len(),
+            "{}",
+            d.to_bytes().len()
+        );
+
+        assert!(apply_delta(&new, &d).is_err());
+        assert!(delta(&old[1..], &new).is_err());
+        assert!(Delta::from_bytes(b"\x00\x00\x00\x00\x00x").is_err());
+    }
+
+    #[test]
+    fn v2_containers() {
+        let encode_v2 = |e: &Typed| {
+            let mut buf = Vec::new();
+            buf.write_typed_version(e, FormatVersion::V2).unwrap();
+            buf
+        };
+        let list = |changed: u64| {
+            let mut l: Vec<_> = (0..300)
+                .map(|i| Typed::String(format!("item-{}", i).into()))
+                .collect();
+            l[150] = Typed::Uint(changed);
+            Typed::List(l)
+        };
+        let mut m = Map::new();
+        m.insert(String::from("list"), list(1));
+        let old = encode_v2(&Typed::Map(m.clone()));
+        m.insert(String::from("list"), list(2));
+        let new = encode_v2(&Typed::Map(m));
+
+        let d = delta(&old, &new).unwrap();
+        assert_eq!(apply_delta(&old, &d).unwrap(), new);
+        // Only the changed element is inserted.
+        assert!(d.to_bytes().len() < 32, "{}", d.to_bytes().len());
+    }
+}
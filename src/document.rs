@@ -0,0 +1,377 @@
+use crate::array::PackedArray;
+use crate::codec::{
+    base_tag, CodecReadExt, Typed, TYPE_ARRAY, TYPE_BYTES, TYPE_BYTES_DEFLATE, TYPE_DECIMAL,
+    TYPE_FALSE, TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT, TYPE_INT_FIXED, TYPE_LIST, TYPE_MAP,
+    TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE, TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT,
+    TYPE_UINT_FIXED,
+};
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::limits::{Budget, Limits};
+use crate::path::{Path, PathSegment};
+use crate::slice::SliceReader;
+use crate::timestamp::Timestamp;
+use std::borrow::Cow;
+use std::str;
+
+/// Document owns an encoded value, e.g. in a `Vec<u8>` or `bytes::Bytes`,
+/// and hands out lazy views into it, nothing is decoded until asked for.
+#[derive(Debug, Clone)]
+pub struct Document<B = Vec<u8>> {
+    buf: B,
+}
+
+impl<B: AsRef<[u8]>> Document<B> {
+    /// Wraps `buf`, which must hold exactly one well-formed value within
+    /// the default `Limits`.
+    pub fn new(buf: B) -> Result<Self> {
+        Self::with_limits(buf, &Limits::default())
+    }
+
+    /// Wraps `buf` like `new`, the value is rejected if it exceeds
+    /// `limits`.
+    pub fn with_limits(buf: B, limits: &Limits) -> Result<Self> {
+        let mut r = SliceReader::new(buf.as_ref());
+        r.skip_value_with(&mut Budget::new(limits))?;
+        if !r.remaining().is_empty() {
+            return Err(Error::TrailingBytes);
+        }
+        Ok(Document { buf })
+    }
+
+    pub fn root(&self) -> ValueRef<'_> {
+        ValueRef {
+            buf: self.buf.as_ref(),
+        }
+    }
+
+    /// Looks up the value at `path`, see `ValueRef::get_path`.
+    pub fn get_path(&self, path: &Path) -> Option<ValueRef<'_>> {
+        self.root().get_path(path)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf.as_ref()
+    }
+
+    pub fn into_inner(self) -> B {
+        self.buf
+    }
+}
+
+/// ValueRef is a view of an encoded value borrowed from a `Document`, its
+/// accessors return None if the value is of another type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueRef<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> ValueRef<'a> {
+    // Lists and maps have the same tags in both format versions.
+    fn tag(&self) -> u8 {
+        base_tag(self.buf[0])
+    }
+
+    fn container_len(&self) -> Option<u64> {
+        self.body().read_container_len(self.buf[0]).ok()
+    }
+
+    // A reader positioned after the tag.
+    fn body(&self) -> SliceReader<'a> {
+        SliceReader::new(&self.buf[1..])
+    }
+
+    /// Returns the name of the type: "null", "bool", "int", "uint", "float",
+    /// "timestamp", "decimal", "bytes", "string", "array", "list" or "map".
+    pub fn type_name(&self) -> &'static str {
+        match self.tag() {
+            TYPE_NULL => "null",
+            TYPE_FALSE | TYPE_TRUE => "bool",
+            TYPE_INT | TYPE_INT_FIXED => "int",
+            TYPE_UINT | TYPE_UINT_FIXED => "uint",
+            TYPE_FLOAT | TYPE_FLOAT_FIXED => "float",
+            TYPE_TIMESTAMP => "timestamp",
+            TYPE_DECIMAL => "decimal",
+            TYPE_BYTES | TYPE_BYTES_DEFLATE => "bytes",
+            TYPE_STRING | TYPE_STRING_DEFLATE => "string",
+            TYPE_ARRAY => "array",
+            TYPE_LIST => "list",
+            _ => "map",
+        }
+    }
+
+    /// Returns the encoded bytes of the value.
+    pub fn encoded(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    /// Decodes the value.
+    pub fn to_typed(&self) -> Result<Typed> {
+        (&self.buf[..]).read_typed()
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.tag() == TYPE_NULL
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.tag() {
+            TYPE_FALSE => Some(false),
+            TYPE_TRUE => Some(true),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self.tag() {
+            TYPE_INT => self.body().read_varint().ok(),
+            TYPE_INT_FIXED => self.body().read_fixed().ok().map(i64::from_le_bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_uint(&self) -> Option<u64> {
+        match self.tag() {
+            TYPE_UINT => self.body().read_uvarint().ok(),
+            TYPE_UINT_FIXED => self.body().read_fixed().ok().map(u64::from_le_bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self.tag() {
+            TYPE_FLOAT => self.body().read_uvarint().ok().map(f64::from_bits),
+            TYPE_FLOAT_FIXED => self.body().read_fixed().ok().map(f64::from_le_bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<Timestamp> {
+        match self.tag() {
+            TYPE_TIMESTAMP => {
+                let mut r = self.body();
+                let secs = r.read_varint().ok()?;
+                Timestamp::from_wire(secs, r.read_uvarint().ok()?).ok()
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self.tag() {
+            TYPE_DECIMAL => {
+                let mut r = self.body();
+                let mantissa = r.read_varint().ok()?;
+                Decimal::from_wire(mantissa, r.read_uvarint().ok()?).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a copy of the packed array.
+    pub fn as_array(&self) -> Option<PackedArray> {
+        match self.tag() {
+            TYPE_ARRAY => self.body().read_array().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self.tag() {
+            TYPE_BYTES => self.body().read_sized().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the string without copying it, None if it is not valid UTF-8
+    /// as well.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self.tag() {
+            TYPE_STRING => self
+                .body()
+                .read_sized()
+                .ok()
+                .and_then(|buf| str::from_utf8(buf).ok()),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of elements of a list or map.
+    pub fn len(&self) -> Option<usize> {
+        match self.tag() {
+            TYPE_LIST | TYPE_MAP => self.container_len().map(|n| n as usize),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the value is an empty list or map.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// Iterates over the elements of a list, it is empty for other types.
+    pub fn elements(&self) -> Elements<'a> {
+        let mut r = self.body();
+        let remaining = match self.tag() {
+            TYPE_LIST => r.read_container_len(self.buf[0]).unwrap_or(0),
+            _ => 0,
+        };
+        Elements { r, remaining }
+    }
+
+    /// Iterates over the entries of a map, it is empty for other types.
+    pub fn entries(&self) -> Entries<'a> {
+        let mut r = self.body();
+        let remaining = match self.tag() {
+            TYPE_MAP => r.read_container_len(self.buf[0]).unwrap_or(0),
+            _ => 0,
+        };
+        Entries { r, remaining }
+    }
+
+    /// Returns the element `i` of a list, the preceding elements are
+    /// skipped rather than decoded.
+    pub fn index(&self, i: usize) -> Option<ValueRef<'a>> {
+        self.elements().nth(i)
+    }
+
+    /// Returns the value of `key` in a map, the other entries are skipped
+    /// rather than decoded.
+    pub fn get(&self, key: &str) -> Option<ValueRef<'a>> {
+        self.entries().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Looks up the value at `path`.
+    pub fn get_path(&self, path: &Path) -> Option<ValueRef<'a>> {
+        let mut v = *self;
+        for seg in path.segments() {
+            v = match seg {
+                PathSegment::Key(k) => v.get(k)?,
+                PathSegment::Index(i) => v.index(*i)?,
+            };
+        }
+        Some(v)
+    }
+}
+
+/// Elements iterates over the elements of a list, see `ValueRef::elements`.
+pub struct Elements<'a> {
+    r: SliceReader<'a>,
+    remaining: u64,
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = ValueRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let buf = self.r.skip_value().ok()?;
+        Some(ValueRef { buf })
+    }
+}
+
+/// Entries iterates over the entries of a map, see `ValueRef::entries`.
+pub struct Entries<'a> {
+    r: SliceReader<'a>,
+    remaining: u64,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (Cow<'a, str>, ValueRef<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let k = self.r.read_sized().ok()?;
+        let buf = self.r.skip_value().ok()?;
+        Some((String::from_utf8_lossy(k), ValueRef { buf }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecWriteExt, Map};
+
+    fn encoded() -> Vec<u8> {
+        let mut user = Map::new();
+        user.insert(String::from("name"), Typed::String("xd".into()));
+        user.insert(String::from("avatar"), Typed::Bytes(vec![1u8, 2u8].into()));
+        let mut m = Map::new();
+        m.insert(
+            String::from("users"),
+            Typed::List(vec![Typed::Int(-1), Typed::Map(user)]),
+        );
+        m.insert(String::from("total"), Typed::Uint(2));
+        m.insert(String::from("ratio"), Typed::Float(0.5));
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::Map(m)).unwrap();
+        buf
+    }
+
+    #[test]
+    fn views() {
+        let doc = Document::new(encoded()).unwrap();
+        let root = doc.root();
+        assert_eq!(root.type_name(), "map");
+        assert_eq!(root.len(), Some(3));
+        assert_eq!(root.get("total").and_then(|v| v.as_uint()), Some(2));
+        assert_eq!(root.get("ratio").and_then(|v| v.as_float()), Some(0.5));
+        assert!(root.get("missing").is_none());
+        assert!(root.get("total").and_then(|v| v.as_int()).is_none());
+
+        let users = root.get("users").unwrap();
+        assert_eq!(users.elements().count(), 2);
+        assert_eq!(users.index(0).and_then(|v| v.as_int()), Some(-1));
+        assert!(users.index(2).is_none());
+
+        let path = Path::root().key("users").index(1).key("name");
+        let name: &str = doc.get_path(&path).and_then(|v| v.as_str()).unwrap();
+        assert_eq!(name, "xd");
+        let path = Path::root().key("users").index(1).key("avatar");
+        assert_eq!(
+            doc.get_path(&path).and_then(|v| v.as_bytes()),
+            Some(&[1u8, 2u8][..])
+        );
+        assert_eq!(
+            users.index(1).unwrap().to_typed().unwrap(),
+            (&users.index(1).unwrap().encoded()[..])
+                .read_typed()
+                .unwrap()
+        );
+        assert_eq!(
+            root.to_typed().unwrap(),
+            (&doc.as_bytes()[..]).read_typed().unwrap()
+        );
+    }
+
+    #[test]
+    fn format_v2() {
+        let e = Typed::List(vec![Typed::Uint(7); 300]);
+        let mut buf = Vec::new();
+        buf.write_typed_version(&e, crate::FormatVersion::V2)
+            .unwrap();
+        let doc = Document::new(buf).unwrap();
+        let root = doc.root();
+        assert_eq!(root.type_name(), "list");
+        assert_eq!(root.len(), Some(300));
+        assert_eq!(root.index(299).and_then(|v| v.as_uint()), Some(7));
+        assert_eq!(root.to_typed().unwrap(), e);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut buf = encoded();
+        buf.push(0);
+        assert!(Document::new(&buf[..]).is_err());
+        buf.pop();
+        buf.pop();
+        assert!(Document::new(&buf[..]).is_err());
+        assert!(Document::new(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::codec::{
+    Typed, CONTAINER_CAPACITY, TYPE_ARRAY, TYPE_BYTES, TYPE_DECIMAL, TYPE_FALSE, TYPE_FLOAT,
+    TYPE_INT, TYPE_LIST, TYPE_MAP, TYPE_NULL, TYPE_STRING, TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT,
+};
+use crate::error::{Error, Result};
+use crate::varint::VarintWriteExt;
+use std::collections::hash_map;
+use std::io::{self, Read};
+use std::slice;
+
+enum Frame<'a> {
+    Value(&'a Typed),
+    List(slice::Iter<'a, Typed>),
+    Map(hash_map::Iter<'a, String, Typed>),
+}
+
+/// EncodedValueReader encodes a value lazily as it is read, only the small
+/// headers are buffered, strings and bytes are copied straight from the
+/// value into the output, so a large value can be streamed into a file or a
+/// request body without holding its whole encoding in memory.
+pub struct EncodedValueReader<'a> {
+    stack: Vec<Frame<'a>>,
+    header: Vec<u8>,
+    pos: usize,
+    data: &'a [u8],
+    err: Option<io::Error>,
+}
+
+impl<'a> EncodedValueReader<'a> {
+    pub fn new(e: &'a Typed) -> Self {
+        EncodedValueReader {
+            stack: vec![Frame::Value(e)],
+            header: Vec::new(),
+            pos: 0,
+            data: &[],
+            err: None,
+        }
+    }
+
+    // Produces the next header and/or data, returns false once the whole
+    // value has been produced.
+    fn fill(&mut self) -> Result<bool> {
+        self.header.clear();
+        self.pos = 0;
+        loop {
+            let e = match self.stack.last_mut() {
+                None => return Ok(false),
+                Some(Frame::Value(e)) => {
+                    let e = *e;
+                    self.stack.pop();
+                    e
+                }
+                Some(Frame::List(iter)) => match iter.next() {
+                    Some(e) => e,
+                    None => {
+                        self.stack.pop();
+                        continue;
+                    }
+                },
+                Some(Frame::Map(iter)) => match iter.next() {
+                    Some((k, v)) => {
+                        self.header.write_uvarint(k.len() as u64)?;
+                        self.data = k.as_bytes();
+                        self.stack.push(Frame::Value(v));
+                        return Ok(true);
+                    }
+                    None => {
+                        self.stack.pop();
+                        continue;
+                    }
+                },
+            };
+            self.encode(e)?;
+            return Ok(true);
+        }
+    }
+
+    fn encode(&mut self, e: &'a Typed) -> Result<()> {
+        let header = &mut self.header;
+        match e {
+            Typed::Null => {
+                header.push(TYPE_NULL);
+                Ok(())
+            }
+            Typed::Bool(b) => {
+                header.push(if *b { TYPE_TRUE } else { TYPE_FALSE });
+                Ok(())
+            }
+            Typed::Int(n) => {
+                header.push(TYPE_INT);
+                header.write_varint(*n)
+            }
+            Typed::Uint(un) => {
+                header.push(TYPE_UINT);
+                header.write_uvarint(*un)
+            }
+            Typed::Float(f) => {
+                header.push(TYPE_FLOAT);
+                header.write_uvarint(f.to_bits())
+            }
+            Typed::Timestamp(ts) => {
+                header.push(TYPE_TIMESTAMP);
+                header.write_varint(ts.secs())?;
+                header.write_uvarint(ts.subsec_nanos().into())
+            }
+            Typed::Decimal(d) => {
+                header.push(TYPE_DECIMAL);
+                header.write_varint(d.mantissa)?;
+                header.write_uvarint(d.scale.into())
+            }
+            Typed::Bytes(buf) => {
+                header.push(TYPE_BYTES);
+                header.write_uvarint(buf.len() as u64)?;
+                self.data = &buf[..];
+                Ok(())
+            }
+            Typed::String(s) => {
+                header.push(TYPE_STRING);
+                header.write_uvarint(s.len() as u64)?;
+                self.data = s.as_bytes();
+                Ok(())
+            }
+            Typed::List(l) => {
+                check_len(l.len())?;
+                header.push(TYPE_LIST);
+                header.push(l.len() as u8);
+                self.stack.push(Frame::List(l.iter()));
+                Ok(())
+            }
+            Typed::Map(m) => {
+                check_len(m.len())?;
+                header.push(TYPE_MAP);
+                header.push(m.len() as u8);
+                self.stack.push(Frame::Map(m.iter()));
+                Ok(())
+            }
+            Typed::Array(a) => {
+                header.push(TYPE_ARRAY);
+                header.push(a.dtype().code());
+                header.write_uvarint(a.shape().len() as u64)?;
+                for dim in a.shape() {
+                    header.write_uvarint(*dim as u64)?;
+                }
+                header.write_uvarint(a.as_bytes().len() as u64)?;
+                self.data = a.as_bytes();
+                Ok(())
+            }
+            Typed::Raw(raw) => {
+                self.data = raw.as_bytes();
+                Ok(())
+            }
+        }
+    }
+}
+
+fn check_len(nelem: usize) -> Result<()> {
+    if nelem >= CONTAINER_CAPACITY {
+        return Err(Error::InvalidInput(
+            "containers can only contain 254 elements".into(),
+        ));
+    }
+    Ok(())
+}
+
+impl Read for EncodedValueReader<'_> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if let Some(err) = self.err.take() {
+            return Err(err);
+        }
+        let mut n = 0;
+        while n < out.len() {
+            if self.pos < self.header.len() {
+                let m = (self.header.len() - self.pos).min(out.len() - n);
+                out[n..n + m].copy_from_slice(&self.header[self.pos..self.pos + m]);
+                self.pos += m;
+                n += m;
+            } else if !self.data.is_empty() {
+                let m = self.data.len().min(out.len() - n);
+                out[n..n + m].copy_from_slice(&self.data[..m]);
+                self.data = &self.data[m..];
+                n += m;
+            } else {
+                match self.fill() {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(err) => {
+                        let err = io::Error::from(err);
+                        // The value can't be encoded any further, the error
+                        // is returned once, after the bytes read so far.
+                        self.stack.clear();
+                        if n == 0 {
+                            return Err(err);
+                        }
+                        self.err = Some(err);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecWriteExt, Map};
+    use crate::raw::RawValue;
+
+    #[test]
+    fn lazy() {
+        let mut m = Map::new();
+        m.insert(String::from("blob"), Typed::Bytes(vec![7u8; 4096].into()));
+        m.insert(String::from("name"), Typed::String("超".into()));
+        m.insert(String::from("empty"), Typed::Map(Map::new()));
+        let e = Typed::List(vec![
+            Typed::Map(m),
+            Typed::Int(-1),
+            Typed::Uint(u64::MAX),
+            Typed::Float(1.5),
+            Typed::List(vec![]),
+            Typed::Raw(RawValue::encode(&Typed::Int(3)).unwrap()),
+        ]);
+        let mut expected = Vec::new();
+        expected.write_typed(&e).unwrap();
+
+        for size in [1, 3, 64, 8192].iter() {
+            let mut r = EncodedValueReader::new(&e);
+            let mut out = Vec::new();
+            let mut chunk = vec![0u8; *size];
+            loop {
+                let n = r.read(&mut chunk).unwrap();
+                if n == 0 {
+                    break;
+                }
+                out.extend_from_slice(&chunk[..n]);
+            }
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn too_many_elements() {
+        let e = Typed::List(vec![
+            Typed::Int(1),
+            Typed::List(vec![Typed::Int(0); CONTAINER_CAPACITY]),
+        ]);
+        let mut out = Vec::new();
+        let err = EncodedValueReader::new(&e)
+            .read_to_end(&mut out)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let mut r = EncodedValueReader::new(&e);
+        let mut chunk = [0u8; 16];
+        assert_eq!(r.read(&mut chunk).unwrap(), 4);
+        assert!(r.read(&mut chunk).is_err());
+        assert_eq!(r.read(&mut chunk).unwrap(), 0);
+    }
+}
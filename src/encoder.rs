@@ -0,0 +1,308 @@
+use crate::codec::{read_sized_limited, CodecReadExt, CodecWriteExt, Typed};
+use crate::config::{write_configured, Config};
+use crate::error::Result;
+use crate::layer::{Crc32, Layer};
+use crate::limits::Budget;
+use crate::options::DecodeOptions;
+use crate::trace::{read_traced, Trace, TraceEvent};
+use std::io::{Read, Write};
+
+/// Encoder writes values as length prefixed messages, each message is
+/// passed through the layers added with `with`.
+pub struct Encoder<W> {
+    writer: W,
+    layers: Vec<Box<dyn Layer>>,
+    // Only the settings of writing values are used.
+    config: Config,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W) -> Self {
+        Encoder {
+            writer,
+            layers: Vec::new(),
+            config: Config::default(),
+        }
+    }
+
+    /// Creates an encoder which writes messages in `Config::format_version`,
+    /// canonical ones if `Config::canonical` is set, and checksums them with
+    /// a `Crc32` layer if `Config::checksum` is set. The values are
+    /// compressed as `Config::compression` tells.
+    pub fn with_config(writer: W, config: &Config) -> Self {
+        let mut enc = Encoder::new(writer);
+        enc.config = config.clone();
+        if config.checksum {
+            enc = enc.with(Crc32);
+        }
+        enc
+    }
+
+    /// Adds a layer on top of the previous ones.
+    pub fn with<L: Layer + 'static>(mut self, layer: L) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    pub fn encode(&mut self, e: &Typed) -> Result<()> {
+        let mut buf = Vec::new();
+        write_configured(&mut buf, e, &self.config)?;
+        for layer in self.layers.iter_mut() {
+            buf = layer.encode(buf)?;
+        }
+        self.writer.write_sized(&buf)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Decoder reads the messages written by an `Encoder` with the same layers.
+pub struct Decoder<R> {
+    reader: R,
+    layers: Vec<Box<dyn Layer>>,
+    trace: Option<Trace>,
+    opts: DecodeOptions,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R) -> Self {
+        Decoder {
+            reader,
+            layers: Vec::new(),
+            trace: None,
+            opts: DecodeOptions::default(),
+        }
+    }
+
+    /// Creates a decoder for the messages written by
+    /// `Encoder::with_config`, the messages are decoded with the options of
+    /// the config and rejected if they exceed its limits. A message longer
+    /// than `Limits::max_bytes_len` is rejected before it is read.
+    pub fn with_config(reader: R, config: &Config) -> Self {
+        let mut dec = Decoder::new(reader);
+        dec.opts = config.decode_options.clone();
+        if config.checksum {
+            dec = dec.with(Crc32);
+        }
+        dec
+    }
+
+    /// Adds a layer on top of the previous ones, which is reverted before
+    /// them.
+    pub fn with<L: Layer + 'static>(mut self, layer: L) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Whether to record how each message is decoded, see `last_trace`.
+    /// It is meant for diagnosing interop issues, as it slows decoding down.
+    pub fn trace(mut self, yes: bool) -> Self {
+        self.trace = if yes { Some(Trace::default()) } else { None };
+        self
+    }
+
+    /// Returns the trace of the last message, whether it was decoded or
+    /// not.
+    pub fn last_trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    // Reads the next message, which is rejected before it is read if it is
+    // longer than `Limits::max_bytes_len`.
+    fn read_message(&mut self) -> Result<Vec<u8>> {
+        read_sized_limited(&mut self.reader, &Budget::new(&self.opts.limits))
+    }
+
+    pub fn decode(&mut self) -> Result<Typed> {
+        let msg = self.read_message();
+        let trace = match self.trace.as_mut() {
+            Some(trace) => trace,
+            None => {
+                let mut buf = msg?;
+                for layer in self.layers.iter_mut().rev() {
+                    buf = layer.decode(buf)?;
+                }
+                return (&buf[..]).read_typed_with(&self.opts);
+            }
+        };
+
+        trace.clear();
+        let mut buf = trace.check(0, 0, msg)?;
+        trace.push(0, 0, TraceEvent::Message(buf.len() as u64));
+        for layer in self.layers.iter_mut().rev() {
+            buf = trace.check(0, 0, layer.decode(buf))?;
+        }
+        read_traced(&buf, &self.opts, trace)
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecWriteExt, Map, TYPE_NULL};
+    use crate::error::{Error, Result};
+    use crate::layer::{Crc32, Metrics};
+
+    struct Xor(u8);
+
+    impl Layer for Xor {
+        fn encode(&mut self, mut buf: Vec<u8>) -> Result<Vec<u8>> {
+            buf.iter_mut().for_each(|b| *b ^= self.0);
+            Ok(buf)
+        }
+
+        fn decode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+            self.encode(buf)
+        }
+    }
+
+    // Prefixes messages with a marker, so decoding with the layers in the
+    // wrong order fails.
+    struct Tagged;
+
+    impl Layer for Tagged {
+        fn encode(&mut self, mut buf: Vec<u8>) -> Result<Vec<u8>> {
+            buf.insert(0, b'!');
+            Ok(buf)
+        }
+
+        fn decode(&mut self, mut buf: Vec<u8>) -> Result<Vec<u8>> {
+            if buf.first() != Some(&b'!') {
+                return Err(Error::InvalidData("untagged".into()));
+            }
+            buf.remove(0);
+            Ok(buf)
+        }
+    }
+
+    #[test]
+    fn layers() {
+        let mut m = Map::new();
+        m.insert(String::from("k"), Typed::String("v".into()));
+        let values = [Typed::Map(m), Typed::Int(-1)];
+
+        let metrics = Metrics::new();
+        let mut enc = Encoder::new(Vec::new())
+            .with(metrics.clone())
+            .with(Xor(0x5a))
+            .with(Tagged)
+            .with(Crc32);
+        for e in values.iter() {
+            enc.encode(e).unwrap();
+        }
+        let buf = enc.into_inner();
+        assert_eq!(metrics.messages(), 2);
+
+        let mut dec = Decoder::new(&buf[..])
+            .with(metrics.clone())
+            .with(Xor(0x5a))
+            .with(Tagged)
+            .with(Crc32);
+        for e in values.iter() {
+            assert_eq!(&dec.decode().unwrap(), e);
+        }
+        assert_eq!(metrics.messages(), 4);
+        assert!(dec.decode().is_err());
+
+        let mut corrupted = buf.clone();
+        corrupted[3] ^= 1;
+        let mut dec = Decoder::new(&corrupted[..])
+            .with(Xor(0x5a))
+            .with(Tagged)
+            .with(Crc32);
+        assert!(dec.decode().is_err());
+
+        // The layers are reverted in reverse order.
+        let mut dec = Decoder::new(&buf[..])
+            .with(Tagged)
+            .with(Xor(0x5a))
+            .with(Crc32);
+        assert!(dec.decode().is_err());
+    }
+
+    #[test]
+    fn message_too_long() {
+        // The length is rejected before the message is read.
+        let buf = b"\xff\xff\xff\xff\x0f";
+        let config = Config::new().limits(crate::Limits::new().max_bytes_len(1024));
+        let mut dec = Decoder::with_config(&buf[..], &config);
+        assert!(matches!(dec.decode(), Err(Error::TooLong(0xffff_ffff))));
+        let mut dec = Decoder::with_config(&buf[..], &config).trace(true);
+        assert!(matches!(dec.decode(), Err(Error::TooLong(_))));
+    }
+
+    #[test]
+    fn trace() {
+        let mut enc = Encoder::new(Vec::new()).with(Crc32);
+        enc.encode(&Typed::Uint(7)).unwrap();
+        enc.encode(&Typed::Int(1)).unwrap();
+        let mut buf = enc.into_inner();
+        let n = buf.len();
+        buf[n - 6] = b'x';
+
+        let mut dec = Decoder::new(&buf[..]).with(Crc32);
+        assert_eq!(dec.decode().unwrap(), Typed::Uint(7));
+        assert!(dec.last_trace().is_none());
+
+        let mut dec = Decoder::new(&buf[..]).with(Crc32).trace(true);
+        assert_eq!(dec.decode().unwrap(), Typed::Uint(7));
+        let trace = dec.last_trace().unwrap();
+        assert_eq!(trace.entries()[0].event, TraceEvent::Message(6));
+        assert_eq!(
+            trace.to_string(),
+            "0000 message 6\n0000 tag 'u'\n0001 number 7\n"
+        );
+        assert!(dec.decode().is_err());
+        let trace = dec.last_trace().unwrap();
+        assert!(trace.error().is_some());
+    }
+
+    #[test]
+    fn trace_agrees() {
+        // Deeper than the default limit, then invalid UTF-8.
+        let mut deep = b"l\x01".repeat(100);
+        deep.push(TYPE_NULL);
+        let messages = [deep, b"s\x01\xff".to_vec()];
+        let mut buf = Vec::new();
+        for msg in messages.iter() {
+            buf.write_sized(msg).unwrap();
+        }
+
+        for config in [Config::new(), Config::new().strict_utf8(true)].iter() {
+            let mut dec = Decoder::with_config(&buf[..], config);
+            let mut traced = Decoder::with_config(&buf[..], config).trace(true);
+            for _ in messages.iter() {
+                match (dec.decode(), traced.decode()) {
+                    (Ok(e), Ok(traced)) => assert_eq!(e, traced),
+                    (Err(err), Err(traced)) => assert_eq!(err.to_string(), traced.to_string()),
+                    (r, traced) => panic!("{:?} != {:?}", r, traced),
+                }
+            }
+        }
+        let mut traced = Decoder::new(&buf[..]).trace(true);
+        assert!(matches!(traced.decode(), Err(Error::DepthExceeded(64))));
+        assert!(traced.last_trace().unwrap().error().is_some());
+    }
+}
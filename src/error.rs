@@ -0,0 +1,137 @@
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+
+/// Error is the error of encoding and decoding values, it tells truncated
+/// input, malformed input and I/O failures apart. The `Read` and `Write`
+/// implementations, e.g. `EncodedValueReader`, carry it inside the
+/// `io::Error`, `Error::from` recovers it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The underlying reader or writer failed.
+    Io(io::Error),
+    /// The input ended in the middle of a value.
+    UnexpectedEof,
+    /// The buffer ends in the middle of a value which needs at least
+    /// `needed` more bytes, see `IncrementalDecoder`.
+    Incomplete { needed: usize },
+    /// The tag of a value is not known.
+    UnknownType(u8),
+    /// A string or map key is not valid UTF-8, see
+    /// `DecodeOptions::strict_utf8`.
+    InvalidUtf8(Utf8Error),
+    /// A varint is longer than 10 bytes or overflows 64 bits.
+    VarintOverflow,
+    /// A list or map has more elements than the format version or the
+    /// limits allow, or all the lists and maps of a value together do.
+    ContainerTooLarge(u64),
+    /// A string, bytes or map key is longer than the limits allow.
+    TooLong(u64),
+    /// Lists and maps are nested deeper than the limit.
+    DepthExceeded(usize),
+    /// Bytes are left after a value which should span the whole input.
+    TrailingBytes,
+    /// The input is malformed otherwise.
+    InvalidData(String),
+    /// The value can't be encoded, e.g. an iterator lied about its length.
+    InvalidInput(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Returns the kind of the `io::Error` it converts into.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::Io(err) => err.kind(),
+            Error::UnexpectedEof | Error::Incomplete { .. } => io::ErrorKind::UnexpectedEof,
+            Error::InvalidInput(_) => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+
+    /// Whether the input ended early, rather than being malformed.
+    pub fn is_eof(&self) -> bool {
+        self.kind() == io::ErrorKind::UnexpectedEof
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => err.fmt(f),
+            Error::UnexpectedEof => f.write_str("unexpected end of input"),
+            Error::Incomplete { needed } => {
+                write!(f, "incomplete value, {} more bytes needed", needed)
+            }
+            Error::UnknownType(t) => write!(f, "unknown type: '{}'", t),
+            Error::InvalidUtf8(err) => err.fmt(f),
+            Error::VarintOverflow => f.write_str("varint overflows 64 bits"),
+            Error::ContainerTooLarge(n) => write!(f, "container of {} elements is too large", n),
+            Error::TooLong(n) => write!(f, "length {} is too long", n),
+            Error::DepthExceeded(max) => write!(f, "nesting exceeds the depth {}", max),
+            Error::TrailingBytes => f.write_str("trailing bytes after the value"),
+            Error::InvalidData(msg) | Error::InvalidInput(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::InvalidUtf8(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = err.into_inner().and_then(|inner| inner.downcast().ok());
+            return *inner.expect("checked above");
+        }
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::UnexpectedEof,
+            io::ErrorKind::InvalidData => Error::InvalidData(err.to_string()),
+            io::ErrorKind::InvalidInput => Error::InvalidInput(err.to_string()),
+            _ => Error::Io(err),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            _ => io::Error::new(err.kind(), err),
+        }
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(err: Utf8Error) -> Self {
+        Error::InvalidUtf8(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_roundtrip() {
+        let err = io::Error::from(Error::UnknownType(b'x'));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unknown type: '120'");
+        assert!(matches!(Error::from(err), Error::UnknownType(b'x')));
+
+        let err = Error::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        assert!(err.is_eof());
+        let err = Error::from(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(matches!(err, Error::Io(_)));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::array::PackedArray;
+use crate::codec::{
+    write_array, write_container_len, CodecWriteExt, CONTAINER_CAPACITY, TYPE_ARRAY, TYPE_BYTES,
+    TYPE_DECIMAL, TYPE_FALSE, TYPE_FLOAT, TYPE_INT, TYPE_NULL, TYPE_STRING, TYPE_TIMESTAMP,
+    TYPE_TRUE, TYPE_UINT,
+};
+use crate::config::FormatVersion;
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::parser::Event;
+use crate::timestamp::Timestamp;
+use crate::varint::VarintWriteExt;
+use byteorder::WriteBytesExt;
+use std::io::Write;
+
+struct Frame {
+    remaining: usize,
+    is_map: bool,
+    expect_key: bool,
+}
+
+/// EventWriter is the dual of `Parser`, it encodes a single value from a
+/// sequence of events and validates that they are properly nested.
+///
+/// Lists and maps must declare their length up front since it is written
+/// before the elements.
+pub struct EventWriter<W> {
+    writer: W,
+    stack: Vec<Frame>,
+    started: bool,
+    version: FormatVersion,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidInput(msg.to_string())
+}
+
+impl<W: Write> EventWriter<W> {
+    pub fn new(writer: W) -> Self {
+        EventWriter {
+            writer,
+            stack: Vec::new(),
+            started: false,
+            version: FormatVersion::default(),
+        }
+    }
+
+    /// Writes the lists and maps in `version`, in `FormatVersion::V1` they
+    /// can't have more than 254 elements.
+    pub fn format_version(mut self, version: FormatVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn start_list(&mut self, nelem: usize) -> Result<()> {
+        self.start_container(false, nelem)
+    }
+
+    pub fn start_map(&mut self, nelem: usize) -> Result<()> {
+        self.start_container(true, nelem)
+    }
+
+    pub fn key(&mut self, k: &str) -> Result<()> {
+        match self.stack.last_mut() {
+            Some(top) if top.is_map && top.expect_key => {
+                if top.remaining == 0 {
+                    return Err(invalid("too many map entries"));
+                }
+                top.expect_key = false;
+            }
+            _ => return Err(invalid("unexpected key")),
+        }
+        self.writer.write_sized(k.as_bytes())
+    }
+
+    pub fn null(&mut self) -> Result<()> {
+        self.begin_value()?;
+        Ok(self.writer.write_u8(TYPE_NULL)?)
+    }
+
+    pub fn bool(&mut self, b: bool) -> Result<()> {
+        self.begin_value()?;
+        Ok(self
+            .writer
+            .write_u8(if b { TYPE_TRUE } else { TYPE_FALSE })?)
+    }
+
+    pub fn int(&mut self, n: i64) -> Result<()> {
+        self.begin_value()?;
+        self.writer.write_u8(TYPE_INT)?;
+        self.writer.write_varint(n)
+    }
+
+    pub fn uint(&mut self, un: u64) -> Result<()> {
+        self.begin_value()?;
+        self.writer.write_u8(TYPE_UINT)?;
+        self.writer.write_uvarint(un)
+    }
+
+    pub fn float(&mut self, f: f64) -> Result<()> {
+        self.begin_value()?;
+        self.writer.write_u8(TYPE_FLOAT)?;
+        self.writer.write_uvarint(f.to_bits())
+    }
+
+    pub fn timestamp(&mut self, ts: Timestamp) -> Result<()> {
+        self.begin_value()?;
+        self.writer.write_u8(TYPE_TIMESTAMP)?;
+        self.writer.write_varint(ts.secs())?;
+        self.writer.write_uvarint(ts.subsec_nanos().into())
+    }
+
+    pub fn decimal(&mut self, d: Decimal) -> Result<()> {
+        self.begin_value()?;
+        self.writer.write_u8(TYPE_DECIMAL)?;
+        self.writer.write_varint(d.mantissa)?;
+        self.writer.write_uvarint(d.scale.into())
+    }
+
+    pub fn bytes(&mut self, buf: &[u8]) -> Result<()> {
+        self.begin_value()?;
+        self.writer.write_u8(TYPE_BYTES)?;
+        self.writer.write_sized(buf)
+    }
+
+    pub fn string(&mut self, s: &str) -> Result<()> {
+        self.begin_value()?;
+        self.writer.write_u8(TYPE_STRING)?;
+        self.writer.write_sized(s.as_bytes())
+    }
+
+    pub fn array(&mut self, a: &PackedArray) -> Result<()> {
+        self.begin_value()?;
+        self.writer.write_u8(TYPE_ARRAY)?;
+        write_array(&mut self.writer, a)
+    }
+
+    /// Ends the innermost list or map, all of its declared elements must
+    /// have been written.
+    pub fn end(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(top) if top.remaining == 0 && (!top.is_map || top.expect_key) => {
+                self.stack.pop();
+                Ok(())
+            }
+            Some(_) => Err(invalid("container ended before all elements are written")),
+            None => Err(invalid("no container to end")),
+        }
+    }
+
+    pub fn write_event(&mut self, ev: &Event) -> Result<()> {
+        match ev {
+            Event::StartList(nelem) => self.start_list(*nelem),
+            Event::StartMap(nelem) => self.start_map(*nelem),
+            Event::Key(k) => self.key(k),
+            Event::Null => self.null(),
+            Event::Bool(b) => self.bool(*b),
+            Event::Int(n) => self.int(*n),
+            Event::Uint(un) => self.uint(*un),
+            Event::Float(f) => self.float(*f),
+            Event::Timestamp(ts) => self.timestamp(*ts),
+            Event::Decimal(d) => self.decimal(*d),
+            Event::Bytes(buf) => self.bytes(buf),
+            Event::String(s) => self.string(s),
+            Event::Array(a) => self.array(a),
+            Event::End => self.end(),
+        }
+    }
+
+    /// Returns true once a complete value has been written.
+    pub fn is_complete(&self) -> bool {
+        self.started && self.stack.is_empty()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns the underlying writer, it fails if the value is incomplete.
+    pub fn finish(self) -> Result<W> {
+        if !self.is_complete() {
+            return Err(invalid("incomplete value"));
+        }
+        Ok(self.writer)
+    }
+
+    fn start_container(&mut self, is_map: bool, nelem: usize) -> Result<()> {
+        if self.version == FormatVersion::V1 && nelem >= CONTAINER_CAPACITY {
+            return Err(invalid(
+                "containers can only contain 254 elements in FormatVersion::V1",
+            ));
+        }
+        self.begin_value()?;
+        let version = self.version;
+        self.writer.write_u8(if is_map {
+            version.map_tag()
+        } else {
+            version.list_tag()
+        })?;
+        write_container_len(&mut self.writer, nelem, version)?;
+        self.stack.push(Frame {
+            remaining: nelem,
+            is_map,
+            expect_key: is_map,
+        });
+        Ok(())
+    }
+
+    fn begin_value(&mut self) -> Result<()> {
+        match self.stack.last_mut() {
+            Some(top) => {
+                if top.is_map {
+                    if top.expect_key {
+                        return Err(invalid("expected a key"));
+                    }
+                    top.expect_key = true;
+                } else if top.remaining == 0 {
+                    return Err(invalid("too many list elements"));
+                }
+                top.remaining -= 1;
+            }
+            None => {
+                if self.started {
+                    return Err(invalid("value already complete"));
+                }
+                self.started = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecReadExt, Map, Typed};
+    use crate::parser::Parser;
+
+    #[test]
+    fn build() {
+        let mut w = EventWriter::new(Vec::new());
+        w.start_map(2).unwrap();
+        w.key("name").unwrap();
+        w.string("xd").unwrap();
+        w.key("list").unwrap();
+        w.start_list(3).unwrap();
+        w.int(-1).unwrap();
+        w.float(0.5).unwrap();
+        w.bytes(&[1u8]).unwrap();
+        w.end().unwrap();
+        w.end().unwrap();
+        let buf = w.finish().unwrap();
+
+        let mut m = Map::new();
+        m.insert(String::from("name"), Typed::String("xd".into()));
+        m.insert(
+            String::from("list"),
+            Typed::List(vec![
+                Typed::Int(-1),
+                Typed::Float(0.5),
+                Typed::Bytes(vec![1u8].into()),
+            ]),
+        );
+        assert_eq!((&buf[..]).read_typed().unwrap(), Typed::Map(m));
+    }
+
+    #[test]
+    fn parser_roundtrip() {
+        let e = Typed::List(vec![
+            Typed::Uint(3),
+            Typed::List(vec![]),
+            Typed::String("超".into()),
+        ]);
+        let mut buf = Vec::new();
+        buf.write_typed(&e).unwrap();
+
+        let mut p = Parser::new(&buf[..]);
+        let mut w = EventWriter::new(Vec::new());
+        while let Some(ev) = p.next_event().unwrap() {
+            w.write_event(&ev).unwrap();
+        }
+        assert_eq!(w.finish().unwrap(), buf);
+    }
+
+    #[test]
+    fn format_v2() {
+        let mut w = EventWriter::new(Vec::new()).format_version(FormatVersion::V2);
+        w.start_list(300).unwrap();
+        for i in 0..300 {
+            w.uint(i).unwrap();
+        }
+        w.end().unwrap();
+        let buf = w.finish().unwrap();
+        assert_eq!(&buf[..3], b"L\xac\x02");
+        let l: Vec<Typed> = (0..300).map(Typed::Uint).collect();
+        assert_eq!((&buf[..]).read_typed().unwrap(), Typed::List(l));
+    }
+
+    #[test]
+    fn nesting_errors() {
+        let mut w = EventWriter::new(Vec::new());
+        assert!(w.key("k").is_err());
+        assert!(w.end().is_err());
+
+        let mut w = EventWriter::new(Vec::new());
+        w.start_map(1).unwrap();
+        assert!(w.int(1).is_err());
+        assert!(w.end().is_err());
+        w.key("k").unwrap();
+        assert!(w.key("k").is_err());
+        w.int(1).unwrap();
+        assert!(w.key("k2").is_err());
+        w.end().unwrap();
+        assert!(w.int(2).is_err());
+
+        let mut w = EventWriter::new(Vec::new());
+        w.start_list(1).unwrap();
+        w.int(1).unwrap();
+        assert!(w.int(2).is_err());
+        let err = w.start_list(255).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "containers can only contain 254 elements in FormatVersion::V1"
+        );
+
+        let mut w = EventWriter::new(Vec::new());
+        w.start_list(2).unwrap();
+        w.int(1).unwrap();
+        assert!(w.finish().is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Frames delimit encoded values in a byte stream, e.g. a TCP connection or
+//! a file, so that truncated and corrupted values are detected rather than
+//! misread.
+//!
+//! A frame is a message of an `Encoder`: the uvarint length of the rest,
+//! the encoded value and, with checksums, the little endian CRC-32C of the
+//! value, which a `Crc32c` layer appends. So a `Decoder` with the same
+//! layers reads the frames, and `Frames` reads the messages of an `Encoder`
+//! and of a `SharedFrameWriter`. The frames don't tell whether they carry
+//! checksums, both ends have to agree on it.
+
+use crate::codec::{read_sized_limited, CodecReadExt, CodecWriteExt, Typed};
+use crate::error::{Error, Result};
+use crate::layer::{Crc32c, Layer};
+use crate::limits::Budget;
+use crate::options::DecodeOptions;
+use std::io::{Read, Write};
+
+/// FrameWriter writes values as frames, with checksums if `checksum` is set.
+pub struct FrameWriter<W> {
+    writer: W,
+    checksum: bool,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(writer: W) -> Self {
+        FrameWriter {
+            writer,
+            checksum: false,
+        }
+    }
+
+    /// Whether the frames carry a CRC-32C checksum.
+    pub fn checksum(mut self, yes: bool) -> Self {
+        self.checksum = yes;
+        self
+    }
+
+    /// Writes `e` as a single frame, the value is encoded before anything
+    /// is written so a failed value leaves nothing behind.
+    pub fn write_frame(&mut self, e: &Typed) -> Result<()> {
+        let mut buf = Vec::new();
+        buf.write_typed(e)?;
+        if self.checksum {
+            buf = Crc32c.encode(buf)?;
+        }
+        self.writer.write_sized(&buf)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Frames reads the frames written by a `FrameWriter`, with checksums if
+/// `checksum` is set.
+///
+/// It is also an iterator of the values, which ends with the reader or
+/// after the first error.
+pub struct Frames<R> {
+    reader: R,
+    opts: DecodeOptions,
+    checksum: bool,
+    failed: bool,
+}
+
+impl<R: Read> Frames<R> {
+    pub fn new(reader: R) -> Self {
+        Frames {
+            reader,
+            opts: DecodeOptions::default(),
+            checksum: false,
+            failed: false,
+        }
+    }
+
+    /// A frame longer than `Limits::max_bytes_len` of `opts` is rejected
+    /// before it is read.
+    pub fn options(mut self, opts: DecodeOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Whether the frames carry a CRC-32C checksum, which is verified.
+    pub fn checksum(mut self, yes: bool) -> Self {
+        self.checksum = yes;
+        self
+    }
+
+    /// Reads the next frame, `None` means the reader ended between frames,
+    /// while ending within a frame is an `Error::UnexpectedEof`.
+    pub fn read_frame(&mut self) -> Result<Option<Typed>> {
+        let mut first = [0u8; 1];
+        if self.reader.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+        let mut r = (&first[..]).chain(&mut self.reader);
+        let mut buf = read_sized_limited(&mut r, &Budget::new(&self.opts.limits))?;
+        if self.checksum {
+            buf = Crc32c.decode(buf)?;
+        }
+        let mut body = &buf[..];
+        let e = body.read_typed_with(&self.opts)?;
+        if !body.is_empty() {
+            return Err(Error::TrailingBytes);
+        }
+        Ok(Some(e))
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for Frames<R> {
+    type Item = Result<Typed>;
+
+    fn next(&mut self) -> Option<Result<Typed>> {
+        if self.failed {
+            return None;
+        }
+        let res = self.read_frame().transpose();
+        self.failed = matches!(res, Some(Err(_)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::Decoder;
+    use crate::limits::Limits;
+    use crate::shared_writer::SharedFrameWriter;
+
+    fn frames(checksum: bool) -> Vec<u8> {
+        let mut w = FrameWriter::new(Vec::new()).checksum(checksum);
+        w.write_frame(&crate::typed!({"k": ["v", 1]})).unwrap();
+        w.write_frame(&Typed::Int(-1)).unwrap();
+        w.into_inner()
+    }
+
+    #[test]
+    fn roundtrip() {
+        for checksum in [false, true].iter() {
+            let values = Frames::new(&frames(*checksum)[..])
+                .checksum(*checksum)
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(values, vec![crate::typed!({"k": ["v", 1]}), Typed::Int(-1)]);
+        }
+
+        let buf = frames(true);
+        assert_eq!(&buf[buf.len() - 7..buf.len() - 4], b"\x06i\x01");
+        let buf = frames(false);
+        let mut r = Frames::new(&buf[..]).checksum(true);
+        assert!(matches!(r.next(), Some(Err(Error::InvalidData(_)))));
+        assert!(r.next().is_none());
+    }
+
+    #[test]
+    fn compatible() {
+        let buf = frames(true);
+        let mut dec = Decoder::new(&buf[..]).with(Crc32c);
+        assert_eq!(dec.decode().unwrap(), crate::typed!({"k": ["v", 1]}));
+        assert_eq!(dec.decode().unwrap(), Typed::Int(-1));
+
+        let w = SharedFrameWriter::new(Vec::new());
+        w.write_frame(&crate::typed!({"k": ["v", 1]})).unwrap();
+        w.write_frame(&Typed::Int(-1)).unwrap();
+        assert_eq!(w.into_inner(), frames(false));
+    }
+
+    #[test]
+    fn corrupted() {
+        for checksum in [false, true].iter() {
+            let buf = frames(*checksum);
+            // A partial write is detected wherever it stops.
+            let last = buf.len() - if *checksum { 7 } else { 3 };
+            for n in last + 1..buf.len() {
+                let mut r = Frames::new(&buf[..n]).checksum(*checksum);
+                assert_eq!(r.next().unwrap().unwrap(), crate::typed!({"k": ["v", 1]}));
+                let err = r.next().unwrap().unwrap_err();
+                assert!(err.is_eof(), "{}: {:?}", n, err);
+                assert!(r.next().is_none());
+            }
+        }
+
+        let mut buf = frames(true);
+        buf[3] ^= 0x20;
+        let err = Frames::new(&buf[..])
+            .checksum(true)
+            .read_frame()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "checksum mismatch");
+
+        let buf = frames(false);
+        let opts = DecodeOptions::new().limits(Limits::new().max_bytes_len(4));
+        let err = Frames::new(&buf[..])
+            .options(opts)
+            .read_frame()
+            .unwrap_err();
+        assert!(matches!(err, Error::TooLong(_)), "{:?}", err);
+        let mut r = Frames::new(&b"\x03i\x01\x00"[..]);
+        assert!(matches!(r.read_frame(), Err(Error::TrailingBytes)));
+    }
+}
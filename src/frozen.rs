@@ -0,0 +1,138 @@
+use crate::array::PackedArray;
+use crate::codec::{List, Map, Str, Typed};
+use crate::decimal::Decimal;
+use crate::raw::RawValue;
+use crate::timestamp::Timestamp;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub type FrozenList = Arc<[FrozenTyped]>;
+pub type FrozenMap = Arc<HashMap<Arc<str>, FrozenTyped>>;
+
+/// FrozenTyped is an immutable version of `Typed`, every heap allocated part
+/// of it is reference counted, so it is cheap to clone and can be shared
+/// across threads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrozenTyped {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Timestamp(Timestamp),
+    Decimal(Decimal),
+    Bytes(Arc<[u8]>),
+    String(Arc<str>),
+    List(FrozenList),
+    Map(FrozenMap),
+    Array(Arc<PackedArray>),
+    Raw(Arc<[u8]>),
+}
+
+impl From<&Typed> for FrozenTyped {
+    fn from(e: &Typed) -> Self {
+        match e {
+            Typed::Null => FrozenTyped::Null,
+            Typed::Bool(b) => FrozenTyped::Bool(*b),
+            Typed::Int(n) => FrozenTyped::Int(*n),
+            Typed::Uint(un) => FrozenTyped::Uint(*un),
+            Typed::Float(f) => FrozenTyped::Float(*f),
+            Typed::Timestamp(ts) => FrozenTyped::Timestamp(*ts),
+            Typed::Decimal(d) => FrozenTyped::Decimal(*d),
+            Typed::Bytes(buf) => FrozenTyped::Bytes(Arc::from(&buf[..])),
+            Typed::String(s) => FrozenTyped::String(Arc::from(&s[..])),
+            Typed::List(l) => FrozenTyped::List(l.iter().map(FrozenTyped::from).collect()),
+            Typed::Map(m) => FrozenTyped::Map(Arc::new(
+                m.iter()
+                    .map(|(k, v)| (Arc::from(k.as_str()), FrozenTyped::from(v)))
+                    .collect(),
+            )),
+            Typed::Array(a) => FrozenTyped::Array(Arc::new(a.clone())),
+            Typed::Raw(raw) => FrozenTyped::Raw(Arc::from(raw.as_bytes())),
+        }
+    }
+}
+
+impl From<Typed> for FrozenTyped {
+    fn from(e: Typed) -> Self {
+        FrozenTyped::from(&e)
+    }
+}
+
+impl From<&FrozenTyped> for Typed {
+    fn from(e: &FrozenTyped) -> Self {
+        match e {
+            FrozenTyped::Null => Typed::Null,
+            FrozenTyped::Bool(b) => Typed::Bool(*b),
+            FrozenTyped::Int(n) => Typed::Int(*n),
+            FrozenTyped::Uint(un) => Typed::Uint(*un),
+            FrozenTyped::Float(f) => Typed::Float(*f),
+            FrozenTyped::Timestamp(ts) => Typed::Timestamp(*ts),
+            FrozenTyped::Decimal(d) => Typed::Decimal(*d),
+            FrozenTyped::Bytes(buf) => Typed::Bytes(buf.to_vec().into()),
+            FrozenTyped::String(s) => Typed::String(Str::from(&s[..])),
+            FrozenTyped::List(l) => Typed::List(l.iter().map(Typed::from).collect::<List>()),
+            FrozenTyped::Map(m) => Typed::Map(
+                m.iter()
+                    .map(|(k, v)| (k.to_string(), Typed::from(v)))
+                    .collect::<Map>(),
+            ),
+            FrozenTyped::Array(a) => Typed::Array(PackedArray::clone(a)),
+            FrozenTyped::Raw(buf) => Typed::Raw(RawValue::from_encoded(buf.to_vec())),
+        }
+    }
+}
+
+impl From<FrozenTyped> for Typed {
+    fn from(e: FrozenTyped) -> Self {
+        Typed::from(&e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn send_sync() {
+        assert_send_sync::<FrozenTyped>();
+    }
+
+    #[test]
+    fn freeze_and_thaw() {
+        let mut m = Map::new();
+        m.insert(String::from("hi"), Typed::String("hello".into()));
+        m.insert(String::from("bytes"), Typed::Bytes(vec![0u8, 255u8].into()));
+        let e = Typed::List(vec![
+            Typed::Int(-1),
+            Typed::Uint(u64::MAX),
+            Typed::Float(1.5),
+            Typed::Map(m),
+        ]);
+
+        let frozen = FrozenTyped::from(&e);
+        assert_eq!(Typed::from(&frozen), e);
+    }
+
+    #[test]
+    fn shared_across_threads() {
+        let mut m = Map::new();
+        m.insert(String::from("k"), Typed::Bytes(vec![1u8; 64].into()));
+        let frozen = FrozenTyped::from(Typed::Map(m));
+
+        let cloned = frozen.clone();
+        match (&frozen, &cloned) {
+            (FrozenTyped::Map(a), FrozenTyped::Map(b)) => assert!(Arc::ptr_eq(a, b)),
+            _ => panic!("not a map"),
+        }
+
+        let handle = thread::spawn(move || match cloned {
+            FrozenTyped::Map(m) => m.len(),
+            _ => 0,
+        });
+        assert_eq!(handle.join().unwrap(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::array::PackedArray;
+use crate::codec::{List, Map, Str, Typed};
+use crate::decimal::Decimal;
+use crate::schema::Schema;
+use crate::timestamp::Timestamp;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+// The bounds of the generated numbers, sizes and lengths when the schema
+// doesn't constrain them, small enough to keep documents readable.
+const NUMBER_BOUND: i64 = 1_000_000;
+const MAX_LEN: usize = 16;
+const MAX_ELEMENTS: usize = 4;
+
+fn string<R: Rng + ?Sized>(rng: &mut R) -> String {
+    let n = rng.gen_range(0..=MAX_LEN);
+    (0..n).map(|_| rng.sample(Alphanumeric) as char).collect()
+}
+
+impl Schema {
+    /// Generates a random value which matches the schema, optional fields
+    /// are present half of the time and `Schema::Any` generates ints.
+    ///
+    /// The generated value of an empty range, whose min is greater than its
+    /// max, is the min, which does not match the schema.
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R) -> Typed {
+        match self {
+            Schema::Any => Typed::Int(rng.gen_range(-NUMBER_BOUND..=NUMBER_BOUND)),
+            Schema::Bool => Typed::Bool(rng.gen()),
+            Schema::Int { min, max } => {
+                let lo = min.unwrap_or_else(|| (-NUMBER_BOUND).min(max.unwrap_or(0)));
+                let hi = max.unwrap_or_else(|| NUMBER_BOUND.max(lo));
+                Typed::Int(if lo < hi { rng.gen_range(lo..=hi) } else { lo })
+            }
+            Schema::Uint { min, max } => {
+                let lo = min.unwrap_or(0);
+                let hi = max.unwrap_or_else(|| (NUMBER_BOUND as u64).max(lo));
+                Typed::Uint(if lo < hi { rng.gen_range(lo..=hi) } else { lo })
+            }
+            Schema::Float { min, max } => {
+                let bound = NUMBER_BOUND as f64;
+                let lo = min.unwrap_or_else(|| (-bound).min(max.unwrap_or(0.0)));
+                let hi = max.unwrap_or_else(|| bound.max(lo));
+                Typed::Float(if lo < hi { rng.gen_range(lo..=hi) } else { lo })
+            }
+            Schema::Timestamp => {
+                // Up to the year 2100.
+                let secs = rng.gen_range(0..=4_102_444_800);
+                Typed::Timestamp(Timestamp::from_millis(secs * 1000 + rng.gen_range(0..1000)))
+            }
+            Schema::Decimal => Typed::Decimal(Decimal::new(
+                rng.gen_range(-NUMBER_BOUND..=NUMBER_BOUND),
+                rng.gen_range(0..=4),
+            )),
+            Schema::Bytes => {
+                let n = rng.gen_range(0..=MAX_LEN);
+                Typed::Bytes((0..n).map(|_| rng.gen::<u8>()).collect::<Vec<u8>>().into())
+            }
+            Schema::String => Typed::String(Str::from(string(rng))),
+            Schema::Array => {
+                let n = rng.gen_range(0..=MAX_ELEMENTS);
+                let data: Vec<f64> = (0..n).map(|_| rng.gen()).collect();
+                Typed::Array(PackedArray::new(vec![n], &data).unwrap())
+            }
+            Schema::List(elem) => {
+                let n = rng.gen_range(0..=MAX_ELEMENTS);
+                Typed::List((0..n).map(|_| elem.generate(rng)).collect::<List>())
+            }
+            Schema::MapOf(value) => {
+                let n = rng.gen_range(0..=MAX_ELEMENTS);
+                let mut m = Map::new();
+                for _ in 0..n {
+                    m.insert(string(rng), value.generate(rng));
+                }
+                Typed::Map(m)
+            }
+            Schema::Record(fields) => {
+                let mut m = Map::new();
+                for field in fields.iter() {
+                    if !field.optional || rng.gen_bool(0.5) {
+                        m.insert(field.name.clone(), field.schema.generate(rng));
+                    }
+                }
+                Typed::Map(m)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Field;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn conforms() {
+        let schema = Schema::record(vec![
+            Field::required("id", Schema::uint()),
+            Field::required(
+                "temperature",
+                Schema::Float {
+                    min: Some(-40.0),
+                    max: Some(60.0),
+                },
+            ),
+            Field::required(
+                "offset",
+                Schema::Int {
+                    min: None,
+                    max: Some(-5),
+                },
+            ),
+            Field::optional("payload", Schema::Bytes),
+            Field::optional("labels", Schema::map_of(Schema::list(Schema::String))),
+            Field::required(
+                "level",
+                Schema::Uint {
+                    min: Some(3),
+                    max: Some(3),
+                },
+            ),
+            Field::optional("any", Schema::Any),
+        ]);
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut optional = 0;
+        for _ in 0..200 {
+            let e = schema.generate(&mut rng);
+            schema.validate(&e).unwrap();
+            if let Typed::Map(m) = &e {
+                assert_eq!(m.get("level"), Some(&Typed::Uint(3)));
+                optional += m.contains_key("payload") as usize;
+            }
+        }
+        assert!(optional > 50 && optional < 150, "{}", optional);
+    }
+}
@@ -0,0 +1,340 @@
+//! Decoding of values which arrive in pieces, e.g. from a socket, without
+//! parsing them again whenever more bytes arrive.
+//!
+//! The bytes are scanned for the end of the value first, which allocates
+//! nothing, and the value is only decoded once it is complete. The scan
+//! resumes where it stopped and enforces the limits as it goes, so a
+//! hostile peer can't make it buffer more than the limits allow.
+
+use crate::codec::{
+    base_tag, CodecReadExt, Typed, TYPE_ARRAY, TYPE_BYTES, TYPE_BYTES_DEFLATE, TYPE_DECIMAL,
+    TYPE_FALSE, TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT, TYPE_INT_FIXED, TYPE_LIST, TYPE_LIST_V2,
+    TYPE_MAP, TYPE_MAP_V2, TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE, TYPE_TIMESTAMP, TYPE_TRUE,
+    TYPE_UINT, TYPE_UINT_FIXED,
+};
+use crate::error::{Error, Result};
+use crate::limits::{Budget, Limits};
+use crate::options::DecodeOptions;
+use crate::varint::decode_uvarint;
+
+// Returns the uvarint at the start of `buf` and its length.
+fn uvarint(buf: &[u8]) -> Result<(u64, usize)> {
+    decode_uvarint(buf).map_err(|err| match err {
+        Error::UnexpectedEof => Error::Incomplete { needed: 1 },
+        err => err,
+    })
+}
+
+// Returns the length of the sized bytes at the start of `buf`.
+fn sized_len(buf: &[u8], budget: &Budget) -> Result<usize> {
+    let (sz, n) = uvarint(buf)?;
+    budget.bytes(sz)?;
+    let avail = (buf.len() - n) as u64;
+    if avail < sz {
+        return Err(Error::Incomplete {
+            needed: (sz - avail) as usize,
+        });
+    }
+    Ok(n + sz as usize)
+}
+
+// Returns the length of the packed array after its tag at the start of
+// `buf`.
+fn array_len(buf: &[u8], budget: &Budget) -> Result<usize> {
+    if buf.is_empty() {
+        return Err(Error::Incomplete { needed: 1 });
+    }
+    let mut pos = 1;
+    let (ndim, n) = uvarint(&buf[pos..])?;
+    pos += n;
+    for _ in 0..ndim {
+        pos += uvarint(&buf[pos..])?.1;
+    }
+    Ok(pos + sized_len(&buf[pos..], budget)?)
+}
+
+/// Scan finds the end of the next value, it resumes where the previous call
+/// stopped when the buffer was incomplete.
+#[derive(Debug)]
+pub(crate) struct Scan {
+    pub(crate) pos: usize,
+    // Whether the container is a map and the number of elements left.
+    stack: Vec<(bool, u64)>,
+    // Whether a map key is expected rather than a value.
+    key: bool,
+    budget: Budget,
+}
+
+impl Default for Scan {
+    fn default() -> Self {
+        Scan::new(&Limits::default())
+    }
+}
+
+impl Scan {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Scan {
+            pos: 0,
+            stack: Vec::new(),
+            key: false,
+            budget: Budget::new(limits),
+        }
+    }
+
+    /// Starts over for the next value.
+    pub(crate) fn reset(&mut self) {
+        self.pos = 0;
+        self.stack.clear();
+        self.key = false;
+        self.budget.reset();
+    }
+
+    // Enters the container tagged `t`, returns false if it is empty.
+    fn push(&mut self, t: u8, nelem: u64) -> Result<bool> {
+        self.budget.enter(nelem)?;
+        if nelem == 0 {
+            self.budget.leave();
+            return Ok(false);
+        }
+        let is_map = base_tag(t) == TYPE_MAP;
+        self.stack.push((is_map, nelem));
+        self.key = is_map;
+        Ok(true)
+    }
+
+    /// Returns once the value ends at `self.pos`, `Error::Incomplete` if
+    /// `buf` ends before.
+    pub(crate) fn scan(&mut self, buf: &[u8]) -> Result<()> {
+        if buf.len() < self.pos {
+            return Err(Error::InvalidInput(format!(
+                "buffer of {} bytes, {} were scanned already",
+                buf.len(),
+                self.pos
+            )));
+        }
+        loop {
+            let rest = &buf[self.pos..];
+            if self.key {
+                self.pos += sized_len(rest, &self.budget)?;
+                self.key = false;
+                continue;
+            }
+
+            let t = *rest.first().ok_or(Error::Incomplete { needed: 1 })?;
+            let body = &rest[1..];
+            let n = match t {
+                TYPE_NULL | TYPE_FALSE | TYPE_TRUE => 0,
+                TYPE_INT | TYPE_UINT | TYPE_FLOAT => uvarint(body)?.1,
+                TYPE_INT_FIXED | TYPE_UINT_FIXED | TYPE_FLOAT_FIXED => {
+                    if body.len() < 8 {
+                        return Err(Error::Incomplete {
+                            needed: 8 - body.len(),
+                        });
+                    }
+                    8
+                }
+                TYPE_TIMESTAMP | TYPE_DECIMAL => {
+                    let n = uvarint(body)?.1;
+                    n + uvarint(&body[n..])?.1
+                }
+                TYPE_BYTES | TYPE_STRING => sized_len(body, &self.budget)?,
+                TYPE_BYTES_DEFLATE | TYPE_STRING_DEFLATE => {
+                    let (len, n) = uvarint(body)?;
+                    self.budget.bytes(len)?;
+                    n + sized_len(&body[n..], &self.budget)?
+                }
+                TYPE_ARRAY => array_len(body, &self.budget)?,
+                TYPE_LIST | TYPE_MAP | TYPE_LIST_V2 | TYPE_MAP_V2 => {
+                    let (nelem, n) = if base_tag(t) == t {
+                        let nelem = *body.first().ok_or(Error::Incomplete { needed: 1 })?;
+                        (nelem.into(), 1)
+                    } else {
+                        uvarint(body)?
+                    };
+                    if self.push(t, nelem)? {
+                        self.pos += 1 + n;
+                        continue;
+                    }
+                    n
+                }
+                _ => return Err(Error::UnknownType(t)),
+            };
+            self.pos += 1 + n;
+
+            // A value is complete, so are the containers it was the last
+            // element of.
+            loop {
+                match self.stack.last_mut() {
+                    None => return Ok(()),
+                    Some((_, 1)) => {
+                        self.stack.pop();
+                        self.budget.leave();
+                    }
+                    Some((is_map, nelem)) => {
+                        *nelem -= 1;
+                        self.key = *is_map;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// IncrementalDecoder decodes the values at the start of a buffer which
+/// grows as bytes arrive. A value which isn't complete yet fails with
+/// `Error::Incomplete`, telling at least how many more bytes it needs, and
+/// the bytes scanned so far are not scanned again by the next call.
+#[derive(Debug, Default)]
+pub struct IncrementalDecoder {
+    opts: DecodeOptions,
+    scan: Scan,
+}
+
+impl IncrementalDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The limits of `opts` are enforced while the value is scanned, before
+    /// it is complete.
+    pub fn options(mut self, opts: DecodeOptions) -> Self {
+        self.scan = Scan::new(&opts.limits);
+        self.opts = opts;
+        self
+    }
+
+    /// Decodes the value at the start of `buf`, returns it with the number
+    /// of bytes it takes. After `Error::Incomplete`, call it again with the
+    /// same bytes followed by more, any other error forgets the progress.
+    pub fn decode(&mut self, buf: &[u8]) -> Result<(Typed, usize)> {
+        match self.scan.scan(buf) {
+            Ok(()) => {}
+            Err(err @ Error::Incomplete { .. }) => return Err(err),
+            Err(err) => {
+                self.scan.reset();
+                return Err(err);
+            }
+        }
+        let n = self.scan.pos;
+        self.scan.reset();
+        let e = (&buf[..n]).read_typed_with(&self.opts)?;
+        Ok((e, n))
+    }
+
+    /// Forgets the progress of an incomplete value, e.g. once its bytes
+    /// were dropped.
+    pub fn reset(&mut self) {
+        self.scan.reset();
+    }
+
+    /// Returns the number of bytes of the incomplete value scanned so far.
+    pub fn scanned(&self) -> usize {
+        self.scan.pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::CodecWriteExt;
+    use crate::config::FormatVersion;
+
+    fn value() -> Typed {
+        crate::typed!({
+            "list": [-300, [], {}, "超", null, true],
+            "f": 0.5,
+            "ts": crate::Timestamp::from_millis(1),
+            "d": crate::Decimal::new(-5, 1),
+            "a": (crate::PackedArray::new(vec![2], &[1u32, 2]).unwrap()),
+        })
+    }
+
+    #[test]
+    fn byte_at_a_time() {
+        let mut buf = Vec::new();
+        buf.write_typed(&value()).unwrap();
+        let n = buf.len();
+        buf.write_typed_version(&Typed::List(vec![value(); 300]), FormatVersion::V2)
+            .unwrap();
+        buf.push(b'I');
+        buf.extend_from_slice(&(-1i64).to_le_bytes());
+
+        let mut dec = IncrementalDecoder::new();
+        let mut values = Vec::new();
+        let (mut start, mut end) = (0, 0);
+        while end < buf.len() {
+            end += 1;
+            match dec.decode(&buf[start..end]) {
+                Ok((e, len)) => {
+                    assert_eq!(start + len, end);
+                    values.push(e);
+                    start = end;
+                }
+                Err(Error::Incomplete { needed }) => {
+                    assert!(needed >= 1);
+                    assert!(end + needed <= buf.len(), "{} + {}", end, needed);
+                }
+                Err(err) => panic!("{}", err),
+            }
+        }
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0], value());
+        assert_eq!(values[2], Typed::Int(-1));
+
+        let err = dec.decode(&buf[..n - 1]).unwrap_err();
+        assert!(err.is_eof());
+        assert!(dec.scanned() > 0);
+        assert!(dec.decode(&buf[..1]).is_err());
+        assert_eq!(dec.scanned(), 0);
+        assert_eq!(dec.decode(&buf).unwrap(), (value(), n));
+    }
+
+    #[test]
+    fn needed() {
+        let mut dec = IncrementalDecoder::new();
+        assert!(matches!(
+            dec.decode(b""),
+            Err(Error::Incomplete { needed: 1 })
+        ));
+        assert!(matches!(
+            dec.decode(b"s\x05ab"),
+            Err(Error::Incomplete { needed: 3 })
+        ));
+        assert!(matches!(
+            dec.decode(b"s\x05abcde"),
+            Ok((Typed::String(_), 7))
+        ));
+        assert!(matches!(
+            dec.decode(b"F\x00"),
+            Err(Error::Incomplete { needed: 7 })
+        ));
+    }
+
+    #[test]
+    fn hostile() {
+        let opts = DecodeOptions::new().limits(Limits::new().max_bytes_len(8).max_depth(2));
+        let mut dec = IncrementalDecoder::new().options(opts);
+        // A huge length is rejected before anything is buffered.
+        let err = dec.decode(b"s\xff\xff\xff\xff\x0f").unwrap_err();
+        assert!(matches!(err, Error::TooLong(_)), "{:?}", err);
+        let err = dec.decode(b"l\x01l\x01l\x01").unwrap_err();
+        assert!(matches!(err, Error::DepthExceeded(2)), "{:?}", err);
+        assert!(matches!(dec.decode(b"x"), Err(Error::UnknownType(b'x'))));
+
+        // Garbage never panics.
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..2000 {
+            let buf: Vec<u8> = (0..16)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    b"nft01iufsdlmLMaIUF\x00\x01\x02\x80\xff"[(seed % 23) as usize]
+                })
+                .collect();
+            let _ = dec.decode(&buf);
+            dec.reset();
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! A single-producer/single-consumer ring buffer of encoded values over a
+//! byte region, e.g. shared memory mapped by two processes on the same host.
+//!
+//! The region starts with a header of two counters, the number of bytes
+//! written and read so far, followed by the ring of frames. Each frame is a
+//! little endian u32 length followed by the encoded value, it becomes
+//! visible to the consumer only once it has been written entirely. A zeroed
+//! region is an empty ring.
+
+use crate::codec::{CodecReadExt, CodecWriteExt, Typed};
+use crate::error::{Error, Result};
+use crate::options::DecodeOptions;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// The counters are on separate cache lines so that the producer and the
+// consumer don't contend for them.
+const HEAD_OFFSET: usize = 0;
+const TAIL_OFFSET: usize = 64;
+
+/// The length of the header which precedes the ring in the region.
+pub const HEADER_LEN: usize = 128;
+
+const FRAME_HEADER_LEN: usize = mem::size_of::<u32>();
+
+struct Ring<'a> {
+    head: &'a AtomicU64,
+    tail: &'a AtomicU64,
+    data: *mut u8,
+    capacity: usize,
+    _region: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Ring<'a> {
+    unsafe fn new(ptr: *mut u8, len: usize) -> Result<Self> {
+        if ptr.align_offset(mem::align_of::<AtomicU64>()) != 0 {
+            return Err(Error::InvalidInput(
+                "the region is not aligned to 8 bytes".into(),
+            ));
+        }
+        if len <= HEADER_LEN + FRAME_HEADER_LEN {
+            return Err(Error::InvalidInput(format!(
+                "the region is too small: {} bytes",
+                len
+            )));
+        }
+        Ok(Ring {
+            head: &*(ptr.add(HEAD_OFFSET) as *const AtomicU64),
+            tail: &*(ptr.add(TAIL_OFFSET) as *const AtomicU64),
+            data: ptr.add(HEADER_LEN),
+            capacity: len - HEADER_LEN,
+            _region: PhantomData,
+        })
+    }
+
+    // Copies `buf` into the ring at the position `pos`, wrapping around.
+    fn write_at(&self, pos: u64, buf: &[u8]) {
+        let start = (pos % self.capacity as u64) as usize;
+        let n = buf.len().min(self.capacity - start);
+        unsafe {
+            ptr::copy_nonoverlapping(buf.as_ptr(), self.data.add(start), n);
+            ptr::copy_nonoverlapping(buf[n..].as_ptr(), self.data, buf.len() - n);
+        }
+    }
+
+    fn read_at(&self, pos: u64, buf: &mut [u8]) {
+        let start = (pos % self.capacity as u64) as usize;
+        let n = buf.len().min(self.capacity - start);
+        unsafe {
+            ptr::copy_nonoverlapping(self.data.add(start), buf.as_mut_ptr(), n);
+            ptr::copy_nonoverlapping(self.data, buf[n..].as_mut_ptr(), buf.len() - n);
+        }
+    }
+}
+
+/// Producer writes values into the ring, see `channel`.
+pub struct Producer<'a> {
+    ring: Ring<'a>,
+    buf: Vec<u8>,
+}
+
+/// Consumer reads values from the ring, see `channel`.
+pub struct Consumer<'a> {
+    ring: Ring<'a>,
+    buf: Vec<u8>,
+}
+
+// The producer and the consumer only touch the disjoint parts of the ring
+// which the counters hand over to them.
+unsafe impl Send for Producer<'_> {}
+unsafe impl Send for Consumer<'_> {}
+
+/// Splits `region` into the two ends of an empty ring, a few leading bytes
+/// are skipped if the region is not aligned to 8 bytes.
+pub fn channel(region: &mut [u8]) -> Result<(Producer<'_>, Consumer<'_>)> {
+    let skip = region.as_ptr().align_offset(mem::align_of::<AtomicU64>());
+    let region = region.get_mut(skip..).unwrap_or_default();
+    let ptr = region.as_mut_ptr();
+    let len = region.len();
+    unsafe {
+        let ring = Ring::new(ptr, len)?;
+        ring.head.store(0, Ordering::Relaxed);
+        ring.tail.store(0, Ordering::Relaxed);
+        Ok((Producer::from_raw(ptr, len)?, Consumer::from_raw(ptr, len)?))
+    }
+}
+
+impl<'a> Producer<'a> {
+    /// Attaches to the ring in the region at `ptr`, which must be aligned to
+    /// 8 bytes.
+    ///
+    /// # Safety
+    ///
+    /// The region must be valid for reads and writes of `len` bytes for
+    /// `'a`, and there must be at most one producer of the ring at a time.
+    pub unsafe fn from_raw(ptr: *mut u8, len: usize) -> Result<Self> {
+        Ok(Producer {
+            ring: Ring::new(ptr, len)?,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Returns the number of bytes the ring holds, frames included.
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity
+    }
+
+    /// Writes `e` as a single frame, an `Error::Io` of kind `WouldBlock` is
+    /// returned if the ring is too full for it for now.
+    pub fn try_send(&mut self, e: &Typed) -> Result<()> {
+        self.buf.clear();
+        self.buf.extend_from_slice(&[0u8; FRAME_HEADER_LEN]);
+        self.buf.write_typed(e)?;
+        let n = self.buf.len() - FRAME_HEADER_LEN;
+        if self.buf.len() > self.ring.capacity || n > u32::MAX as usize {
+            return Err(Error::InvalidInput(format!(
+                "the value is too large for the ring: {} bytes",
+                n
+            )));
+        }
+        self.buf[..FRAME_HEADER_LEN].copy_from_slice(&(n as u32).to_le_bytes());
+
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        let used = head.wrapping_sub(tail) as usize;
+        if self.ring.capacity.saturating_sub(used) < self.buf.len() {
+            return Err(Error::Io(io::ErrorKind::WouldBlock.into()));
+        }
+        self.ring.write_at(head, &self.buf);
+        self.ring
+            .head
+            .store(head + self.buf.len() as u64, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<'a> Consumer<'a> {
+    /// Attaches to the ring in the region at `ptr`, which must be aligned to
+    /// 8 bytes.
+    ///
+    /// # Safety
+    ///
+    /// The region must be valid for reads and writes of `len` bytes for
+    /// `'a`, and there must be at most one consumer of the ring at a time.
+    pub unsafe fn from_raw(ptr: *mut u8, len: usize) -> Result<Self> {
+        Ok(Consumer {
+            ring: Ring::new(ptr, len)?,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Returns true if there is no frame to read for now.
+    pub fn is_empty(&self) -> bool {
+        self.ring.head.load(Ordering::Acquire) == self.ring.tail.load(Ordering::Relaxed)
+    }
+
+    /// Reads the next value, an `Error::Io` of kind `WouldBlock` is returned
+    /// if there is none for now.
+    pub fn try_recv(&mut self) -> Result<Typed> {
+        self.try_recv_with(&DecodeOptions::default())
+    }
+
+    pub fn try_recv_with(&mut self, opts: &DecodeOptions) -> Result<Typed> {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        if head == tail {
+            return Err(Error::Io(io::ErrorKind::WouldBlock.into()));
+        }
+        // The counters are in the shared region, so they are checked before
+        // they are trusted with the bounds of the copies.
+        let used = head.wrapping_sub(tail);
+        if used > self.ring.capacity as u64 {
+            return Err(Error::InvalidData(format!(
+                "corrupted header: {} bytes used of {}",
+                used, self.ring.capacity
+            )));
+        }
+        let mut len = [0u8; FRAME_HEADER_LEN];
+        self.ring.read_at(tail, &mut len);
+        let n = u32::from_le_bytes(len) as usize;
+        let frame_len = FRAME_HEADER_LEN + n;
+        // Never more than the capacity, as `used` isn't.
+        if frame_len as u64 > used {
+            return Err(Error::InvalidData(format!(
+                "corrupted frame of {} bytes",
+                n
+            )));
+        }
+        self.buf.resize(n, 0);
+        self.ring
+            .read_at(tail + FRAME_HEADER_LEN as u64, &mut self.buf);
+        self.ring
+            .tail
+            .store(tail + frame_len as u64, Ordering::Release);
+        (&self.buf[..]).read_typed_with(opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::List;
+    use std::thread;
+
+    #[test]
+    fn wrap_around() {
+        let mut region = vec![0u8; HEADER_LEN + 50];
+        let (mut tx, mut rx) = channel(&mut region).unwrap();
+        assert!(tx.capacity() <= 50);
+        assert_eq!(rx.try_recv().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        let e = Typed::String("x".repeat(20).into());
+        for _ in 0..10 {
+            tx.try_send(&e).unwrap();
+            tx.try_send(&Typed::Int(-1)).unwrap();
+            assert_eq!(
+                tx.try_send(&e).unwrap_err().kind(),
+                io::ErrorKind::WouldBlock
+            );
+            assert_eq!(rx.try_recv().unwrap(), e);
+            assert_eq!(rx.try_recv().unwrap(), Typed::Int(-1));
+            assert!(rx.is_empty());
+        }
+
+        let large = Typed::Bytes(vec![0u8; 64].into());
+        assert_eq!(
+            tx.try_send(&large).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(channel(&mut [0u8; HEADER_LEN]).is_err());
+    }
+
+    #[test]
+    fn corrupted_header() {
+        let mut region = vec![0u64; (HEADER_LEN + 64) / 8];
+        let ptr = region.as_mut_ptr() as *mut u8;
+        let len = region.len() * 8;
+        let (mut tx, mut rx) = unsafe {
+            (
+                Producer::from_raw(ptr, len).unwrap(),
+                Consumer::from_raw(ptr, len).unwrap(),
+            )
+        };
+        tx.try_send(&Typed::Int(1)).unwrap();
+        let head = unsafe { &*(ptr.add(HEAD_OFFSET) as *const AtomicU64) };
+        let written = head.load(Ordering::Relaxed);
+
+        head.store(1 << 40, Ordering::Relaxed);
+        let err = rx.try_recv().unwrap_err();
+        assert!(matches!(err, Error::InvalidData(_)), "{:?}", err);
+        assert_eq!(
+            err.to_string(),
+            "corrupted header: 1099511627776 bytes used of 64"
+        );
+
+        // A frame longer than the ring.
+        let set_frame_len = |n: u32| unsafe {
+            ptr::copy_nonoverlapping(n.to_le_bytes().as_ptr(), ptr.add(HEADER_LEN), 4);
+        };
+        head.store(64, Ordering::Relaxed);
+        set_frame_len(u32::MAX);
+        assert!(matches!(rx.try_recv(), Err(Error::InvalidData(_))));
+
+        head.store(written, Ordering::Relaxed);
+        set_frame_len(2);
+        assert_eq!(rx.try_recv().unwrap(), Typed::Int(1));
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn threads() {
+        let mut region = vec![0u8; HEADER_LEN + 256];
+        let (mut tx, mut rx) = channel(&mut region).unwrap();
+        thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..1000u64 {
+                    let e = Typed::List(List::from(vec![Typed::Uint(i); (i % 7) as usize]));
+                    loop {
+                        match tx.try_send(&e) {
+                            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                                thread::yield_now()
+                            }
+                            r => break r.unwrap(),
+                        }
+                    }
+                }
+            });
+            for i in 0..1000u64 {
+                let e = loop {
+                    match rx.try_recv() {
+                        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                            thread::yield_now()
+                        }
+                        r => break r.unwrap(),
+                    }
+                };
+                assert_eq!(e, Typed::List(vec![Typed::Uint(i); (i % 7) as usize]));
+            }
+        });
+    }
+}
@@ -0,0 +1,202 @@
+//! Conversions between `Typed` and `serde_json::Value`.
+//!
+//! JSON has fewer types, so the conversion into JSON is lossy:
+//! - integers that fit an i64 come back as `Typed::Int`, others as
+//!   `Typed::Uint`, and floats which are not finite become `null`;
+//! - timestamps are RFC 3339 strings and decimals are strings like "12.30";
+//! - bytes are base64 encoded strings;
+//! - `Typed::Raw` values are decoded first.
+//!
+//! The timestamps, decimals and bytes come back as `Typed::String`, the
+//! lenient conversions of `FromTyped` parse the timestamps and decimals.
+
+use crate::codec::{Map, Typed};
+use crate::convert::ToTyped;
+use crate::error::{Error, Result};
+use serde_json::{Number, Value};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Encodes with the standard alphabet and padding.
+fn base64(buf: &[u8]) -> String {
+    let mut out = String::with_capacity(buf.len().div_ceil(3) * 4);
+    for chunk in buf.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+impl Typed {
+    /// Converts the value into JSON, see the module documentation for what
+    /// is lost. It fails if a `Typed::Raw` value can't be decoded.
+    pub fn to_json(&self) -> Result<Value> {
+        Ok(match self {
+            Typed::Null => Value::Null,
+            Typed::Bool(b) => Value::Bool(*b),
+            Typed::Int(n) => Value::from(*n),
+            Typed::Uint(un) => Value::from(*un),
+            Typed::Float(f) => Number::from_f64(*f).map_or(Value::Null, Value::Number),
+            Typed::Timestamp(ts) => Value::String(ts.to_string()),
+            Typed::Decimal(d) => Value::String(d.to_string()),
+            Typed::Bytes(b) => Value::String(base64(b)),
+            Typed::String(s) => Value::String(s.to_string()),
+            Typed::List(l) => Value::Array(l.iter().map(Typed::to_json).collect::<Result<_>>()?),
+            Typed::Map(m) => Value::Object(
+                m.iter()
+                    .map(|(k, v)| Ok((k.clone(), v.to_json()?)))
+                    .collect::<Result<_>>()?,
+            ),
+            // The map of `PackedArray::to_typed`.
+            Typed::Array(a) => a.to_typed().to_json()?,
+            Typed::Raw(raw) => raw.decode()?.to_json()?,
+        })
+    }
+
+    /// Converts JSON into a value, integers that fit an i64 become
+    /// `Typed::Int`, larger ones `Typed::Uint` and the other numbers
+    /// `Typed::Float`.
+    pub fn from_json(v: Value) -> Typed {
+        match v {
+            Value::Null => Typed::Null,
+            Value::Bool(b) => Typed::Bool(b),
+            Value::Number(n) => {
+                if let Some(n) = n.as_i64() {
+                    Typed::Int(n)
+                } else if let Some(un) = n.as_u64() {
+                    Typed::Uint(un)
+                } else {
+                    Typed::Float(n.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            Value::String(s) => Typed::String(s.into()),
+            Value::Array(l) => Typed::List(l.into_iter().map(Typed::from_json).collect()),
+            Value::Object(m) => Typed::Map(
+                m.into_iter()
+                    .map(|(k, v)| (k, Typed::from_json(v)))
+                    .collect::<Map>(),
+            ),
+        }
+    }
+
+    /// Like `to_json`, but returns the compact JSON text.
+    pub fn to_json_string(&self) -> Result<String> {
+        serde_json::to_string(&self.to_json()?).map_err(|err| Error::InvalidInput(err.to_string()))
+    }
+
+    /// Parses JSON text, like `from_json`.
+    pub fn from_json_str(s: &str) -> Result<Typed> {
+        serde_json::from_str(s)
+            .map(Typed::from_json)
+            .map_err(|err| Error::InvalidData(err.to_string()))
+    }
+}
+
+impl From<Value> for Typed {
+    fn from(v: Value) -> Self {
+        Typed::from_json(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::CodecWriteExt;
+    use crate::convert::FromTyped;
+    use crate::decimal::Decimal;
+    use crate::raw::RawValue;
+    use crate::timestamp::Timestamp;
+    use serde_json::json;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn to_json() {
+        let mut m = Map::new();
+        m.insert("null".into(), Typed::Null);
+        m.insert("bool".into(), Typed::Bool(true));
+        m.insert("int".into(), Typed::Int(-1));
+        m.insert("uint".into(), Typed::Uint(u64::MAX));
+        m.insert("float".into(), Typed::Float(0.5));
+        m.insert("nan".into(), Typed::Float(f64::NAN));
+        m.insert("ts".into(), Typed::Timestamp(Timestamp::from_millis(1)));
+        m.insert("dec".into(), Typed::Decimal(Decimal::new(-1230, 2)));
+        m.insert("bytes".into(), Typed::Bytes(b"foo".to_vec().into()));
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::List(vec![Typed::String("超".into())]))
+            .unwrap();
+        m.insert("raw".into(), Typed::Raw(RawValue::from_encoded(buf)));
+
+        let expected = json!({
+            "null": null,
+            "bool": true,
+            "int": -1,
+            "uint": u64::MAX,
+            "float": 0.5,
+            "nan": null,
+            "ts": "1970-01-01T00:00:00.001Z",
+            "dec": "-12.30",
+            "bytes": "Zm9v",
+            "raw": ["超"],
+        });
+        let e = Typed::Map(m);
+        assert_eq!(e.to_json().unwrap(), expected);
+        let s = e.to_json_string().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&s).unwrap(), expected);
+
+        let raw = Typed::Raw(RawValue::from_encoded(b"x".to_vec()));
+        assert!(raw.to_json().is_err());
+    }
+
+    #[test]
+    fn from_json() {
+        let e = Typed::from_json_str(
+            r#"{"a": [null, false, -1, 18446744073709551615, 1.5, "1970-01-01T00:00:00Z"]}"#,
+        )
+        .unwrap();
+        let mut m = Map::new();
+        m.insert(
+            "a".into(),
+            Typed::List(vec![
+                Typed::Null,
+                Typed::Bool(false),
+                Typed::Int(-1),
+                Typed::Uint(u64::MAX),
+                Typed::Float(1.5),
+                Typed::String("1970-01-01T00:00:00Z".into()),
+            ]),
+        );
+        assert_eq!(e, Typed::Map(m));
+        assert_eq!(Typed::from(json!(1)), Typed::Int(1));
+
+        // Timestamps and decimals survive through the lenient conversions.
+        let ts = Timestamp::new(-1, 5).unwrap();
+        let back = Typed::from_json(Typed::Timestamp(ts).to_json().unwrap());
+        assert_eq!(Timestamp::from_typed_lenient(&back).unwrap(), ts);
+        let d = Decimal::new(5, 3);
+        let back = Typed::from_json(Typed::Decimal(d).to_json().unwrap());
+        assert_eq!(Decimal::from_typed_lenient(&back).unwrap(), d);
+
+        assert!(Typed::from_json_str("{").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::codec::{Map, Typed};
+
+/// KeyMode controls how map keys are transformed while decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyMode {
+    /// Keys are kept as is.
+    #[default]
+    Preserve,
+    /// Keys are lowercased.
+    Lowercase,
+    /// Keys are normalized with `normalize_key`.
+    Normalize,
+}
+
+impl KeyMode {
+    pub(crate) fn apply(self, k: String) -> String {
+        match self {
+            KeyMode::Preserve => k,
+            KeyMode::Lowercase => k.to_lowercase(),
+            KeyMode::Normalize => normalize_key(&k),
+        }
+    }
+}
+
+/// Normalizes a key so that header-like spellings compare equal: the key is
+/// trimmed, lowercased and underscores are replaced with dashes, e.g.
+/// "Content_Type" becomes "content-type".
+pub fn normalize_key(k: &str) -> String {
+    k.trim().to_lowercase().replace('_', "-")
+}
+
+/// MapExt adds lookup helpers to `Map` for documents whose producers
+/// disagree on how keys are spelled.
+pub trait MapExt {
+    /// Looks up `key` ignoring case, an exact match wins.
+    fn get_ci(&self, key: &str) -> Option<&Typed>;
+
+    /// Looks up `key` comparing keys normalized with `normalize_key`, an
+    /// exact match wins.
+    fn get_normalized(&self, key: &str) -> Option<&Typed>;
+}
+
+impl MapExt for Map {
+    fn get_ci(&self, key: &str) -> Option<&Typed> {
+        if let Some(v) = self.get(key) {
+            return Some(v);
+        }
+        let key = key.to_lowercase();
+        self.iter()
+            .find(|(k, _)| k.to_lowercase() == key)
+            .map(|(_, v)| v)
+    }
+
+    fn get_normalized(&self, key: &str) -> Option<&Typed> {
+        if let Some(v) = self.get(key) {
+            return Some(v);
+        }
+        let key = normalize_key(key);
+        self.iter()
+            .find(|(k, _)| normalize_key(k) == key)
+            .map(|(_, v)| v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> Map {
+        let mut m = Map::new();
+        m.insert(
+            String::from("Content-Type"),
+            Typed::String("text/plain".into()),
+        );
+        m.insert(String::from("x_request_id"), Typed::Uint(1));
+        m
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let m = headers();
+        assert_eq!(
+            m.get_ci("content-type"),
+            Some(&Typed::String("text/plain".into()))
+        );
+        assert_eq!(m.get_ci("X_REQUEST_ID"), Some(&Typed::Uint(1)));
+        assert_eq!(m.get_ci("x-request-id"), None);
+    }
+
+    #[test]
+    fn normalized() {
+        let m = headers();
+        assert_eq!(m.get_normalized("X-Request-Id"), Some(&Typed::Uint(1)));
+        assert!(m.get_normalized(" content_type ").is_some());
+        assert_eq!(m.get_normalized("content"), None);
+        assert_eq!(normalize_key("Content_Type"), "content-type");
+    }
+}
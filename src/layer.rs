@@ -0,0 +1,200 @@
+use crate::error::{Error, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Layer transforms the encoded messages of an `Encoder` or `Decoder`, e.g.
+/// to compress, encrypt or checksum them.
+///
+/// The layers added to an `Encoder` are applied in order, the ones added to
+/// a `Decoder` are reverted in reverse order, so both sides are built with
+/// the same sequence of `with` calls.
+pub trait Layer {
+    /// Transforms an encoded message before it is written.
+    fn encode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>>;
+
+    /// Reverts `encode` after a message is read.
+    fn decode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+impl<L: Layer + ?Sized> Layer for Box<L> {
+    fn encode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+        (**self).encode(buf)
+    }
+
+    fn decode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+        (**self).decode(buf)
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = crc_table(0xedb8_8320);
+const CRC32C_TABLE: [u32; 256] = crc_table(0x82f6_3b78);
+
+// Builds the lookup table of the reflected polynomial `poly`.
+const fn crc_table(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { poly ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+fn crc(table: &[u32; 256], buf: &[u8]) -> u32 {
+    let mut c = !0u32;
+    for b in buf {
+        c = table[((c ^ *b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+/// Computes the CRC-32 (IEEE) checksum of `buf`.
+pub(crate) fn crc32(buf: &[u8]) -> u32 {
+    crc(&CRC32_TABLE, buf)
+}
+
+/// Computes the CRC-32C (Castagnoli) checksum of `buf`.
+pub(crate) fn crc32c(buf: &[u8]) -> u32 {
+    crc(&CRC32C_TABLE, buf)
+}
+
+// Appends the little endian checksum `sum` of `buf` to it.
+fn append_checksum(mut buf: Vec<u8>, sum: fn(&[u8]) -> u32) -> Vec<u8> {
+    let n = sum(&buf);
+    buf.extend_from_slice(&n.to_le_bytes());
+    buf
+}
+
+// Verifies and removes the checksum appended by `append_checksum`.
+fn strip_checksum(mut buf: Vec<u8>, sum: fn(&[u8]) -> u32) -> Result<Vec<u8>> {
+    if buf.len() < 4 {
+        return Err(Error::UnexpectedEof);
+    }
+    let n = buf.len() - 4;
+    let mut expected = [0u8; 4];
+    expected.copy_from_slice(&buf[n..]);
+    if u32::from_le_bytes(expected) != sum(&buf[..n]) {
+        return Err(Error::InvalidData("checksum mismatch".into()));
+    }
+    buf.truncate(n);
+    Ok(buf)
+}
+
+/// Crc32 appends a CRC-32 checksum to messages and verifies it on read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc32;
+
+impl Layer for Crc32 {
+    fn encode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(append_checksum(buf, crc32))
+    }
+
+    fn decode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+        strip_checksum(buf, crc32)
+    }
+}
+
+/// Crc32c is like `Crc32` with a CRC-32C (Castagnoli) checksum, the one of
+/// the frames of `FrameWriter`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc32c;
+
+impl Layer for Crc32c {
+    fn encode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(append_checksum(buf, crc32c))
+    }
+
+    fn decode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+        strip_checksum(buf, crc32c)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+}
+
+/// Metrics counts the messages passing through it and their sizes at its
+/// position in the stack, clones share the counters.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Counters>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages(&self) -> u64 {
+        self.counters.messages.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.counters.bytes.load(Ordering::Relaxed)
+    }
+
+    fn observe(&self, buf: &[u8]) {
+        self.counters.messages.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes
+            .fetch_add(buf.len() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Layer for Metrics {
+    fn encode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+        self.observe(&buf);
+        Ok(buf)
+    }
+
+    fn decode(&mut self, buf: Vec<u8>) -> Result<Vec<u8>> {
+        self.observe(&buf);
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_checksum() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+
+        let mut layer = Crc32;
+        let buf = layer.encode(b"hello".to_vec()).unwrap();
+        assert_eq!(buf.len(), 9);
+        assert_eq!(layer.decode(buf.clone()).unwrap(), b"hello".to_vec());
+
+        let mut corrupted = buf;
+        corrupted[0] ^= 1;
+        let err = layer.decode(corrupted).unwrap_err();
+        assert!(matches!(err, Error::InvalidData(_)), "{:?}", err);
+        assert!(layer.decode(vec![1u8]).is_err());
+
+        let mut layer = Crc32c;
+        let buf = layer.encode(b"hello".to_vec()).unwrap();
+        assert_eq!(buf[5..], crc32c(b"hello").to_le_bytes());
+        assert_eq!(layer.decode(buf).unwrap(), b"hello".to_vec());
+    }
+
+    #[test]
+    fn metrics() {
+        let metrics = Metrics::new();
+        let mut layer = metrics.clone();
+        layer.encode(vec![0u8; 3]).unwrap();
+        layer.decode(vec![0u8; 4]).unwrap();
+        assert_eq!(metrics.messages(), 2);
+        assert_eq!(metrics.bytes(), 7);
+    }
+}
@@ -1,5 +1,167 @@
-mod codec;
+// `ByteBuf` and `Str` are plain `Vec<u8>` and `String` without the `bytes`
+// and `smol_str` features, converting into them is required to compile with
+// and without the features.
+#![cfg_attr(
+    not(all(feature = "bytes", feature = "smol_str")),
+    allow(clippy::useless_conversion)
+)]
+
+extern crate self as xdcodec_rs;
+
+// Everything but the varint module is only built with the `codec` feature.
+macro_rules! cfg_codec {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "codec")]
+            $item
+        )*
+    };
+}
+
+mod error;
+// Not in `cfg_codec!`, the exported macros of a macro-expanded module
+// can't be referred to by `$crate::` paths.
+#[cfg(feature = "codec")]
+mod macros;
 mod varint;
+pub use crate::error::{Error, Result};
+pub use crate::varint::{
+    decode_uvarint, decode_varint, encode_uvarint, encode_varint, uvarint_len, varint_len,
+    VarintReadExt, VarintWriteExt, MAX_VARINT_LEN,
+};
+
+cfg_codec! {
+    mod access;
+    mod array;
+    #[cfg(feature = "tokio")]
+    mod async_codec;
+    mod batch;
+    mod bloom;
+    mod codec;
+    mod compact;
+    mod compress;
+    mod config;
+    mod convert;
+    mod cursor;
+    mod decimal;
+    mod delta;
+    mod document;
+    mod encoded_reader;
+    mod encoder;
+    mod frame;
+    mod event_writer;
+    mod frozen;
+    mod incremental;
+    #[cfg(feature = "rand")]
+    mod generate;
+    pub mod ipc;
+    #[cfg(feature = "json")]
+    mod json;
+    mod keys;
+    mod layer;
+    mod limits;
+    mod loose;
+    mod mux;
+    mod number;
+    mod options;
+    mod ordered_map;
+    mod parser;
+    mod path;
+    mod raw;
+    mod registry;
+    mod rewrite;
+    mod sanitize;
+    mod schema;
+    #[cfg(feature = "serde")]
+    pub mod serde_codec;
+    mod schema_export;
+    mod shape;
+    #[cfg(feature = "bytes")]
+    mod shared_bytes;
+    mod shared_writer;
+    mod slice;
+    mod stats;
+    mod text;
+    mod timestamp;
+    #[cfg(feature = "tokio")]
+    mod tokio_codec;
+    mod trace;
+    mod typed_ref;
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    mod temporal;
+    pub mod testing;
+    #[cfg(feature = "testvectors")]
+    pub mod testvectors;
+    #[cfg(feature = "unicode-normalization")]
+    mod unicode;
+    mod value;
+    mod walk;
+
+    pub use crate::array::{DType, Element, PackedArray};
+    #[cfg(feature = "tokio")]
+    pub use crate::async_codec::{AsyncCodecReadExt, AsyncCodecWriteExt};
+    pub use crate::batch::{Batch, BatchBuilder};
+    pub use crate::bloom::{KeyBloom, KeyBloomReadExt, KeyBloomWriteExt};
+    pub use crate::codec::{
+        encode, encode_into, encoded_len, ByteBuf, CodecReadExt, CodecWriteExt, List, Map,
+        RawKeyMap, Str, Typed,
+    };
+    #[cfg(feature = "flate2")]
+    pub use crate::compress::Compression;
+    pub use crate::config::{Codec, Config, FormatVersion};
+    pub use crate::convert::{FromMap, FromTyped, ToMap, ToTyped, XdDecode, XdEncode};
+    pub use crate::cursor::Cursor;
+    pub use crate::decimal::Decimal;
+    pub use crate::delta::{apply_delta, delta, delta_with, Delta};
+    pub use crate::document::{Document, Elements, Entries, ValueRef};
+    pub use crate::encoded_reader::EncodedValueReader;
+    pub use crate::encoder::{Decoder, Encoder};
+    pub use crate::frame::{FrameWriter, Frames};
+    pub use crate::event_writer::EventWriter;
+    pub use crate::frozen::{FrozenList, FrozenMap, FrozenTyped};
+    pub use crate::incremental::IncrementalDecoder;
+    pub use crate::keys::{normalize_key, KeyMode, MapExt};
+    pub use crate::layer::{Crc32, Crc32c, Layer, Metrics};
+    pub use crate::limits::Limits;
+    pub use crate::loose::LooseEqOptions;
+    pub use crate::mux::{MuxReader, MuxWriter};
+    pub use crate::number::Number;
+    pub use crate::options::DecodeOptions;
+    pub use crate::ordered_map::OrderedMap;
+    pub use crate::parser::{Event, Parser};
+    pub use crate::path::{Path, PathSegment};
+    pub use crate::raw::RawValue;
+    pub use crate::registry::{read_stamp, SchemaRegistry, Stamped};
+    pub use crate::rewrite::{rewrite, Action};
+    pub use crate::sanitize::SanitizeReport;
+    pub use crate::schema::{Describe, Field, Schema};
+    pub use crate::shape::Strictness;
+    pub use crate::stats::{Largest, TypeStats, WireStats};
+    pub use crate::text::TextEncoding;
+    pub use crate::timestamp::Timestamp;
+    #[cfg(feature = "tokio")]
+    pub use crate::tokio_codec::XdCodec;
+    pub use crate::trace::{Trace, TraceEntry, TraceEvent};
+    pub use crate::typed_ref::{decode_ref, decode_ref_with, TypedRef};
+    pub use crate::value::{Change, Conflict, MergeOptions};
+    #[cfg(feature = "bytes")]
+    pub use crate::shared_bytes::{
+        BufExt, BufMutExt, read_typed_from_buf, read_typed_from_buf_with, read_typed_from_bytes,
+        read_typed_from_bytes_with,
+    };
+    pub use crate::shared_writer::SharedFrameWriter;
+
+    pub use crate::walk::{BreadthFirst, DepthFirst};
+
+    #[cfg(feature = "derive")]
+    pub use xdcodec_derive::{Describe, FromMap, ToMap, XdDecode, XdEncode};
 
-pub use crate::codec::{CodecReadExt, CodecWriteExt};
-pub use crate::varint::{VarintReadExt, VarintWriteExt};
+    #[doc(hidden)]
+    pub mod __private {
+        pub use crate::convert::{
+            extend_extra, extra_fields, from_field, from_field_lenient, from_field_or_default,
+            from_field_or_default_lenient, from_map_value,
+            from_map_value_lenient, from_optional_field, from_optional_field_lenient,
+        };
+    }
+}
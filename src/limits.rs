@@ -0,0 +1,122 @@
+use crate::error::{Error, Result};
+use std::convert::TryFrom;
+
+/// Limits caps the size of values, see `Typed::sanitize` and
+/// `DecodeOptions::limits`. The defaults keep untrusted input from
+/// allocating much or nesting deep enough to overflow the stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum length of strings, bytes and map keys, in bytes.
+    pub max_bytes_len: usize,
+    /// The maximum nesting depth of lists and maps, the root list or map is
+    /// at depth 1.
+    pub max_depth: usize,
+    /// The maximum number of elements in a single list or map.
+    pub max_container_len: usize,
+    /// The maximum number of elements in all the lists and maps of a value.
+    pub max_elements: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_bytes_len: 16 << 20,
+            max_depth: 64,
+            max_container_len: usize::MAX,
+            max_elements: 1 << 20,
+        }
+    }
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits which never cut anything, for input which is trusted.
+    pub fn unlimited() -> Self {
+        Limits {
+            max_bytes_len: usize::MAX,
+            max_depth: usize::MAX,
+            max_container_len: usize::MAX,
+            max_elements: usize::MAX,
+        }
+    }
+
+    pub fn max_bytes_len(mut self, n: usize) -> Self {
+        self.max_bytes_len = n;
+        self
+    }
+
+    pub fn max_depth(mut self, n: usize) -> Self {
+        self.max_depth = n;
+        self
+    }
+
+    pub fn max_container_len(mut self, n: usize) -> Self {
+        self.max_container_len = n;
+        self
+    }
+
+    pub fn max_elements(mut self, n: usize) -> Self {
+        self.max_elements = n;
+        self
+    }
+}
+
+/// Budget tracks the depth and the number of elements of a single value
+/// while it is decoded, so the limits are enforced before anything is
+/// allocated.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    limits: Limits,
+    depth: usize,
+    elements: usize,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Budget {
+            limits: limits.clone(),
+            depth: 0,
+            elements: 0,
+        }
+    }
+
+    /// Checks the length of a string, bytes or a map key.
+    pub(crate) fn bytes(&self, n: u64) -> Result<()> {
+        if n > self.limits.max_bytes_len as u64 {
+            return Err(Error::TooLong(n));
+        }
+        Ok(())
+    }
+
+    /// Enters a list or map of `nelem` elements, `leave` is called once
+    /// they are read.
+    pub(crate) fn enter(&mut self, nelem: u64) -> Result<()> {
+        self.depth += 1;
+        if self.depth > self.limits.max_depth {
+            return Err(Error::DepthExceeded(self.limits.max_depth));
+        }
+        if nelem > self.limits.max_container_len as u64 {
+            return Err(Error::ContainerTooLarge(nelem));
+        }
+        self.elements = self
+            .elements
+            .saturating_add(usize::try_from(nelem).unwrap_or(usize::MAX));
+        if self.elements > self.limits.max_elements {
+            return Err(Error::ContainerTooLarge(self.elements as u64));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Starts over for another value.
+    pub(crate) fn reset(&mut self) {
+        self.depth = 0;
+        self.elements = 0;
+    }
+}
@@ -0,0 +1,111 @@
+use crate::codec::{Map, Typed};
+
+/// LooseEqOptions customizes `Typed::loose_eq_with`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LooseEqOptions {
+    /// Whether a map entry whose value is empty (null, an empty string,
+    /// bytes, list or map) equals a missing entry, since some encoders omit
+    /// them.
+    pub missing_as_empty: bool,
+}
+
+impl LooseEqOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn missing_as_empty(mut self, yes: bool) -> Self {
+        self.missing_as_empty = yes;
+        self
+    }
+}
+
+fn is_empty(e: &Typed) -> bool {
+    match e {
+        Typed::Null => true,
+        Typed::Bytes(buf) => buf.is_empty(),
+        Typed::String(s) => s.is_empty(),
+        Typed::List(l) => l.is_empty(),
+        Typed::Map(m) => m.is_empty(),
+        Typed::Array(a) => a.is_empty(),
+        _ => false,
+    }
+}
+
+impl Typed {
+    /// Compares structurally like `==`, except that numbers are compared by
+    /// their values across variants (see `Number`), and `Typed::Raw` values
+    /// are decoded first.
+    pub fn loose_eq(&self, other: &Typed) -> bool {
+        self.loose_eq_with(other, &LooseEqOptions::default())
+    }
+
+    pub fn loose_eq_with(&self, other: &Typed, opts: &LooseEqOptions) -> bool {
+        if let Typed::Raw(raw) = self {
+            return raw.decode().is_ok_and(|e| e.loose_eq_with(other, opts));
+        }
+        if let Typed::Raw(raw) = other {
+            return raw.decode().is_ok_and(|e| self.loose_eq_with(&e, opts));
+        }
+        if let (Some(x), Some(y)) = (self.as_number(), other.as_number()) {
+            return x == y;
+        }
+        match (self, other) {
+            (Typed::List(x), Typed::List(y)) => {
+                x.len() == y.len()
+                    && x.iter()
+                        .zip(y.iter())
+                        .all(|(x, y)| x.loose_eq_with(y, opts))
+            }
+            (Typed::Map(x), Typed::Map(y)) => maps_loose_eq(x, y, opts),
+            _ => self == other,
+        }
+    }
+}
+
+fn maps_loose_eq(x: &Map, y: &Map, opts: &LooseEqOptions) -> bool {
+    let contains = |x: &Map, y: &Map| {
+        x.iter().all(|(k, v)| match y.get(k) {
+            Some(w) => v.loose_eq_with(w, opts),
+            None => opts.missing_as_empty && is_empty(v),
+        })
+    };
+    contains(x, y) && contains(y, x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::RawValue;
+
+    #[test]
+    fn numbers() {
+        assert!(Typed::Int(5).loose_eq(&Typed::Uint(5)));
+        assert!(Typed::Uint(5).loose_eq(&Typed::Float(5.0)));
+        assert!(!Typed::Int(5).loose_eq(&Typed::Float(5.5)));
+        assert!(!Typed::Int(5).loose_eq(&Typed::String("5".into())));
+        assert!(Typed::List(vec![Typed::Int(1), Typed::Float(2.0)])
+            .loose_eq(&Typed::List(vec![Typed::Uint(1), Typed::Int(2)])));
+        assert!(!Typed::List(vec![Typed::Int(1)])
+            .loose_eq(&Typed::List(vec![Typed::Int(1), Typed::Int(1)])));
+        let raw = RawValue::encode(&Typed::Uint(3)).unwrap();
+        assert!(Typed::Raw(raw.clone()).loose_eq(&Typed::Int(3)));
+        assert!(Typed::Float(3.0).loose_eq(&Typed::Raw(raw)));
+    }
+
+    #[test]
+    fn maps() {
+        let mut x = Map::new();
+        x.insert(String::from("n"), Typed::Int(1));
+        x.insert(String::from("tags"), Typed::List(vec![]));
+        let mut y = Map::new();
+        y.insert(String::from("n"), Typed::Float(1.0));
+        let (x, y) = (Typed::Map(x), Typed::Map(y));
+
+        assert!(!x.loose_eq(&y));
+        assert!(!y.loose_eq(&x));
+        let opts = LooseEqOptions::new().missing_as_empty(true);
+        assert!(x.loose_eq_with(&y, &opts));
+        assert!(y.loose_eq_with(&x, &opts));
+    }
+}
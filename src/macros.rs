@@ -0,0 +1,133 @@
+/// Builds a `Typed` with a JSON like syntax, e.g.
+///
+/// ```
+/// use xdcodec_rs::{typed, Typed};
+///
+/// let id = 42;
+/// let e = typed!({"user": {"id": id, "tags": ["a", "b"], "email": null}});
+/// assert_eq!(e.get("user").and_then(|u| u.get("id")), Some(&Typed::Int(42)));
+/// ```
+///
+/// `null` is `Typed::Null`, `[...]` a list and `{...}` a map, any other
+/// expression is converted with `Typed::from`. Map keys are string literals
+/// or expressions in parentheses which convert into `String`.
+#[macro_export]
+macro_rules! typed {
+    (null) => {
+        $crate::Typed::Null
+    };
+    ([ $($elem:tt),* $(,)? ]) => {
+        $crate::Typed::List(::std::vec![$($crate::typed!($elem)),*])
+    };
+    ([ $($tt:tt)+ ]) => {
+        $crate::Typed::List($crate::typed!(@list [] () $($tt)+))
+    };
+    ({ $($tt:tt)* }) => {
+        $crate::Typed::Map($crate::map!($($tt)*))
+    };
+
+    // Munches the elements of a list which are not single tokens, each
+    // element is collected into the parentheses until the next comma.
+    (@list [$($elems:expr,)*] ()) => {
+        ::std::vec![$($elems,)*]
+    };
+    (@list [$($elems:expr,)*] ($($cur:tt)+) $(, $($rest:tt)*)?) => {
+        $crate::typed!(@list [$($elems,)* $crate::typed!($($cur)+),] () $($($rest)*)?)
+    };
+    (@list [$($elems:expr,)*] ($($cur:tt)*) $next:tt $($rest:tt)*) => {
+        $crate::typed!(@list [$($elems,)*] ($($cur)* $next) $($rest)*)
+    };
+
+    ($e:expr) => {
+        $crate::Typed::from($e)
+    };
+}
+
+/// Builds a `Map`, the entries are written like in the maps of `typed!`,
+/// e.g. `map!{"id": 42, "tags": ["a", "b"]}`.
+#[macro_export]
+macro_rules! map {
+    // Munches the entries whose values are not single tokens, each value
+    // is collected into the parentheses until the next comma.
+    (@entry $m:ident ()) => {};
+    (@entry $m:ident () $key:tt : $($rest:tt)+) => {
+        $crate::map!(@value $m $key () $($rest)+)
+    };
+    (@value $m:ident $key:tt ($($cur:tt)+) $(, $($rest:tt)*)?) => {
+        $m.insert(::std::string::String::from($key), $crate::typed!($($cur)+));
+        $crate::map!(@entry $m () $($($rest)*)?)
+    };
+    (@value $m:ident $key:tt ($($cur:tt)*) $next:tt $($rest:tt)*) => {
+        $crate::map!(@value $m $key ($($cur)* $next) $($rest)*)
+    };
+
+    () => {
+        $crate::Map::new()
+    };
+    ($($key:tt : $value:tt),+ $(,)?) => {{
+        let mut m = $crate::Map::new();
+        $(
+            m.insert(::std::string::String::from($key), $crate::typed!($value));
+        )*
+        m
+    }};
+    ($($tt:tt)+) => {{
+        let mut m = $crate::Map::new();
+        $crate::map!(@entry m () $($tt)+);
+        m
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::{Map, Typed};
+    use crate::timestamp::Timestamp;
+
+    #[test]
+    fn typed() {
+        assert_eq!(typed!(null), Typed::Null);
+        assert_eq!(typed!(-1), Typed::Int(-1));
+        assert_eq!(typed!("x"), Typed::String("x".into()));
+        assert_eq!(typed!([]), Typed::List(vec![]));
+        assert_eq!(typed!({}), Typed::Map(Map::new()));
+
+        let n = 2u64;
+        let l = typed!([1, n + 1, "x", [true, null], {"k": -0.5},]);
+        assert_eq!(
+            l,
+            Typed::List(vec![
+                Typed::Int(1),
+                Typed::Uint(3),
+                Typed::String("x".into()),
+                Typed::List(vec![Typed::Bool(true), Typed::Null]),
+                Typed::Map(map! {"k": Typed::Float(-0.5)}),
+            ])
+        );
+
+        let key = String::from("dynamic");
+        let ts = Timestamp::from_millis(1);
+        let e = typed!({
+            "user": {"id": 42, "tags": ["a", "b"]},
+            (key.as_str()): Some(ts),
+            "missing": None::<i64>,
+            "bytes": b"xd".to_vec(),
+        });
+        let mut user = Map::new();
+        user.insert("id".into(), Typed::Int(42));
+        user.insert("tags".into(), typed!(["a", "b"]));
+        let mut m = Map::new();
+        m.insert("user".into(), Typed::Map(user));
+        m.insert("dynamic".into(), Typed::Timestamp(ts));
+        m.insert("missing".into(), Typed::Null);
+        m.insert("bytes".into(), Typed::Bytes(b"xd".to_vec().into()));
+        assert_eq!(e, Typed::Map(m));
+    }
+
+    #[test]
+    fn map() {
+        assert!(map! {}.is_empty());
+        let m = map! {"a": 1, "b": [1, 2]};
+        assert_eq!(m.len(), 2);
+        assert_eq!(m["b"], typed!([1, 2]));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::codec::{read_sized_limited, CodecReadExt, CodecWriteExt, Typed};
+use crate::error::{Error, Result};
+use crate::limits::Budget;
+use crate::options::DecodeOptions;
+use crate::varint::{VarintReadExt, VarintWriteExt};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// MuxWriter writes values of many logical streams into one writer. Each
+/// frame is length prefixed and holds the uvarint stream id followed by the
+/// encoded value.
+pub struct MuxWriter<W> {
+    writer: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> MuxWriter<W> {
+    pub fn new(writer: W) -> Self {
+        MuxWriter {
+            writer,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, stream: u64, e: &Typed) -> Result<()> {
+        self.buf.clear();
+        self.buf.write_uvarint(stream)?;
+        self.buf.write_typed(e)?;
+        self.writer.write_sized(&self.buf)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// MuxReader reads the frames written by a `MuxWriter` and routes the values
+/// to the channels of their streams, see `subscribe`. The values of streams
+/// nobody subscribed to are dropped.
+pub struct MuxReader<R> {
+    reader: R,
+    opts: DecodeOptions,
+    streams: HashMap<u64, Sender<Typed>>,
+}
+
+impl<R: Read> MuxReader<R> {
+    pub fn new(reader: R) -> Self {
+        MuxReader {
+            reader,
+            opts: DecodeOptions::default(),
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn options(mut self, opts: DecodeOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Returns the channel of the values of `stream`, subscribing again
+    /// replaces the previous channel.
+    pub fn subscribe(&mut self, stream: u64) -> Receiver<Typed> {
+        let (tx, rx) = channel();
+        self.streams.insert(stream, tx);
+        rx
+    }
+
+    /// Reads the next frame, `None` means the reader ended between frames.
+    /// A frame longer than `Limits::max_bytes_len` is rejected before it is
+    /// read.
+    pub fn read_frame(&mut self) -> Result<Option<(u64, Typed)>> {
+        let mut first = [0u8; 1];
+        if self.reader.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+        let mut r = (&first[..]).chain(&mut self.reader);
+        let body = read_sized_limited(&mut r, &Budget::new(&self.opts.limits))?;
+        let mut body = &body[..];
+        let stream = body.read_uvarint()?;
+        let e = body.read_typed_with(&self.opts)?;
+        if !body.is_empty() {
+            return Err(Error::TrailingBytes);
+        }
+        Ok(Some((stream, e)))
+    }
+
+    /// Reads and routes the next frame, returns false once the reader ended.
+    pub fn dispatch(&mut self) -> Result<bool> {
+        let (stream, e) = match self.read_frame()? {
+            Some(frame) => frame,
+            None => return Ok(false),
+        };
+        if let Some(tx) = self.streams.get(&stream) {
+            if tx.send(e).is_err() {
+                // The receiver is gone.
+                self.streams.remove(&stream);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Routes all the frames until the reader ends, the channels are closed
+    /// when it returns.
+    pub fn run(mut self) -> Result<()> {
+        while self.dispatch()? {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+    use std::io::ErrorKind;
+    use std::thread;
+
+    #[test]
+    fn route() {
+        let mut w = MuxWriter::new(Vec::new());
+        for i in 0..10 {
+            w.write(i % 3, &Typed::Uint(i)).unwrap();
+        }
+        w.write(1000, &Typed::String("control".into())).unwrap();
+        let buf = w.into_inner();
+
+        let mut r = MuxReader::new(std::io::Cursor::new(buf));
+        let metrics = r.subscribe(0);
+        let logs = r.subscribe(1);
+        let control = r.subscribe(1000);
+        drop(logs);
+        thread::spawn(move || r.run()).join().unwrap().unwrap();
+
+        assert_eq!(
+            metrics.iter().collect::<Vec<_>>(),
+            vec![
+                Typed::Uint(0),
+                Typed::Uint(3),
+                Typed::Uint(6),
+                Typed::Uint(9)
+            ]
+        );
+        assert_eq!(
+            control.iter().collect::<Vec<_>>(),
+            vec![Typed::String("control".into())]
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let mut w = MuxWriter::new(Vec::new());
+        w.write(7, &Typed::Int(-1)).unwrap();
+        let buf = w.into_inner();
+        assert_eq!(buf, b"\x03\x07i\x01");
+
+        let mut r = MuxReader::new(&buf[..]);
+        assert_eq!(r.read_frame().unwrap(), Some((7, Typed::Int(-1))));
+        assert_eq!(r.read_frame().unwrap(), None);
+
+        let mut r = MuxReader::new(&buf[..3]);
+        let err = r.read_frame().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert!(MuxReader::new(&b"\x04\x07i\x01\x00"[..]).run().is_err());
+    }
+
+    #[test]
+    fn frame_too_long() {
+        let mut w = MuxWriter::new(Vec::new());
+        w.write(1, &Typed::String("x".repeat(100).into())).unwrap();
+        let buf = w.into_inner();
+
+        let opts = DecodeOptions::default().limits(Limits::new().max_bytes_len(64));
+        let mut r = MuxReader::new(&buf[..]).options(opts);
+        assert!(matches!(r.read_frame(), Err(Error::TooLong(103))));
+    }
+}
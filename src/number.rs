@@ -0,0 +1,208 @@
+use crate::codec::Typed;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+/// Number is a view of the numeric values, it compares and converts across
+/// `Typed::Int`, `Typed::Uint` and `Typed::Float` by their mathematical
+/// values, e.g. `Int(5)`, `Uint(5)` and `Float(5.0)` are all equal.
+#[derive(Debug, Clone, Copy)]
+pub enum Number {
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+}
+
+// 2^127, every float whose magnitude is below it truncates into an i128.
+const I128_BOUND: f64 = 170_141_183_460_469_231_731_687_303_715_884_105_728.0;
+
+fn cmp_int_float(n: i128, f: f64) -> Option<Ordering> {
+    if f.is_nan() {
+        return None;
+    }
+    if f >= I128_BOUND {
+        return Some(Ordering::Less);
+    }
+    if f < -I128_BOUND {
+        return Some(Ordering::Greater);
+    }
+    let t = f.trunc();
+    match n.cmp(&(t as i128)) {
+        Ordering::Equal => 0.0.partial_cmp(&(f - t)),
+        ord => Some(ord),
+    }
+}
+
+impl Number {
+    fn as_i128(self) -> Option<i128> {
+        match self {
+            Number::Int(n) => Some(n as i128),
+            Number::Uint(un) => Some(un as i128),
+            Number::Float(_) => None,
+        }
+    }
+
+    /// Returns the value as an i64 if it is exactly representable.
+    pub fn as_i64(self) -> Option<i64> {
+        match self {
+            Number::Int(n) => Some(n),
+            Number::Uint(un) => i64::try_from(un).ok(),
+            Number::Float(f) => {
+                if f.fract() == 0.0 && f >= i64::MIN as f64 && f < -(i64::MIN as f64) {
+                    Some(f as i64)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Returns the value as an u64 if it is exactly representable.
+    pub fn as_u64(self) -> Option<u64> {
+        match self {
+            Number::Int(n) => u64::try_from(n).ok(),
+            Number::Uint(un) => Some(un),
+            Number::Float(f) => {
+                if f.fract() == 0.0 && f >= 0.0 && f < u64::MAX as f64 {
+                    Some(f as u64)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Returns the value as an f64 if it is exactly representable.
+    pub fn as_f64(self) -> Option<f64> {
+        match self {
+            Number::Float(f) => Some(f),
+            _ => {
+                let n = self.as_i128().unwrap();
+                let f = n as f64;
+                if f < I128_BOUND && f as i128 == n {
+                    Some(f)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Returns the value as an f64, rounding large integers.
+    pub fn to_f64_lossy(self) -> f64 {
+        match self {
+            Number::Int(n) => n as f64,
+            Number::Uint(un) => un as f64,
+            Number::Float(f) => f,
+        }
+    }
+
+    pub fn is_integer(self) -> bool {
+        match self {
+            Number::Float(f) => f.is_finite() && f.fract() == 0.0,
+            _ => true,
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        match (self.as_i128(), other.as_i128()) {
+            (Some(x), Some(y)) => Some(x.cmp(&y)),
+            (Some(x), None) => cmp_int_float(x, other.to_f64_lossy()),
+            (None, Some(y)) => cmp_int_float(y, self.to_f64_lossy()).map(Ordering::reverse),
+            (None, None) => self.to_f64_lossy().partial_cmp(&other.to_f64_lossy()),
+        }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        Number::Int(n)
+    }
+}
+
+impl From<u64> for Number {
+    fn from(un: u64) -> Self {
+        Number::Uint(un)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(f: f64) -> Self {
+        Number::Float(f)
+    }
+}
+
+impl From<Number> for Typed {
+    fn from(n: Number) -> Self {
+        match n {
+            Number::Int(n) => Typed::Int(n),
+            Number::Uint(un) => Typed::Uint(un),
+            Number::Float(f) => Typed::Float(f),
+        }
+    }
+}
+
+impl Typed {
+    /// Returns the numeric view of an int, uint or float.
+    pub fn as_number(&self) -> Option<Number> {
+        match self {
+            Typed::Int(n) => Some(Number::Int(*n)),
+            Typed::Uint(un) => Some(Number::Uint(*un)),
+            Typed::Float(f) => Some(Number::Float(*f)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare() {
+        let five = [Number::Int(5), Number::Uint(5), Number::Float(5.0)];
+        for x in five.iter() {
+            for y in five.iter() {
+                assert_eq!(x, y);
+            }
+        }
+        assert!(Number::Int(-1) < Number::Uint(0));
+        assert!(Number::Uint(u64::MAX) > Number::Int(i64::MAX));
+        assert!(Number::Int(5) < Number::Float(5.5));
+        assert!(Number::Float(-5.5) < Number::Int(-5));
+        assert!(Number::Float(f64::INFINITY) > Number::Uint(u64::MAX));
+        assert!(Number::Float(f64::NEG_INFINITY) < Number::Int(i64::MIN));
+        assert_ne!(Number::Float(f64::NAN), Number::Float(f64::NAN));
+        assert_eq!(Number::Float(f64::NAN).partial_cmp(&Number::Int(0)), None);
+        // 2^63 as a float is not i64::MAX, even though casting saturates.
+        assert_ne!(Number::Float(i64::MAX as f64), Number::Int(i64::MAX));
+        assert_eq!(Number::Float(i64::MAX as f64), Number::Uint(1 << 63));
+    }
+
+    #[test]
+    fn convert() {
+        assert_eq!(Number::Float(-3.0).as_i64(), Some(-3));
+        assert_eq!(Number::Float(-3.5).as_i64(), None);
+        assert_eq!(Number::Float(i64::MAX as f64).as_i64(), None);
+        assert_eq!(Number::Float(i64::MIN as f64).as_i64(), Some(i64::MIN));
+        assert_eq!(Number::Float(u64::MAX as f64).as_u64(), None);
+        assert_eq!(Number::Int(-1).as_u64(), None);
+        assert_eq!(Number::Uint(u64::MAX).as_i64(), None);
+        assert_eq!(Number::Uint(1 << 53).as_f64(), Some(9007199254740992.0));
+        assert_eq!(Number::Uint((1 << 53) + 1).as_f64(), None);
+        assert_eq!(Number::Int(i64::MAX).as_f64(), None);
+        assert!(Number::Float(2.0).is_integer());
+        assert!(!Number::Float(f64::INFINITY).is_integer());
+
+        assert_eq!(Typed::Uint(5).as_number(), Some(Number::Float(5.0)));
+        assert!(Typed::String("5".into()).as_number().is_none());
+        assert_eq!(Typed::from(Number::Int(-1)), Typed::Int(-1));
+    }
+}
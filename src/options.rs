@@ -0,0 +1,96 @@
+use crate::error::Result;
+use crate::keys::KeyMode;
+use crate::limits::Limits;
+use crate::text::TextEncoding;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::str;
+#[cfg(feature = "unicode-normalization")]
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// DecodeOptions customizes how values are decoded, see
+/// `CodecReadExt::read_typed_with`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecodeOptions {
+    /// How map keys are transformed, keys which end up equal overwrite the
+    /// previous entries.
+    pub key_mode: KeyMode,
+    /// Whether strings and map keys are NFC normalized, so that keys which
+    /// only differ in Unicode composition end up equal.
+    #[cfg(feature = "unicode-normalization")]
+    pub nfc: bool,
+    /// The encoding of strings and map keys.
+    pub text_encoding: TextEncoding,
+    /// The encodings of the strings under map entries by their keys, as
+    /// the keys end up after `key_mode`, they override `text_encoding` for
+    /// the whole value of the entry, except for the nested entries which
+    /// have encodings of their own.
+    pub field_encodings: HashMap<String, TextEncoding>,
+    /// Whether invalid UTF-8 in strings and map keys is an error rather
+    /// than replaced with U+FFFD.
+    pub strict_utf8: bool,
+    /// The limits values are checked against while they are decoded, use
+    /// `Limits::unlimited` for trusted input only.
+    pub limits: Limits,
+}
+
+impl DecodeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn key_mode(mut self, mode: KeyMode) -> Self {
+        self.key_mode = mode;
+        self
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    pub fn nfc(mut self, nfc: bool) -> Self {
+        self.nfc = nfc;
+        self
+    }
+
+    pub fn text_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.text_encoding = encoding;
+        self
+    }
+
+    pub fn field_encoding<K: Into<String>>(mut self, key: K, encoding: TextEncoding) -> Self {
+        self.field_encodings.insert(key.into(), encoding);
+        self
+    }
+
+    pub fn strict_utf8(mut self, yes: bool) -> Self {
+        self.strict_utf8 = yes;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub(crate) fn text<'a>(&self, enc: TextEncoding, buf: &'a [u8]) -> Result<Cow<'a, str>> {
+        if self.strict_utf8 && enc == TextEncoding::Utf8 {
+            return Ok(Cow::Borrowed(str::from_utf8(buf)?));
+        }
+        Ok(enc.decode(buf))
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    pub(crate) fn string<'a>(&self, s: Cow<'a, str>) -> Cow<'a, str> {
+        if self.nfc && !is_nfc(&s) {
+            return Cow::Owned(s.nfc().collect());
+        }
+        s
+    }
+
+    #[cfg(not(feature = "unicode-normalization"))]
+    pub(crate) fn string<'a>(&self, s: Cow<'a, str>) -> Cow<'a, str> {
+        s
+    }
+
+    pub(crate) fn key(&self, k: Cow<str>) -> String {
+        self.key_mode.apply(self.string(k).into_owned())
+    }
+}
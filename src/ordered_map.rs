@@ -0,0 +1,174 @@
+use crate::codec::{Map, Typed};
+use crate::convert::ToTyped;
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::ops::Index;
+
+/// OrderedMap is a map which keeps its entries in insertion order, for maps
+/// whose order matters, e.g. headers, see `CodecWriteExt::write_ordered_map`
+/// and `CodecReadExt::read_ordered_map`. Inserting an existing key replaces
+/// the value in place.
+#[derive(Debug, Clone, Default)]
+pub struct OrderedMap {
+    entries: Vec<(String, Typed)>,
+    indices: HashMap<String, usize>,
+}
+
+impl OrderedMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(n: usize) -> Self {
+        OrderedMap {
+            entries: Vec::with_capacity(n),
+            indices: HashMap::with_capacity(n),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Typed> {
+        self.indices.get(key).map(|i| &self.entries[*i].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Typed> {
+        let i = *self.indices.get(key)?;
+        Some(&mut self.entries[i].1)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    /// Returns the previous value of `key`, whose position is kept.
+    pub fn insert<K: Into<String>>(&mut self, key: K, v: Typed) -> Option<Typed> {
+        let key = key.into();
+        if let Some(i) = self.indices.get(&key) {
+            return Some(std::mem::replace(&mut self.entries[*i].1, v));
+        }
+        self.indices.insert(key.clone(), self.entries.len());
+        self.entries.push((key, v));
+        None
+    }
+
+    /// Removes `key`, the entries after it are shifted, so it takes linear
+    /// time.
+    pub fn remove(&mut self, key: &str) -> Option<Typed> {
+        let i = self.indices.remove(key)?;
+        let (_, v) = self.entries.remove(i);
+        for j in self.indices.values_mut() {
+            if *j > i {
+                *j -= 1;
+            }
+        }
+        Some(v)
+    }
+
+    /// Iterates over the entries in order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&String, &Typed)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = &String> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+}
+
+/// Maps are equal if they have the same entries in the same order.
+impl PartialEq for OrderedMap {
+    fn eq(&self, other: &OrderedMap) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl Index<&str> for OrderedMap {
+    type Output = Typed;
+
+    fn index(&self, key: &str) -> &Typed {
+        self.get(key).expect("key not found")
+    }
+}
+
+impl<K: Into<String>> FromIterator<(K, Typed)> for OrderedMap {
+    fn from_iter<I: IntoIterator<Item = (K, Typed)>>(iter: I) -> Self {
+        let mut m = OrderedMap::new();
+        for (k, v) in iter {
+            m.insert(k, v);
+        }
+        m
+    }
+}
+
+impl IntoIterator for OrderedMap {
+    type Item = (String, Typed);
+    type IntoIter = std::vec::IntoIter<(String, Typed)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// The order is lost.
+impl From<OrderedMap> for Map {
+    fn from(m: OrderedMap) -> Self {
+        m.entries.into_iter().collect()
+    }
+}
+
+/// The order is lost, it is a `Typed::Map`.
+impl ToTyped for OrderedMap {
+    fn to_typed(&self) -> Typed {
+        Typed::Map(self.entries.iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecReadExt, CodecWriteExt};
+
+    #[test]
+    fn order() {
+        let mut m: OrderedMap = vec![("c", Typed::Int(1)), ("a", Typed::Int(2))]
+            .into_iter()
+            .collect();
+        assert_eq!(m.insert("b", Typed::Int(3)), None);
+        assert_eq!(m.insert("c", Typed::Int(4)), Some(Typed::Int(1)));
+        assert_eq!(m.keys().collect::<Vec<_>>(), vec!["c", "a", "b"]);
+        assert_eq!(m["c"], Typed::Int(4));
+
+        assert_eq!(m.remove("c"), Some(Typed::Int(4)));
+        assert_eq!(m.remove("c"), None);
+        assert_eq!(m.get("b"), Some(&Typed::Int(3)));
+        *m.get_mut("a").unwrap() = Typed::Null;
+        assert_eq!(
+            m.clone().into_iter().collect::<Vec<_>>(),
+            vec![
+                ("a".to_string(), Typed::Null),
+                ("b".to_string(), Typed::Int(3))
+            ]
+        );
+        assert_eq!(Map::from(m.clone()).len(), 2);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let m: OrderedMap = (0..10)
+            .rev()
+            .map(|i| (i.to_string(), Typed::Uint(i)))
+            .collect();
+        let mut buf = Vec::new();
+        buf.write_ordered_map(&m).unwrap();
+        let decoded = (&buf[..]).read_ordered_map().unwrap();
+        assert_eq!(decoded, m);
+        assert_eq!(decoded.keys().next().unwrap(), "9");
+        assert_eq!((&buf[..]).read_map().unwrap(), Map::from(m));
+    }
+}
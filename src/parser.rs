@@ -0,0 +1,342 @@
+use crate::array::PackedArray;
+use crate::codec::{
+    base_tag, read_array, read_container_len, read_sized_limited, TYPE_ARRAY, TYPE_BYTES,
+    TYPE_BYTES_DEFLATE, TYPE_DECIMAL, TYPE_FALSE, TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT,
+    TYPE_INT_FIXED, TYPE_LIST, TYPE_MAP, TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE,
+    TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT, TYPE_UINT_FIXED,
+};
+use crate::compress::read_deflated;
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::limits::Budget;
+use crate::options::DecodeOptions;
+use crate::text::TextEncoding;
+use crate::timestamp::Timestamp;
+use crate::varint::VarintReadExt;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::Read;
+
+/// Event is a single token of an encoded document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Starts a list with the given number of elements.
+    StartList(usize),
+    /// Starts a map with the given number of entries, each entry is a `Key`
+    /// followed by the value.
+    StartMap(usize),
+    Key(String),
+    Null,
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Timestamp(Timestamp),
+    Decimal(Decimal),
+    Bytes(Vec<u8>),
+    String(String),
+    Array(PackedArray),
+    /// Ends the innermost list or map.
+    End,
+}
+
+struct Frame {
+    remaining: usize,
+    is_map: bool,
+    expect_key: bool,
+    // The encoding of the strings of the elements, and of the value of the
+    // current entry of a map, see `DecodeOptions::field_encodings`.
+    enc: TextEncoding,
+    value_enc: TextEncoding,
+}
+
+/// Parser reads a single encoded value as a sequence of events, so the
+/// document can be processed without building `Typed` values, memory usage
+/// is proportional to the nesting depth only.
+///
+/// It is also an iterator of the events, which ends after the first error.
+pub struct Parser<R> {
+    reader: R,
+    opts: DecodeOptions,
+    budget: Budget,
+    stack: Vec<Frame>,
+    started: bool,
+    failed: bool,
+}
+
+impl<R: Read> Parser<R> {
+    pub fn new(reader: R) -> Self {
+        let opts = DecodeOptions::default();
+        Parser {
+            reader,
+            budget: Budget::new(&opts.limits),
+            opts,
+            stack: Vec::new(),
+            started: false,
+            failed: false,
+        }
+    }
+
+    /// Decodes strings and map keys, and enforces the limits, as `opts`
+    /// tell, like `CodecReadExt::read_typed_with`.
+    pub fn options(mut self, opts: DecodeOptions) -> Self {
+        self.budget = Budget::new(&opts.limits);
+        self.opts = opts;
+        self
+    }
+
+    /// Returns the next event, or `None` once the value is fully consumed.
+    pub fn next_event(&mut self) -> Result<Option<Event>> {
+        let enc = match self.stack.last_mut() {
+            Some(top) => {
+                if top.remaining == 0 {
+                    self.stack.pop();
+                    self.budget.leave();
+                    return Ok(Some(Event::End));
+                }
+                if top.is_map {
+                    if top.expect_key {
+                        top.expect_key = false;
+                        let k = read_sized_limited(&mut self.reader, &self.budget)?;
+                        let k = self.opts.key(self.opts.text(top.enc, &k)?);
+                        top.value_enc = self
+                            .opts
+                            .field_encodings
+                            .get(&k)
+                            .copied()
+                            .unwrap_or(top.enc);
+                        return Ok(Some(Event::Key(k)));
+                    }
+                    top.expect_key = true;
+                }
+                top.remaining -= 1;
+                top.value_enc
+            }
+            None => {
+                if self.started {
+                    return Ok(None);
+                }
+                self.started = true;
+                self.opts.text_encoding
+            }
+        };
+        self.read_value(enc).map(Some)
+    }
+
+    /// Skips the rest of the innermost list or map, including its `End`,
+    /// without returning the events. It does nothing at the top level.
+    pub fn skip_container(&mut self) -> Result<()> {
+        let depth = self.stack.len();
+        while depth > 0 && self.stack.len() >= depth {
+            self.next_event()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of lists and maps currently open.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_value(&mut self, enc: TextEncoding) -> Result<Event> {
+        let t = self.reader.read_u8()?;
+        match base_tag(t) {
+            TYPE_NULL => Ok(Event::Null),
+            TYPE_FALSE => Ok(Event::Bool(false)),
+            TYPE_TRUE => Ok(Event::Bool(true)),
+            TYPE_INT => Ok(Event::Int(self.reader.read_varint()?)),
+            TYPE_UINT => Ok(Event::Uint(self.reader.read_uvarint()?)),
+            TYPE_FLOAT => Ok(Event::Float(f64::from_bits(self.reader.read_uvarint()?))),
+            TYPE_INT_FIXED => Ok(Event::Int(self.reader.read_i64::<LittleEndian>()?)),
+            TYPE_UINT_FIXED => Ok(Event::Uint(self.reader.read_u64::<LittleEndian>()?)),
+            TYPE_FLOAT_FIXED => Ok(Event::Float(self.reader.read_f64::<LittleEndian>()?)),
+            TYPE_TIMESTAMP => {
+                let secs = self.reader.read_varint()?;
+                let ts = Timestamp::from_wire(secs, self.reader.read_uvarint()?)?;
+                Ok(Event::Timestamp(ts))
+            }
+            TYPE_DECIMAL => {
+                let mantissa = self.reader.read_varint()?;
+                let d = Decimal::from_wire(mantissa, self.reader.read_uvarint()?)?;
+                Ok(Event::Decimal(d))
+            }
+            TYPE_BYTES => Ok(Event::Bytes(read_sized_limited(
+                &mut self.reader,
+                &self.budget,
+            )?)),
+            TYPE_STRING => {
+                let buf = read_sized_limited(&mut self.reader, &self.budget)?;
+                let s = self.opts.string(self.opts.text(enc, &buf)?);
+                Ok(Event::String(s.into_owned()))
+            }
+            TYPE_BYTES_DEFLATE => Ok(Event::Bytes(read_deflated(&mut self.reader, &self.budget)?)),
+            TYPE_STRING_DEFLATE => {
+                let buf = read_deflated(&mut self.reader, &self.budget)?;
+                let s = self.opts.string(self.opts.text(enc, &buf)?);
+                Ok(Event::String(s.into_owned()))
+            }
+            TYPE_ARRAY => Ok(Event::Array(read_array(&mut self.reader, &self.budget)?)),
+            TYPE_LIST | TYPE_MAP => {
+                let nelem = read_container_len(&mut self.reader, t)?;
+                self.budget.enter(nelem)?;
+                let nelem = nelem as usize;
+                let is_map = base_tag(t) == TYPE_MAP;
+                self.stack.push(Frame {
+                    remaining: nelem,
+                    is_map,
+                    expect_key: is_map,
+                    enc,
+                    value_enc: enc,
+                });
+                if is_map {
+                    Ok(Event::StartMap(nelem))
+                } else {
+                    Ok(Event::StartList(nelem))
+                }
+            }
+            _ => Err(Error::UnknownType(t)),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Parser<R> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Result<Event>> {
+        if self.failed {
+            return None;
+        }
+        let res = self.next_event().transpose();
+        self.failed = matches!(res, Some(Err(_)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecWriteExt, Map, Typed};
+    use std::io::ErrorKind;
+
+    fn events(buf: &[u8]) -> Result<Vec<Event>> {
+        let mut p = Parser::new(buf);
+        let mut events = Vec::new();
+        while let Some(ev) = p.next_event()? {
+            events.push(ev);
+        }
+        Ok(events)
+    }
+
+    #[test]
+    fn scalar() {
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::Int(-42)).unwrap();
+        assert_eq!(events(&buf).unwrap(), vec![Event::Int(-42)]);
+    }
+
+    #[test]
+    fn nested() {
+        let mut m = Map::new();
+        m.insert(
+            String::from("l"),
+            Typed::List(vec![
+                Typed::Uint(1),
+                Typed::List(vec![]),
+                Typed::Bytes(vec![7u8].into()),
+            ]),
+        );
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::List(vec![
+            Typed::Map(m),
+            Typed::Float(1.5),
+            Typed::String("超".into()),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            events(&buf).unwrap(),
+            vec![
+                Event::StartList(3),
+                Event::StartMap(1),
+                Event::Key(String::from("l")),
+                Event::StartList(3),
+                Event::Uint(1),
+                Event::StartList(0),
+                Event::End,
+                Event::Bytes(vec![7u8]),
+                Event::End,
+                Event::End,
+                Event::Float(1.5),
+                Event::String(String::from("超")),
+                Event::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::List(vec![Typed::Int(1), Typed::Int(2)]))
+            .unwrap();
+        buf.pop();
+        let err = events(&buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn options() {
+        let e = crate::typed!({"Name": [[b"\xe9".to_vec()], "\u{e9}"]});
+        let mut buf = Vec::new();
+        buf.write_typed(&e).unwrap();
+        let mut latin1 = buf.clone();
+        let n = latin1.len();
+        latin1.splice(n - 3.., vec![b'\x01', 0xe9]);
+
+        let opts = DecodeOptions::new()
+            .key_mode(crate::KeyMode::Lowercase)
+            .field_encoding("name", TextEncoding::Latin1);
+        let events: Vec<_> = Parser::new(&latin1[..])
+            .options(opts)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(events[1], Event::Key(String::from("name")));
+        assert_eq!(events[6], Event::String(String::from("\u{e9}")));
+
+        let mut p = Parser::new(&buf[..]).options(DecodeOptions::new().strict_utf8(true));
+        assert_eq!(p.nth(6).unwrap().unwrap(), Event::String("\u{e9}".into()));
+        let mut p = Parser::new(&latin1[..]).options(DecodeOptions::new().strict_utf8(true));
+        assert!(p.nth(6).unwrap().is_err());
+        assert!(p.next().is_none());
+
+        let limits = crate::Limits::new().max_depth(2);
+        let mut p = Parser::new(&buf[..]).options(DecodeOptions::new().limits(limits));
+        assert_eq!(p.nth(2).unwrap().unwrap(), Event::StartList(2));
+        assert!(p.next().unwrap().is_err());
+        let limits = crate::Limits::new().max_bytes_len(0);
+        let mut p = Parser::new(&buf[..]).options(DecodeOptions::new().limits(limits));
+        assert!(p.nth(1).unwrap().is_err());
+    }
+
+    #[test]
+    fn skip_container() {
+        let e = crate::typed!([{"a": [1, 2], "b": 3}, "x"]);
+        let mut buf = Vec::new();
+        buf.write_typed(&e).unwrap();
+        let mut p = Parser::new(&buf[..]);
+        assert_eq!(p.next_event().unwrap(), Some(Event::StartList(2)));
+        assert_eq!(p.next_event().unwrap(), Some(Event::StartMap(2)));
+        p.skip_container().unwrap();
+        assert_eq!(p.depth(), 1);
+        assert_eq!(p.next_event().unwrap(), Some(Event::String("x".into())));
+        assert_eq!(p.next_event().unwrap(), Some(Event::End));
+        p.skip_container().unwrap();
+        assert_eq!(p.next_event().unwrap(), None);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::error::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// PathSegment is a step from a list or map into one of its elements.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Path locates a value within a document, the empty path is the root.
+///
+/// It is displayed like `users[2].name`, keys which are not plain
+/// identifiers are quoted, e.g. `headers["content-type"]`, and parsed back
+/// from the same form with `str::parse`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Path(Vec<PathSegment>);
+
+impl Path {
+    /// Returns the root path.
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    pub fn push(&mut self, seg: PathSegment) {
+        self.0.push(seg);
+    }
+
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.0.pop()
+    }
+
+    /// Returns a new path with `key` appended.
+    pub fn key(&self, key: &str) -> Self {
+        let mut p = self.clone();
+        p.push(PathSegment::Key(key.to_string()));
+        p
+    }
+
+    /// Returns a new path with `index` appended.
+    pub fn index(&self, index: usize) -> Self {
+        let mut p = self.clone();
+        p.push(PathSegment::Index(index));
+        p
+    }
+}
+
+impl From<Vec<PathSegment>> for Path {
+    fn from(segs: Vec<PathSegment>) -> Self {
+        Path(segs)
+    }
+}
+
+fn is_plain_key(k: &str) -> bool {
+    !k.is_empty() && k.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, seg) in self.0.iter().enumerate() {
+            match seg {
+                PathSegment::Key(k) if is_plain_key(k) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    f.write_str(k)?;
+                }
+                PathSegment::Key(k) => write!(f, "[{:?}]", k)?,
+                PathSegment::Index(n) => write!(f, "[{}]", n)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Path {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Path> {
+        let invalid = |what: &str| Error::InvalidInput(format!("invalid path {:?}: {}", s, what));
+        let mut segs = Vec::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.peek().copied() {
+            match c {
+                '[' => {
+                    chars.next();
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        segs.push(PathSegment::Key(
+                            parse_quoted(&mut chars)
+                                .ok_or_else(|| invalid("unterminated or malformed quoted key"))?,
+                        ));
+                    } else {
+                        let mut digits = String::new();
+                        while let Some(c) = chars.peek().filter(|c| **c != ']') {
+                            digits.push(*c);
+                            chars.next();
+                        }
+                        let i = digits.parse().map_err(|_| invalid("invalid index"))?;
+                        segs.push(PathSegment::Index(i));
+                    }
+                    if chars.next() != Some(']') {
+                        return Err(invalid("expected ']'"));
+                    }
+                }
+                '.' if segs.is_empty() => return Err(invalid("leading '.'")),
+                _ => {
+                    if c == '.' {
+                        chars.next();
+                    }
+                    let mut key = String::new();
+                    while let Some(c) = chars.peek().copied() {
+                        if c == '.' || c == '[' {
+                            break;
+                        }
+                        key.push(c);
+                        chars.next();
+                    }
+                    if key.is_empty() {
+                        return Err(invalid("empty key"));
+                    }
+                    segs.push(PathSegment::Key(key));
+                }
+            }
+        }
+        Ok(Path(segs))
+    }
+}
+
+// Parses the rest of a key quoted like `{:?}` does, after the opening quote.
+fn parse_quoted<I: Iterator<Item = char>>(chars: &mut I) -> Option<String> {
+    let mut key = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(key),
+            '\\' => key.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                'u' => {
+                    if chars.next()? != '{' {
+                        return None;
+                    }
+                    let hex: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                c @ ('\\' | '"' | '\'') => c,
+                _ => return None,
+            }),
+            c => key.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!(Path::root().to_string(), "");
+        let p = Path::root().key("users").index(2).key("name");
+        assert_eq!(p.to_string(), "users[2].name");
+        assert_eq!(p.len(), 3);
+        let p = Path::root().index(0).key("content-type").key("x");
+        assert_eq!(p.to_string(), "[0][\"content-type\"].x");
+    }
+
+    #[test]
+    fn parse() {
+        let p: Path = "a.b[2].c".parse().unwrap();
+        assert_eq!(p, Path::root().key("a").key("b").index(2).key("c"));
+        assert_eq!("".parse::<Path>().unwrap(), Path::root());
+        for p in [
+            Path::root().index(0).key("content-type").key("x"),
+            Path::root().key("say \"hi\"\n\u{7f}").index(10),
+            Path::root().key("超").key("").key("a b"),
+        ]
+        .iter()
+        {
+            assert_eq!(&p.to_string().parse::<Path>().unwrap(), p);
+        }
+        for s in [
+            ".a",
+            "a..b",
+            "a.",
+            "[x]",
+            "a[1",
+            "[\"k",
+            "[\"k\"x",
+            "[\"\\q\"]",
+        ]
+        .iter()
+        {
+            let err = s.parse::<Path>().unwrap_err();
+            assert!(matches!(err, Error::InvalidInput(_)), "{}: {:?}", s, err);
+        }
+    }
+}
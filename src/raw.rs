@@ -0,0 +1,204 @@
+use crate::codec::{
+    CodecWriteExt, Typed, TYPE_ARRAY, TYPE_BYTES, TYPE_BYTES_DEFLATE, TYPE_DECIMAL, TYPE_FALSE,
+    TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT, TYPE_INT_FIXED, TYPE_LIST, TYPE_LIST_V2, TYPE_MAP,
+    TYPE_MAP_V2, TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE, TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT,
+    TYPE_UINT_FIXED,
+};
+use crate::error::{Error, Result};
+use crate::limits::Budget;
+use crate::varint::MAX_VARINT_LEN;
+use crate::CodecReadExt;
+use std::io::Read;
+
+/// RawValue holds the encoded bytes of a single value, it lets proxies pass
+/// values through without a decode/re-encode round trip.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawValue(Vec<u8>);
+
+impl RawValue {
+    /// Wraps bytes which must already be a complete encoded value, they are
+    /// not validated.
+    pub fn from_encoded(buf: Vec<u8>) -> Self {
+        RawValue(buf)
+    }
+
+    /// Encodes `e` into a new RawValue.
+    pub fn encode(e: &Typed) -> Result<Self> {
+        let mut buf = Vec::new();
+        buf.write_typed(e)?;
+        Ok(RawValue(buf))
+    }
+
+    /// Decodes the held bytes.
+    pub fn decode(&self) -> Result<Typed> {
+        (&self.0[..]).read_typed()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.0.shrink_to_fit();
+    }
+}
+
+fn read_byte<R: Read + ?Sized>(r: &mut R, buf: &mut Vec<u8>) -> Result<u8> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)?;
+    buf.push(b[0]);
+    Ok(b[0])
+}
+
+fn copy_uvarint<R: Read + ?Sized>(r: &mut R, buf: &mut Vec<u8>) -> Result<u64> {
+    let mut x = 0u64;
+    let mut s = 0u64;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = read_byte(r, buf)?;
+        if byte < 0x80 {
+            if i == MAX_VARINT_LEN - 1 && byte > 1 {
+                break;
+            }
+            return Ok(x | (byte as u64) << s);
+        }
+        x |= ((byte & 0x7f) as u64) << s;
+        s += 7;
+    }
+    Err(Error::VarintOverflow)
+}
+
+fn copy_exact<R: Read + ?Sized>(r: &mut R, buf: &mut Vec<u8>, sz: u64) -> Result<()> {
+    let n = r.take(sz).read_to_end(buf)?;
+    if (n as u64) < sz {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(())
+}
+
+fn copy_sized<R: Read + ?Sized>(r: &mut R, buf: &mut Vec<u8>, budget: &Budget) -> Result<()> {
+    let sz = copy_uvarint(r, buf)?;
+    budget.bytes(sz)?;
+    copy_exact(r, buf, sz)
+}
+
+fn copy_container_len<R: Read + ?Sized>(r: &mut R, buf: &mut Vec<u8>, t: u8) -> Result<u64> {
+    match t {
+        TYPE_LIST_V2 | TYPE_MAP_V2 => copy_uvarint(r, buf),
+        _ => read_byte(r, buf).map(u64::from),
+    }
+}
+
+/// Copies the next encoded value from `r` into `buf` without decoding it,
+/// it is rejected as soon as it exceeds the limits of `budget`.
+pub(crate) fn copy_typed<R: Read + ?Sized>(
+    r: &mut R,
+    buf: &mut Vec<u8>,
+    budget: &mut Budget,
+) -> Result<()> {
+    let t = read_byte(r, buf)?;
+    match t {
+        TYPE_NULL | TYPE_FALSE | TYPE_TRUE => Ok(()),
+        TYPE_INT | TYPE_UINT | TYPE_FLOAT => copy_uvarint(r, buf).map(|_| ()),
+        TYPE_INT_FIXED | TYPE_UINT_FIXED | TYPE_FLOAT_FIXED => copy_exact(r, buf, 8),
+        TYPE_TIMESTAMP | TYPE_DECIMAL => {
+            copy_uvarint(r, buf)?;
+            copy_uvarint(r, buf).map(|_| ())
+        }
+        TYPE_BYTES | TYPE_STRING => copy_sized(r, buf, budget),
+        TYPE_BYTES_DEFLATE | TYPE_STRING_DEFLATE => {
+            budget.bytes(copy_uvarint(r, buf)?)?;
+            copy_sized(r, buf, budget)
+        }
+        TYPE_ARRAY => {
+            read_byte(r, buf)?;
+            for _ in 0..copy_uvarint(r, buf)? {
+                copy_uvarint(r, buf)?;
+            }
+            copy_sized(r, buf, budget)
+        }
+        TYPE_LIST | TYPE_LIST_V2 => {
+            let nelem = copy_container_len(r, buf, t)?;
+            budget.enter(nelem)?;
+            for _ in 0..nelem {
+                copy_typed(r, buf, budget)?;
+            }
+            budget.leave();
+            Ok(())
+        }
+        TYPE_MAP | TYPE_MAP_V2 => {
+            let nelem = copy_container_len(r, buf, t)?;
+            budget.enter(nelem)?;
+            for _ in 0..nelem {
+                copy_sized(r, buf, budget)?;
+                copy_typed(r, buf, budget)?;
+            }
+            budget.leave();
+            Ok(())
+        }
+        _ => Err(Error::UnknownType(t)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+    use crate::varint::VarintWriteExt;
+    use std::io;
+    use std::io::prelude::*;
+
+    #[test]
+    fn passthrough() {
+        let mut m = Map::new();
+        m.insert(
+            String::from("payload"),
+            Typed::Bytes(vec![1u8, 2u8, 3u8].into()),
+        );
+        m.insert(String::from("n"), Typed::Int(-300));
+        let inner = Typed::List(vec![Typed::Map(m), Typed::String("超".into())]);
+
+        let mut buf = io::Cursor::new(Vec::new());
+        buf.write_typed(&inner).unwrap();
+        buf.write_typed(&Typed::Uint(7)).unwrap();
+        buf.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let raw = buf.read_raw().unwrap();
+        assert_eq!(raw, RawValue::encode(&inner).unwrap());
+        assert_eq!(raw.decode().unwrap(), inner);
+        assert_eq!(buf.read_typed().unwrap(), Typed::Uint(7));
+
+        let mut out = Vec::new();
+        out.write_typed(&Typed::List(vec![Typed::Raw(raw)]))
+            .unwrap();
+        match (&out[..]).read_typed() {
+            Ok(Typed::List(l)) => assert_eq!(l, vec![inner]),
+            Ok(e) => panic!("unexpected: {:?}", e),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let raw = RawValue::encode(&Typed::String("hello".into())).unwrap();
+        let buf = raw.as_bytes();
+        let err = (&buf[..buf.len() - 1]).read_raw().unwrap_err();
+        assert!(err.is_eof());
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let mut buf = vec![TYPE_UINT];
+        buf.extend_from_slice(&[0xff; MAX_VARINT_LEN]);
+        buf.push(0);
+        let err = (&buf[..]).read_raw().unwrap_err();
+        assert!(matches!(err, Error::VarintOverflow), "{:?}", err);
+
+        let mut buf = vec![TYPE_UINT];
+        buf.write_uvarint(u64::MAX).unwrap();
+        assert!((&buf[..]).read_raw().is_ok());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::codec::{CodecReadExt, CodecWriteExt, Type, Typed};
+use crate::error::{Error, Result};
+use crate::schema::Schema;
+use crate::varint::{VarintReadExt, VarintWriteExt};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+// The stamp is written before the value, its tag never collides with the
+// tags of values.
+pub(crate) const HEADER_SCHEMA: Type = b'S';
+
+/// Stamped is a value read by `SchemaRegistry::read_stamped`, it has been
+/// validated against the schema it was stamped with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamped {
+    pub id: String,
+    pub version: u32,
+    pub value: Typed,
+}
+
+/// SchemaRegistry keeps versioned schemas by id, documents written with
+/// `write_stamped` carry the (id, version) of their schema, so readers pick
+/// the matching schema, even while writers are being upgraded.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<String, BTreeMap<u32, Schema>>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a schema, returns the schema previously registered with the
+    /// same id and version.
+    pub fn register(&mut self, id: &str, version: u32, schema: Schema) -> Option<Schema> {
+        self.schemas
+            .entry(id.to_string())
+            .or_default()
+            .insert(version, schema)
+    }
+
+    pub fn get(&self, id: &str, version: u32) -> Option<&Schema> {
+        self.schemas.get(id).and_then(|m| m.get(&version))
+    }
+
+    /// Returns the highest version registered for `id`.
+    pub fn latest(&self, id: &str) -> Option<(u32, &Schema)> {
+        self.schemas
+            .get(id)
+            .and_then(|m| m.iter().next_back())
+            .map(|(v, s)| (*v, s))
+    }
+
+    /// Returns the versions registered for `id` in ascending order.
+    pub fn versions(&self, id: &str) -> Vec<u32> {
+        self.schemas
+            .get(id)
+            .map(|m| m.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Picks the highest version of `id` known by both this registry and a
+    /// peer which supports `peer_versions`.
+    pub fn negotiate(&self, id: &str, peer_versions: &[u32]) -> Option<u32> {
+        let m = self.schemas.get(id)?;
+        peer_versions
+            .iter()
+            .filter(|v| m.contains_key(v))
+            .max()
+            .cloned()
+    }
+
+    /// Validates `e` against the registered schema and writes it prefixed
+    /// with the stamp of the schema.
+    pub fn write_stamped<W: Write + ?Sized>(
+        &self,
+        w: &mut W,
+        id: &str,
+        version: u32,
+        e: &Typed,
+    ) -> Result<()> {
+        let schema = self
+            .get(id, version)
+            .ok_or_else(|| Error::InvalidInput(unknown_schema(id, version)))?;
+        schema.validate(e)?;
+        w.write_u8(HEADER_SCHEMA)?;
+        w.write_sized(id.as_bytes())?;
+        w.write_uvarint(version as u64)?;
+        w.write_typed(e)
+    }
+
+    /// Reads a stamped value and validates it against the schema named by
+    /// the stamp, which must be registered.
+    pub fn read_stamped<R: Read + ?Sized>(&self, r: &mut R) -> Result<Stamped> {
+        let (id, version) = read_stamp(r)?;
+        let schema = self
+            .get(&id, version)
+            .ok_or_else(|| Error::InvalidData(unknown_schema(&id, version)))?;
+        let value = r.read_typed()?;
+        schema.validate(&value)?;
+        Ok(Stamped { id, version, value })
+    }
+}
+
+/// Reads the stamp only, the value is left in the reader.
+pub fn read_stamp<R: Read + ?Sized>(r: &mut R) -> Result<(String, u32)> {
+    if r.read_u8()? != HEADER_SCHEMA {
+        return Err(Error::InvalidData("no schema stamp".into()));
+    }
+    let id = String::from_utf8(r.read_sized()?)
+        .map_err(|_| Error::InvalidData("invalid schema id".into()))?;
+    let version = r.read_uvarint()?;
+    if version > u32::MAX as u64 {
+        return Err(Error::InvalidData("invalid schema version".into()));
+    }
+    Ok((id, version as u32))
+}
+
+fn unknown_schema(id: &str, version: u32) -> String {
+    format!("unknown schema: '{}' version {}", id, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+    use crate::schema::Field;
+
+    fn registry() -> SchemaRegistry {
+        let mut registry = SchemaRegistry::new();
+        registry.register(
+            "user",
+            1,
+            Schema::record(vec![Field::required("name", Schema::String)]),
+        );
+        registry.register(
+            "user",
+            2,
+            Schema::record(vec![
+                Field::required("name", Schema::String),
+                Field::required("age", Schema::uint()),
+            ]),
+        );
+        registry
+    }
+
+    #[test]
+    fn versions() {
+        let registry = registry();
+        assert_eq!(registry.versions("user"), vec![1, 2]);
+        assert_eq!(registry.latest("user").map(|(v, _)| v), Some(2));
+        assert_eq!(registry.negotiate("user", &[1, 3]), Some(1));
+        assert_eq!(registry.negotiate("user", &[2, 1]), Some(2));
+        assert_eq!(registry.negotiate("user", &[3]), None);
+        assert_eq!(registry.negotiate("group", &[1]), None);
+        assert!(registry.latest("group").is_none());
+    }
+
+    #[test]
+    fn stamped() {
+        let registry = registry();
+        let mut m = Map::new();
+        m.insert(String::from("name"), Typed::String("xd".into()));
+        let e = Typed::Map(m);
+
+        let mut buf = Vec::new();
+        registry.write_stamped(&mut buf, "user", 1, &e).unwrap();
+        assert!(registry.write_stamped(&mut buf, "user", 2, &e).is_err());
+        assert!(registry.write_stamped(&mut buf, "user", 3, &e).is_err());
+
+        assert_eq!(
+            read_stamp(&mut &buf[..]).unwrap(),
+            (String::from("user"), 1)
+        );
+        let stamped = registry.read_stamped(&mut &buf[..]).unwrap();
+        assert_eq!(
+            stamped,
+            Stamped {
+                id: String::from("user"),
+                version: 1,
+                value: e.clone()
+            }
+        );
+
+        // A reader which only knows the newer version rejects the document.
+        let mut newer = SchemaRegistry::new();
+        newer.register("user", 2, registry.get("user", 2).unwrap().clone());
+        let err = newer.read_stamped(&mut &buf[..]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown schema: 'user' version 1");
+
+        let mut plain = Vec::new();
+        plain.write_typed(&e).unwrap();
+        assert!(registry.read_stamped(&mut &plain[..]).is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::codec::{CodecWriteExt, CONTAINER_CAPACITY};
+use crate::config::FormatVersion;
+use crate::error::{Error, Result};
+use crate::event_writer::EventWriter;
+use crate::parser::{Event, Parser};
+use crate::varint::uvarint_len;
+use byteorder::WriteBytesExt;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Action tells `rewrite` what to do with an event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Keep,
+    /// Drops the event, dropping a key, a list or a map drops the whole
+    /// entry or subtree.
+    Drop,
+    /// Replaces the event, a key can only be replaced with another key, a
+    /// scalar or a whole list/map can be replaced with a scalar.
+    Replace(Event),
+}
+
+struct Frame {
+    is_map: bool,
+    nelem: usize,
+    // The key of the entry whose value is next, it is written along with
+    // the value, so dropping the value drops it too.
+    key: Option<String>,
+    // Where the number of elements is written once the container ends, and
+    // how many bytes it takes, 0 for the byte of `FormatVersion::V1`.
+    len_pos: u64,
+    len_width: usize,
+}
+
+/// Streams a single value from `reader` to `writer`, passing every event
+/// through `f` on the way, the value is never decoded into `Typed`.
+///
+/// Nothing but the open lists and maps is kept, so the memory it takes is
+/// bounded by the nesting depth. Since dropping elements changes the length
+/// of their parents, the numbers of elements are written once the
+/// containers end, by seeking back in `writer`. The containers are written
+/// in `FormatVersion::V1`, unless they have too many elements for it in the
+/// input, whatever its version. Then their numbers of elements are uvarints
+/// as long as in the input, padded if elements were dropped.
+pub fn rewrite<R, W, F>(reader: R, mut writer: W, mut f: F) -> Result<()>
+where
+    R: Read,
+    W: Write + Seek,
+    F: FnMut(&Event) -> Action,
+{
+    let mut parser = Parser::new(reader);
+    let mut stack: Vec<Frame> = Vec::new();
+
+    while let Some(ev) = parser.next_event()? {
+        let action = f(&ev);
+        match ev {
+            Event::Key(_) => match action {
+                Action::Keep => set_key(&mut stack, ev),
+                Action::Replace(ev @ Event::Key(_)) => set_key(&mut stack, ev),
+                Action::Drop => match parser.next_event()? {
+                    Some(v) => skip(&mut parser, &v)?,
+                    None => return Err(Error::UnexpectedEof),
+                },
+                Action::Replace(_) => return Err(invalid_replacement()),
+            },
+            Event::End => {
+                if action != Action::Keep {
+                    return Err(Error::InvalidInput(
+                        "the end of a container can only be kept".into(),
+                    ));
+                }
+                let frame = stack.pop().unwrap();
+                let end = writer.stream_position()?;
+                writer.seek(SeekFrom::Start(frame.len_pos))?;
+                write_padded_len(&mut writer, frame.nelem, frame.len_width)?;
+                writer.seek(SeekFrom::Start(end))?;
+            }
+            Event::StartList(nelem) | Event::StartMap(nelem) => match action {
+                Action::Keep => {
+                    let is_map = matches!(ev, Event::StartMap(_));
+                    let (version, len_width) = if nelem < CONTAINER_CAPACITY {
+                        (FormatVersion::V1, 0)
+                    } else {
+                        (FormatVersion::V2, uvarint_len(nelem as u64))
+                    };
+                    let tag = if is_map {
+                        version.map_tag()
+                    } else {
+                        version.list_tag()
+                    };
+                    emit(&mut stack, &mut writer, &[tag])?;
+                    let len_pos = writer.stream_position()?;
+                    // The number of elements of the input, it can only
+                    // shrink.
+                    write_padded_len(&mut writer, nelem, len_width)?;
+                    stack.push(Frame {
+                        is_map,
+                        nelem: 0,
+                        key: None,
+                        len_pos,
+                        len_width,
+                    });
+                }
+                Action::Drop => {
+                    skip(&mut parser, &ev)?;
+                    drop_key(&mut stack);
+                }
+                Action::Replace(new) => {
+                    skip(&mut parser, &ev)?;
+                    emit_scalar(&mut stack, &mut writer, &new)?;
+                }
+            },
+            _ => match action {
+                Action::Keep => emit_scalar(&mut stack, &mut writer, &ev)?,
+                Action::Drop => drop_key(&mut stack),
+                Action::Replace(new) => emit_scalar(&mut stack, &mut writer, &new)?,
+            },
+        }
+    }
+    Ok(())
+}
+
+fn invalid_replacement() -> Error {
+    Error::InvalidInput("invalid replacement event".into())
+}
+
+fn set_key(stack: &mut [Frame], ev: Event) {
+    if let (Some(top), Event::Key(k)) = (stack.last_mut(), ev) {
+        top.key = Some(k);
+    }
+}
+
+fn drop_key(stack: &mut [Frame]) {
+    if let Some(top) = stack.last_mut() {
+        top.key = None;
+    }
+}
+
+// Consumes the rest of the value started by `ev`.
+fn skip<R: Read>(parser: &mut Parser<R>, ev: &Event) -> Result<()> {
+    match ev {
+        Event::StartList(_) | Event::StartMap(_) => {}
+        _ => return Ok(()),
+    }
+    let depth = parser.depth() - 1;
+    while parser.depth() > depth {
+        if parser.next_event()?.is_none() {
+            return Err(Error::UnexpectedEof);
+        }
+    }
+    Ok(())
+}
+
+fn emit_scalar<W: Write>(stack: &mut [Frame], writer: &mut W, ev: &Event) -> Result<()> {
+    match ev {
+        Event::StartList(_) | Event::StartMap(_) | Event::Key(_) | Event::End => {
+            return Err(invalid_replacement())
+        }
+        _ => {}
+    }
+    let mut w = EventWriter::new(Vec::new());
+    w.write_event(ev)?;
+    emit(stack, writer, &w.finish()?)
+}
+
+// Writes the start of a value, along with its key if it is in a map.
+fn emit<W: Write>(stack: &mut [Frame], writer: &mut W, encoded: &[u8]) -> Result<()> {
+    if let Some(top) = stack.last_mut() {
+        if top.is_map {
+            let k = top.key.take().unwrap();
+            writer.write_sized(k.as_bytes())?;
+        }
+        top.nelem += 1;
+    }
+    Ok(writer.write_all(encoded)?)
+}
+
+// Writes the number of elements in a byte if `width` is 0, or in a uvarint
+// of `width` bytes, which readers accept even if a shorter one would do.
+fn write_padded_len<W: Write>(writer: &mut W, nelem: usize, width: usize) -> Result<()> {
+    if width == 0 {
+        return Ok(writer.write_u8(nelem as u8)?);
+    }
+    let mut un = nelem as u64;
+    for _ in 1..width {
+        writer.write_u8(un as u8 | 0x80)?;
+        un >>= 7;
+    }
+    Ok(writer.write_u8(un as u8)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecReadExt, Map, Typed};
+    use std::io::Cursor;
+
+    fn run<F: FnMut(&Event) -> Action>(e: &Typed, f: F) -> Result<Typed> {
+        let mut buf = Vec::new();
+        buf.write_typed(e).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        rewrite(&buf[..], &mut out, f)?;
+        (&out.into_inner()[..]).read_typed()
+    }
+
+    #[test]
+    fn keep_all() {
+        let mut m = Map::new();
+        m.insert(String::from("a"), Typed::List(vec![Typed::Int(1)]));
+        m.insert(String::from("b"), Typed::Map(Map::new()));
+        let e = Typed::Map(m);
+        assert_eq!(run(&e, |_| Action::Keep).unwrap(), e);
+    }
+
+    #[test]
+    fn format_v2() {
+        let e = Typed::List(vec![Typed::Int(1); 300]);
+        let mut buf = Vec::new();
+        buf.write_typed_version(&e, FormatVersion::V2).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        rewrite(&buf[..], &mut out, |_| Action::Keep).unwrap();
+        assert_eq!(out.into_inner(), buf);
+
+        // The number of elements keeps its length when elements are dropped.
+        let mut out = Cursor::new(Vec::new());
+        let mut n = 0;
+        rewrite(&buf[..], &mut out, |ev| match ev {
+            Event::Int(_) => {
+                n += 1;
+                if n > 3 {
+                    Action::Drop
+                } else {
+                    Action::Keep
+                }
+            }
+            _ => Action::Keep,
+        })
+        .unwrap();
+        let out = out.into_inner();
+        assert_eq!(out, b"L\x83\x00i\x02i\x02i\x02");
+        assert_eq!(
+            (&out[..]).read_typed().unwrap(),
+            Typed::List(vec![Typed::Int(1); 3])
+        );
+    }
+
+    #[test]
+    fn scrub() {
+        let mut user = Map::new();
+        user.insert(String::from("name"), Typed::String("xd".into()));
+        user.insert(String::from("password"), Typed::String("x".into()));
+        user.insert(
+            String::from("secrets"),
+            Typed::List(vec![Typed::Bytes(vec![1u8].into())]),
+        );
+        user.insert(String::from("Age"), Typed::Uint(18));
+        let e = Typed::List(vec![
+            Typed::Map(user),
+            Typed::Int(-1),
+            Typed::Int(2),
+            Typed::List(vec![Typed::Int(3)]),
+        ]);
+
+        let mut in_secrets = false;
+        let out = run(&e, |ev| match ev {
+            Event::Key(k) if k == "password" => Action::Drop,
+            Event::Key(k) if k == "secrets" => {
+                in_secrets = true;
+                Action::Keep
+            }
+            Event::Key(k) if k == "Age" => Action::Replace(Event::Key(String::from("age"))),
+            Event::StartList(_) if in_secrets => {
+                in_secrets = false;
+                Action::Replace(Event::String(String::from("<redacted>")))
+            }
+            Event::Int(n) if *n < 0 => Action::Drop,
+            Event::Int(n) => Action::Replace(Event::Int(n * 10)),
+            _ => Action::Keep,
+        })
+        .unwrap();
+
+        let mut user = Map::new();
+        user.insert(String::from("name"), Typed::String("xd".into()));
+        user.insert(String::from("secrets"), Typed::String("<redacted>".into()));
+        user.insert(String::from("age"), Typed::Uint(18));
+        assert_eq!(
+            out,
+            Typed::List(vec![
+                Typed::Map(user),
+                Typed::Int(20),
+                Typed::List(vec![Typed::Int(30)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn drop_subtree() {
+        let e = Typed::List(vec![
+            Typed::List(vec![Typed::Int(1), Typed::List(vec![])]),
+            Typed::Uint(2),
+        ]);
+        let mut depth = 0;
+        let out = run(&e, |ev| match ev {
+            Event::StartList(_) => {
+                depth += 1;
+                if depth == 2 {
+                    Action::Drop
+                } else {
+                    Action::Keep
+                }
+            }
+            _ => Action::Keep,
+        })
+        .unwrap();
+        assert_eq!(out, Typed::List(vec![Typed::Uint(2)]));
+    }
+
+    #[test]
+    fn invalid_actions() {
+        let e = Typed::List(vec![Typed::Int(1)]);
+        assert!(run(&e, |ev| match ev {
+            Event::Int(_) => Action::Replace(Event::StartList(0)),
+            _ => Action::Keep,
+        })
+        .is_err());
+        assert!(run(&e, |ev| match ev {
+            Event::End => Action::Drop,
+            _ => Action::Keep,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::codec::{Str, Typed};
+use crate::limits::Limits;
+
+/// SanitizeReport tells what `Typed::sanitize` has cut.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SanitizeReport {
+    /// The number of strings which have been truncated.
+    pub truncated_strings: usize,
+    /// The number of bytes values which have been truncated.
+    pub truncated_bytes: usize,
+    /// The number of elements removed from lists and maps which are nested
+    /// too deep.
+    pub pruned: usize,
+    /// The number of elements removed from lists and maps which are too
+    /// long.
+    pub dropped: usize,
+}
+
+impl SanitizeReport {
+    /// Returns true if nothing has been cut.
+    pub fn is_clean(&self) -> bool {
+        *self == SanitizeReport::default()
+    }
+}
+
+fn truncate_str(s: &str, max: usize) -> &str {
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+impl Typed {
+    /// Cuts the value down to `limits`: strings and bytes are truncated,
+    /// lists and maps are truncated to `max_container_len` elements (the
+    /// map entries with the smallest keys are kept) and the lists and maps
+    /// nested deeper than `max_depth` are removed from their parents, the
+    /// value itself is never removed.
+    ///
+    /// `Typed::Raw` values are decoded so they can be sanitized as well.
+    pub fn sanitize(&mut self, limits: &Limits) -> SanitizeReport {
+        let mut report = SanitizeReport::default();
+        sanitize(self, limits, 1, &mut report);
+        report
+    }
+}
+
+// `depth` is the depth of `e` if it is a list or map.
+fn sanitize(e: &mut Typed, limits: &Limits, depth: usize, report: &mut SanitizeReport) {
+    decode_raw(e, report);
+    match e {
+        Typed::String(s) => {
+            if s.len() > limits.max_bytes_len {
+                *s = Str::from(truncate_str(s, limits.max_bytes_len));
+                report.truncated_strings += 1;
+            }
+        }
+        Typed::Bytes(buf) => {
+            if buf.len() > limits.max_bytes_len {
+                buf.truncate(limits.max_bytes_len);
+                report.truncated_bytes += 1;
+            }
+        }
+        Typed::List(l) => {
+            if l.len() > limits.max_container_len {
+                report.dropped += l.len() - limits.max_container_len;
+                l.truncate(limits.max_container_len);
+            }
+            if depth >= limits.max_depth {
+                l.iter_mut().for_each(|e| decode_raw(e, report));
+                let n = l.len();
+                l.retain(|e| !is_container(e));
+                report.pruned += n - l.len();
+            }
+            for e in l.iter_mut() {
+                sanitize(e, limits, depth + 1, report);
+            }
+        }
+        Typed::Map(m) => {
+            if m.len() > limits.max_container_len {
+                let mut keys: Vec<String> = m.keys().cloned().collect();
+                keys.sort();
+                for k in keys[limits.max_container_len..].iter() {
+                    m.remove(k);
+                }
+                report.dropped += keys.len() - limits.max_container_len;
+            }
+            if depth >= limits.max_depth {
+                m.values_mut().for_each(|e| decode_raw(e, report));
+                let n = m.len();
+                m.retain(|_, e| !is_container(e));
+                report.pruned += n - m.len();
+            }
+            for e in m.values_mut() {
+                sanitize(e, limits, depth + 1, report);
+            }
+        }
+        Typed::Null
+        | Typed::Bool(_)
+        | Typed::Int(_)
+        | Typed::Uint(_)
+        | Typed::Float(_)
+        | Typed::Timestamp(_)
+        | Typed::Decimal(_)
+        | Typed::Array(_)
+        | Typed::Raw(_) => {}
+    }
+}
+
+// Replaces a raw value with its decoded value, or with empty bytes if it
+// is malformed.
+fn decode_raw(e: &mut Typed, report: &mut SanitizeReport) {
+    if let Typed::Raw(raw) = e {
+        *e = match raw.decode() {
+            Ok(decoded) => decoded,
+            Err(_) => {
+                report.truncated_bytes += 1;
+                Typed::Bytes(Vec::new().into())
+            }
+        };
+    }
+}
+
+fn is_container(e: &Typed) -> bool {
+    matches!(e, Typed::List(_) | Typed::Map(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+    use crate::raw::RawValue;
+
+    #[test]
+    fn truncate() {
+        let mut e = Typed::List(vec![
+            Typed::String("超超".into()),
+            Typed::Bytes(vec![1u8, 2u8, 3u8, 4u8].into()),
+            Typed::String("ok".into()),
+        ]);
+        let report = e.sanitize(&Limits::new().max_bytes_len(4));
+        assert_eq!(report.truncated_strings, 1);
+        assert_eq!(report.truncated_bytes, 0);
+        assert_eq!(
+            e,
+            Typed::List(vec![
+                Typed::String("超".into()),
+                Typed::Bytes(vec![1u8, 2u8, 3u8, 4u8].into()),
+                Typed::String("ok".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn depth() {
+        let mut e = Typed::List(vec![
+            Typed::Int(1),
+            Typed::List(vec![Typed::Int(2), Typed::List(vec![Typed::Int(3)])]),
+        ]);
+        let report = e.sanitize(&Limits::new().max_depth(2));
+        assert_eq!(report.pruned, 1);
+        assert_eq!(
+            e,
+            Typed::List(vec![Typed::Int(1), Typed::List(vec![Typed::Int(2)])])
+        );
+
+        let mut e = Typed::List(vec![Typed::Int(1)]);
+        assert!(e.sanitize(&Limits::new().max_depth(0)).is_clean());
+
+        let mut m = Map::new();
+        m.insert(String::from("a"), Typed::Map(Map::new()));
+        m.insert(String::from("b"), Typed::Uint(1));
+        let mut e = Typed::Map(m);
+        assert_eq!(e.sanitize(&Limits::new().max_depth(1)).pruned, 1);
+        match e {
+            Typed::Map(m) => assert_eq!(m.get("b"), Some(&Typed::Uint(1))),
+            v => panic!("unexpected: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn containers() {
+        let mut m = Map::new();
+        for k in ["c", "a", "b"].iter() {
+            m.insert(k.to_string(), Typed::Int(0));
+        }
+        let mut e = Typed::List(vec![
+            Typed::Map(m),
+            Typed::Int(1),
+            Typed::Raw(RawValue::encode(&Typed::String("xyz".into())).unwrap()),
+        ]);
+        let report = e.sanitize(&Limits::new().max_container_len(2).max_bytes_len(2));
+        assert_eq!(report.dropped, 2);
+        assert_eq!(report.truncated_strings, 0);
+
+        let mut expected = Map::new();
+        expected.insert(String::from("a"), Typed::Int(0));
+        expected.insert(String::from("b"), Typed::Int(0));
+        assert_eq!(e, Typed::List(vec![Typed::Map(expected), Typed::Int(1)]));
+
+        let mut e = Typed::Raw(RawValue::encode(&Typed::String("xyz".into())).unwrap());
+        let report = e.sanitize(&Limits::new().max_bytes_len(2));
+        assert_eq!(report.truncated_strings, 1);
+        assert_eq!(e, Typed::String("xy".into()));
+    }
+}
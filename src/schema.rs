@@ -0,0 +1,457 @@
+use crate::codec::{container_capacity, List, Map, Typed};
+use crate::error::{Error, Result};
+use crate::options::DecodeOptions;
+use crate::parser::{Event, Parser};
+use crate::raw::RawValue;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Schema describes the expected structure of a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    /// Any value.
+    Any,
+    Bool,
+    Int {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Uint {
+        min: Option<u64>,
+        max: Option<u64>,
+    },
+    Float {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    Timestamp,
+    Decimal,
+    Bytes,
+    String,
+    /// A packed array of numbers of any dtype and shape, see `PackedArray`.
+    Array,
+    /// A list whose elements all match the schema.
+    List(Box<Schema>),
+    /// A map with arbitrary keys whose values all match the schema.
+    MapOf(Box<Schema>),
+    /// A map with known fields, other keys are allowed.
+    Record(Vec<Field>),
+}
+
+/// Field is a key of a `Schema::Record`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub schema: Schema,
+    pub optional: bool,
+}
+
+impl Field {
+    pub fn required(name: &str, schema: Schema) -> Self {
+        Field {
+            name: name.to_string(),
+            schema,
+            optional: false,
+        }
+    }
+
+    pub fn optional(name: &str, schema: Schema) -> Self {
+        Field {
+            name: name.to_string(),
+            schema,
+            optional: true,
+        }
+    }
+}
+
+/// Describe tells the `Schema` of the values a Rust type converts into with
+/// `ToTyped`, it can be derived with `#[derive(Describe)]` when the `derive`
+/// feature is enabled.
+pub trait Describe {
+    fn schema() -> Schema;
+}
+
+macro_rules! impl_describe {
+    ($schema:expr; $($t:ty),*) => {
+        $(
+            impl Describe for $t {
+                fn schema() -> Schema {
+                    $schema
+                }
+            }
+        )*
+    };
+}
+
+impl_describe!(Schema::int(); i8, i16, i32, i64, isize);
+impl_describe!(Schema::uint(); u8, u16, u32, u64, usize);
+impl_describe!(Schema::float(); f32, f64);
+impl_describe!(Schema::Bool; bool);
+impl_describe!(Schema::String; String);
+impl_describe!(Schema::Any; Typed, RawValue);
+
+impl<T: Describe> Describe for Vec<T> {
+    fn schema() -> Schema {
+        Schema::list(T::schema())
+    }
+}
+
+impl<T: Describe> Describe for HashMap<String, T> {
+    fn schema() -> Schema {
+        Schema::map_of(T::schema())
+    }
+}
+
+// Only for the fields of derived structs, which are left out when they are
+// `None`.
+impl<T: Describe> Describe for Option<T> {
+    fn schema() -> Schema {
+        T::schema()
+    }
+}
+
+impl Schema {
+    /// An int without range constraints.
+    pub fn int() -> Self {
+        Schema::Int {
+            min: None,
+            max: None,
+        }
+    }
+
+    /// An uint without range constraints.
+    pub fn uint() -> Self {
+        Schema::Uint {
+            min: None,
+            max: None,
+        }
+    }
+
+    /// A float without range constraints.
+    pub fn float() -> Self {
+        Schema::Float {
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn list(elem: Schema) -> Self {
+        Schema::List(Box::new(elem))
+    }
+
+    pub fn map_of(value: Schema) -> Self {
+        Schema::MapOf(Box::new(value))
+    }
+
+    pub fn record(fields: Vec<Field>) -> Self {
+        Schema::Record(fields)
+    }
+
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Schema::Any => "any",
+            Schema::Bool => "bool",
+            Schema::Int { .. } => "int",
+            Schema::Uint { .. } => "uint",
+            Schema::Float { .. } => "float",
+            Schema::Timestamp => "timestamp",
+            Schema::Decimal => "decimal",
+            Schema::Bytes => "bytes",
+            Schema::String => "string",
+            Schema::Array => "array",
+            Schema::List(_) => "list",
+            Schema::MapOf(_) | Schema::Record(_) => "map",
+        }
+    }
+
+    /// Checks that `e` matches the schema, the error is `Error::InvalidData`
+    /// and tells where the mismatch is.
+    pub fn validate(&self, e: &Typed) -> Result<()> {
+        if let Typed::Raw(raw) = e {
+            return self.validate(&raw.decode()?);
+        }
+        match (self, e) {
+            (Schema::Any, _)
+            | (Schema::Bool, Typed::Bool(_))
+            | (Schema::Timestamp, Typed::Timestamp(_))
+            | (Schema::Decimal, Typed::Decimal(_)) => Ok(()),
+            (Schema::Int { min, max }, Typed::Int(n)) => check_range(n, min, max),
+            (Schema::Uint { min, max }, Typed::Uint(un)) => check_range(un, min, max),
+            (Schema::Float { min, max }, Typed::Float(f)) => check_range(f, min, max),
+            (Schema::Bytes, Typed::Bytes(_))
+            | (Schema::String, Typed::String(_))
+            | (Schema::Array, Typed::Array(_)) => Ok(()),
+            (Schema::List(elem), Typed::List(l)) => {
+                for (i, e) in l.iter().enumerate() {
+                    elem.validate(e).map_err(|err| {
+                        Error::InvalidData(format!("invalid element {}: {}", i, err))
+                    })?;
+                }
+                Ok(())
+            }
+            (Schema::MapOf(value), Typed::Map(m)) => {
+                for (k, v) in m.iter() {
+                    value.validate(v).map_err(|err| invalid_field(k, err))?;
+                }
+                Ok(())
+            }
+            (Schema::Record(fields), Typed::Map(m)) => {
+                for field in fields.iter() {
+                    match m.get(&field.name) {
+                        Some(v) => field
+                            .schema
+                            .validate(v)
+                            .map_err(|err| invalid_field(&field.name, err))?,
+                        None if field.optional => {}
+                        None => {
+                            return Err(Error::InvalidData(format!(
+                                "missing field: '{}'",
+                                field.name
+                            )))
+                        }
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(Error::InvalidData(format!(
+                "expected {}, found {}",
+                self.type_name(),
+                e.type_name()
+            ))),
+        }
+    }
+}
+
+impl Schema {
+    /// Decodes a value from `r` and validates it as it is decoded, so a
+    /// value which doesn't match is rejected at the first mismatch, before
+    /// the rest of it is read. The errors tell where the mismatch is like
+    /// the ones of `validate`.
+    pub fn decode<R: Read>(&self, r: R) -> Result<Typed> {
+        self.decode_with(r, DecodeOptions::default())
+    }
+
+    pub fn decode_with<R: Read>(&self, r: R, opts: DecodeOptions) -> Result<Typed> {
+        let mut p = Parser::new(r).options(opts);
+        let ev = p.next_event()?.ok_or(Error::UnexpectedEof)?;
+        self.decode_event(&mut p, ev)
+    }
+
+    fn decode_event<R: Read>(&self, p: &mut Parser<R>, ev: Event) -> Result<Typed> {
+        match ev {
+            Event::StartList(n) => {
+                let elem = match self {
+                    Schema::List(elem) => elem,
+                    Schema::Any => &Schema::Any,
+                    _ => return Err(mismatch(self, "list")),
+                };
+                let mut l = List::with_capacity(container_capacity(n as u64));
+                while let Some(ev) = next_in_container(p)? {
+                    let i = l.len();
+                    l.push(elem.decode_event(p, ev).map_err(|err| {
+                        wrap(err, |err| format!("invalid element {}: {}", i, err))
+                    })?);
+                }
+                Ok(Typed::List(l))
+            }
+            Event::StartMap(_) => {
+                if !matches!(self, Schema::MapOf(_) | Schema::Record(_) | Schema::Any) {
+                    return Err(mismatch(self, "map"));
+                }
+                let mut m = Map::new();
+                while let Some(ev) = next_in_container(p)? {
+                    let k = match ev {
+                        Event::Key(k) => k,
+                        ev => unreachable!("unexpected event: {:?}", ev),
+                    };
+                    let value = match self {
+                        Schema::MapOf(value) => value,
+                        Schema::Record(fields) => fields
+                            .iter()
+                            .find(|f| f.name == k)
+                            .map_or(&Schema::Any, |f| &f.schema),
+                        _ => &Schema::Any,
+                    };
+                    let ev = p.next_event()?.ok_or(Error::UnexpectedEof)?;
+                    let v = value.decode_event(p, ev).map_err(|err| {
+                        wrap(err, |err| format!("invalid field '{}': {}", k, err))
+                    })?;
+                    m.insert(k, v);
+                }
+                if let Schema::Record(fields) = self {
+                    if let Some(f) = fields
+                        .iter()
+                        .find(|f| !f.optional && !m.contains_key(&f.name))
+                    {
+                        return Err(Error::InvalidData(format!("missing field: '{}'", f.name)));
+                    }
+                }
+                Ok(Typed::Map(m))
+            }
+            ev => {
+                let e = match ev {
+                    Event::Null => Typed::Null,
+                    Event::Bool(b) => Typed::Bool(b),
+                    Event::Int(n) => Typed::Int(n),
+                    Event::Uint(un) => Typed::Uint(un),
+                    Event::Float(f) => Typed::Float(f),
+                    Event::Timestamp(ts) => Typed::Timestamp(ts),
+                    Event::Decimal(d) => Typed::Decimal(d),
+                    Event::Bytes(buf) => Typed::Bytes(buf.into()),
+                    Event::String(s) => Typed::String(s.into()),
+                    Event::Array(a) => Typed::Array(a),
+                    ev => unreachable!("unexpected event: {:?}", ev),
+                };
+                self.validate(&e)?;
+                Ok(e)
+            }
+        }
+    }
+}
+
+// Returns the next event of the current container, or None at its end.
+fn next_in_container<R: Read>(p: &mut Parser<R>) -> Result<Option<Event>> {
+    match p.next_event()? {
+        Some(Event::End) => Ok(None),
+        Some(ev) => Ok(Some(ev)),
+        None => Err(Error::UnexpectedEof),
+    }
+}
+
+fn mismatch(schema: &Schema, found: &str) -> crate::Error {
+    Error::InvalidData(format!("expected {}, found {}", schema.type_name(), found))
+}
+
+// Adds the location to the schema mismatches, other errors are kept.
+fn wrap<F: FnOnce(String) -> String>(err: crate::Error, f: F) -> crate::Error {
+    match err {
+        Error::InvalidData(msg) => Error::InvalidData(f(msg)),
+        err => err,
+    }
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(
+    v: &T,
+    min: &Option<T>,
+    max: &Option<T>,
+) -> Result<()> {
+    let below = min.as_ref().is_some_and(|min| v < min);
+    let above = max.as_ref().is_some_and(|max| v > max);
+    // NaN is never within a range.
+    let nan = v.partial_cmp(v).is_none();
+    if below || above || (nan && (min.is_some() || max.is_some())) {
+        return Err(Error::InvalidData(format!("value out of range: {}", v)));
+    }
+    Ok(())
+}
+
+fn invalid_field(name: &str, err: Error) -> Error {
+    Error::InvalidData(format!("invalid field '{}': {}", name, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::CodecWriteExt;
+    use crate::raw::RawValue;
+
+    fn user() -> Schema {
+        Schema::record(vec![
+            Field::required("name", Schema::String),
+            Field::required(
+                "age",
+                Schema::Uint {
+                    min: None,
+                    max: Some(150),
+                },
+            ),
+            Field::optional("tags", Schema::list(Schema::String)),
+            Field::optional("scores", Schema::map_of(Schema::float())),
+        ])
+    }
+
+    #[test]
+    fn validate() {
+        let mut m = Map::new();
+        m.insert(String::from("name"), Typed::String("xd".into()));
+        m.insert(String::from("age"), Typed::Uint(18));
+        m.insert(String::from("extra"), Typed::Int(-1));
+        assert!(user().validate(&Typed::Map(m.clone())).is_ok());
+
+        m.insert(
+            String::from("tags"),
+            Typed::List(vec![Typed::String("a".into()), Typed::Int(1)]),
+        );
+        let err = user().validate(&Typed::Map(m.clone())).unwrap_err();
+        assert!(matches!(err, Error::InvalidData(_)), "{:?}", err);
+        assert_eq!(
+            err.to_string(),
+            "invalid field 'tags': invalid element 1: expected string, found int"
+        );
+
+        m.remove("tags");
+        m.insert(String::from("age"), Typed::Uint(200));
+        let err = user().validate(&Typed::Map(m.clone())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid field 'age': value out of range: 200"
+        );
+
+        m.remove("age");
+        let err = user().validate(&Typed::Map(m)).unwrap_err();
+        assert_eq!(err.to_string(), "missing field: 'age'");
+    }
+
+    #[test]
+    fn raw_and_any() {
+        let raw = RawValue::encode(&Typed::List(vec![Typed::Int(5)])).unwrap();
+        let schema = Schema::list(Schema::Int {
+            min: Some(0),
+            max: Some(10),
+        });
+        assert!(schema.validate(&Typed::Raw(raw)).is_ok());
+        assert!(Schema::Any.validate(&Typed::Float(1.0)).is_ok());
+        assert!(Schema::Float {
+            min: Some(0.0),
+            max: None
+        }
+        .validate(&Typed::Float(f64::NAN))
+        .is_err());
+    }
+
+    #[test]
+    fn decode() {
+        let mut m = Map::new();
+        m.insert(String::from("name"), Typed::String("xd".into()));
+        m.insert(String::from("age"), Typed::Uint(18));
+        m.insert(
+            String::from("tags"),
+            Typed::List(vec![Typed::String("a".into())]),
+        );
+        m.insert(String::from("extra"), Typed::List(vec![Typed::Null]));
+        let e = Typed::Map(m);
+        let mut buf = Vec::new();
+        buf.write_typed(&e).unwrap();
+        assert_eq!(user().decode(&buf[..]).unwrap(), e);
+        assert!(user().decode(&buf[..buf.len() - 1]).unwrap_err().is_eof());
+
+        // The mismatch is found before the truncated rest is read.
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::List(vec![Typed::Int(1), Typed::Int(2)]))
+            .unwrap();
+        buf.pop();
+        let err = Schema::list(Schema::uint()).decode(&buf[..]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid element 0: expected uint, found int"
+        );
+
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::Map(Map::new())).unwrap();
+        let err = user().decode(&buf[..]).unwrap_err();
+        assert_eq!(err.to_string(), "missing field: 'name'");
+        let err = Schema::list(Schema::Any).decode(&buf[..]).unwrap_err();
+        assert_eq!(err.to_string(), "expected list, found map");
+        assert!(Schema::Any.decode(&b""[..]).unwrap_err().is_eof());
+    }
+}
@@ -0,0 +1,324 @@
+use crate::array::PackedArray;
+use crate::schema::{Describe, Schema};
+use std::fmt::Write;
+
+const JSON_SCHEMA_DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// Just enough JSON to write schema documents.
+enum Json {
+    Str(String),
+    Num(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Json {
+    fn str(s: &str) -> Json {
+        Json::Str(s.to_string())
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        let pad = |out: &mut String, n: usize| out.extend(std::iter::repeat_n(' ', n * 2));
+        match self {
+            Json::Str(s) => out.push_str(&quote(s)),
+            Json::Num(n) => out.push_str(n),
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            Json::Array(items) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    pad(out, indent + 1);
+                    item.write(out, indent + 1);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                pad(out, indent);
+                out.push(']');
+            }
+            Json::Object(entries) if entries.is_empty() => out.push_str("{}"),
+            Json::Object(entries) => {
+                out.push_str("{\n");
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    pad(out, indent + 1);
+                    out.push_str(&quote(k));
+                    out.push_str(": ");
+                    v.write(out, indent + 1);
+                    out.push_str(if i + 1 < entries.len() { ",\n" } else { "\n" });
+                }
+                pad(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn bounds<T: ToString>(entries: &mut Vec<(String, Json)>, min: Option<T>, max: Option<T>) {
+    if let Some(min) = min {
+        entries.push(("minimum".to_string(), Json::Num(min.to_string())));
+    }
+    if let Some(max) = max {
+        entries.push(("maximum".to_string(), Json::Num(max.to_string())));
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+impl Schema {
+    fn json(&self) -> Json {
+        let typ = |t: &str| vec![("type".to_string(), Json::str(t))];
+        let entries = match self {
+            Schema::Any => Vec::new(),
+            Schema::Bool => typ("boolean"),
+            Schema::Int { min, max } => {
+                let mut entries = typ("integer");
+                bounds(&mut entries, *min, *max);
+                entries
+            }
+            Schema::Uint { min, max } => {
+                let mut entries = typ("integer");
+                bounds(&mut entries, Some(min.unwrap_or(0)), *max);
+                entries
+            }
+            Schema::Float { min, max } => {
+                let mut entries = typ("number");
+                // JSON has no representation for NaN and the infinities.
+                let finite = |f: &Option<f64>| f.filter(|f| f.is_finite());
+                bounds(&mut entries, finite(min), finite(max));
+                entries
+            }
+            Schema::Timestamp => {
+                let mut entries = typ("string");
+                entries.push(("format".to_string(), Json::str("date-time")));
+                entries
+            }
+            // Decimals are strings so they don't lose their precision.
+            Schema::Decimal => typ("string"),
+            Schema::Bytes => {
+                let mut entries = typ("string");
+                entries.push(("contentEncoding".to_string(), Json::str("base64")));
+                entries
+            }
+            Schema::String => typ("string"),
+            // Arrays are transcoded into the maps of `PackedArray::to_typed`.
+            Schema::Array => return PackedArray::schema().json(),
+            Schema::List(elem) => {
+                let mut entries = typ("array");
+                entries.push(("items".to_string(), elem.json()));
+                entries
+            }
+            Schema::MapOf(value) => {
+                let mut entries = typ("object");
+                entries.push(("additionalProperties".to_string(), value.json()));
+                entries
+            }
+            Schema::Record(fields) => {
+                let mut entries = typ("object");
+                let properties = fields
+                    .iter()
+                    .map(|f| (f.name.clone(), f.schema.json()))
+                    .collect();
+                entries.push(("properties".to_string(), Json::Object(properties)));
+                let required: Vec<_> = fields
+                    .iter()
+                    .filter(|f| !f.optional)
+                    .map(|f| Json::str(&f.name))
+                    .collect();
+                if !required.is_empty() {
+                    entries.push(("required".to_string(), Json::Array(required)));
+                }
+                entries
+            }
+        };
+        Json::Object(entries)
+    }
+
+    /// Generates a JSON Schema document titled `title`, which describes the
+    /// values transcoded into JSON, bytes are base64 encoded strings.
+    pub fn to_json_schema(&self, title: &str) -> String {
+        let mut entries = vec![
+            ("$schema".to_string(), Json::str(JSON_SCHEMA_DRAFT)),
+            ("title".to_string(), Json::str(title)),
+        ];
+        if let Json::Object(rest) = self.json() {
+            entries.extend(rest);
+        }
+        let mut out = String::new();
+        Json::Object(entries).write(&mut out, 0);
+        out.push('\n');
+        out
+    }
+
+    fn write_typescript(&self, out: &mut String, indent: usize) {
+        match self {
+            Schema::Any => out.push_str("unknown"),
+            Schema::Bool => out.push_str("boolean"),
+            Schema::Int { .. } | Schema::Uint { .. } | Schema::Float { .. } => {
+                out.push_str("number")
+            }
+            Schema::Timestamp | Schema::Decimal | Schema::Bytes | Schema::String => {
+                out.push_str("string")
+            }
+            Schema::Array => PackedArray::schema().write_typescript(out, indent),
+            Schema::List(elem) => {
+                out.push_str("Array<");
+                elem.write_typescript(out, indent);
+                out.push('>');
+            }
+            Schema::MapOf(value) => {
+                out.push_str("Record<string, ");
+                value.write_typescript(out, indent);
+                out.push('>');
+            }
+            Schema::Record(fields) if fields.is_empty() => {
+                out.push_str("{ [key: string]: unknown }")
+            }
+            Schema::Record(fields) => {
+                out.push_str("{\n");
+                for f in fields.iter() {
+                    out.extend(std::iter::repeat_n(' ', (indent + 1) * 2));
+                    if is_identifier(&f.name) {
+                        out.push_str(&f.name);
+                    } else {
+                        out.push_str(&quote(&f.name));
+                    }
+                    out.push_str(if f.optional { "?: " } else { ": " });
+                    f.schema.write_typescript(out, indent + 1);
+                    out.push_str(";\n");
+                }
+                out.extend(std::iter::repeat_n(' ', indent * 2));
+                out.push('}');
+            }
+        }
+    }
+
+    /// Generates a TypeScript type definition named `name`, which describes
+    /// the values transcoded into JSON. Numbers beyond 2^53 lose precision
+    /// in JavaScript.
+    pub fn to_typescript(&self, name: &str) -> String {
+        let mut out = format!("export type {} = ", name);
+        self.write_typescript(&mut out, 0);
+        out.push_str(";\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Field;
+
+    fn user() -> Schema {
+        Schema::record(vec![
+            Field::required("name", Schema::String),
+            Field::required(
+                "age",
+                Schema::Uint {
+                    min: None,
+                    max: Some(150),
+                },
+            ),
+            Field::optional("avatar", Schema::Bytes),
+            Field::optional("x-tags", Schema::list(Schema::String)),
+            Field::optional(
+                "address",
+                Schema::record(vec![Field::required("city", Schema::String)]),
+            ),
+            Field::required("scores", Schema::map_of(Schema::float())),
+        ])
+    }
+
+    #[test]
+    fn typescript() {
+        assert_eq!(
+            user().to_typescript("User"),
+            "export type User = {\n  \
+               name: string;\n  \
+               age: number;\n  \
+               avatar?: string;\n  \
+               \"x-tags\"?: Array<string>;\n  \
+               address?: {\n    \
+                 city: string;\n  \
+               };\n  \
+               scores: Record<string, number>;\n\
+             };\n"
+        );
+        assert_eq!(
+            Schema::list(Schema::Any).to_typescript("L"),
+            "export type L = Array<unknown>;\n"
+        );
+    }
+
+    #[test]
+    fn json_schema() {
+        let schema = Schema::record(vec![
+            Field::required(
+                "age",
+                Schema::Uint {
+                    min: None,
+                    max: Some(150),
+                },
+            ),
+            Field::optional("avatar", Schema::Bytes),
+            Field::optional("tags", Schema::list(Schema::Any)),
+        ]);
+        assert_eq!(
+            schema.to_json_schema("User \"v1\""),
+            r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "User \"v1\"",
+  "type": "object",
+  "properties": {
+    "age": {
+      "type": "integer",
+      "minimum": 0,
+      "maximum": 150
+    },
+    "avatar": {
+      "type": "string",
+      "contentEncoding": "base64"
+    },
+    "tags": {
+      "type": "array",
+      "items": {}
+    }
+  },
+  "required": [
+    "age"
+  ]
+}
+"#
+        );
+
+        let float = Schema::Float {
+            min: Some(f64::NEG_INFINITY),
+            max: Some(1.5),
+        };
+        assert!(float.to_json_schema("F").contains("\"maximum\": 1.5\n"));
+        assert!(!float.to_json_schema("F").contains("minimum"));
+        assert!(user()
+            .to_json_schema("User")
+            .contains("additionalProperties"));
+    }
+}
@@ -0,0 +1,837 @@
+//! Serde support, values are serialized into `Typed` and encoded like
+//! `CodecWriteExt::write_typed`, and decoded the other way around:
+//!
+//! - booleans are the uints 0 and 1, like `ToTyped for bool`,
+//! - `None` struct fields and map values are left out, like the fields of
+//!   `#[derive(ToMap)]` structs, `None` anywhere else is an error,
+//! - units and unit structs are empty lists,
+//! - unit variants are the strings of their names, the other variants are
+//!   maps with a single entry from their names to their values,
+//! - map keys must serialize into strings.
+
+use crate::array::{DType, PackedArray};
+use crate::codec::{CodecReadExt, CodecWriteExt, List, Map, Str, Typed};
+use serde::de::value::{self, BorrowedStrDeserializer, StrDeserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use serde::{forward_to_deserialize_any, Deserialize};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+enum Repr {
+    Codec(crate::Error),
+    // A `None`, which is only valid as a field.
+    None,
+}
+
+/// Error is the error of serialization and deserialization, it converts
+/// from and into `xdcodec_rs::Error` and `io::Error`.
+pub struct Error(Repr);
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    fn invalid<T: fmt::Display>(msg: T) -> Self {
+        Error(Repr::Codec(crate::Error::InvalidData(msg.to_string())))
+    }
+
+    fn none() -> Self {
+        Error::invalid("None can only be a struct field or a map value")
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Repr::Codec(err) => fmt::Debug::fmt(err, f),
+            Repr::None => fmt::Debug::fmt(&Error::none(), f),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Repr::Codec(err) => fmt::Display::fmt(err, f),
+            Repr::None => fmt::Display::fmt(&Error::none(), f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::invalid(msg)
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::invalid(msg)
+    }
+}
+
+impl From<crate::Error> for Error {
+    fn from(err: crate::Error) -> Self {
+        Error(Repr::Codec(err))
+    }
+}
+
+impl From<Error> for crate::Error {
+    fn from(err: Error) -> Self {
+        match err.0 {
+            Repr::Codec(err) => err,
+            Repr::None => Error::none().into(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error(Repr::Codec(err.into()))
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        crate::Error::from(err).into()
+    }
+}
+
+// Serializes a value which can't be left out.
+fn element<T: Serialize + ?Sized>(v: &T) -> Result<Typed> {
+    v.serialize(Serializer).map_err(|err| match err.0 {
+        Repr::None => Error::none(),
+        _ => err,
+    })
+}
+
+// Serializes a value which is left out if it is `None`.
+fn field<T: Serialize + ?Sized>(v: &T) -> Result<Option<Typed>> {
+    match v.serialize(Serializer) {
+        Ok(e) => Ok(Some(e)),
+        Err(Error(Repr::None)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn key<T: Serialize + ?Sized>(k: &T) -> Result<String> {
+    match element(k)? {
+        Typed::String(s) => Ok(s.to_string()),
+        e => Err(Error::invalid(format!(
+            "map keys must be strings, found {}",
+            e.type_name()
+        ))),
+    }
+}
+
+fn variant(name: &str, e: Typed) -> Typed {
+    let mut m = Map::new();
+    m.insert(String::from(name), e);
+    Typed::Map(m)
+}
+
+/// Serializes values into `Typed`.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Typed;
+    type Error = Error;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeList;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<Typed> {
+        Ok(Typed::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Typed> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Typed> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Typed> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Typed> {
+        Ok(Typed::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Typed> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Typed> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Typed> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Typed> {
+        Ok(Typed::Uint(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Typed> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Typed> {
+        Ok(Typed::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Typed> {
+        self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Typed> {
+        Ok(Typed::String(Str::from(v)))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Typed> {
+        Ok(Typed::Bytes(v.to_vec().into()))
+    }
+
+    fn serialize_none(self) -> Result<Typed> {
+        Err(Error(Repr::None))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, v: &T) -> Result<Typed> {
+        v.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Typed> {
+        Ok(Typed::List(List::new()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Typed> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Typed> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        v: &T,
+    ) -> Result<Typed> {
+        v.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        v: &T,
+    ) -> Result<Typed> {
+        Ok(variant(name, element(v)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList> {
+        Ok(SerializeList {
+            variant: None,
+            list: List::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeList> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<SerializeList> {
+        Ok(SerializeList {
+            variant: Some(name),
+            list: List::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap> {
+        Ok(SerializeMap {
+            variant: None,
+            map: Map::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<SerializeMap> {
+        Ok(SerializeMap {
+            variant: Some(name),
+            map: Map::new(),
+            key: None,
+        })
+    }
+}
+
+pub struct SerializeList {
+    variant: Option<&'static str>,
+    list: List,
+}
+
+impl SerializeList {
+    fn push<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<()> {
+        self.list.push(element(v)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Typed> {
+        let e = Typed::List(self.list);
+        Ok(match self.variant {
+            Some(name) => variant(name, e),
+            None => e,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Typed;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<()> {
+        self.push(v)
+    }
+
+    fn end(self) -> Result<Typed> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Typed;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<()> {
+        self.push(v)
+    }
+
+    fn end(self) -> Result<Typed> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Typed;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<()> {
+        self.push(v)
+    }
+
+    fn end(self) -> Result<Typed> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+    type Ok = Typed;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<()> {
+        self.push(v)
+    }
+
+    fn end(self) -> Result<Typed> {
+        self.finish()
+    }
+}
+
+pub struct SerializeMap {
+    variant: Option<&'static str>,
+    map: Map,
+    key: Option<String>,
+}
+
+impl SerializeMap {
+    fn insert<T: Serialize + ?Sized>(&mut self, k: String, v: &T) -> Result<()> {
+        if let Some(e) = field(v)? {
+            self.map.insert(k, e);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Typed> {
+        let e = Typed::Map(self.map);
+        Ok(match self.variant {
+            Some(name) => variant(name, e),
+            None => e,
+        })
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Typed;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, k: &T) -> Result<()> {
+        self.key = Some(key(k)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<()> {
+        let k = self
+            .key
+            .take()
+            .ok_or_else(|| Error::invalid("serialize_value called before serialize_key"))?;
+        self.insert(k, v)
+    }
+
+    fn end(self) -> Result<Typed> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Typed;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, k: &'static str, v: &T) -> Result<()> {
+        self.insert(String::from(k), v)
+    }
+
+    fn end(self) -> Result<Typed> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+    type Ok = Typed;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, k: &'static str, v: &T) -> Result<()> {
+        self.insert(String::from(k), v)
+    }
+
+    fn end(self) -> Result<Typed> {
+        self.finish()
+    }
+}
+
+/// Deserializes values from a `Typed`, strings and bytes are borrowed from
+/// it. `Typed::Raw` values are not supported.
+pub struct Deserializer<'de> {
+    e: &'de Typed,
+}
+
+impl<'de> Deserializer<'de> {
+    pub fn new(e: &'de Typed) -> Self {
+        Deserializer { e }
+    }
+}
+
+// Packed arrays are sequences of their elements in row-major order.
+fn visit_array<'de, V: Visitor<'de>>(a: &PackedArray, visitor: V) -> Result<V::Value> {
+    macro_rules! visit {
+        ($($dtype:ident => $t:ty),*) => {
+            match a.dtype() {
+                $(DType::$dtype => {
+                    let elements = a.to_vec::<$t>()?;
+                    visitor.visit_seq(value::SeqDeserializer::new(elements.into_iter()))
+                })*
+            }
+        };
+    }
+    visit!(
+        I8 => i8, U8 => u8, I16 => i16, U16 => u16, I32 => i32,
+        U32 => u32, I64 => i64, U64 => u64, F32 => f32, F64 => f64
+    )
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.e {
+            Typed::Null => visitor.visit_unit(),
+            Typed::Bool(b) => visitor.visit_bool(*b),
+            Typed::Int(n) => visitor.visit_i64(*n),
+            Typed::Uint(un) => visitor.visit_u64(*un),
+            Typed::Float(f) => visitor.visit_f64(*f),
+            // As strings, like the serde implementations of datetimes and
+            // decimals commonly expect.
+            Typed::Timestamp(ts) => visitor.visit_string(ts.to_string()),
+            Typed::Decimal(d) => visitor.visit_string(d.to_string()),
+            Typed::Bytes(buf) => visitor.visit_borrowed_bytes(&buf[..]),
+            Typed::String(s) => visitor.visit_borrowed_str(s.as_str()),
+            Typed::List(l) => {
+                let mut seq = SeqDeserializer { iter: l.iter() };
+                let v = visitor.visit_seq(&mut seq)?;
+                if seq.iter.len() != 0 {
+                    return Err(de::Error::invalid_length(
+                        l.len(),
+                        &"fewer elements in the list",
+                    ));
+                }
+                Ok(v)
+            }
+            Typed::Map(m) => visitor.visit_map(MapDeserializer {
+                iter: m.iter(),
+                value: None,
+            }),
+            Typed::Array(a) => visit_array(a, visitor),
+            Typed::Raw(_) => Err(Error::invalid("raw values can't be deserialized")),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.e {
+            Typed::Uint(0) => visitor.visit_bool(false),
+            Typed::Uint(1) => visitor.visit_bool(true),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if let Typed::Null = self.e {
+            return visitor.visit_none();
+        }
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.e {
+            Typed::List(l) if l.is_empty() => visitor.visit_unit(),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.e {
+            Typed::String(s) => {
+                let name: StrDeserializer<'_, Error> = s.as_str().into_deserializer();
+                visitor.visit_enum(name)
+            }
+            Typed::Map(m) if m.len() == 1 => {
+                let (name, value) = m.iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer { name, value })
+            }
+            e => Err(Error::invalid(format!(
+                "expected a string or a map with a single entry for an enum, found {}",
+                e.type_name()
+            ))),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct SeqDeserializer<'de> {
+    iter: std::slice::Iter<'de, Typed>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDeserializer<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.iter.next() {
+            Some(e) => seed.deserialize(Deserializer::new(e)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapDeserializer<'de> {
+    iter: std::collections::hash_map::Iter<'de, String, Typed>,
+    value: Option<&'de Typed>,
+}
+
+impl<'de> de::MapAccess<'de> for MapDeserializer<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.iter.next() {
+            Some((k, v)) => {
+                self.value = Some(v);
+                seed.deserialize(BorrowedStrDeserializer::<Error>::new(k))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let e = self
+            .value
+            .take()
+            .ok_or_else(|| Error::invalid("next_value called before next_key"))?;
+        seed.deserialize(Deserializer::new(e))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumDeserializer<'de> {
+    name: &'de str,
+    value: &'de Typed,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer<'de> {
+    type Error = Error;
+    type Variant = Deserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant)> {
+        let name = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.name))?;
+        Ok((name, Deserializer::new(self.value)))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+pub fn to_typed<T: Serialize + ?Sized>(v: &T) -> Result<Typed> {
+    element(v)
+}
+
+pub fn to_writer<W: Write + ?Sized, T: Serialize + ?Sized>(w: &mut W, v: &T) -> Result<()> {
+    w.write_typed(&to_typed(v)?)?;
+    Ok(())
+}
+
+pub fn to_vec<T: Serialize + ?Sized>(v: &T) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    to_writer(&mut buf, v)?;
+    Ok(buf)
+}
+
+pub fn from_typed<'de, T: Deserialize<'de>>(e: &'de Typed) -> Result<T> {
+    T::deserialize(Deserializer::new(e))
+}
+
+pub fn from_reader<R: Read + ?Sized, T: DeserializeOwned>(r: &mut R) -> Result<T> {
+    from_typed(&r.read_typed()?)
+}
+
+/// Decodes a single value, which must span the whole buffer.
+pub fn from_slice<T: DeserializeOwned>(mut buf: &[u8]) -> Result<T> {
+    let e = buf.read_typed()?;
+    if !buf.is_empty() {
+        return Err(crate::Error::TrailingBytes.into());
+    }
+    from_typed(&e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::ToTyped;
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use xdcodec_derive::ToMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize, ToMap)]
+    struct Endpoint {
+        host: String,
+        port: u16,
+        secure: bool,
+        weight: Option<f64>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Action {
+        Stop,
+        Move(i32, i32),
+        Rename(String),
+        Resize { width: u32, height: u32 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        name: String,
+        endpoints: Vec<Endpoint>,
+        actions: Vec<Action>,
+        labels: HashMap<String, String>,
+        checksum: [u8; 4],
+        unit: (),
+    }
+
+    fn config() -> Config {
+        let mut labels = HashMap::new();
+        labels.insert(String::from("env"), String::from("prod"));
+        Config {
+            name: String::from("svc"),
+            endpoints: vec![Endpoint {
+                host: String::from("localhost"),
+                port: 8080,
+                secure: true,
+                weight: None,
+            }],
+            actions: vec![
+                Action::Stop,
+                Action::Move(1, -1),
+                Action::Rename(String::from("x")),
+                Action::Resize {
+                    width: 3,
+                    height: 4,
+                },
+            ],
+            labels,
+            checksum: [1, 2, 3, 4],
+            unit: (),
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let buf = to_vec(&config()).unwrap();
+        assert_eq!(from_slice::<Config>(&buf).unwrap(), config());
+        assert_eq!(from_reader::<_, Config>(&mut &buf[..]).unwrap(), config());
+        assert!(from_slice::<Config>(&buf[..buf.len() - 1]).is_err());
+
+        let mut trailing = buf.clone();
+        trailing.push(0);
+        assert!(from_slice::<Config>(&trailing).is_err());
+    }
+
+    #[test]
+    fn representation() {
+        let endpoint = Endpoint {
+            host: String::from("h"),
+            port: 80,
+            secure: false,
+            weight: Some(0.5),
+        };
+        assert_eq!(to_typed(&endpoint).unwrap(), endpoint.to_typed());
+
+        let action = to_typed(&Action::Move(1, 2)).unwrap();
+        assert_eq!(
+            action,
+            variant("Move", Typed::List(vec![Typed::Int(1), Typed::Int(2)]))
+        );
+        assert_eq!(
+            to_typed(&Action::Stop).unwrap(),
+            Typed::String("Stop".into())
+        );
+    }
+
+    #[test]
+    fn borrowed() {
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            #[serde(borrow)]
+            tags: Vec<&'a str>,
+        }
+
+        let mut m = Map::new();
+        m.insert(String::from("name"), Typed::String("n".into()));
+        m.insert(
+            String::from("tags"),
+            Typed::List(vec![Typed::String("a".into())]),
+        );
+        let e = Typed::Map(m);
+        let b: Borrowed<'_> = from_typed(&e).unwrap();
+        assert_eq!((b.name, b.tags), ("n", vec!["a"]));
+    }
+
+    #[test]
+    fn errors() {
+        let err = to_typed(&vec![Some(1), None]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "None can only be a struct field or a map value"
+        );
+        assert!(to_typed(&None::<u8>).is_err());
+
+        let mut keys = HashMap::new();
+        keys.insert(1u8, 1u8);
+        let err = io::Error::from(to_typed(&keys).unwrap_err());
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        assert!(from_typed::<u8>(&Typed::Uint(256)).is_err());
+        assert!(from_typed::<bool>(&Typed::Uint(2)).is_err());
+        assert!(from_typed::<(u8, u8)>(&Typed::List(vec![Typed::Uint(1); 3])).is_err());
+        assert!(from_typed::<Action>(&Typed::Int(1)).is_err());
+    }
+}
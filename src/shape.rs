@@ -0,0 +1,112 @@
+use crate::codec::Typed;
+use std::mem;
+
+/// Strictness controls how maps are compared by `Typed::matches_shape`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// Maps must have exactly the keys of the template.
+    Exact,
+    /// Maps may have keys which are not in the template.
+    AllowExtraKeys,
+}
+
+impl Typed {
+    /// Checks whether the value has the same structure and types as
+    /// `template`, scalar values themselves are not compared.
+    ///
+    /// An empty list in the template matches any list, a list with a single
+    /// element matches lists whose elements all match that element, other
+    /// lists are compared element by element.
+    pub fn matches_shape(&self, template: &Typed, strictness: Strictness) -> bool {
+        match (self, template) {
+            (Typed::List(l), Typed::List(tl)) => match tl.len() {
+                0 => true,
+                1 => l.iter().all(|e| e.matches_shape(&tl[0], strictness)),
+                _ => {
+                    l.len() == tl.len()
+                        && l.iter()
+                            .zip(tl.iter())
+                            .all(|(e, te)| e.matches_shape(te, strictness))
+                }
+            },
+            (Typed::Map(m), Typed::Map(tm)) => {
+                if strictness == Strictness::Exact && m.len() != tm.len() {
+                    return false;
+                }
+                tm.iter().all(|(k, tv)| match m.get(k) {
+                    Some(v) => v.matches_shape(tv, strictness),
+                    None => false,
+                })
+            }
+            (Typed::Raw(raw), _) => match raw.decode() {
+                Ok(e) => e.matches_shape(template, strictness),
+                Err(_) => false,
+            },
+            (_, Typed::Raw(raw)) => match raw.decode() {
+                Ok(te) => self.matches_shape(&te, strictness),
+                Err(_) => false,
+            },
+            _ => mem::discriminant(self) == mem::discriminant(template),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+    use crate::raw::RawValue;
+
+    fn user(name: &str, tags: Vec<Typed>) -> Typed {
+        let mut m = Map::new();
+        m.insert(String::from("name"), Typed::String(name.into()));
+        m.insert(String::from("tags"), Typed::List(tags));
+        Typed::Map(m)
+    }
+
+    #[test]
+    fn scalars() {
+        assert!(Typed::Int(1).matches_shape(&Typed::Int(2), Strictness::Exact));
+        assert!(!Typed::Int(1).matches_shape(&Typed::Uint(1), Strictness::Exact));
+        assert!(Typed::Bytes(vec![1u8].into())
+            .matches_shape(&Typed::Bytes(vec![].into()), Strictness::Exact));
+    }
+
+    #[test]
+    fn lists() {
+        let any = Typed::List(vec![]);
+        let ints = Typed::List(vec![Typed::Int(0)]);
+        let pair = Typed::List(vec![Typed::Int(0), Typed::String("".into())]);
+
+        let l = Typed::List(vec![Typed::Int(1), Typed::Int(2)]);
+        assert!(l.matches_shape(&any, Strictness::Exact));
+        assert!(l.matches_shape(&ints, Strictness::Exact));
+        assert!(!l.matches_shape(&pair, Strictness::Exact));
+        assert!(Typed::List(vec![]).matches_shape(&ints, Strictness::Exact));
+
+        let l = Typed::List(vec![Typed::Int(1), Typed::String("a".into())]);
+        assert!(!l.matches_shape(&ints, Strictness::Exact));
+        assert!(l.matches_shape(&pair, Strictness::Exact));
+    }
+
+    #[test]
+    fn maps() {
+        let template = user("", vec![Typed::String("".into())]);
+        let u = user("xd", vec![Typed::String("a".into())]);
+        assert!(u.matches_shape(&template, Strictness::Exact));
+        assert!(!user("xd", vec![Typed::Int(1)]).matches_shape(&template, Strictness::Exact));
+
+        let mut extra = match u {
+            Typed::Map(m) => m,
+            _ => unreachable!(),
+        };
+        extra.insert(String::from("age"), Typed::Uint(1));
+        let extra = Typed::Map(extra);
+        assert!(!extra.matches_shape(&template, Strictness::Exact));
+        assert!(extra.matches_shape(&template, Strictness::AllowExtraKeys));
+        assert!(!template.matches_shape(&extra, Strictness::AllowExtraKeys));
+
+        let raw = Typed::Raw(RawValue::encode(&extra).unwrap());
+        assert!(raw.matches_shape(&template, Strictness::AllowExtraKeys));
+    }
+}
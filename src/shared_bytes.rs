@@ -0,0 +1,290 @@
+use crate::codec::{
+    container_capacity, packed_array, read_array_header, read_container_len, CodecWriteExt, List,
+    Map, Str, Typed, TYPE_ARRAY, TYPE_BYTES, TYPE_BYTES_DEFLATE, TYPE_DECIMAL, TYPE_FALSE,
+    TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT, TYPE_INT_FIXED, TYPE_LIST, TYPE_LIST_V2, TYPE_MAP,
+    TYPE_MAP_V2, TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE, TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT,
+    TYPE_UINT_FIXED,
+};
+use crate::compress::read_deflated;
+use crate::config::FormatVersion;
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::limits::Budget;
+use crate::options::DecodeOptions;
+use crate::text::TextEncoding;
+use crate::timestamp::Timestamp;
+use crate::varint::VarintReadExt;
+use byteorder::{LittleEndian, ReadBytesExt};
+use bytes::{Buf, BufMut, Bytes};
+
+/// Decodes the value at the front of `buf` and advances past it, the
+/// `Typed::Bytes` values are slices of `buf` rather than copies.
+pub fn read_typed_from_bytes(buf: &mut Bytes) -> Result<Typed> {
+    read_typed_from_buf(buf)
+}
+
+pub fn read_typed_from_bytes_with(buf: &mut Bytes, opts: &DecodeOptions) -> Result<Typed> {
+    read_typed_from_buf_with(buf, opts)
+}
+
+/// Decodes the value at the front of any `bytes::Buf`, such as a chain of
+/// segments, without copying it into one contiguous buffer first. The
+/// `Typed::Bytes` values are only copied if they span several segments of
+/// `Bytes`.
+///
+/// Chains of readers, like `std::io::Chain`, can be decoded by
+/// `CodecReadExt::read_typed` directly.
+pub fn read_typed_from_buf<B: Buf + ?Sized>(buf: &mut B) -> Result<Typed> {
+    read_typed_from_buf_with(buf, &DecodeOptions::default())
+}
+
+pub fn read_typed_from_buf_with<B: Buf + ?Sized>(
+    buf: &mut B,
+    opts: &DecodeOptions,
+) -> Result<Typed> {
+    read_encoded(
+        buf,
+        opts,
+        opts.text_encoding,
+        &mut Budget::new(&opts.limits),
+    )
+}
+
+/// BufExt decodes values from any `bytes::Buf`, named after the `get_*`
+/// methods of `Buf`, see `read_typed_from_buf`. Decoding from `BytesMut`
+/// splits the `Typed::Bytes` values off rather than copying them.
+pub trait BufExt: Buf {
+    fn get_typed(&mut self) -> Result<Typed> {
+        read_typed_from_buf(self)
+    }
+
+    fn get_typed_with(&mut self, opts: &DecodeOptions) -> Result<Typed> {
+        read_typed_from_buf_with(self, opts)
+    }
+}
+
+impl<B: Buf + ?Sized> BufExt for B {}
+
+/// BufMutExt encodes values into any `bytes::BufMut`, e.g. a `BytesMut`,
+/// without an intermediate `Vec<u8>`. Writing into a buffer which can't
+/// grow fails with `Error::Io` once it is full, with the encoded prefix
+/// left in it.
+pub trait BufMutExt: BufMut {
+    fn put_typed(&mut self, e: &Typed) -> Result<()> {
+        writer(self).write_typed(e)
+    }
+
+    fn put_typed_version(&mut self, e: &Typed, version: FormatVersion) -> Result<()> {
+        writer(self).write_typed_version(e, version)
+    }
+
+    fn put_typed_canonical(&mut self, e: &Typed) -> Result<()> {
+        writer(self).write_typed_canonical(e)
+    }
+}
+
+impl<B: BufMut + ?Sized> BufMutExt for B {}
+
+fn read_encoded<B: Buf + ?Sized>(
+    buf: &mut B,
+    opts: &DecodeOptions,
+    enc: TextEncoding,
+    budget: &mut Budget,
+) -> Result<Typed> {
+    let t = read_u8(buf)?;
+    match t {
+        TYPE_NULL => Ok(Typed::Null),
+        TYPE_FALSE => Ok(Typed::Bool(false)),
+        TYPE_TRUE => Ok(Typed::Bool(true)),
+        TYPE_INT => Ok(Typed::Int(reader(buf).read_varint()?)),
+        TYPE_UINT => Ok(Typed::Uint(reader(buf).read_uvarint()?)),
+        TYPE_FLOAT => {
+            let un = reader(buf).read_uvarint()?;
+            Ok(Typed::Float(f64::from_bits(un)))
+        }
+        TYPE_INT_FIXED => Ok(Typed::Int(reader(buf).read_i64::<LittleEndian>()?)),
+        TYPE_UINT_FIXED => Ok(Typed::Uint(reader(buf).read_u64::<LittleEndian>()?)),
+        TYPE_FLOAT_FIXED => Ok(Typed::Float(reader(buf).read_f64::<LittleEndian>()?)),
+        TYPE_TIMESTAMP => {
+            let secs = reader(buf).read_varint()?;
+            let ts = Timestamp::from_wire(secs, reader(buf).read_uvarint()?)?;
+            Ok(Typed::Timestamp(ts))
+        }
+        TYPE_DECIMAL => {
+            let mantissa = reader(buf).read_varint()?;
+            let d = Decimal::from_wire(mantissa, reader(buf).read_uvarint()?)?;
+            Ok(Typed::Decimal(d))
+        }
+        TYPE_BYTES => Ok(Typed::Bytes(read_sized(buf, budget)?)),
+        TYPE_STRING => {
+            let bs = read_sized(buf, budget)?;
+            let s = opts.string(opts.text(enc, &bs)?);
+            Ok(Typed::String(Str::from(s.as_ref())))
+        }
+        TYPE_BYTES_DEFLATE => Ok(Typed::Bytes(
+            read_deflated(&mut reader(buf), budget)?.into(),
+        )),
+        TYPE_STRING_DEFLATE => {
+            let bs = read_deflated(&mut reader(buf), budget)?;
+            let s = opts.string(opts.text(enc, &bs)?);
+            Ok(Typed::String(Str::from(s.as_ref())))
+        }
+        TYPE_ARRAY => {
+            let (dtype, shape) = read_array_header(&mut reader(buf))?;
+            let data = read_sized(buf, budget)?;
+            Ok(Typed::Array(packed_array(dtype, shape, data)?))
+        }
+        TYPE_LIST | TYPE_LIST_V2 => {
+            let nelem = read_container_len(&mut reader(buf), t)?;
+            budget.enter(nelem)?;
+            let mut l = List::with_capacity(container_capacity(nelem));
+            for _ in 0..nelem {
+                l.push(read_encoded(buf, opts, enc, budget)?);
+            }
+            budget.leave();
+            Ok(Typed::List(l))
+        }
+        TYPE_MAP | TYPE_MAP_V2 => {
+            let nelem = read_container_len(&mut reader(buf), t)?;
+            budget.enter(nelem)?;
+            let mut m = Map::new();
+            for _ in 0..nelem {
+                let k = read_sized(buf, budget)?;
+                let k = opts.key(opts.text(enc, &k)?);
+                let field_enc = opts.field_encodings.get(&k).copied().unwrap_or(enc);
+                let v = read_encoded(buf, opts, field_enc, budget)?;
+                m.insert(k, v);
+            }
+            budget.leave();
+            Ok(Typed::Map(m))
+        }
+        _ => Err(Error::UnknownType(t)),
+    }
+}
+
+// `Buf::reader` and `BufMut::writer` would take `buf` by value.
+fn reader<B: Buf + ?Sized>(buf: &mut B) -> bytes::buf::Reader<&mut B> {
+    buf.reader()
+}
+
+fn writer<B: BufMut + ?Sized>(buf: &mut B) -> bytes::buf::Writer<&mut B> {
+    buf.writer()
+}
+
+fn read_u8<B: Buf + ?Sized>(buf: &mut B) -> Result<u8> {
+    Ok(reader(buf).read_u8()?)
+}
+
+fn read_sized<B: Buf + ?Sized>(buf: &mut B, budget: &Budget) -> Result<Bytes> {
+    let sz = reader(buf).read_uvarint()?;
+    budget.bytes(sz)?;
+    if (buf.remaining() as u64) < sz {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(buf.copy_to_bytes(sz as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecReadExt, CodecWriteExt};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn zero_copy() {
+        let blob = Bytes::from(vec![7u8; 1024]);
+        let mut m = Map::new();
+        m.insert(String::from("blob"), Typed::Bytes(blob.clone()));
+        m.insert(String::from("name"), Typed::String("超".into()));
+        let e = Typed::List(vec![Typed::Map(m), Typed::Int(-1)]);
+
+        let mut out = Vec::new();
+        out.write_typed(&e).unwrap();
+        out.write_typed(&Typed::Uint(1)).unwrap();
+        let input = Bytes::from(out);
+        let mut buf = input.clone();
+
+        let decoded = read_typed_from_bytes(&mut buf).unwrap();
+        assert_eq!(decoded, e);
+        assert_eq!(read_typed_from_bytes(&mut buf).unwrap(), Typed::Uint(1));
+        assert!(buf.is_empty());
+
+        let range = input.as_ptr() as usize..input.as_ptr() as usize + input.len();
+        match decoded {
+            Typed::List(l) => match &l[0] {
+                Typed::Map(m) => match m.get("blob") {
+                    Some(Typed::Bytes(bs)) => assert!(range.contains(&(bs.as_ptr() as usize))),
+                    v => panic!("unexpected: {:?}", v),
+                },
+                v => panic!("unexpected: {:?}", v),
+            },
+            v => panic!("unexpected: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let mut out = Vec::new();
+        out.write_typed(&Typed::Bytes(Bytes::from_static(b"hello")))
+            .unwrap();
+        out.pop();
+        let err = read_typed_from_bytes(&mut Bytes::from(out)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn chain() {
+        let mut m = Map::new();
+        m.insert(
+            String::from("blob"),
+            Typed::Bytes(Bytes::from(vec![7u8; 300])),
+        );
+        m.insert(String::from("n"), Typed::Uint(u64::MAX));
+        let e = Typed::List(vec![Typed::Map(m), Typed::String("超".into())]);
+        let mut out = Vec::new();
+        out.write_typed(&e).unwrap();
+
+        for i in 0..out.len() {
+            let (a, b) = out.split_at(i);
+            let mut buf = Bytes::copy_from_slice(a).chain(Bytes::copy_from_slice(b));
+            assert_eq!(read_typed_from_buf(&mut buf).unwrap(), e);
+            assert!(!buf.has_remaining());
+
+            let mut r = std::io::Read::chain(a, b);
+            assert_eq!(r.read_typed().unwrap(), e);
+        }
+
+        let mut buf = Bytes::copy_from_slice(&out[..3]).chain(Bytes::new());
+        assert!(read_typed_from_buf(&mut buf).is_err());
+    }
+
+    #[test]
+    fn buf_ext() {
+        let e = Typed::List(vec![
+            Typed::Bytes(Bytes::from(vec![7u8; 300])),
+            Typed::String("超".into()),
+        ]);
+        let mut buf = bytes::BytesMut::new();
+        buf.put_typed(&e).unwrap();
+        buf.put_typed_version(&e, FormatVersion::V2).unwrap();
+        let mut out = Vec::new();
+        out.write_typed(&e).unwrap();
+        assert_eq!(&buf[..out.len()], &out[..]);
+
+        let ptr = buf.as_ptr() as usize;
+        assert_eq!(buf.get_typed().unwrap(), e);
+        match buf.get_typed_with(&DecodeOptions::default()).unwrap() {
+            Typed::List(l) => match &l[0] {
+                Typed::Bytes(bs) => assert!(bs.as_ptr() as usize > ptr),
+                v => panic!("unexpected: {:?}", v),
+            },
+            v => panic!("unexpected: {:?}", v),
+        }
+        assert!(buf.is_empty());
+
+        let mut fixed = [0u8; 16];
+        let err = (&mut fixed[..]).put_typed(&e).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WriteZero);
+        assert_eq!(&fixed[..], &out[..16]);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::codec::{CodecWriteExt, Typed};
+use crate::error::{Error, Result};
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard};
+
+struct Inner<W> {
+    writer: W,
+    // Set once a frame was written partially, the following frames would
+    // be misread.
+    broken: bool,
+}
+
+/// SharedFrameWriter writes values as length prefixed frames, which can be
+/// read by a `Decoder` without layers or by `Frames` without checksums,
+/// from many threads at once. A frame is
+/// encoded before the writer is locked and then written with a single
+/// `write_all`, so frames never interleave.
+///
+/// If writing a frame fails, the stream may end with a partial frame, and
+/// all the later writes fail.
+pub struct SharedFrameWriter<W> {
+    inner: Mutex<Inner<W>>,
+}
+
+impl<W: Write> SharedFrameWriter<W> {
+    pub fn new(writer: W) -> Self {
+        SharedFrameWriter {
+            inner: Mutex::new(Inner {
+                writer,
+                broken: false,
+            }),
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Inner<W>>> {
+        match self.inner.lock() {
+            Ok(inner) if !inner.broken => Ok(inner),
+            _ => Err(Error::Io(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "a previous frame was written partially",
+            ))),
+        }
+    }
+
+    pub fn write_frame(&self, e: &Typed) -> Result<()> {
+        let mut body = Vec::new();
+        body.write_typed(e)?;
+        let mut frame = Vec::with_capacity(body.len() + 10);
+        frame.write_sized(&body)?;
+
+        let mut inner = self.lock()?;
+        Ok(inner.writer.write_all(&frame).inspect_err(|_| {
+            inner.broken = true;
+        })?)
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        Ok(self.lock()?.writer.flush()?)
+    }
+
+    /// Returns the writer, even if a frame was written partially.
+    pub fn into_inner(self) -> W {
+        let inner = match self.inner.into_inner() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        inner.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::Decoder;
+    use std::thread;
+
+    // Writes a byte at a time, so unsynchronized frames would interleave.
+    struct Trickle(Vec<u8>);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            match buf.first() {
+                Some(b) => {
+                    self.0.push(*b);
+                    thread::yield_now();
+                    Ok(1)
+                }
+                None => Ok(0),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn threads() {
+        let w = SharedFrameWriter::new(Trickle(Vec::new()));
+        thread::scope(|s| {
+            for t in 0..4 {
+                let w = &w;
+                s.spawn(move || {
+                    for i in 0..50 {
+                        let e = Typed::String(format!("{}:{};", t, i).repeat(t + 1).into());
+                        w.write_frame(&e).unwrap();
+                    }
+                });
+            }
+        });
+        w.flush().unwrap();
+
+        let buf = w.into_inner().0;
+        let mut dec = Decoder::new(&buf[..]);
+        let mut next = [0; 4];
+        for _ in 0..200 {
+            let s = match dec.decode().unwrap() {
+                Typed::String(s) => s,
+                e => panic!("{:?}", e),
+            };
+            let (t, i) = s.split(';').next().unwrap().split_once(':').unwrap();
+            let (t, i): (usize, usize) = (t.parse().unwrap(), i.parse().unwrap());
+            assert_eq!(s, format!("{}:{};", t, i).repeat(t + 1));
+            assert_eq!(i, next[t]);
+            next[t] += 1;
+        }
+        assert!(dec.decode().is_err());
+    }
+
+    #[test]
+    fn broken() {
+        let mut buf = [0u8; 4];
+        let w = SharedFrameWriter::new(&mut buf[..]);
+        w.write_frame(&Typed::Int(1)).unwrap();
+        assert!(w.write_frame(&Typed::Int(1)).is_err());
+        let err = w.write_frame(&Typed::Int(1)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::array::PackedArray;
+use crate::codec::{
+    base_tag, read_array, read_container_len, Type, TYPE_ARRAY, TYPE_BYTES, TYPE_BYTES_DEFLATE,
+    TYPE_DECIMAL, TYPE_FALSE, TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT, TYPE_INT_FIXED, TYPE_LIST,
+    TYPE_LIST_V2, TYPE_MAP, TYPE_MAP_V2, TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE,
+    TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT, TYPE_UINT_FIXED,
+};
+use crate::error::{Error, Result};
+use crate::limits::{Budget, Limits};
+use crate::varint::{decode_uvarint, decode_varint};
+use std::convert::TryInto;
+
+/// SliceReader walks encoded values in a slice without copying them, the
+/// slices it returns borrow the input.
+#[derive(Debug, Clone)]
+pub(crate) struct SliceReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        SliceReader { buf, pos: 0 }
+    }
+
+    pub(crate) fn offset(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        &self.buf[self.pos..]
+    }
+
+    fn advance<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut &'a [u8]) -> Result<T>,
+    {
+        let mut r = self.remaining();
+        let v = f(&mut r)?;
+        self.pos = self.buf.len() - r.len();
+        Ok(v)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8> {
+        match self.buf.get(self.pos) {
+            Some(b) => {
+                self.pos += 1;
+                Ok(*b)
+            }
+            None => Err(Error::UnexpectedEof),
+        }
+    }
+
+    pub(crate) fn read_uvarint(&mut self) -> Result<u64> {
+        let (un, n) = decode_uvarint(self.remaining())?;
+        self.pos += n;
+        Ok(un)
+    }
+
+    pub(crate) fn read_varint(&mut self) -> Result<i64> {
+        let (n, len) = decode_varint(self.remaining())?;
+        self.pos += len;
+        Ok(n)
+    }
+
+    /// Reads the number of elements of the list or map tagged `t`.
+    pub(crate) fn read_container_len(&mut self, t: Type) -> Result<u64> {
+        self.advance(|r| read_container_len(r, t))
+    }
+
+    pub(crate) fn read_exact(&mut self, sz: u64) -> Result<&'a [u8]> {
+        let rest = self.remaining();
+        if (rest.len() as u64) < sz {
+            return Err(Error::UnexpectedEof);
+        }
+        self.pos += sz as usize;
+        Ok(&rest[..sz as usize])
+    }
+
+    /// Reads the 8 bytes of a fixed-width number.
+    pub(crate) fn read_fixed(&mut self) -> Result<[u8; 8]> {
+        Ok(self.read_exact(8)?.try_into().unwrap())
+    }
+
+    pub(crate) fn read_sized(&mut self) -> Result<&'a [u8]> {
+        let sz = self.read_uvarint()?;
+        self.read_exact(sz)
+    }
+
+    /// Reads the rest of a packed array whose tag was already read.
+    pub(crate) fn read_array(&mut self) -> Result<PackedArray> {
+        self.read_array_with(&Budget::new(&Limits::unlimited()))
+    }
+
+    /// Reads a packed array like `read_array`, its data is checked against
+    /// the limits of `budget`.
+    pub(crate) fn read_array_with(&mut self, budget: &Budget) -> Result<PackedArray> {
+        self.advance(|r| read_array(r, budget))
+    }
+
+    /// Skips the next value, returns its encoded bytes.
+    pub(crate) fn skip_value(&mut self) -> Result<&'a [u8]> {
+        self.skip_value_with(&mut Budget::new(&Limits::unlimited()))
+    }
+
+    fn skip_sized(&mut self, budget: &Budget) -> Result<()> {
+        let buf = self.read_sized()?;
+        budget.bytes(buf.len() as u64)?;
+        Ok(())
+    }
+
+    /// Skips the next value like `skip_value`, it is rejected as soon as it
+    /// exceeds the limits of `budget`. The nesting is tracked with a stack
+    /// rather than recursion, so no input can overflow the call stack.
+    pub(crate) fn skip_value_with(&mut self, budget: &mut Budget) -> Result<&'a [u8]> {
+        let start = self.pos;
+        // Whether the container is a map and the number of elements left.
+        let mut stack: Vec<(bool, u64)> = Vec::new();
+        loop {
+            let t = self.read_u8()?;
+            match t {
+                TYPE_NULL | TYPE_FALSE | TYPE_TRUE => {}
+                TYPE_INT => {
+                    self.read_varint()?;
+                }
+                TYPE_UINT | TYPE_FLOAT => {
+                    self.read_uvarint()?;
+                }
+                TYPE_INT_FIXED | TYPE_UINT_FIXED | TYPE_FLOAT_FIXED => {
+                    self.read_fixed()?;
+                }
+                TYPE_TIMESTAMP | TYPE_DECIMAL => {
+                    self.read_varint()?;
+                    self.read_uvarint()?;
+                }
+                TYPE_BYTES | TYPE_STRING => self.skip_sized(budget)?,
+                TYPE_BYTES_DEFLATE | TYPE_STRING_DEFLATE => {
+                    budget.bytes(self.read_uvarint()?)?;
+                    self.skip_sized(budget)?;
+                }
+                TYPE_ARRAY => {
+                    self.read_u8()?;
+                    for _ in 0..self.read_uvarint()? {
+                        self.read_uvarint()?;
+                    }
+                    self.skip_sized(budget)?;
+                }
+                TYPE_LIST | TYPE_LIST_V2 | TYPE_MAP | TYPE_MAP_V2 => {
+                    let nelem = self.read_container_len(t)?;
+                    budget.enter(nelem)?;
+                    if nelem > 0 {
+                        let is_map = base_tag(t) == TYPE_MAP;
+                        stack.push((is_map, nelem));
+                        if is_map {
+                            self.skip_sized(budget)?;
+                        }
+                        continue;
+                    }
+                    budget.leave();
+                }
+                _ => return Err(Error::UnknownType(t)),
+            }
+
+            // A value is complete, so are the containers it was the last
+            // element of.
+            loop {
+                match stack.last_mut() {
+                    None => return Ok(&self.buf[start..self.pos]),
+                    Some((_, 1)) => {
+                        stack.pop();
+                        budget.leave();
+                    }
+                    Some((is_map, nelem)) => {
+                        *nelem -= 1;
+                        if *is_map {
+                            self.skip_sized(budget)?;
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecWriteExt, Map, Typed};
+
+    #[test]
+    fn skip() {
+        let mut m = Map::new();
+        m.insert(String::from("k"), Typed::List(vec![Typed::Int(-1)]));
+        let values = [
+            Typed::Map(m),
+            Typed::Float(1.5),
+            Typed::String("超".into()),
+            Typed::Uint(300),
+        ];
+        let mut buf = Vec::new();
+        let mut lens = Vec::new();
+        for e in values.iter() {
+            let n = buf.len();
+            buf.write_typed(e).unwrap();
+            lens.push(buf.len() - n);
+        }
+
+        let mut r = SliceReader::new(&buf);
+        for n in lens.iter() {
+            assert_eq!(r.skip_value().unwrap().len(), *n);
+        }
+        assert!(r.remaining().is_empty());
+
+        for i in 0..lens[0] {
+            let err = SliceReader::new(&buf[..i]).skip_value().unwrap_err();
+            assert!(err.is_eof());
+        }
+        assert!(SliceReader::new(b"x").skip_value().is_err());
+    }
+}
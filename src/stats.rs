@@ -0,0 +1,293 @@
+use crate::codec::{
+    base_tag, read_container_len, TYPE_ARRAY, TYPE_BYTES, TYPE_BYTES_DEFLATE, TYPE_DECIMAL,
+    TYPE_FALSE, TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT, TYPE_INT_FIXED, TYPE_LIST, TYPE_MAP,
+    TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE, TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT,
+    TYPE_UINT_FIXED,
+};
+use crate::error::{Error, Result};
+use crate::varint::VarintReadExt;
+use std::io::{self, Read};
+
+// Booleans are counted together under `TYPE_FALSE`.
+const TYPES: [(u8, &str); 12] = [
+    (TYPE_NULL, "null"),
+    (TYPE_FALSE, "bool"),
+    (TYPE_INT, "int"),
+    (TYPE_UINT, "uint"),
+    (TYPE_FLOAT, "float"),
+    (TYPE_TIMESTAMP, "timestamp"),
+    (TYPE_DECIMAL, "decimal"),
+    (TYPE_BYTES, "bytes"),
+    (TYPE_STRING, "string"),
+    (TYPE_LIST, "list"),
+    (TYPE_MAP, "map"),
+    (TYPE_ARRAY, "array"),
+];
+
+/// Largest locates the largest value of a type in the analyzed stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Largest {
+    /// The offset of the value in the stream.
+    pub offset: u64,
+    /// The encoded length of the value.
+    pub len: u64,
+}
+
+/// TypeStats are the statistics of the values of a single type, the lengths
+/// of lists and maps include their elements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeStats {
+    pub count: u64,
+    /// The encoded bytes of all the values.
+    pub bytes: u64,
+    pub largest: Option<Largest>,
+    histogram: [u64; 65],
+}
+
+impl Default for TypeStats {
+    fn default() -> Self {
+        TypeStats {
+            count: 0,
+            bytes: 0,
+            largest: None,
+            histogram: [0; 65],
+        }
+    }
+}
+
+impl TypeStats {
+    /// Returns the histogram of the encoded lengths in power of two buckets,
+    /// the bucket `i` counts the values whose length is in `2^(i-1)..2^i`.
+    pub fn histogram(&self) -> &[u64] {
+        &self.histogram
+    }
+
+    fn record(&mut self, offset: u64, len: u64) {
+        self.count += 1;
+        self.bytes += len;
+        self.histogram[(64 - len.leading_zeros()) as usize] += 1;
+        if self.largest.is_none_or(|l| len > l.len) {
+            self.largest = Some(Largest { offset, len });
+        }
+    }
+}
+
+/// WireStats summarizes a stream of encoded values by scanning the bytes,
+/// no `Typed` is built and strings and bytes are skipped rather than
+/// buffered, so arbitrarily large streams are analyzed in constant memory
+/// except for the nesting depth.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WireStats {
+    /// The number of top level values.
+    pub values: u64,
+    /// The total number of bytes.
+    pub bytes: u64,
+    /// The number of map keys.
+    pub keys: u64,
+    /// The maximum nesting depth of lists and maps, a top level list or map
+    /// is at depth 1.
+    pub max_depth: usize,
+    types: [TypeStats; 12],
+}
+
+struct Frame {
+    index: usize,
+    offset: u64,
+    remaining: u64,
+}
+
+struct Counter<R> {
+    inner: R,
+    pos: u64,
+}
+
+impl<R: Read> Read for Counter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Counter<R> {
+    // Returns None at the end of the stream.
+    fn next_u8(&mut self) -> Result<Option<u8>> {
+        let mut b = [0u8; 1];
+        loop {
+            return match self.read(&mut b) {
+                Ok(0) => Ok(None),
+                Ok(_) => Ok(Some(b[0])),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e.into()),
+            };
+        }
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        self.next_u8()?.ok_or(Error::UnexpectedEof)
+    }
+
+    fn skip(&mut self, n: u64) -> Result<()> {
+        if io::copy(&mut self.take(n), &mut io::sink())? < n {
+            return Err(Error::UnexpectedEof);
+        }
+        Ok(())
+    }
+
+    fn skip_sized(&mut self) -> Result<()> {
+        let sz = self.read_uvarint()?;
+        self.skip(sz)
+    }
+}
+
+impl WireStats {
+    /// Analyzes the values read from `r` until the end of it.
+    pub fn analyze<R: Read>(r: R) -> Result<WireStats> {
+        let mut r = Counter { inner: r, pos: 0 };
+        let mut stats = WireStats::default();
+        let mut stack: Vec<Frame> = Vec::new();
+        loop {
+            let t = match stack.last_mut() {
+                Some(top) if top.remaining == 0 => {
+                    let len = r.pos - top.offset;
+                    stats.types[top.index].record(top.offset, len);
+                    stack.pop();
+                    continue;
+                }
+                Some(top) => {
+                    top.remaining -= 1;
+                    if TYPES[top.index].0 == TYPE_MAP {
+                        r.skip_sized()?;
+                        stats.keys += 1;
+                    }
+                    r.read_u8()?
+                }
+                None => match r.next_u8()? {
+                    Some(t) => {
+                        stats.values += 1;
+                        t
+                    }
+                    None => break,
+                },
+            };
+
+            let offset = r.pos - 1;
+            let tag = match t {
+                TYPE_TRUE => TYPE_FALSE,
+                TYPE_BYTES_DEFLATE => TYPE_BYTES,
+                TYPE_STRING_DEFLATE => TYPE_STRING,
+                TYPE_INT_FIXED => TYPE_INT,
+                TYPE_UINT_FIXED => TYPE_UINT,
+                TYPE_FLOAT_FIXED => TYPE_FLOAT,
+                _ => base_tag(t),
+            };
+            let index = TYPES
+                .iter()
+                .position(|(known, _)| *known == tag)
+                .ok_or(Error::UnknownType(t))?;
+            match tag {
+                TYPE_NULL | TYPE_FALSE => {}
+                TYPE_INT | TYPE_UINT | TYPE_FLOAT if t != tag => r.skip(8)?,
+                TYPE_INT => {
+                    r.read_varint()?;
+                }
+                TYPE_UINT | TYPE_FLOAT => {
+                    r.read_uvarint()?;
+                }
+                TYPE_TIMESTAMP | TYPE_DECIMAL => {
+                    r.read_varint()?;
+                    r.read_uvarint()?;
+                }
+                TYPE_BYTES | TYPE_STRING => {
+                    if t != tag {
+                        r.read_uvarint()?;
+                    }
+                    r.skip_sized()?
+                }
+                TYPE_ARRAY => {
+                    r.read_u8()?;
+                    for _ in 0..r.read_uvarint()? {
+                        r.read_uvarint()?;
+                    }
+                    r.skip_sized()?
+                }
+                _ => {
+                    let remaining = read_container_len(&mut r, t)?;
+                    stack.push(Frame {
+                        index,
+                        offset,
+                        remaining,
+                    });
+                    stats.max_depth = stats.max_depth.max(stack.len());
+                    continue;
+                }
+            }
+            stats.types[index].record(offset, r.pos - offset);
+        }
+        stats.bytes = r.pos;
+        Ok(stats)
+    }
+
+    /// Returns the statistics of a type by its name: "null", "bool", "int",
+    /// "uint", "float", "timestamp", "decimal", "bytes", "string", "list",
+    /// "map" or "array". Fixed-width numbers are counted with the others.
+    pub fn get(&self, type_name: &str) -> Option<&TypeStats> {
+        TYPES
+            .iter()
+            .position(|(_, name)| *name == type_name)
+            .map(|i| &self.types[i])
+    }
+
+    /// Iterates over the statistics of all the types by their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &TypeStats)> {
+        TYPES.iter().map(|(_, name)| *name).zip(self.types.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecWriteExt, Map, Typed};
+
+    #[test]
+    fn analyze() {
+        let mut m = Map::new();
+        m.insert(
+            String::from("l"),
+            Typed::List(vec![Typed::Int(-1), Typed::String("abc".into())]),
+        );
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::Uint(300)).unwrap();
+        let offset = buf.len() as u64;
+        buf.write_typed(&Typed::Map(m)).unwrap();
+        buf.write_typed(&Typed::Bytes(vec![0u8; 100].into()))
+            .unwrap();
+
+        let stats = WireStats::analyze(&buf[..]).unwrap();
+        assert_eq!(stats.values, 3);
+        assert_eq!(stats.bytes, buf.len() as u64);
+        assert_eq!(stats.keys, 1);
+        assert_eq!(stats.max_depth, 2);
+        assert_eq!(stats.iter().map(|(_, s)| s.count).sum::<u64>(), 6);
+
+        let map = stats.get("map").unwrap();
+        assert_eq!(map.largest, Some(Largest { offset, len: 13 }));
+        let list = stats.get("list").unwrap();
+        assert_eq!(list.largest.map(|l| l.offset), Some(offset + 4));
+        assert_eq!(stats.get("string").unwrap().bytes, 5);
+        let bytes = stats.get("bytes").unwrap();
+        assert_eq!(bytes.bytes, 102);
+        assert_eq!(bytes.histogram()[7], 1);
+        assert!(stats.get("raw").is_none());
+
+        assert_eq!(WireStats::analyze(&[][..]).unwrap(), WireStats::default());
+        for i in 1..buf.len() - 102 {
+            if i == offset as usize {
+                continue;
+            }
+            let err = WireStats::analyze(&buf[..i]).unwrap_err();
+            assert!(err.is_eof());
+        }
+        assert!(WireStats::analyze(&b"x"[..]).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! Conversions of the `chrono` and `time` datetimes, they are stored as
+//! `Typed::Timestamp`. The `Typed::Int` nanoseconds since the Unix epoch
+//! they were stored as before are still accepted.
+//!
+//! The lenient conversions also accept `Typed::Uint` nanoseconds and RFC 3339
+//! strings.
+
+use crate::codec::Typed;
+use crate::convert::{cannot_coerce, invalid_type, out_of_range, FromTyped, ToTyped};
+use crate::error::{Error, Result};
+use crate::schema::{Describe, Schema};
+use crate::timestamp::Timestamp;
+use std::convert::TryFrom;
+
+fn typed_to_timestamp(e: &Typed) -> Result<Timestamp> {
+    match e {
+        Typed::Timestamp(ts) => Ok(*ts),
+        Typed::Int(n) => {
+            Timestamp::from_nanos(*n as i128).ok_or_else(|| out_of_range(e, "timestamp"))
+        }
+        _ => Err(invalid_type(e, "timestamp")),
+    }
+}
+
+// Returns the timestamp, or the string to be parsed.
+fn lenient_timestamp(e: &Typed) -> Result<std::result::Result<Timestamp, &str>> {
+    match e {
+        Typed::Uint(un) => Timestamp::from_nanos(*un as i128)
+            .map(Ok)
+            .ok_or_else(|| out_of_range(e, "timestamp")),
+        Typed::String(s) => Ok(Err(s.trim())),
+        _ => typed_to_timestamp(e).map(Ok),
+    }
+}
+
+fn timestamp_out_of_range(ts: Timestamp, target: &str) -> Error {
+    Error::InvalidData(format!("timestamp {} is out of range for {}", ts, target))
+}
+
+macro_rules! impl_describe {
+    ($($t:ty),*) => {
+        $(
+            impl Describe for $t {
+                fn schema() -> Schema {
+                    Schema::Timestamp
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_impls {
+    use super::*;
+    use chrono::{DateTime, NaiveDateTime, Utc};
+
+    const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+    impl_describe!(DateTime<Utc>, NaiveDateTime);
+
+    /// Leap seconds are carried into the next second.
+    impl From<DateTime<Utc>> for Timestamp {
+        fn from(dt: DateTime<Utc>) -> Self {
+            let nanos =
+                dt.timestamp() as i128 * NANOS_PER_SEC + dt.timestamp_subsec_nanos() as i128;
+            Timestamp::from_nanos(nanos).expect("chrono datetimes fit into timestamps")
+        }
+    }
+
+    impl TryFrom<Timestamp> for DateTime<Utc> {
+        type Error = Error;
+
+        fn try_from(ts: Timestamp) -> Result<Self> {
+            DateTime::from_timestamp(ts.secs(), ts.subsec_nanos())
+                .ok_or_else(|| timestamp_out_of_range(ts, "DateTime"))
+        }
+    }
+
+    fn from_timestamp(e: &Typed, ts: Timestamp) -> Result<DateTime<Utc>> {
+        DateTime::try_from(ts).map_err(|_| out_of_range(e, "DateTime"))
+    }
+
+    impl ToTyped for DateTime<Utc> {
+        fn to_typed(&self) -> Typed {
+            Typed::Timestamp(Timestamp::from(*self))
+        }
+    }
+
+    impl FromTyped for DateTime<Utc> {
+        fn from_typed(e: &Typed) -> Result<Self> {
+            from_timestamp(e, typed_to_timestamp(e)?)
+        }
+
+        fn from_typed_lenient(e: &Typed) -> Result<Self> {
+            match lenient_timestamp(e)? {
+                Ok(ts) => from_timestamp(e, ts),
+                Err(s) => DateTime::parse_from_rfc3339(s)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|_| cannot_coerce(e, "DateTime")),
+            }
+        }
+    }
+
+    /// `NaiveDateTime`s are taken as UTC.
+    impl ToTyped for NaiveDateTime {
+        fn to_typed(&self) -> Typed {
+            self.and_utc().to_typed()
+        }
+    }
+
+    impl FromTyped for NaiveDateTime {
+        fn from_typed(e: &Typed) -> Result<Self> {
+            DateTime::<Utc>::from_typed(e).map(|dt| dt.naive_utc())
+        }
+
+        fn from_typed_lenient(e: &Typed) -> Result<Self> {
+            DateTime::<Utc>::from_typed_lenient(e).map(|dt| dt.naive_utc())
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_impls {
+    use super::*;
+    use time::format_description::well_known::Rfc3339;
+    use time::{OffsetDateTime, PrimitiveDateTime};
+
+    impl_describe!(OffsetDateTime, PrimitiveDateTime);
+
+    impl From<OffsetDateTime> for Timestamp {
+        fn from(dt: OffsetDateTime) -> Self {
+            Timestamp::from_nanos(dt.unix_timestamp_nanos())
+                .expect("time datetimes fit into timestamps")
+        }
+    }
+
+    impl TryFrom<Timestamp> for OffsetDateTime {
+        type Error = Error;
+
+        fn try_from(ts: Timestamp) -> Result<Self> {
+            OffsetDateTime::from_unix_timestamp_nanos(ts.as_nanos())
+                .map_err(|_| timestamp_out_of_range(ts, "OffsetDateTime"))
+        }
+    }
+
+    fn from_timestamp(e: &Typed, ts: Timestamp) -> Result<OffsetDateTime> {
+        OffsetDateTime::try_from(ts).map_err(|_| out_of_range(e, "OffsetDateTime"))
+    }
+
+    impl ToTyped for OffsetDateTime {
+        fn to_typed(&self) -> Typed {
+            Typed::Timestamp(Timestamp::from(*self))
+        }
+    }
+
+    impl FromTyped for OffsetDateTime {
+        fn from_typed(e: &Typed) -> Result<Self> {
+            from_timestamp(e, typed_to_timestamp(e)?)
+        }
+
+        fn from_typed_lenient(e: &Typed) -> Result<Self> {
+            match lenient_timestamp(e)? {
+                Ok(ts) => from_timestamp(e, ts),
+                Err(s) => OffsetDateTime::parse(s, &Rfc3339)
+                    .map_err(|_| cannot_coerce(e, "OffsetDateTime")),
+            }
+        }
+    }
+
+    /// `PrimitiveDateTime`s are taken as UTC.
+    impl ToTyped for PrimitiveDateTime {
+        fn to_typed(&self) -> Typed {
+            self.assume_utc().to_typed()
+        }
+    }
+
+    impl FromTyped for PrimitiveDateTime {
+        fn from_typed(e: &Typed) -> Result<Self> {
+            OffsetDateTime::from_typed(e).map(utc)
+        }
+
+        fn from_typed_lenient(e: &Typed) -> Result<Self> {
+            OffsetDateTime::from_typed_lenient(e).map(utc)
+        }
+    }
+
+    fn utc(dt: OffsetDateTime) -> PrimitiveDateTime {
+        let dt = dt.to_offset(time::UtcOffset::UTC);
+        PrimitiveDateTime::new(dt.date(), dt.time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2021-03-04T05:06:07.000000089Z
+    const NANOS: i64 = 1_614_834_367_000_000_089;
+
+    fn timestamp() -> Typed {
+        Typed::Timestamp(Timestamp::new(1_614_834_367, 89).unwrap())
+    }
+
+    #[test]
+    fn legacy() {
+        assert_eq!(
+            typed_to_timestamp(&Typed::Int(NANOS)).unwrap(),
+            typed_to_timestamp(&timestamp()).unwrap()
+        );
+        assert!(typed_to_timestamp(&Typed::Uint(1)).is_err());
+        assert!(lenient_timestamp(&Typed::Uint(1)).unwrap().is_ok());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono() {
+        use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+        let dt =
+            Utc.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap() + chrono::Duration::nanoseconds(89);
+        assert_eq!(dt.to_typed(), timestamp());
+        assert_eq!(DateTime::<Utc>::from_typed(&timestamp()).unwrap(), dt);
+        assert_eq!(DateTime::<Utc>::from_typed(&Typed::Int(NANOS)).unwrap(), dt);
+        assert_eq!(
+            NaiveDateTime::from_typed(&dt.naive_utc().to_typed()).unwrap(),
+            dt.naive_utc()
+        );
+        let before = DateTime::<Utc>::from_typed(&Typed::Int(-1)).unwrap();
+        assert_eq!(Timestamp::from(before).as_nanos(), -1);
+        let far = Timestamp::new(i64::MAX, 0).unwrap();
+        assert!(DateTime::<Utc>::try_from(far).is_err());
+
+        assert!(DateTime::<Utc>::from_typed(&Typed::Uint(NANOS as u64)).is_err());
+        let s = Typed::String("2021-03-04T06:06:07.000000089+01:00".into());
+        assert_eq!(DateTime::<Utc>::from_typed_lenient(&s).unwrap(), dt);
+        assert!(DateTime::<Utc>::from_typed_lenient(&Typed::String("x".into())).is_err());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time() {
+        use time::macros::datetime;
+        use time::{OffsetDateTime, PrimitiveDateTime};
+
+        let dt = datetime!(2021-03-04 05:06:07.000000089 UTC);
+        assert_eq!(dt.to_typed(), timestamp());
+        assert_eq!(OffsetDateTime::from_typed(&timestamp()).unwrap(), dt);
+        assert_eq!(OffsetDateTime::from_typed(&Typed::Int(NANOS)).unwrap(), dt);
+        let primitive = datetime!(2021-03-04 05:06:07.000000089);
+        assert_eq!(primitive.to_typed(), timestamp());
+        assert_eq!(
+            PrimitiveDateTime::from_typed(&timestamp()).unwrap(),
+            primitive
+        );
+        let far = Timestamp::new(i64::MAX, 0).unwrap();
+        assert!(OffsetDateTime::try_from(far).is_err());
+
+        let s = Typed::String("2021-03-04T06:06:07.000000089+01:00".into());
+        assert_eq!(OffsetDateTime::from_typed_lenient(&s).unwrap(), dt);
+        assert_eq!(
+            OffsetDateTime::from_typed_lenient(&Typed::Uint(NANOS as u64)).unwrap(),
+            dt
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Helpers for pinning the encoded form of values in snapshot files, so
+//! accidental changes of the wire output are caught by tests.
+//!
+//! Map entries are written in key order, so snapshots are stable even though
+//! `Map` is unordered. Set `XDCODEC_UPDATE_SNAPSHOTS=1` to make
+//! `assert_snapshot` rewrite the files instead of comparing them.
+
+use crate::codec::{write_canonical, CodecReadExt, Typed};
+use crate::config::FormatVersion;
+use crate::error::{Error, Result};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const UPDATE_ENV: &str = "XDCODEC_UPDATE_SNAPSHOTS";
+
+/// Writes the encoded `e` into the snapshot file at `path`, creating the
+/// missing parent directories.
+pub fn write_snapshot<P: AsRef<Path>>(path: P, e: &Typed) -> Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Ok(fs::write(path, encode(e)?)?)
+}
+
+/// Compares the encoded `e` with the snapshot file at `path`, the error of a
+/// mismatch is `Error::InvalidData` with a diff as its message.
+pub fn compare_snapshot<P: AsRef<Path>>(path: P, e: &Typed) -> Result<()> {
+    let expected = fs::read(path.as_ref())?;
+    let actual = encode(e)?;
+    if expected == actual {
+        return Ok(());
+    }
+    Err(Error::InvalidData(format!(
+        "snapshot mismatch: {}\n{}",
+        path.as_ref().display(),
+        diff_encoded(&expected, &actual)
+    )))
+}
+
+/// Panics with a diff if the encoded `e` differs from the snapshot file at
+/// `path`, the file is written if it does not exist yet or
+/// `XDCODEC_UPDATE_SNAPSHOTS` is set.
+pub fn assert_snapshot<P: AsRef<Path>>(path: P, e: &Typed) {
+    let path = path.as_ref();
+    let update = env::var_os(UPDATE_ENV).is_some_and(|v| !v.is_empty() && v != "0");
+    if update || !path.exists() {
+        if let Err(err) = write_snapshot(path, e) {
+            panic!("failed to write snapshot {}: {}", path.display(), err);
+        }
+        return;
+    }
+    if let Err(err) = compare_snapshot(path, e) {
+        panic!("{}", err);
+    }
+}
+
+fn encode(e: &Typed) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    write_canonical(&mut buf, e, FormatVersion::V1)?;
+    Ok(buf)
+}
+
+// Diffs the decoded values, falls back to a diff of hex dumps if they can't
+// be decoded or only differ in their encoding.
+fn diff_encoded(expected: &[u8], actual: &[u8]) -> String {
+    if let (Ok(x), Ok(y)) = ((&expected[..]).read_typed(), (&actual[..]).read_typed()) {
+        let (x, y) = (render(&x), render(&y));
+        if x != y {
+            return diff_lines(&x, &y);
+        }
+    }
+    diff_lines(&hex_dump(expected), &hex_dump(actual))
+}
+
+/// Renders `e` as the indented tree the snapshot diffs show, map entries in
+/// key order.
+pub fn render(e: &Typed) -> String {
+    let mut out = String::new();
+    render_into(&mut out, e, 0);
+    out
+}
+
+fn render_into(out: &mut String, e: &Typed, indent: usize) {
+    let pad = "  ".repeat(indent);
+    match e {
+        Typed::Null => write!(out, "null"),
+        Typed::Bool(b) => write!(out, "bool {}", b),
+        Typed::Int(n) => write!(out, "int {}", n),
+        Typed::Uint(un) => write!(out, "uint {}", un),
+        Typed::Float(f) => write!(out, "float {:?}", f),
+        Typed::Timestamp(ts) => write!(out, "timestamp {}", ts),
+        Typed::Decimal(d) => write!(out, "decimal {}", d),
+        Typed::Bytes(buf) => write!(out, "bytes {:02x?}", &buf[..]),
+        Typed::String(s) => write!(out, "string {:?}", &s[..]),
+        Typed::Array(a) => write!(
+            out,
+            "array {} {:?} {:02x?}",
+            a.dtype().name(),
+            a.shape(),
+            a.as_bytes()
+        ),
+        Typed::Raw(raw) => write!(out, "raw {:02x?}", raw.as_bytes()),
+        Typed::List(l) => {
+            out.push_str("list [\n");
+            for e in l.iter() {
+                out.push_str(&pad);
+                out.push_str("  ");
+                render_into(out, e, indent + 1);
+                out.push('\n');
+            }
+            write!(out, "{}]", pad)
+        }
+        Typed::Map(m) => {
+            out.push_str("map {\n");
+            let mut entries: Vec<_> = m.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (k, v) in entries {
+                let _ = write!(out, "{}  {:?}: ", pad, k);
+                render_into(out, v, indent + 1);
+                out.push('\n');
+            }
+            write!(out, "{}}}", pad)
+        }
+    }
+    .unwrap();
+}
+
+fn hex_dump(buf: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in buf.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x}:", i * 16);
+        for b in chunk {
+            let _ = write!(out, " {:02x}", b);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// A line diff based on the longest common subsequence, the lines only in
+// `expected` are prefixed with '-' and the lines only in `actual` with '+'.
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let x: Vec<&str> = expected.lines().collect();
+    let y: Vec<&str> = actual.lines().collect();
+    let mut lcs = vec![vec![0usize; y.len() + 1]; x.len() + 1];
+    for i in (0..x.len()).rev() {
+        for j in (0..y.len()).rev() {
+            lcs[i][j] = if x[i] == y[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < x.len() || j < y.len() {
+        if i < x.len() && j < y.len() && x[i] == y[j] {
+            let _ = writeln!(out, "  {}", x[i]);
+            i += 1;
+            j += 1;
+        } else if i < x.len() && (j == y.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            let _ = writeln!(out, "- {}", x[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(out, "+ {}", y[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+
+    fn value(n: u64) -> Typed {
+        let mut m = Map::new();
+        for k in ["z", "a", "m", "b"].iter() {
+            m.insert(k.to_string(), Typed::Uint(n));
+        }
+        m.insert(
+            String::from("list"),
+            Typed::List(vec![Typed::String("x".into()), Typed::Float(1.5)]),
+        );
+        Typed::Map(m)
+    }
+
+    #[test]
+    fn snapshot() {
+        let dir = env::temp_dir().join(format!("xdcodec-snapshot-{}", std::process::id()));
+        let path = dir.join("nested").join("value.bin");
+
+        assert!(compare_snapshot(&path, &value(1)).is_err());
+        assert_snapshot(&path, &value(1));
+        assert!(path.exists());
+        for _ in 0..8 {
+            assert_snapshot(&path, &value(1));
+        }
+
+        let err = compare_snapshot(&path, &value(2)).unwrap_err();
+        assert!(matches!(err, Error::InvalidData(_)), "{:?}", err);
+        let msg = err.to_string();
+        assert!(msg.contains("-   \"a\": uint 1"), "{}", msg);
+        assert!(msg.contains("+   \"a\": uint 2"), "{}", msg);
+        assert!(msg.contains("    \"list\": list ["), "{}", msg);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hex_diff() {
+        let diff = diff_encoded(&[1, 2, 3], &[1, 2, 4]);
+        assert_eq!(diff, "- 00000000: 01 02 03\n+ 00000000: 01 02 04\n");
+    }
+}
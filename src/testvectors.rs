@@ -0,0 +1,391 @@
+//! Conformance test vectors, for checking that other implementations of
+//! the format stay compatible with this one.
+//!
+//! Every vector is a pair of files named after it: `<name>.xd` holds the
+//! canonical encoding of the value and `<name>.txt` the value rendered like
+//! `testing::render` does. An implementation decodes every `.xd` file into
+//! the value of the `.txt` file and encodes it back into the same bytes.
+//! The vectors whose names start with `v2_` use `FormatVersion::V2`, those
+//! starting with `fixed_` use `Config::fixed_width`. Those starting with
+//! `deflate_` hold compressed values, they are only decoded since another
+//! deflate implementation compresses into other bytes.
+//!
+//! The rejected inputs, whose names start with `reject_`, fail to decode
+//! with the default limits, their `.txt` file tells why, e.g.
+//! `error unexpected eof`. See `testvectors/README.md` for the rendering.
+
+use crate::array::PackedArray;
+use crate::codec::{CodecWriteExt, Map, Typed, TYPE_BYTES_DEFLATE, TYPE_STRING_DEFLATE};
+use crate::config::{Codec, Config, FormatVersion};
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::testing::render;
+use crate::timestamp::Timestamp;
+use crate::varint::{encode_uvarint, MAX_VARINT_LEN};
+use std::fs;
+use std::path::Path;
+
+/// Vector is a value and its encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vector {
+    pub name: String,
+    pub value: Typed,
+    pub encoded: Vec<u8>,
+}
+
+/// Rejected is an input which fails to decode, `error` tells why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub name: String,
+    pub encoded: Vec<u8>,
+    pub error: &'static str,
+}
+
+fn invalid(msg: String) -> Error {
+    Error::InvalidData(msg.to_string())
+}
+
+fn nested(depth: usize) -> Typed {
+    (0..depth).fold(Typed::Null, |e, i| {
+        if i % 2 == 0 {
+            Typed::List(vec![e])
+        } else {
+            let mut m = Map::new();
+            m.insert("k".into(), e);
+            Typed::Map(m)
+        }
+    })
+}
+
+fn values() -> Vec<(&'static str, Typed)> {
+    let ts = |secs, nanos| Typed::Timestamp(Timestamp::new(secs, nanos).unwrap());
+    let array = |a: Result<PackedArray>| Typed::Array(a.unwrap());
+    vec![
+        ("null", Typed::Null),
+        ("false", Typed::Bool(false)),
+        ("true", Typed::Bool(true)),
+        ("int_zero", Typed::Int(0)),
+        ("int_one", Typed::Int(1)),
+        ("int_minus_one", Typed::Int(-1)),
+        ("int_63", Typed::Int(63)),
+        ("int_minus_64", Typed::Int(-64)),
+        ("int_64", Typed::Int(64)),
+        ("int_minus_65", Typed::Int(-65)),
+        ("int_2_pow_31", Typed::Int(1 << 31)),
+        ("int_max", Typed::Int(i64::MAX)),
+        ("int_min", Typed::Int(i64::MIN)),
+        ("uint_zero", Typed::Uint(0)),
+        ("uint_127", Typed::Uint(127)),
+        ("uint_2_pow_7", Typed::Uint(1 << 7)),
+        ("uint_2_pow_14_minus_1", Typed::Uint((1 << 14) - 1)),
+        ("uint_2_pow_14", Typed::Uint(1 << 14)),
+        ("uint_2_pow_56", Typed::Uint(1 << 56)),
+        ("uint_2_pow_63", Typed::Uint(1 << 63)),
+        ("uint_max", Typed::Uint(u64::MAX)),
+        ("float_zero", Typed::Float(0.0)),
+        ("float_minus_zero", Typed::Float(-0.0)),
+        ("float_half", Typed::Float(0.5)),
+        ("float_max", Typed::Float(f64::MAX)),
+        ("float_min_positive", Typed::Float(f64::MIN_POSITIVE)),
+        ("float_subnormal", Typed::Float(f64::from_bits(1))),
+        ("float_infinity", Typed::Float(f64::INFINITY)),
+        ("float_minus_infinity", Typed::Float(f64::NEG_INFINITY)),
+        ("timestamp_epoch", ts(0, 0)),
+        ("timestamp_nanos", ts(1_700_000_000, 123_456_789)),
+        ("timestamp_negative", ts(-1, 999_999_999)),
+        ("decimal_zero", Typed::Decimal(Decimal::new(0, 0))),
+        ("decimal_negative", Typed::Decimal(Decimal::new(-12345, 2))),
+        ("decimal_min", Typed::Decimal(Decimal::new(i64::MIN, 18))),
+        ("string_empty", Typed::String("".into())),
+        ("string_ascii", Typed::String("xdcodec".into())),
+        ("string_utf8", Typed::String("超 é \u{1f600}".into())),
+        ("string_128", Typed::String("s".repeat(128).into())),
+        ("bytes_empty", Typed::Bytes(Vec::new().into())),
+        (
+            "bytes_all",
+            Typed::Bytes((0..=255u8).collect::<Vec<_>>().into()),
+        ),
+        ("list_empty", Typed::List(vec![])),
+        ("list_mixed", crate::typed!([null, true, -1, "x", [], {}])),
+        ("list_254", Typed::List(vec![Typed::Uint(0); 254])),
+        ("map_empty", Typed::Map(Map::new())),
+        (
+            "map_sorted",
+            crate::typed!({"b": 1, "a": 2, "": 3, "B": 4, "aa": 5, "é": 6}),
+        ),
+        (
+            "map_nested",
+            crate::typed!({"users": [{"id": 1, "tags": ["a"]}, {"id": 2, "tags": []}]}),
+        ),
+        ("nested_64", nested(64)),
+        (
+            "array_f32",
+            array(PackedArray::new(vec![2, 2], &[1.5f32, -2.0, 0.0, 4.0])),
+        ),
+        (
+            "array_u8_empty",
+            array(PackedArray::new::<u8>(vec![0], &[])),
+        ),
+        (
+            "array_i64",
+            array(PackedArray::new(vec![3], &[i64::MIN, 0, i64::MAX])),
+        ),
+        ("v2_list_300", Typed::List(vec![Typed::Bool(true); 300])),
+        ("v2_map", crate::typed!({"list": [], "map": {"k": [1, 2]}})),
+        ("fixed_int_min", Typed::Int(i64::MIN)),
+        ("fixed_uint_max", Typed::Uint(u64::MAX)),
+        ("fixed_float", Typed::Float(0.1)),
+        ("fixed_small", crate::typed!([1, 1u64, 0.0])),
+    ]
+}
+
+// A raw deflate stream of "xdcodec " repeated 64 times, compressed with
+// fixed Huffman codes.
+const DEFLATE_STRING: [u8; 15] = [
+    0xab, 0x48, 0x49, 0xce, 0x4f, 0x49, 0x4d, 0x56, 0xa8, 0x18, 0xa5, 0x47, 0x24, 0x0d, 0x00,
+];
+
+// Returns the encoding of a compressed value tagged `t`.
+fn deflated(t: u8, len: u64, stream: &[u8]) -> Vec<u8> {
+    [
+        &[t][..],
+        &uvarint(len),
+        &uvarint(stream.len() as u64),
+        stream,
+    ]
+    .concat()
+}
+
+// Returns a raw deflate stream which stores `data` uncompressed.
+fn stored(data: &[u8]) -> Vec<u8> {
+    let len = data.len() as u16;
+    let mut buf = vec![0x01];
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(&(!len).to_le_bytes());
+    buf.extend_from_slice(data);
+    buf
+}
+
+fn uvarint(un: u64) -> Vec<u8> {
+    let mut buf = [0u8; MAX_VARINT_LEN];
+    let n = encode_uvarint(un, &mut buf);
+    buf[..n].to_vec()
+}
+
+fn compressed() -> Vec<(&'static str, Typed, Vec<u8>)> {
+    let data: Vec<u8> = (0..16).collect();
+    vec![
+        (
+            "deflate_bytes_stored",
+            Typed::Bytes(data.clone().into()),
+            deflated(TYPE_BYTES_DEFLATE, 16, &stored(&data)),
+        ),
+        (
+            "deflate_string",
+            Typed::String("xdcodec ".repeat(64).into()),
+            deflated(TYPE_STRING_DEFLATE, 512, &DEFLATE_STRING),
+        ),
+    ]
+}
+
+/// Returns the vectors.
+pub fn vectors() -> Vec<Vector> {
+    let compressed = compressed()
+        .into_iter()
+        .map(|(name, value, encoded)| Vector {
+            name: name.to_string(),
+            value,
+            encoded,
+        });
+    values()
+        .into_iter()
+        .map(|(name, value)| {
+            let mut config = Config::new().canonical(true);
+            if name.starts_with("v2_") {
+                config = config.format_version(FormatVersion::V2);
+            }
+            if name.starts_with("fixed_") {
+                config = config.fixed_width(true);
+            }
+            let encoded = Codec::new(config)
+                .encode(&value)
+                .expect("the vectors are encodable");
+            Vector {
+                name: name.to_string(),
+                value,
+                encoded,
+            }
+        })
+        .chain(compressed)
+        .collect()
+}
+
+/// Returns the rejected inputs.
+pub fn rejected() -> Vec<Rejected> {
+    let eof = "unexpected eof";
+    let overflow = "varint overflow";
+    let mut nested_65 = Vec::new();
+    nested_65.write_typed(&nested(65)).unwrap();
+    let inputs: Vec<(&str, Vec<u8>, &str)> = vec![
+        ("reject_empty", vec![], eof),
+        ("reject_unknown_type", b"x".to_vec(), "unknown type"),
+        ("reject_truncated_int", b"i\x80".to_vec(), eof),
+        ("reject_truncated_fixed", b"I\x01\x02\x03\x04".to_vec(), eof),
+        ("reject_truncated_string", b"s\x05ab".to_vec(), eof),
+        ("reject_truncated_list", b"l\x02n".to_vec(), eof),
+        ("reject_truncated_key", b"m\x01\x03ab".to_vec(), eof),
+        (
+            "reject_varint_overflow",
+            [&b"u"[..], &[0xff; 9], b"\x02"].concat(),
+            overflow,
+        ),
+        (
+            "reject_varint_11_bytes",
+            [&b"u"[..], &[0x80; 10], b"\x00"].concat(),
+            overflow,
+        ),
+        ("reject_trailing_bytes", b"nn".to_vec(), "trailing bytes"),
+        ("reject_nested_65", nested_65, "depth exceeded"),
+        (
+            "reject_deflate_length",
+            deflated(TYPE_STRING_DEFLATE, 5, &stored(b"abc")),
+            "invalid data",
+        ),
+        (
+            "reject_deflate_stream",
+            deflated(TYPE_BYTES_DEFLATE, 3, b"\xff\xff\xff"),
+            "invalid data",
+        ),
+    ];
+    inputs
+        .into_iter()
+        .map(|(name, encoded, error)| Rejected {
+            name: name.to_string(),
+            encoded,
+            error,
+        })
+        .collect()
+}
+
+/// Writes the files of the vectors into `dir`, creating it if it is
+/// missing.
+pub fn write_vectors<P: AsRef<Path>>(dir: P) -> Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    for v in vectors() {
+        fs::write(dir.join(format!("{}.xd", v.name)), &v.encoded)?;
+        fs::write(dir.join(format!("{}.txt", v.name)), render(&v.value) + "\n")?;
+    }
+    for r in rejected() {
+        fs::write(dir.join(format!("{}.xd", r.name)), &r.encoded)?;
+        fs::write(
+            dir.join(format!("{}.txt", r.name)),
+            format!("error {}\n", r.error),
+        )?;
+    }
+    Ok(())
+}
+
+/// Checks the files in `dir` against the vectors: every vector has its
+/// files, which decode into the value and encode back into the same bytes,
+/// and every rejected input has its files, which fail to decode. The error
+/// of the first mismatch is `Error::InvalidData`.
+pub fn check_vectors<P: AsRef<Path>>(dir: P) -> Result<()> {
+    let dir = dir.as_ref();
+    let vectors = vectors();
+    for v in vectors.iter() {
+        let encoded = fs::read(dir.join(format!("{}.xd", v.name)))?;
+        if encoded != v.encoded {
+            return Err(invalid(format!("{}: the encoding differs", v.name)));
+        }
+        let rendered = fs::read_to_string(dir.join(format!("{}.txt", v.name)))?;
+        if rendered.trim_end() != render(&v.value) {
+            return Err(invalid(format!("{}: the rendered value differs", v.name)));
+        }
+        let decoded = Codec::default()
+            .decode(&encoded)
+            .map_err(|err| invalid(format!("{}: {}", v.name, err)))?;
+        if decoded != v.value {
+            return Err(invalid(format!("{}: decoded into another value", v.name)));
+        }
+    }
+    let rejected = rejected();
+    for r in rejected.iter() {
+        let encoded = fs::read(dir.join(format!("{}.xd", r.name)))?;
+        if encoded != r.encoded {
+            return Err(invalid(format!("{}: the encoding differs", r.name)));
+        }
+        let error = fs::read_to_string(dir.join(format!("{}.txt", r.name)))?;
+        if error.trim_end() != format!("error {}", r.error) {
+            return Err(invalid(format!("{}: the error differs", r.name)));
+        }
+        if Codec::default().decode(&encoded).is_ok() {
+            return Err(invalid(format!("{}: decoded", r.name)));
+        }
+    }
+
+    // Files of vectors which no longer exist are stale.
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        let is_vector = matches!(
+            path.extension().and_then(|s| s.to_str()),
+            Some("xd") | Some("txt")
+        );
+        let known =
+            vectors.iter().any(|v| v.name == stem) || rejected.iter().any(|r| r.name == stem);
+        if is_vector && !known {
+            return Err(invalid(format!("{}: not a vector", path.display())));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn golden() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testvectors");
+        if env::var_os("XDCODEC_UPDATE_SNAPSHOTS").is_some_and(|v| !v.is_empty() && v != "0") {
+            write_vectors(&dir).unwrap();
+        }
+        if let Err(err) = check_vectors(&dir) {
+            panic!("{}, set XDCODEC_UPDATE_SNAPSHOTS=1 to regenerate", err);
+        }
+    }
+
+    #[test]
+    fn rejected_errors() {
+        for r in rejected() {
+            let error = match Codec::default().decode(&r.encoded).unwrap_err() {
+                Error::UnexpectedEof => "unexpected eof",
+                Error::UnknownType(_) => "unknown type",
+                Error::VarintOverflow => "varint overflow",
+                Error::TrailingBytes => "trailing bytes",
+                Error::DepthExceeded(_) => "depth exceeded",
+                Error::InvalidData(_) => "invalid data",
+                err => panic!("{}: {:?}", r.name, err),
+            };
+            assert_eq!(error, r.error, "{}", r.name);
+        }
+    }
+
+    #[test]
+    fn mismatch() {
+        let dir = env::temp_dir().join(format!("xdcodec-vectors-{}", std::process::id()));
+        write_vectors(&dir).unwrap();
+        check_vectors(&dir).unwrap();
+
+        fs::write(dir.join("int_one.xd"), b"i\x04").unwrap();
+        let err = check_vectors(&dir).unwrap_err();
+        assert_eq!(err.to_string(), "int_one: the encoding differs");
+        write_vectors(&dir).unwrap();
+        fs::write(dir.join("gone.xd"), b"n").unwrap();
+        assert!(check_vectors(&dir).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use std::borrow::Cow;
+
+/// TextEncoding is the encoding of the strings being decoded, for producers
+/// which predate UTF-8, see `DecodeOptions::text_encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextEncoding {
+    /// Invalid sequences are replaced with U+FFFD.
+    #[default]
+    Utf8,
+    /// A leading byte order mark is dropped, unpaired surrogates and a
+    /// trailing odd byte are replaced with U+FFFD.
+    Utf16Le,
+    /// ISO 8859-1, every byte is the code point of the same value.
+    Latin1,
+}
+
+impl TextEncoding {
+    pub(crate) fn decode(self, buf: &[u8]) -> Cow<'_, str> {
+        match self {
+            TextEncoding::Utf8 => String::from_utf8_lossy(buf),
+            TextEncoding::Utf16Le => {
+                let units = buf
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]));
+                let mut s: String = char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect();
+                if s.starts_with('\u{feff}') {
+                    s.remove(0);
+                }
+                if buf.len() % 2 == 1 {
+                    s.push(char::REPLACEMENT_CHARACTER);
+                }
+                Cow::Owned(s)
+            }
+            TextEncoding::Latin1 => {
+                if buf.is_ascii() {
+                    // ASCII is valid UTF-8.
+                    return String::from_utf8_lossy(buf);
+                }
+                Cow::Owned(buf.iter().map(|b| *b as char).collect())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecReadExt, CodecWriteExt, Map, Typed};
+    use crate::options::DecodeOptions;
+
+    #[test]
+    fn decode() {
+        let utf16: Vec<u8> = [0xfeff, 0x68, 0xe9, 0xd83d, 0xde00]
+            .iter()
+            .flat_map(|u: &u16| u.to_le_bytes())
+            .collect();
+        assert_eq!(TextEncoding::Utf16Le.decode(&utf16), "hé😀");
+        assert_eq!(TextEncoding::Utf16Le.decode(&utf16[2..5]), "h\u{fffd}");
+        assert_eq!(TextEncoding::Utf16Le.decode(&[0x00, 0xd8]), "\u{fffd}");
+        assert_eq!(TextEncoding::Latin1.decode(b"caf\xe9"), "café");
+        assert_eq!(TextEncoding::Latin1.decode(b"abc"), "abc");
+        assert_eq!(TextEncoding::Utf8.decode(b"caf\xe9"), "caf\u{fffd}");
+    }
+
+    #[test]
+    fn fields() {
+        // A map whose strings are not UTF-8.
+        let mut buf = vec![b'm', 3];
+        let entries: [(&str, &[u8]); 3] = [
+            ("name", b"s\x01\xe9"),
+            ("title", b"s\x01\xe9"),
+            ("tags", b"l\x01s\x02\xe9\x00"),
+        ];
+        for (k, v) in entries.iter() {
+            buf.write_sized(k.as_bytes()).unwrap();
+            buf.extend_from_slice(v);
+        }
+
+        let opts = DecodeOptions::new()
+            .text_encoding(TextEncoding::Latin1)
+            .field_encoding("tags", TextEncoding::Utf16Le)
+            .field_encoding("title", TextEncoding::Utf8);
+        let mut m = Map::new();
+        m.insert(String::from("name"), Typed::String("é".into()));
+        m.insert(String::from("title"), Typed::String("\u{fffd}".into()));
+        m.insert(
+            String::from("tags"),
+            Typed::List(vec![Typed::String("é".into())]),
+        );
+        assert_eq!((&buf[..]).read_typed_with(&opts).unwrap(), Typed::Map(m));
+    }
+}
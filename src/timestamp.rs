@@ -0,0 +1,298 @@
+use crate::codec::Typed;
+use crate::convert::{cannot_coerce, invalid_type, FromTyped, ToTyped};
+use crate::error::Error;
+use crate::schema::{Describe, Schema};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Timestamp is an instant as the seconds and nanoseconds since the Unix
+/// epoch, the nanoseconds always count forward, so instants before the
+/// epoch have negative seconds.
+///
+/// It is displayed and parsed as a RFC 3339 UTC datetime, e.g.
+/// "2021-03-04T05:06:07.089Z".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    secs: i64,
+    nanos: u32,
+}
+
+impl Timestamp {
+    /// Returns None if `nanos` is not less than a second.
+    pub fn new(secs: i64, nanos: u32) -> Option<Self> {
+        if nanos >= NANOS_PER_SEC {
+            return None;
+        }
+        Some(Timestamp { secs, nanos })
+    }
+
+    pub fn from_millis(millis: i64) -> Self {
+        Timestamp {
+            secs: millis.div_euclid(1000),
+            nanos: millis.rem_euclid(1000) as u32 * 1_000_000,
+        }
+    }
+
+    /// Returns None if the seconds overflow an i64.
+    pub fn from_nanos(nanos: i128) -> Option<Self> {
+        let secs = i64::try_from(nanos.div_euclid(NANOS_PER_SEC as i128)).ok()?;
+        Some(Timestamp {
+            secs,
+            nanos: nanos.rem_euclid(NANOS_PER_SEC as i128) as u32,
+        })
+    }
+
+    pub fn secs(&self) -> i64 {
+        self.secs
+    }
+
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    /// Returns the milliseconds since the epoch, rounded towards the past.
+    pub fn as_millis(&self) -> i128 {
+        self.secs as i128 * 1000 + (self.nanos / 1_000_000) as i128
+    }
+
+    pub fn as_nanos(&self) -> i128 {
+        self.secs as i128 * NANOS_PER_SEC as i128 + self.nanos as i128
+    }
+
+    // The nanoseconds come from the wire, they are checked rather than
+    // carried into the seconds so every timestamp has a single encoding.
+    pub(crate) fn from_wire(secs: i64, nanos: u64) -> crate::Result<Self> {
+        u32::try_from(nanos)
+            .ok()
+            .and_then(|nanos| Timestamp::new(secs, nanos))
+            .ok_or_else(|| Error::InvalidData(format!("invalid timestamp nanoseconds: {}", nanos)))
+    }
+}
+
+// Converts the days since the epoch into a date, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+// The inverse of `civil_from_days`.
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if m > 2 { m - 3 } else { m + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn days_in_month(y: i64, m: u32) -> u32 {
+    match m {
+        2 if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The fraction of a second is left out when it is zero, otherwise it is
+/// written in milli, micro or nanoseconds.
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (y, m, d) = civil_from_days(self.secs.div_euclid(SECS_PER_DAY));
+        let secs = self.secs.rem_euclid(SECS_PER_DAY);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            y,
+            m,
+            d,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )?;
+        match self.nanos {
+            0 => {}
+            n if n % 1_000_000 == 0 => write!(f, ".{:03}", n / 1_000_000)?,
+            n if n % 1000 == 0 => write!(f, ".{:06}", n / 1000)?,
+            n => write!(f, ".{:09}", n)?,
+        }
+        f.write_str("Z")
+    }
+}
+
+// Parses exactly `n` digits at the start of `s`.
+fn digits(s: &mut &str, n: usize) -> Option<u32> {
+    let (head, rest) = (s.get(..n)?, &s[n..]);
+    if !head.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    *s = rest;
+    head.parse().ok()
+}
+
+fn expect(s: &mut &str, c: char) -> Option<()> {
+    *s = s.strip_prefix(c)?;
+    Some(())
+}
+
+fn parse_rfc3339(mut s: &str) -> Option<Timestamp> {
+    let s = &mut s;
+    let y = digits(s, 4)? as i64;
+    expect(s, '-')?;
+    let m = digits(s, 2)?;
+    expect(s, '-')?;
+    let d = digits(s, 2)?;
+    if !(1..=12).contains(&m) || d == 0 || d > days_in_month(y, m) {
+        return None;
+    }
+    *s = s.strip_prefix(|c| c == 'T' || c == 't' || c == ' ')?;
+    let hour = digits(s, 2)?;
+    expect(s, ':')?;
+    let min = digits(s, 2)?;
+    expect(s, ':')?;
+    let sec = digits(s, 2)?;
+    if hour > 23 || min > 59 || sec > 59 {
+        return None;
+    }
+
+    let mut nanos = 0;
+    if let Some(rest) = s.strip_prefix('.') {
+        let n = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if n == 0 || n > 9 {
+            return None;
+        }
+        nanos = digits(&mut &rest[..n], n)? * 10u32.pow(9 - n as u32);
+        *s = &rest[n..];
+    }
+
+    let offset = match *s {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match s.chars().next()? {
+                '+' => 1,
+                '-' => -1,
+                _ => return None,
+            };
+            *s = &s[1..];
+            let oh = digits(s, 2)?;
+            expect(s, ':')?;
+            let om = digits(s, 2)?;
+            if !s.is_empty() || oh > 23 || om > 59 {
+                return None;
+            }
+            sign * (oh * 3600 + om * 60) as i64
+        }
+    };
+    let secs =
+        days_from_civil(y, m, d) * SECS_PER_DAY + (hour * 3600 + min * 60 + sec) as i64 - offset;
+    Timestamp::new(secs, nanos)
+}
+
+/// Parses a RFC 3339 datetime, the offset is applied so the timestamp is
+/// in UTC. Leap seconds are not supported.
+impl FromStr for Timestamp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        parse_rfc3339(s).ok_or_else(|| Error::InvalidData(format!("invalid timestamp: {:?}", s)))
+    }
+}
+
+impl Describe for Timestamp {
+    fn schema() -> Schema {
+        Schema::Timestamp
+    }
+}
+
+impl ToTyped for Timestamp {
+    fn to_typed(&self) -> Typed {
+        Typed::Timestamp(*self)
+    }
+}
+
+/// The lenient conversion also accepts nanoseconds since the epoch, like
+/// the datetimes of `chrono` and `time` were stored before
+/// `Typed::Timestamp`, and RFC 3339 strings.
+impl FromTyped for Timestamp {
+    fn from_typed(e: &Typed) -> crate::Result<Self> {
+        match e {
+            Typed::Timestamp(ts) => Ok(*ts),
+            _ => Err(invalid_type(e, "timestamp")),
+        }
+    }
+
+    fn from_typed_lenient(e: &Typed) -> crate::Result<Self> {
+        let ts = match e {
+            Typed::Timestamp(ts) => Some(*ts),
+            Typed::Int(n) => Timestamp::from_nanos(*n as i128),
+            Typed::Uint(un) => Timestamp::from_nanos(*un as i128),
+            Typed::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        ts.ok_or_else(|| cannot_coerce(e, "timestamp"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc3339() {
+        let ts = Timestamp::new(1_614_834_367, 89).unwrap();
+        assert_eq!(ts.to_string(), "2021-03-04T05:06:07.000000089Z");
+        assert_eq!("2021-03-04T06:06:07.000000089+01:00".parse().ok(), Some(ts));
+        assert_eq!(
+            Timestamp::from_millis(-1).to_string(),
+            "1969-12-31T23:59:59.999Z"
+        );
+        assert_eq!(Timestamp::default().to_string(), "1970-01-01T00:00:00Z");
+        let leap = Timestamp::from_millis(951_782_400_000);
+        assert_eq!(leap.to_string(), "2000-02-29T00:00:00Z");
+        assert_eq!("2000-02-29t00:00:00.000z".parse().ok(), Some(leap));
+
+        for s in [
+            "2021-02-29T00:00:00Z",
+            "2021-03-04T05:06:07",
+            "2021-03-04T05:06:07.Z",
+            "2021-03-04T24:00:00Z",
+            "2021-3-04T05:06:07Z",
+            "2021-03-04T05:06:07+0100",
+        ]
+        .iter()
+        {
+            assert!(s.parse::<Timestamp>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let ts = Timestamp::from_nanos(-1).unwrap();
+        assert_eq!((ts.secs(), ts.subsec_nanos()), (-1, 999_999_999));
+        assert_eq!(ts.as_nanos(), -1);
+        assert_eq!(ts.as_millis(), -1);
+        assert!(Timestamp::new(0, NANOS_PER_SEC).is_none());
+        assert!(Timestamp::from_nanos(i128::MAX).is_none());
+        assert!(Timestamp::from_wire(0, NANOS_PER_SEC as u64).is_err());
+
+        assert_eq!(Timestamp::from_typed(&ts.to_typed()).unwrap(), ts);
+        assert!(Timestamp::from_typed(&Typed::Int(-1)).is_err());
+        assert_eq!(Timestamp::from_typed_lenient(&Typed::Int(-1)).unwrap(), ts);
+        let s = Typed::String("1969-12-31T23:59:59.999999999Z".into());
+        assert_eq!(Timestamp::from_typed_lenient(&s).unwrap(), ts);
+    }
+}
@@ -0,0 +1,190 @@
+#[cfg(not(feature = "bytes"))]
+use crate::codec::CodecReadExt;
+use crate::codec::{CodecWriteExt, Typed};
+use crate::error::{Error, Result};
+use crate::incremental::Scan;
+use crate::options::DecodeOptions;
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// XdCodec frames a byte stream as consecutive encoded values, for
+/// `tokio_util::codec::Framed`. Values arriving in pieces are scanned
+/// incrementally and only decoded once complete.
+#[derive(Debug, Default)]
+pub struct XdCodec {
+    opts: DecodeOptions,
+    max_len: Option<usize>,
+    scan: Scan,
+}
+
+impl XdCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn options(mut self, opts: DecodeOptions) -> Self {
+        self.scan = Scan::new(&opts.limits);
+        self.opts = opts;
+        self
+    }
+
+    /// Rejects values whose encoding is longer than `n` bytes, rather than
+    /// buffering them without bound.
+    pub fn max_len(mut self, n: usize) -> Self {
+        self.max_len = Some(n);
+        self
+    }
+}
+
+impl Decoder for XdCodec {
+    type Item = Typed;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Typed>> {
+        let done = match self.scan.scan(src) {
+            Ok(()) => true,
+            Err(Error::Incomplete { .. }) => false,
+            Err(err) => return Err(err),
+        };
+        let len = if done { self.scan.pos } else { src.len() };
+        if self.max_len.is_some_and(|max| len > max) {
+            return Err(Error::TooLong(len as u64));
+        }
+        if !done {
+            return Ok(None);
+        }
+
+        let frame = src.split_to(self.scan.pos);
+        self.scan.reset();
+        #[cfg(feature = "bytes")]
+        let e = crate::shared_bytes::read_typed_from_bytes_with(&mut frame.freeze(), &self.opts);
+        #[cfg(not(feature = "bytes"))]
+        let e = (&frame[..]).read_typed_with(&self.opts);
+        e.map(Some)
+    }
+}
+
+impl Encoder<&Typed> for XdCodec {
+    type Error = Error;
+
+    fn encode(&mut self, e: &Typed, dst: &mut BytesMut) -> Result<()> {
+        dst.writer().write_typed(e)
+    }
+}
+
+impl Encoder<Typed> for XdCodec {
+    type Error = Error;
+
+    fn encode(&mut self, e: Typed, dst: &mut BytesMut) -> Result<()> {
+        self.encode(&e, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+
+    fn value() -> Typed {
+        let mut m = Map::new();
+        m.insert(
+            String::from("list"),
+            Typed::List(vec![
+                Typed::Int(-300),
+                Typed::List(vec![]),
+                Typed::Map(Map::new()),
+                Typed::String("超".into()),
+            ]),
+        );
+        m.insert(String::from("f"), Typed::Float(0.5));
+        Typed::Map(m)
+    }
+
+    #[test]
+    fn partial() {
+        let mut codec = XdCodec::new();
+        let mut encoded = BytesMut::new();
+        codec.encode(value(), &mut encoded).unwrap();
+        codec.encode(&Typed::Uint(300), &mut encoded).unwrap();
+
+        // Fed a byte at a time.
+        let mut src = BytesMut::new();
+        let mut values = Vec::new();
+        for b in encoded.iter() {
+            src.put_u8(*b);
+            if let Some(e) = codec.decode(&mut src).unwrap() {
+                values.push(e);
+            }
+        }
+        assert_eq!(values, vec![value(), Typed::Uint(300)]);
+        assert!(src.is_empty());
+
+        // Both at once.
+        let mut src = encoded.clone();
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(value()));
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(Typed::Uint(300)));
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        assert!(codec.decode_eof(&mut encoded.split_to(3)).is_err());
+
+        let mut codec = XdCodec::new();
+        let mut src = BytesMut::new();
+        let e = Typed::List(vec![value(); 200]);
+        (&mut src)
+            .writer()
+            .write_typed_version(&e, crate::FormatVersion::V2)
+            .unwrap();
+        let tail = src.split_off(src.len() / 2);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        src.unsplit(tail);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(e));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut codec = XdCodec::new();
+        assert!(codec.decode(&mut BytesMut::from(&b"x"[..])).is_err());
+        let mut codec = XdCodec::new();
+        let mut src = BytesMut::from(&[b'u', 0xff, 0xff, 0xff, 0xff, 0xff][..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        src.extend_from_slice(&[0xff; 5]);
+        assert!(codec.decode(&mut src).is_err());
+
+        let mut codec = XdCodec::new().max_len(4);
+        let mut src = BytesMut::from(&b"s\x05ab"[..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        src.put_u8(b'c');
+        assert!(codec.decode(&mut src).is_err());
+    }
+
+    #[tokio::test]
+    async fn framed() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::{FramedRead, FramedWrite};
+
+        let (w, r) = tokio::io::duplex(5);
+        let mut sink = FramedWrite::new(w, XdCodec::new());
+        let writer = tokio::spawn(async move {
+            for i in 0..3 {
+                sink.send(Typed::Int(i)).await.unwrap();
+                sink.send(value()).await.unwrap();
+            }
+        });
+
+        let mut stream = FramedRead::new(r, XdCodec::new());
+        let mut n = 0;
+        while let Some(e) = stream.next().await {
+            let e = e.unwrap();
+            assert_eq!(
+                e,
+                if n % 2 == 0 {
+                    Typed::Int(n / 2)
+                } else {
+                    value()
+                }
+            );
+            n += 1;
+        }
+        assert_eq!(n, 6);
+        writer.await.unwrap();
+    }
+}
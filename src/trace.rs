@@ -0,0 +1,324 @@
+use crate::codec::{
+    container_capacity, List, Map, Str, Type, Typed, TYPE_ARRAY, TYPE_BYTES, TYPE_BYTES_DEFLATE,
+    TYPE_DECIMAL, TYPE_FALSE, TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT, TYPE_INT_FIXED, TYPE_LIST,
+    TYPE_LIST_V2, TYPE_MAP, TYPE_MAP_V2, TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE,
+    TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT, TYPE_UINT_FIXED,
+};
+use crate::compress::inflate;
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::limits::Budget;
+use crate::options::DecodeOptions;
+use crate::slice::SliceReader;
+use crate::text::TextEncoding;
+use crate::timestamp::Timestamp;
+use std::fmt;
+
+/// TraceEvent is a single step taken while decoding a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+    /// The length of the message, before it is passed through the layers.
+    Message(u64),
+    /// A type tag.
+    Tag(u8),
+    /// The length of a string or bytes, or the number of elements of a list
+    /// or map.
+    Len(u64),
+    /// A map key, the following events belong to its value.
+    Key(String),
+    /// A decoded number.
+    Number(String),
+    /// Decoding failed.
+    Error(String),
+}
+
+/// TraceEntry is an event at an offset of the message, after the layers are
+/// reverted, and at a nesting depth.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub offset: usize,
+    pub depth: usize,
+    pub event: TraceEvent,
+}
+
+/// Trace records how a message was decoded, see `Decoder::trace`. It is
+/// displayed as one event per line, e.g.
+///
+/// ```text
+/// 0000 tag 'm'
+/// 0001 len 1
+/// 0002   len 1
+/// 0002   key "k"
+/// 0004   tag 'i'
+/// 0005   number -1
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+    entries: Vec<TraceEntry>,
+}
+
+impl Trace {
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// Returns the error which ended the trace, if any.
+    pub fn error(&self) -> Option<&TraceEntry> {
+        self.entries
+            .last()
+            .filter(|e| matches!(e.event, TraceEvent::Error(_)))
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn push(&mut self, offset: usize, depth: usize, event: TraceEvent) {
+        self.entries.push(TraceEntry {
+            offset,
+            depth,
+            event,
+        });
+    }
+
+    // Records the error of `r`.
+    pub(crate) fn check<T, E: fmt::Display>(
+        &mut self,
+        offset: usize,
+        depth: usize,
+        r: std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        if let Err(err) = &r {
+            self.push(offset, depth, TraceEvent::Error(err.to_string()));
+        }
+        r
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for e in self.entries.iter() {
+            write!(f, "{:04x} {:indent$}", e.offset, "", indent = e.depth * 2)?;
+            match &e.event {
+                TraceEvent::Message(n) => writeln!(f, "message {}", n)?,
+                TraceEvent::Tag(t) if t.is_ascii_graphic() => writeln!(f, "tag '{}'", *t as char)?,
+                TraceEvent::Tag(t) => writeln!(f, "tag 0x{:02x}", t)?,
+                TraceEvent::Len(n) => writeln!(f, "len {}", n)?,
+                TraceEvent::Key(k) => writeln!(f, "key {:?}", k)?,
+                TraceEvent::Number(n) => writeln!(f, "number {}", n)?,
+                TraceEvent::Error(err) => writeln!(f, "error: {}", err)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodes `buf` like `CodecReadExt::read_typed_with`, recording into
+/// `trace`.
+pub(crate) fn read_traced(buf: &[u8], opts: &DecodeOptions, trace: &mut Trace) -> Result<Typed> {
+    let mut budget = Budget::new(&opts.limits);
+    let mut r = SliceReader::new(buf);
+    read_value(&mut r, opts, opts.text_encoding, 0, &mut budget, trace)
+}
+
+// Strings are decoded with `enc`, like `read_typed_encoded` does.
+fn read_value(
+    r: &mut SliceReader<'_>,
+    opts: &DecodeOptions,
+    enc: TextEncoding,
+    depth: usize,
+    budget: &mut Budget,
+    trace: &mut Trace,
+) -> Result<Typed> {
+    let offset = r.offset();
+    let t = trace.check(offset, depth, r.read_u8())?;
+    trace.push(offset, depth, TraceEvent::Tag(t));
+
+    let offset = r.offset();
+    let number = |trace: &mut Trace, n: String| {
+        trace.push(offset, depth, TraceEvent::Number(n));
+    };
+    let e = match t {
+        TYPE_NULL => Typed::Null,
+        TYPE_FALSE => Typed::Bool(false),
+        TYPE_TRUE => Typed::Bool(true),
+        TYPE_INT => {
+            let n = trace.check(offset, depth, r.read_varint())?;
+            number(trace, n.to_string());
+            Typed::Int(n)
+        }
+        TYPE_UINT => {
+            let un = trace.check(offset, depth, r.read_uvarint())?;
+            number(trace, un.to_string());
+            Typed::Uint(un)
+        }
+        TYPE_FLOAT => {
+            let f = f64::from_bits(trace.check(offset, depth, r.read_uvarint())?);
+            number(trace, f.to_string());
+            Typed::Float(f)
+        }
+        TYPE_INT_FIXED => {
+            let n = i64::from_le_bytes(trace.check(offset, depth, r.read_fixed())?);
+            number(trace, n.to_string());
+            Typed::Int(n)
+        }
+        TYPE_UINT_FIXED => {
+            let un = u64::from_le_bytes(trace.check(offset, depth, r.read_fixed())?);
+            number(trace, un.to_string());
+            Typed::Uint(un)
+        }
+        TYPE_FLOAT_FIXED => {
+            let f = f64::from_le_bytes(trace.check(offset, depth, r.read_fixed())?);
+            number(trace, f.to_string());
+            Typed::Float(f)
+        }
+        TYPE_TIMESTAMP | TYPE_DECIMAL => {
+            let n = trace.check(offset, depth, r.read_varint())?;
+            number(trace, n.to_string());
+            let offset = r.offset();
+            let un = trace.check(offset, depth, r.read_uvarint())?;
+            trace.push(offset, depth, TraceEvent::Number(un.to_string()));
+            if t == TYPE_TIMESTAMP {
+                Typed::Timestamp(trace.check(offset, depth, Timestamp::from_wire(n, un))?)
+            } else {
+                Typed::Decimal(trace.check(offset, depth, Decimal::from_wire(n, un))?)
+            }
+        }
+        TYPE_BYTES | TYPE_STRING => {
+            let buf = read_sized(r, depth, budget, trace)?;
+            if t == TYPE_BYTES {
+                Typed::Bytes(buf.to_vec().into())
+            } else {
+                let s = trace.check(offset, depth, opts.text(enc, buf))?;
+                Typed::String(Str::from(opts.string(s).as_ref()))
+            }
+        }
+        TYPE_BYTES_DEFLATE | TYPE_STRING_DEFLATE => {
+            let offset = r.offset();
+            let len = trace.check(offset, depth, r.read_uvarint())?;
+            trace.push(offset, depth, TraceEvent::Len(len));
+            trace.check(offset, depth, budget.bytes(len))?;
+            let stream = read_sized(r, depth, budget, trace)?;
+            let buf = trace.check(offset, depth, inflate(stream, len))?;
+            if t == TYPE_BYTES_DEFLATE {
+                Typed::Bytes(buf.into())
+            } else {
+                let s = trace.check(offset, depth, opts.text(enc, &buf))?;
+                Typed::String(Str::from(opts.string(s).as_ref()))
+            }
+        }
+        TYPE_ARRAY => {
+            let a = trace.check(offset, depth, r.read_array_with(budget))?;
+            trace.push(offset, depth, TraceEvent::Len(a.len() as u64));
+            Typed::Array(a)
+        }
+        TYPE_LIST | TYPE_LIST_V2 => {
+            let n = read_count(r, t, depth, budget, trace)?;
+            let mut l = List::with_capacity(container_capacity(n));
+            for _ in 0..n {
+                l.push(read_value(r, opts, enc, depth + 1, budget, trace)?);
+            }
+            budget.leave();
+            Typed::List(l)
+        }
+        TYPE_MAP | TYPE_MAP_V2 => {
+            let n = read_count(r, t, depth, budget, trace)?;
+            let mut m = Map::new();
+            for _ in 0..n {
+                let offset = r.offset();
+                let k = read_sized(r, depth + 1, budget, trace)?;
+                let k = opts.key(trace.check(offset, depth + 1, opts.text(enc, k))?);
+                trace.push(offset, depth + 1, TraceEvent::Key(k.clone()));
+                let field_enc = opts.field_encodings.get(&k).copied().unwrap_or(enc);
+                let v = read_value(r, opts, field_enc, depth + 1, budget, trace)?;
+                m.insert(k, v);
+            }
+            budget.leave();
+            Typed::Map(m)
+        }
+        _ => {
+            let err = Error::UnknownType(t);
+            return trace.check(offset - 1, depth, Err(err));
+        }
+    };
+    Ok(e)
+}
+
+// Reads the number of elements of a container and enters it, the caller
+// leaves it once they are read.
+fn read_count(
+    r: &mut SliceReader<'_>,
+    t: Type,
+    depth: usize,
+    budget: &mut Budget,
+    trace: &mut Trace,
+) -> Result<u64> {
+    let offset = r.offset();
+    let n = trace.check(offset, depth, r.read_container_len(t))?;
+    trace.push(offset, depth, TraceEvent::Len(n));
+    trace.check(offset, depth, budget.enter(n))?;
+    Ok(n)
+}
+
+fn read_sized<'a>(
+    r: &mut SliceReader<'a>,
+    depth: usize,
+    budget: &Budget,
+    trace: &mut Trace,
+) -> Result<&'a [u8]> {
+    let offset = r.offset();
+    let n = trace.check(offset, depth, r.read_uvarint())?;
+    trace.push(offset, depth, TraceEvent::Len(n));
+    trace.check(offset, depth, budget.bytes(n))?;
+    let offset = r.offset();
+    trace.check(offset, depth, r.read_exact(n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecReadExt, CodecWriteExt};
+
+    #[test]
+    fn traced() {
+        let mut m = Map::new();
+        m.insert(
+            String::from("k"),
+            Typed::List(vec![Typed::Int(-1), Typed::String("v".into())]),
+        );
+        let mut buf = Vec::new();
+        buf.write_typed(&Typed::Map(m)).unwrap();
+
+        let opts = DecodeOptions::default();
+        let mut trace = Trace::default();
+        let e = read_traced(&buf, &opts, &mut trace).unwrap();
+        assert_eq!(e, (&buf[..]).read_typed().unwrap());
+        assert!(trace.error().is_none());
+        assert_eq!(
+            trace.to_string(),
+            "0000 tag 'm'\n\
+             0001 len 1\n\
+             0002   len 1\n\
+             0002   key \"k\"\n\
+             0004   tag 'l'\n\
+             0005   len 2\n\
+             0006     tag 'i'\n\
+             0007     number -1\n\
+             0008     tag 's'\n\
+             0009     len 1\n"
+        );
+
+        buf.pop();
+        trace.clear();
+        assert!(read_traced(&buf, &opts, &mut trace).is_err());
+        let err = trace.error().unwrap();
+        assert_eq!((err.offset, err.depth), (10, 2));
+
+        trace.clear();
+        assert!(read_traced(b"x", &opts, &mut trace).is_err());
+        assert_eq!(
+            trace.to_string(),
+            "0000 tag 'x'\n0000 error: unknown type: '120'\n"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use crate::array::PackedArray;
+use crate::codec::{
+    container_capacity, List, Map, Str, Typed, TYPE_ARRAY, TYPE_BYTES, TYPE_BYTES_DEFLATE,
+    TYPE_DECIMAL, TYPE_FALSE, TYPE_FLOAT, TYPE_FLOAT_FIXED, TYPE_INT, TYPE_INT_FIXED, TYPE_LIST,
+    TYPE_LIST_V2, TYPE_MAP, TYPE_MAP_V2, TYPE_NULL, TYPE_STRING, TYPE_STRING_DEFLATE,
+    TYPE_TIMESTAMP, TYPE_TRUE, TYPE_UINT, TYPE_UINT_FIXED,
+};
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::limits::{Budget, Limits};
+use crate::slice::SliceReader;
+use crate::timestamp::Timestamp;
+use std::str;
+
+/// TypedRef is a decoded value whose strings and bytes borrow the input
+/// rather than being copied, see `decode_ref`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedRef<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Timestamp(Timestamp),
+    Decimal(Decimal),
+    Bytes(&'a [u8]),
+    Str(&'a str),
+    List(Vec<TypedRef<'a>>),
+    /// The entries in the order they were encoded, duplicated keys are
+    /// kept.
+    Map(Vec<(&'a str, TypedRef<'a>)>),
+    /// The elements are copied, so they are aligned.
+    Array(PackedArray),
+}
+
+impl<'a> TypedRef<'a> {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            TypedRef::Null => "null",
+            TypedRef::Bool(_) => "bool",
+            TypedRef::Int(_) => "int",
+            TypedRef::Uint(_) => "uint",
+            TypedRef::Float(_) => "float",
+            TypedRef::Timestamp(_) => "timestamp",
+            TypedRef::Decimal(_) => "decimal",
+            TypedRef::Bytes(_) => "bytes",
+            TypedRef::Str(_) => "string",
+            TypedRef::List(_) => "list",
+            TypedRef::Map(_) => "map",
+            TypedRef::Array(_) => "array",
+        }
+    }
+
+    /// Returns the value of `key` if this is a map, the last one wins like
+    /// in a decoded `Map`.
+    pub fn get(&self, key: &str) -> Option<&TypedRef<'a>> {
+        match self {
+            TypedRef::Map(entries) => entries.iter().rev().find(|(k, _)| *k == key).map(|e| &e.1),
+            _ => None,
+        }
+    }
+
+    /// Copies the value into a `Typed`.
+    pub fn to_typed(&self) -> Typed {
+        match self {
+            TypedRef::Null => Typed::Null,
+            TypedRef::Bool(b) => Typed::Bool(*b),
+            TypedRef::Int(n) => Typed::Int(*n),
+            TypedRef::Uint(un) => Typed::Uint(*un),
+            TypedRef::Float(f) => Typed::Float(*f),
+            TypedRef::Timestamp(ts) => Typed::Timestamp(*ts),
+            TypedRef::Decimal(d) => Typed::Decimal(*d),
+            TypedRef::Bytes(buf) => Typed::Bytes(buf.to_vec().into()),
+            TypedRef::Str(s) => Typed::String(Str::from(*s)),
+            TypedRef::List(l) => Typed::List(l.iter().map(TypedRef::to_typed).collect::<List>()),
+            TypedRef::Map(entries) => Typed::Map(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_typed()))
+                    .collect::<Map>(),
+            ),
+            TypedRef::Array(a) => Typed::Array(a.clone()),
+        }
+    }
+}
+
+fn utf8(buf: &[u8]) -> Result<&str> {
+    Ok(str::from_utf8(buf)?)
+}
+
+fn read_sized<'a>(r: &mut SliceReader<'a>, budget: &Budget) -> Result<&'a [u8]> {
+    let buf = r.read_sized()?;
+    budget.bytes(buf.len() as u64)?;
+    Ok(buf)
+}
+
+fn read_ref<'a>(r: &mut SliceReader<'a>, budget: &mut Budget) -> Result<TypedRef<'a>> {
+    let t = r.read_u8()?;
+    match t {
+        TYPE_NULL => Ok(TypedRef::Null),
+        TYPE_FALSE => Ok(TypedRef::Bool(false)),
+        TYPE_TRUE => Ok(TypedRef::Bool(true)),
+        TYPE_INT => Ok(TypedRef::Int(r.read_varint()?)),
+        TYPE_UINT => Ok(TypedRef::Uint(r.read_uvarint()?)),
+        TYPE_FLOAT => Ok(TypedRef::Float(f64::from_bits(r.read_uvarint()?))),
+        TYPE_INT_FIXED => Ok(TypedRef::Int(i64::from_le_bytes(r.read_fixed()?))),
+        TYPE_UINT_FIXED => Ok(TypedRef::Uint(u64::from_le_bytes(r.read_fixed()?))),
+        TYPE_FLOAT_FIXED => Ok(TypedRef::Float(f64::from_le_bytes(r.read_fixed()?))),
+        TYPE_TIMESTAMP => {
+            let secs = r.read_varint()?;
+            Ok(TypedRef::Timestamp(Timestamp::from_wire(
+                secs,
+                r.read_uvarint()?,
+            )?))
+        }
+        TYPE_DECIMAL => {
+            let mantissa = r.read_varint()?;
+            Ok(TypedRef::Decimal(Decimal::from_wire(
+                mantissa,
+                r.read_uvarint()?,
+            )?))
+        }
+        TYPE_BYTES => Ok(TypedRef::Bytes(read_sized(r, budget)?)),
+        TYPE_STRING => Ok(TypedRef::Str(utf8(read_sized(r, budget)?)?)),
+        TYPE_BYTES_DEFLATE | TYPE_STRING_DEFLATE => Err(Error::InvalidData(
+            "compressed values can't be borrowed".into(),
+        )),
+        TYPE_ARRAY => Ok(TypedRef::Array(r.read_array()?)),
+        TYPE_LIST | TYPE_LIST_V2 => {
+            let n = r.read_container_len(t)?;
+            budget.enter(n)?;
+            let mut l = Vec::with_capacity(container_capacity(n));
+            for _ in 0..n {
+                l.push(read_ref(r, budget)?);
+            }
+            budget.leave();
+            Ok(TypedRef::List(l))
+        }
+        TYPE_MAP | TYPE_MAP_V2 => {
+            let n = r.read_container_len(t)?;
+            budget.enter(n)?;
+            let mut entries = Vec::with_capacity(container_capacity(n));
+            for _ in 0..n {
+                let k = utf8(read_sized(r, budget)?)?;
+                entries.push((k, read_ref(r, budget)?));
+            }
+            budget.leave();
+            Ok(TypedRef::Map(entries))
+        }
+        _ => Err(Error::UnknownType(t)),
+    }
+}
+
+/// Decodes the value in `buf` without copying its strings and bytes, which
+/// must span the whole buffer. Unlike `CodecReadExt::read_typed`, invalid
+/// UTF-8 is an error, as it can't be replaced in place, and so are
+/// compressed values, see `Config::compression`. The default `Limits`
+/// apply.
+pub fn decode_ref(buf: &[u8]) -> Result<TypedRef<'_>> {
+    decode_ref_with(buf, &Limits::default())
+}
+
+/// Decodes the value in `buf` like `decode_ref`, it is rejected as soon as
+/// it exceeds `limits`.
+pub fn decode_ref_with<'a>(buf: &'a [u8], limits: &Limits) -> Result<TypedRef<'a>> {
+    let mut r = SliceReader::new(buf);
+    let e = read_ref(&mut r, &mut Budget::new(limits))?;
+    if !r.remaining().is_empty() {
+        return Err(Error::TrailingBytes);
+    }
+    Ok(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::CodecWriteExt;
+
+    #[test]
+    fn borrowed() {
+        let mut m = Map::new();
+        m.insert(
+            String::from("list"),
+            Typed::List(vec![
+                Typed::Int(-1),
+                Typed::Uint(2),
+                Typed::Float(0.5),
+                Typed::Bytes(b"\x00\xff".to_vec().into()),
+            ]),
+        );
+        m.insert(String::from("name"), Typed::String("xd".into()));
+        let e = Typed::Map(m);
+        let mut buf = Vec::new();
+        buf.write_typed(&e).unwrap();
+
+        let r = decode_ref(&buf).unwrap();
+        assert_eq!(r.to_typed(), e);
+        let name = match r.get("name") {
+            Some(TypedRef::Str(s)) => *s,
+            v => panic!("{:?}", v),
+        };
+        assert_eq!(name, "xd");
+        let range = buf.as_ptr_range();
+        assert!(range.contains(&name.as_ptr()));
+        assert!(r.get("missing").is_none());
+
+        // The last duplicated key wins.
+        let r = decode_ref(b"m\x02\x01ki\x02\x01ki\x04").unwrap();
+        assert_eq!(r.get("k"), Some(&TypedRef::Int(2)));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(decode_ref(b"s\x01\xff").is_err());
+        assert!(decode_ref(b"m\x01\x01\xffi\x00").is_err());
+        assert!(decode_ref(b"s\x02x").is_err());
+        assert!(decode_ref(b"i\x00\x00").is_err());
+        assert!(decode_ref(b"x").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::codec::{Map, Str, Typed};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+// Returns the NFC form of `s`, unless it already is.
+pub(crate) fn nfc(s: &str) -> Option<String> {
+    if is_nfc(s) {
+        None
+    } else {
+        Some(s.nfc().collect())
+    }
+}
+
+impl Typed {
+    /// Recursively NFC normalizes strings and map keys, e.g. before encoding,
+    /// keys which end up equal overwrite each other in an unspecified order.
+    pub fn normalize_unicode(&mut self) {
+        match self {
+            Typed::String(s) => {
+                if let Some(n) = nfc(s) {
+                    *s = Str::from(n);
+                }
+            }
+            Typed::List(l) => l.iter_mut().for_each(Typed::normalize_unicode),
+            Typed::Map(m) => {
+                *m = m
+                    .drain()
+                    .map(|(k, mut v)| {
+                        v.normalize_unicode();
+                        (nfc(&k).unwrap_or(k), v)
+                    })
+                    .collect::<Map>();
+            }
+            Typed::Null
+            | Typed::Bool(_)
+            | Typed::Int(_)
+            | Typed::Uint(_)
+            | Typed::Float(_)
+            | Typed::Timestamp(_)
+            | Typed::Decimal(_)
+            | Typed::Bytes(_)
+            | Typed::Array(_)
+            | Typed::Raw(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecReadExt, CodecWriteExt};
+    use crate::config::{Codec, Config};
+    use crate::options::DecodeOptions;
+
+    const COMPOSED: &str = "caf\u{e9}";
+    const DECOMPOSED: &str = "cafe\u{301}";
+
+    #[test]
+    fn normalize() {
+        let mut m = Map::new();
+        m.insert(String::from(DECOMPOSED), Typed::String(DECOMPOSED.into()));
+        let mut e = Typed::List(vec![Typed::Map(m)]);
+        e.normalize_unicode();
+
+        let mut expected = Map::new();
+        expected.insert(String::from(COMPOSED), Typed::String(COMPOSED.into()));
+        assert_eq!(e, Typed::List(vec![Typed::Map(expected)]));
+    }
+
+    #[test]
+    fn decode() {
+        let mut m = Map::new();
+        m.insert(String::from(DECOMPOSED), Typed::Int(1));
+        let mut buf = Vec::new();
+        buf.write_map(&m).unwrap();
+        buf.write_typed(&Typed::String(DECOMPOSED.into())).unwrap();
+
+        let opts = DecodeOptions::new().nfc(true);
+        let mut r = &buf[..];
+        let decoded = r.read_map_with(&opts).unwrap();
+        assert_eq!(decoded.get(COMPOSED), Some(&Typed::Int(1)));
+        assert_eq!(
+            r.read_typed_with(&opts).unwrap(),
+            Typed::String(COMPOSED.into())
+        );
+
+        let decoded = (&buf[..]).read_map().unwrap();
+        assert_eq!(decoded.get(DECOMPOSED), Some(&Typed::Int(1)));
+    }
+
+    #[test]
+    fn encode() {
+        let mut m = Map::new();
+        m.insert(String::from(DECOMPOSED), Typed::String(DECOMPOSED.into()));
+        let e = Typed::List(vec![Typed::Map(m)]);
+        let mut normalized = e.clone();
+        normalized.normalize_unicode();
+
+        let codec = Codec::new(Config::new().nfc(true));
+        let buf = codec.encode(&e).unwrap();
+        assert_eq!((&buf[..]).read_typed().unwrap(), normalized);
+        let canonical = Codec::new(Config::new().nfc(true).canonical(true));
+        assert_eq!(canonical.encode(&e).unwrap(), buf);
+        assert_eq!(Codec::default().decode(&buf).unwrap(), normalized);
+    }
+}
@@ -0,0 +1,403 @@
+//! Operations on whole documents, e.g. configurations kept in maps: setting
+//! values at paths, merging documents and listing their differences.
+
+use crate::codec::{Map, Typed};
+use crate::error::{Error, Result};
+use crate::path::{Path, PathSegment};
+
+/// Conflict tells which value `Typed::merge_with` keeps when both documents
+/// have a value at the same path and they can't be merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conflict {
+    /// The value of the other document replaces the value.
+    #[default]
+    Replace,
+    /// The value is kept.
+    Keep,
+    /// The merge fails with an `Error::InvalidInput`.
+    Fail,
+}
+
+/// MergeOptions customizes `Typed::merge_with`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeOptions {
+    pub conflict: Conflict,
+    /// Whether the elements of the other lists are appended to the lists,
+    /// rather than lists conflicting like other values.
+    pub append_lists: bool,
+    /// Whether a null in the other document removes the map entry, like
+    /// JSON merge patches do.
+    pub null_removes: bool,
+}
+
+impl MergeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn conflict(mut self, conflict: Conflict) -> Self {
+        self.conflict = conflict;
+        self
+    }
+
+    pub fn append_lists(mut self, yes: bool) -> Self {
+        self.append_lists = yes;
+        self
+    }
+
+    pub fn null_removes(mut self, yes: bool) -> Self {
+        self.null_removes = yes;
+        self
+    }
+}
+
+/// Change is a difference between two documents, see `Typed::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The other document has a value where there was none.
+    Added { path: Path, value: Typed },
+    /// The other document has no value where there was one.
+    Removed { path: Path, value: Typed },
+    /// The other document has another value.
+    Changed { path: Path, old: Typed, new: Typed },
+}
+
+impl Change {
+    pub fn path(&self) -> &Path {
+        match self {
+            Change::Added { path, .. } | Change::Removed { path, .. } => path,
+            Change::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl Typed {
+    /// Sets the value at `path`, returns the value it replaced unless it was
+    /// missing or null. The missing map entries and nulls on the way become
+    /// maps or lists, depending on the next segment, and an index equal to
+    /// the length of a list appends to it. The whole path is checked first,
+    /// so the value is left as it was if setting fails.
+    pub fn set_path(&mut self, path: &Path, v: Typed) -> Result<Option<Typed>> {
+        self.check_settable(path)?;
+        let mut e = self;
+        for seg in path.segments() {
+            if e.is_null() {
+                *e = match seg {
+                    PathSegment::Key(_) => Typed::Map(Map::new()),
+                    PathSegment::Index(_) => Typed::List(Vec::new()),
+                };
+            }
+            e = match (e, seg) {
+                (Typed::Map(m), PathSegment::Key(k)) => m.entry(k.clone()).or_insert(Typed::Null),
+                (Typed::List(l), PathSegment::Index(n)) => {
+                    if *n == l.len() {
+                        l.push(Typed::Null);
+                    }
+                    &mut l[*n]
+                }
+                _ => unreachable!("the path was checked"),
+            };
+        }
+        Ok(Some(std::mem::replace(e, v)).filter(|old| !old.is_null()))
+    }
+
+    // Checks that `set_path` can step through every segment of `path`, the
+    // missing values and nulls on the way are empty maps or lists then.
+    fn check_settable(&self, path: &Path) -> Result<()> {
+        let mut e = Some(self).filter(|e| !e.is_null());
+        for (i, seg) in path.segments().iter().enumerate() {
+            let at = || Path::from(path.segments()[..i].to_vec());
+            let check_index = |n: usize, len: usize| {
+                if n > len {
+                    return Err(Error::InvalidInput(format!(
+                        "index {} out of the list of {} at '{}'",
+                        n,
+                        len,
+                        at()
+                    )));
+                }
+                Ok(())
+            };
+            match (e, seg) {
+                (None, PathSegment::Key(_)) => {}
+                (None, PathSegment::Index(n)) => check_index(*n, 0)?,
+                (Some(Typed::Map(m)), PathSegment::Key(k)) => {
+                    e = m.get(k).filter(|e| !e.is_null());
+                }
+                (Some(Typed::List(l)), PathSegment::Index(n)) => {
+                    check_index(*n, l.len())?;
+                    e = l.get(*n).filter(|e| !e.is_null());
+                }
+                (Some(e), _) => {
+                    return Err(Error::InvalidInput(format!(
+                        "can't step into the {} at '{}'",
+                        e.type_name(),
+                        at()
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges `other` into the value, the maps recursively, any other value
+    /// of `other` replaces it.
+    pub fn merge(&mut self, other: Typed) {
+        self.merge_with(other, &MergeOptions::default())
+            .expect("replacing never fails")
+    }
+
+    pub fn merge_with(&mut self, other: Typed, opts: &MergeOptions) -> Result<()> {
+        merge_at(self, other, opts, &mut Path::root())
+    }
+
+    /// Returns the differences which turn the value into `other`, the map
+    /// entries in key order. Lists are compared element by element, the
+    /// elements past the end of the shorter one are added or removed.
+    pub fn diff(&self, other: &Typed) -> Vec<Change> {
+        let mut changes = Vec::new();
+        diff_at(self, other, &mut Path::root(), &mut changes);
+        changes
+    }
+}
+
+fn merge_at(e: &mut Typed, other: Typed, opts: &MergeOptions, path: &mut Path) -> Result<()> {
+    match (e, other) {
+        (Typed::Map(m), Typed::Map(other)) => {
+            for (k, v) in other {
+                if opts.null_removes && v.is_null() {
+                    m.remove(&k);
+                    continue;
+                }
+                match m.get_mut(&k) {
+                    Some(e) => {
+                        path.push(PathSegment::Key(k));
+                        merge_at(e, v, opts, path)?;
+                        path.pop();
+                    }
+                    None => {
+                        m.insert(k, v);
+                    }
+                }
+            }
+            Ok(())
+        }
+        (Typed::List(l), Typed::List(other)) if opts.append_lists => {
+            l.extend(other);
+            Ok(())
+        }
+        (e, other) if *e == other => Ok(()),
+        (e, other) => match opts.conflict {
+            Conflict::Replace => {
+                *e = other;
+                Ok(())
+            }
+            Conflict::Keep => Ok(()),
+            Conflict::Fail => Err(Error::InvalidInput(format!(
+                "conflicting values at '{}'",
+                path
+            ))),
+        },
+    }
+}
+
+fn diff_at(x: &Typed, y: &Typed, path: &mut Path, changes: &mut Vec<Change>) {
+    match (x, y) {
+        (Typed::Map(x), Typed::Map(y)) => {
+            let mut keys: Vec<&String> = x
+                .keys()
+                .chain(y.keys().filter(|k| !x.contains_key(*k)))
+                .collect();
+            keys.sort();
+            for k in keys {
+                path.push(PathSegment::Key(k.clone()));
+                match (x.get(k), y.get(k)) {
+                    (Some(v), Some(w)) => diff_at(v, w, path, changes),
+                    (Some(v), None) => changes.push(Change::Removed {
+                        path: path.clone(),
+                        value: v.clone(),
+                    }),
+                    (None, Some(w)) => changes.push(Change::Added {
+                        path: path.clone(),
+                        value: w.clone(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+                path.pop();
+            }
+        }
+        (Typed::List(x), Typed::List(y)) => {
+            for i in 0..x.len().max(y.len()) {
+                path.push(PathSegment::Index(i));
+                match (x.get(i), y.get(i)) {
+                    (Some(v), Some(w)) => diff_at(v, w, path, changes),
+                    (Some(v), None) => changes.push(Change::Removed {
+                        path: path.clone(),
+                        value: v.clone(),
+                    }),
+                    (None, Some(w)) => changes.push(Change::Added {
+                        path: path.clone(),
+                        value: w.clone(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+                path.pop();
+            }
+        }
+        _ if x == y => {}
+        _ => changes.push(Change::Changed {
+            path: path.clone(),
+            old: x.clone(),
+            new: y.clone(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typed;
+
+    fn config() -> Typed {
+        typed!({
+            "server": {"host": "localhost", "ports": [80, 443]},
+            "debug": false,
+        })
+    }
+
+    #[test]
+    fn set_path() {
+        let mut e = config();
+        let path: Path = "server.ports[1]".parse().unwrap();
+        assert_eq!(
+            e.set_path(&path, Typed::Int(8443)).unwrap(),
+            Some(Typed::Int(443))
+        );
+        assert_eq!(e.get_path(&path), Some(&Typed::Int(8443)));
+        let path = "server.ports[2]".parse().unwrap();
+        assert_eq!(e.set_path(&path, Typed::Int(9000)).unwrap(), None);
+        let path = "log.outputs[0].file".parse().unwrap();
+        assert_eq!(e.set_path(&path, "x.log".into()).unwrap(), None);
+        assert_eq!(
+            e.get("log"),
+            Some(&typed!({"outputs": [{"file": "x.log"}]}))
+        );
+
+        let err = e
+            .set_path(&"server.ports[5]".parse().unwrap(), Typed::Null)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "index 5 out of the list of 3 at 'server.ports'"
+        );
+        let err = e
+            .set_path(&"debug.level".parse().unwrap(), Typed::Null)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "can't step into the bool at 'debug'");
+        // A failure leaves the value as it was.
+        let before = e.clone();
+        let err = e
+            .set_path(&"log.outputs[2].file".parse().unwrap(), Typed::Null)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "index 2 out of the list of 1 at 'log.outputs'"
+        );
+        assert!(e
+            .set_path(&"new.list[1]".parse().unwrap(), Typed::Null)
+            .is_err());
+        assert!(e
+            .set_path(&"debug.x".parse().unwrap(), Typed::Null)
+            .is_err());
+        assert_eq!(e, before);
+        let mut null = Typed::Null;
+        assert!(null
+            .set_path(&"a[5]".parse().unwrap(), Typed::Null)
+            .is_err());
+        assert_eq!(null, Typed::Null);
+
+        let mut root = Typed::Int(1);
+        assert_eq!(
+            root.set_path(&Path::root(), Typed::Int(2)).unwrap(),
+            Some(Typed::Int(1))
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let mut e = config();
+        e.merge(typed!({"server": {"host": "example.com", "tls": true}, "debug": null}));
+        assert_eq!(
+            e,
+            typed!({
+                "server": {"host": "example.com", "ports": [80, 443], "tls": true},
+                "debug": null,
+            })
+        );
+
+        let mut e = config();
+        let opts = MergeOptions::new()
+            .conflict(Conflict::Keep)
+            .append_lists(true)
+            .null_removes(true);
+        e.merge_with(
+            typed!({"server": {"host": "example.com", "ports": [8080]}, "debug": null}),
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(
+            e,
+            typed!({"server": {"host": "localhost", "ports": [80, 443, 8080]}})
+        );
+
+        let mut e = config();
+        let opts = MergeOptions::new().conflict(Conflict::Fail);
+        assert!(e.merge_with(typed!({"debug": false}), &opts).is_ok());
+        let err = e
+            .merge_with(typed!({"server": {"ports": []}}), &opts)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "conflicting values at 'server.ports'");
+    }
+
+    #[test]
+    fn diff() {
+        let x = config();
+        assert!(x.diff(&x).is_empty());
+        let y = typed!({
+            "server": {"host": "example.com", "ports": [80]},
+            "log": "stderr",
+        });
+        let changes = x.diff(&y);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Removed {
+                    path: "debug".parse().unwrap(),
+                    value: Typed::Bool(false),
+                },
+                Change::Added {
+                    path: "log".parse().unwrap(),
+                    value: "stderr".into(),
+                },
+                Change::Changed {
+                    path: "server.host".parse().unwrap(),
+                    old: "localhost".into(),
+                    new: "example.com".into(),
+                },
+                Change::Removed {
+                    path: "server.ports[1]".parse().unwrap(),
+                    value: Typed::Int(443),
+                },
+            ]
+        );
+        assert_eq!(changes[3].path().to_string(), "server.ports[1]");
+        assert_eq!(
+            Typed::Int(1).diff(&Typed::Uint(1)),
+            vec![Change::Changed {
+                path: Path::root(),
+                old: Typed::Int(1),
+                new: Typed::Uint(1),
+            }]
+        );
+    }
+}
@@ -1,37 +1,102 @@
-use std::io::{Error, ErrorKind, Read, Result, Write};
+use crate::error::{Error, Result};
+use std::io::{Read, Write};
 
 pub const MAX_VARINT_LEN: usize = 10;
 
+/// Encodes `un` into the start of `buf`, returns the number of bytes
+/// written.
+pub fn encode_uvarint(un: u64, buf: &mut [u8; MAX_VARINT_LEN]) -> usize {
+    let mut un = un;
+    let mut i = 0;
+    while un >= 0x80 {
+        buf[i] = (un as u8) | 0x80;
+        un >>= 7;
+        i += 1;
+    }
+    buf[i] = un as u8;
+    i + 1
+}
+
+/// Encodes `n` zigzag encoded, like `encode_uvarint`.
+pub fn encode_varint(n: i64, buf: &mut [u8; MAX_VARINT_LEN]) -> usize {
+    encode_uvarint(zigzag(n), buf)
+}
+
+/// Returns the number of bytes `un` takes encoded.
+pub fn uvarint_len(un: u64) -> usize {
+    // Every byte holds 7 bits, and 0 takes a byte too.
+    (64 - (un | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// Returns the number of bytes `n` takes zigzag encoded.
+pub fn varint_len(n: i64) -> usize {
+    uvarint_len(zigzag(n))
+}
+
+/// Decodes the uvarint at the start of `buf`, returns it with the number
+/// of bytes it takes. A `buf` which ends within the uvarint is an
+/// `Error::UnexpectedEof`.
+pub fn decode_uvarint(buf: &[u8]) -> Result<(u64, usize)> {
+    let mut x = 0u64;
+    for (i, b) in buf.iter().take(MAX_VARINT_LEN).enumerate() {
+        if *b < 0x80 {
+            if i == MAX_VARINT_LEN - 1 && *b > 1 {
+                return Err(Error::VarintOverflow);
+            }
+            return Ok((x | u64::from(*b) << (7 * i), i + 1));
+        }
+        x |= u64::from(b & 0x7f) << (7 * i);
+    }
+    if buf.len() >= MAX_VARINT_LEN {
+        return Err(Error::VarintOverflow);
+    }
+    Err(Error::UnexpectedEof)
+}
+
+/// Decodes the zigzag encoded varint at the start of `buf`, like
+/// `decode_uvarint`.
+pub fn decode_varint(buf: &[u8]) -> Result<(i64, usize)> {
+    decode_uvarint(buf).map(|(un, n)| (unzigzag(un), n))
+}
+
+fn zigzag(n: i64) -> u64 {
+    let mut un = (n as u64) << 1;
+    if n < 0 {
+        un = !un;
+    }
+    un
+}
+
+fn unzigzag(un: u64) -> i64 {
+    let mut n = (un >> 1) as i64;
+    if un & 1 != 0 {
+        n = !n;
+    }
+    n
+}
+
 /// VarintReadExt extends `Read` trait with signed varint support.
 pub trait VarintReadExt: Read {
     fn read_uvarint(&mut self) -> Result<u64> {
         let mut x = 0u64;
-        let mut s = 0u64;
-        let mut i = 0;
         let mut buf = [0u8; 1];
 
-        loop {
+        for i in 0..MAX_VARINT_LEN {
             self.read_exact(&mut buf)?;
-            let byte = buf[0] as u8;
+            let byte = buf[0];
             if byte < 0x80 {
-                if i > 9 || i == 9 && byte > 1 {
-                    return Err(Error::from(ErrorKind::InvalidData));
+                if i == MAX_VARINT_LEN - 1 && byte > 1 {
+                    return Err(Error::VarintOverflow);
                 }
-                return Ok(x | (byte as u64) << s);
+                return Ok(x | u64::from(byte) << (7 * i));
             }
-            x |= ((byte & 0x7f) as u64) << s;
-            s += 7;
-            i += 1;
+            x |= u64::from(byte & 0x7f) << (7 * i);
         }
+        Err(Error::VarintOverflow)
     }
 
     fn read_varint(&mut self) -> Result<i64> {
-        let un = self.read_uvarint()?;
-        let mut n = (un >> 1) as i64;
-        if un & 1 != 0 {
-            n = !n;
-        }
-        Ok(n)
+        self.read_uvarint().map(unzigzag)
     }
 }
 
@@ -43,25 +108,12 @@ impl<R: Read + ?Sized> VarintReadExt for R {}
 pub trait VarintWriteExt: Write {
     fn write_uvarint(&mut self, un: u64) -> Result<()> {
         let mut buf = [0u8; MAX_VARINT_LEN];
-        let mut un = un;
-        let mut i = 0usize;
-
-        while un >= 0x80 {
-            buf[i] = (un as u8) | 0x80;
-            un >>= 7;
-            i += 1;
-        }
-        buf[i] = un as u8;
-        i += 1;
-        self.write_all(&buf[..i])
+        let n = encode_uvarint(un, &mut buf);
+        Ok(self.write_all(&buf[..n])?)
     }
 
     fn write_varint(&mut self, n: i64) -> Result<()> {
-        let mut un = (n as u64) << 1;
-        if n < 0 {
-            un = !un;
-        }
-        self.write_uvarint(un)
+        self.write_uvarint(zigzag(n))
     }
 }
 
@@ -81,22 +133,23 @@ mod tests {
         for n in [
             0u64,
             123456789,
-            i8::max_value() as u64,
-            u8::max_value() as u64,
-            i16::max_value() as u64,
-            u16::max_value() as u64,
-            i32::max_value() as u64,
-            u32::max_value() as u64,
-            u64::max_value(),
+            i8::MAX as u64,
+            u8::MAX as u64,
+            i16::MAX as u64,
+            u16::MAX as u64,
+            i32::MAX as u64,
+            u32::MAX as u64,
+            u64::MAX,
         ]
-        .into_iter()
+        .iter()
         {
             buf.seek(io::SeekFrom::Start(0)).unwrap();
             assert!(buf.write_uvarint(*n).is_ok());
+            assert_eq!(buf.position() as usize, uvarint_len(*n));
             buf.seek(io::SeekFrom::Start(0)).unwrap();
             match buf.read_uvarint() {
                 Ok(nread) => assert_eq!(nread, *n),
-                Err(err) => assert!(false, "{}", err),
+                Err(err) => panic!("{}", err),
             }
         }
     }
@@ -107,23 +160,72 @@ mod tests {
         for n in [
             0i64,
             123456789,
-            i8::max_value() as i64,
-            u8::max_value() as i64,
-            i16::max_value() as i64,
-            u16::max_value() as i64,
-            i32::max_value() as i64,
-            u32::max_value() as i64,
-            i64::max_value(),
+            i8::MAX as i64,
+            u8::MAX as i64,
+            i16::MAX as i64,
+            u16::MAX as i64,
+            i32::MAX as i64,
+            u32::MAX as i64,
+            i64::MAX,
         ]
-        .into_iter()
+        .iter()
         {
             buf.seek(io::SeekFrom::Start(0)).unwrap();
             assert!(buf.write_varint(*n).is_ok());
+            assert_eq!(buf.position() as usize, varint_len(*n));
             buf.seek(io::SeekFrom::Start(0)).unwrap();
             match buf.read_varint() {
                 Ok(nread) => assert_eq!(nread, *n),
-                Err(err) => assert!(false, "{}", err),
+                Err(err) => panic!("{}", err),
             }
         }
     }
+
+    #[test]
+    fn slices() {
+        let mut buf = [0u8; MAX_VARINT_LEN];
+        for n in [0u64, 0x7f, 0x80, 300, u32::MAX as u64, u64::MAX].iter() {
+            let len = encode_uvarint(*n, &mut buf);
+            let mut written = Vec::new();
+            written.write_uvarint(*n).unwrap();
+            assert_eq!(&buf[..len], &written[..]);
+            assert_eq!(decode_uvarint(&buf[..len]).unwrap(), (*n, len));
+            let mut trailing = written.clone();
+            trailing.push(0xff);
+            assert_eq!(decode_uvarint(&trailing).unwrap(), (*n, len));
+            assert!(decode_uvarint(&buf[..len - 1]).unwrap_err().is_eof());
+        }
+        for n in [0i64, -1, 1, i64::MIN, i64::MAX].iter() {
+            let len = encode_varint(*n, &mut buf);
+            assert_eq!(decode_varint(&buf[..len]).unwrap(), (*n, len));
+        }
+        assert_eq!(encode_varint(-1, &mut buf), 1);
+        assert_eq!(buf[0], 1);
+
+        let overflow = [0xffu8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+        assert!(matches!(
+            decode_uvarint(&overflow),
+            Err(Error::VarintOverflow)
+        ));
+        assert!(matches!(
+            decode_uvarint(&[0x80; 11]),
+            Err(Error::VarintOverflow)
+        ));
+        assert!(decode_uvarint(&[]).unwrap_err().is_eof());
+    }
+
+    #[test]
+    fn read_overflow() {
+        // The reader stops at the 10th byte rather than shifting past 64
+        // bits.
+        let mut buf = vec![0x80u8; 11];
+        buf.push(0x01);
+        let err = (&buf[..]).read_uvarint().unwrap_err();
+        assert!(matches!(err, Error::VarintOverflow));
+        let mut overflow = vec![0xffu8; 9];
+        overflow.push(0x02);
+        assert!((&overflow[..]).read_uvarint().is_err());
+        overflow[9] = 0x01;
+        assert_eq!((&overflow[..]).read_uvarint().unwrap(), u64::MAX);
+    }
 }
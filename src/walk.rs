@@ -0,0 +1,140 @@
+use crate::codec::Typed;
+use crate::path::Path;
+use std::collections::VecDeque;
+
+// The children of `e` with their paths, map entries are ordered by key so
+// the iteration order is stable.
+fn children<'a>(path: &Path, e: &'a Typed) -> Vec<(Path, &'a Typed)> {
+    match e {
+        Typed::List(l) => l
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (path.index(i), e))
+            .collect(),
+        Typed::Map(m) => {
+            let mut entries: Vec<_> = m.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            entries.into_iter().map(|(k, v)| (path.key(k), v)).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// DepthFirst yields every node of a value in pre-order, see
+/// `Typed::iter_depth_first`.
+pub struct DepthFirst<'a> {
+    stack: Vec<(Path, &'a Typed)>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = (Path, &'a Typed);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, e) = self.stack.pop()?;
+        self.stack.extend(children(&path, e).into_iter().rev());
+        Some((path, e))
+    }
+}
+
+/// BreadthFirst yields every node of a value level by level, see
+/// `Typed::iter_breadth_first`.
+pub struct BreadthFirst<'a> {
+    queue: VecDeque<(Path, &'a Typed)>,
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = (Path, &'a Typed);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, e) = self.queue.pop_front()?;
+        self.queue.extend(children(&path, e));
+        Some((path, e))
+    }
+}
+
+impl Typed {
+    /// Iterates over the value itself and all the nested values depth first,
+    /// each value comes before its elements. Map entries are visited in key
+    /// order and `Typed::Raw` values are not decoded.
+    pub fn iter_depth_first(&self) -> DepthFirst<'_> {
+        DepthFirst {
+            stack: vec![(Path::root(), self)],
+        }
+    }
+
+    /// Like `iter_depth_first`, but all the values at a depth are visited
+    /// before the deeper ones.
+    pub fn iter_breadth_first(&self) -> BreadthFirst<'_> {
+        let mut queue = VecDeque::new();
+        queue.push_back((Path::root(), self));
+        BreadthFirst { queue }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Map;
+
+    fn doc() -> Typed {
+        let mut user = Map::new();
+        user.insert(String::from("name"), Typed::String("xd".into()));
+        user.insert(
+            String::from("tags"),
+            Typed::List(vec![Typed::Int(1), Typed::Int(2)]),
+        );
+        let mut m = Map::new();
+        m.insert(String::from("user"), Typed::Map(user));
+        m.insert(String::from("id"), Typed::Uint(7));
+        Typed::Map(m)
+    }
+
+    fn paths<'a, I: Iterator<Item = (Path, &'a Typed)>>(iter: I) -> Vec<String> {
+        iter.map(|(p, _)| p.to_string()).collect()
+    }
+
+    #[test]
+    fn depth_first() {
+        let e = doc();
+        assert_eq!(
+            paths(e.iter_depth_first()),
+            vec![
+                "",
+                "id",
+                "user",
+                "user.name",
+                "user.tags",
+                "user.tags[0]",
+                "user.tags[1]"
+            ]
+        );
+        let (path, v) = e.iter_depth_first().last().unwrap();
+        assert_eq!(path, Path::root().key("user").key("tags").index(1));
+        assert_eq!(v, &Typed::Int(2));
+    }
+
+    #[test]
+    fn breadth_first() {
+        assert_eq!(
+            paths(doc().iter_breadth_first()),
+            vec![
+                "",
+                "id",
+                "user",
+                "user.name",
+                "user.tags",
+                "user.tags[0]",
+                "user.tags[1]"
+            ]
+        );
+        let e = Typed::List(vec![Typed::List(vec![Typed::Int(1)]), Typed::Int(2)]);
+        assert_eq!(
+            paths(e.iter_breadth_first()),
+            vec!["", "[0]", "[1]", "[0][0]"]
+        );
+        assert_eq!(
+            paths(e.iter_depth_first()),
+            vec!["", "[0]", "[0][0]", "[1]"]
+        );
+    }
+}
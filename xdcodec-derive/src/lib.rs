@@ -0,0 +1,461 @@
+//! Derive macros for `xdcodec-rs`, use them through the `derive` feature
+//! of the main crate.
+
+extern crate proc_macro;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, GenericArgument, PathArguments, Type};
+
+/// Derives `ToMap` and `ToTyped` for structs with named fields.
+///
+/// The fields are the keys of the map, the attributes of a field are:
+/// - `#[xd(rename = "key")]` uses another key;
+/// - `#[xd(skip)]` leaves the field out, it is `Default::default()` when
+///   the struct is built from a map;
+/// - `#[xd(default)]` makes the key optional, the field is
+///   `Default::default()` when it is missing;
+/// - a `Map` field marked with `#[xd(extra)]` collects the keys which don't
+///   belong to other fields, they are written back as is.
+///
+/// `#[xdcodec(...)]` is the long form of `#[xd(...)]`.
+#[proc_macro_derive(ToMap, attributes(xd, xdcodec))]
+pub fn derive_to_map(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_map(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `FromMap` and `FromTyped` for structs with named fields, see
+/// `ToMap` for the attributes of the fields.
+#[proc_macro_derive(FromMap, attributes(xd, xdcodec))]
+pub fn derive_from_map(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_map(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `Describe` for structs with named fields, the schema is a
+/// record of the fields, the `Option` and `#[xd(default)]` fields are
+/// optional and the `#[xd(skip)]` and `#[xd(extra)]` fields are left out.
+#[proc_macro_derive(Describe, attributes(xd, xdcodec))]
+pub fn derive_describe(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_describe(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `XdEncode`, which encodes the map of `ToMap`, so the struct
+/// derives `ToMap` too: `#[derive(ToMap, XdEncode)]`.
+#[proc_macro_derive(XdEncode, attributes(xd, xdcodec))]
+pub fn derive_xd_encode(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_xd_encode(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `XdDecode`, which decodes a map with `FromMap`, so the struct
+/// derives `FromMap` too: `#[derive(FromMap, XdDecode)]`.
+#[proc_macro_derive(XdDecode, attributes(xd, xdcodec))]
+pub fn derive_xd_decode(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_xd_decode(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct Field<'a> {
+    ident: &'a syn::Ident,
+    ty: &'a Type,
+    key: String,
+    optional: bool,
+    attrs: Attrs,
+}
+
+impl Field<'_> {
+    // Whether the field is a key of the map.
+    fn is_key(&self) -> bool {
+        !self.attrs.extra && !self.attrs.skip
+    }
+}
+
+#[derive(Default)]
+struct Attrs {
+    rename: Option<String>,
+    skip: bool,
+    default: bool,
+    extra: bool,
+}
+
+fn field_attrs(f: &syn::Field) -> syn::Result<Attrs> {
+    let mut attrs = Attrs::default();
+    let is_ours = |a: &&syn::Attribute| a.path().is_ident("xd") || a.path().is_ident("xdcodec");
+    for attr in f.attrs.iter().filter(is_ours) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let key: syn::LitStr = meta.value()?.parse()?;
+                attrs.rename = Some(key.value());
+            } else if meta.path.is_ident("skip") {
+                attrs.skip = true;
+            } else if meta.path.is_ident("default") {
+                attrs.default = true;
+            } else if meta.path.is_ident("extra") {
+                attrs.extra = true;
+            } else {
+                return Err(meta.error("unknown xd attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    if attrs.extra && (attrs.rename.is_some() || attrs.skip || attrs.default) {
+        return Err(syn::Error::new_spanned(
+            &f.ident,
+            "#[xd(extra)] can't be combined with other attributes",
+        ));
+    }
+    Ok(attrs)
+}
+
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<Field<'_>>> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "only structs with named fields are supported",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "only structs with named fields are supported",
+            ))
+        }
+    };
+
+    let mut named: Vec<Field> = Vec::new();
+    for f in fields.iter() {
+        let ident = f.ident.as_ref().unwrap();
+        let attrs = field_attrs(f)?;
+        if attrs.extra && named.iter().any(|f| f.attrs.extra) {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "only one field can be marked with #[xd(extra)]",
+            ));
+        }
+        let field = Field {
+            ident,
+            ty: &f.ty,
+            key: attrs.rename.clone().unwrap_or_else(|| ident.to_string()),
+            optional: option_inner(&f.ty).is_some(),
+            attrs,
+        };
+        if field.is_key() && named.iter().any(|f| f.is_key() && f.key == field.key) {
+            return Err(syn::Error::new_spanned(
+                ident,
+                format!("duplicate key: '{}'", field.key),
+            ));
+        }
+        named.push(field);
+    }
+    Ok(named)
+}
+
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(ty) if ty.qself.is_none() => &ty.path,
+        _ => return None,
+    };
+    let last = path.segments.last()?;
+    if last.ident != "Option" {
+        return None;
+    }
+    match &last.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn expand_to_map(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = named_fields(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    // The extra keys are inserted first, so they never shadow fields.
+    let extras = fields.iter().filter(|f| f.attrs.extra).map(|f| {
+        let ident = f.ident;
+        quote! { ::xdcodec_rs::__private::extend_extra(&mut m, &self.#ident); }
+    });
+    let inserts = fields.iter().filter(|f| f.is_key()).map(|f| {
+        let ident = f.ident;
+        let key = &f.key;
+        if f.optional {
+            quote! {
+                if let ::std::option::Option::Some(v) = &self.#ident {
+                    m.insert(
+                        ::std::string::String::from(#key),
+                        ::xdcodec_rs::ToTyped::to_typed(v),
+                    );
+                }
+            }
+        } else {
+            quote! {
+                m.insert(
+                    ::std::string::String::from(#key),
+                    ::xdcodec_rs::ToTyped::to_typed(&self.#ident),
+                );
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::xdcodec_rs::ToMap for #name #ty_generics #where_clause {
+            fn to_map(&self) -> ::xdcodec_rs::Map {
+                let mut m = ::xdcodec_rs::Map::new();
+                #(#extras)*
+                #(#inserts)*
+                m
+            }
+        }
+
+        impl #impl_generics ::xdcodec_rs::ToTyped for #name #ty_generics #where_clause {
+            fn to_typed(&self) -> ::xdcodec_rs::Typed {
+                ::xdcodec_rs::Typed::Map(::xdcodec_rs::ToMap::to_map(self))
+            }
+        }
+    })
+}
+
+fn expand_from_map(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = named_fields(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let keys: Vec<&String> = fields
+        .iter()
+        .filter(|f| f.is_key())
+        .map(|f| &f.key)
+        .collect();
+    let inits = |lenient: bool| {
+        fields
+            .iter()
+            .map(|f| {
+                let ident = f.ident;
+                let key = &f.key;
+                if f.attrs.extra {
+                    return quote! {
+                        #ident: ::xdcodec_rs::__private::extra_fields(m, &[#(#keys),*]),
+                    };
+                }
+                if f.attrs.skip {
+                    return quote! { #ident: ::std::default::Default::default(), };
+                }
+                let helper = match (f.optional, f.attrs.default, lenient) {
+                    (false, false, false) => quote!(from_field),
+                    (false, false, true) => quote!(from_field_lenient),
+                    (false, true, false) => quote!(from_field_or_default),
+                    (false, true, true) => quote!(from_field_or_default_lenient),
+                    (true, _, false) => quote!(from_optional_field),
+                    (true, _, true) => quote!(from_optional_field_lenient),
+                };
+                quote! { #ident: ::xdcodec_rs::__private::#helper(m, #key)?, }
+            })
+            .collect::<Vec<_>>()
+    };
+    let strict_inits = inits(false);
+    let lenient_inits = inits(true);
+
+    Ok(quote! {
+        impl #impl_generics ::xdcodec_rs::FromMap for #name #ty_generics #where_clause {
+            fn from_map(m: &::xdcodec_rs::Map) -> ::xdcodec_rs::Result<Self> {
+                ::std::result::Result::Ok(#name {
+                    #(#strict_inits)*
+                })
+            }
+
+            fn from_map_lenient(m: &::xdcodec_rs::Map) -> ::xdcodec_rs::Result<Self> {
+                ::std::result::Result::Ok(#name {
+                    #(#lenient_inits)*
+                })
+            }
+        }
+
+        impl #impl_generics ::xdcodec_rs::FromTyped for #name #ty_generics #where_clause {
+            fn from_typed(e: &::xdcodec_rs::Typed) -> ::xdcodec_rs::Result<Self> {
+                ::xdcodec_rs::__private::from_map_value(e)
+            }
+
+            fn from_typed_lenient(e: &::xdcodec_rs::Typed) -> ::xdcodec_rs::Result<Self> {
+                ::xdcodec_rs::__private::from_map_value_lenient(e)
+            }
+        }
+    })
+}
+
+fn expand_describe(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = named_fields(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let schemas = fields.iter().filter(|f| f.is_key()).map(|f| {
+        let key = &f.key;
+        match option_inner(f.ty) {
+            Some(ty) => quote! {
+                ::xdcodec_rs::Field::optional(
+                    #key,
+                    <#ty as ::xdcodec_rs::Describe>::schema(),
+                ),
+            },
+            None => {
+                let ty = f.ty;
+                let constructor = if f.attrs.default {
+                    quote!(optional)
+                } else {
+                    quote!(required)
+                };
+                quote! {
+                    ::xdcodec_rs::Field::#constructor(
+                        #key,
+                        <#ty as ::xdcodec_rs::Describe>::schema(),
+                    ),
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::xdcodec_rs::Describe for #name #ty_generics #where_clause {
+            fn schema() -> ::xdcodec_rs::Schema {
+                ::xdcodec_rs::Schema::record(::std::vec![#(#schemas)*])
+            }
+        }
+    })
+}
+
+fn expand_xd_encode(input: &DeriveInput) -> syn::Result<TokenStream> {
+    named_fields(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::xdcodec_rs::XdEncode for #name #ty_generics #where_clause {}
+    })
+}
+
+fn expand_xd_decode(input: &DeriveInput) -> syn::Result<TokenStream> {
+    named_fields(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::xdcodec_rs::XdDecode for #name #ty_generics #where_clause {}
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_detection() {
+        let ty: Type = syn::parse_quote!(Option<String>);
+        assert!(option_inner(&ty).is_some());
+        let ty: Type = syn::parse_quote!(std::option::Option<u8>);
+        assert!(option_inner(&ty).is_some());
+        let ty: Type = syn::parse_quote!(Vec<Option<u8>>);
+        assert!(option_inner(&ty).is_none());
+    }
+
+    #[test]
+    fn reject_non_struct() {
+        let input: DeriveInput = syn::parse_quote! {
+            enum E { A, B }
+        };
+        assert!(expand_to_map(&input).is_err());
+        let input: DeriveInput = syn::parse_quote! {
+            struct T(u8);
+        };
+        assert!(expand_from_map(&input).is_err());
+        assert!(expand_describe(&input).is_err());
+    }
+
+    #[test]
+    fn extra_attribute() {
+        let input: DeriveInput = syn::parse_quote! {
+            struct T {
+                a: u8,
+                #[xdcodec(extra)]
+                rest: Map,
+            }
+        };
+        let fields = named_fields(&input).unwrap();
+        assert!(!fields[0].attrs.extra);
+        assert!(fields[1].attrs.extra);
+
+        let input: DeriveInput = syn::parse_quote! {
+            struct T {
+                #[xd(extra)]
+                a: Map,
+                #[xd(extra)]
+                b: Map,
+            }
+        };
+        assert!(expand_to_map(&input).is_err());
+
+        let input: DeriveInput = syn::parse_quote! {
+            struct T {
+                #[xd(rename)]
+                a: u8,
+            }
+        };
+        assert!(expand_from_map(&input).is_err());
+    }
+
+    #[test]
+    fn field_attributes() {
+        let input: DeriveInput = syn::parse_quote! {
+            struct T {
+                #[xd(rename = "type")]
+                kind: u8,
+                #[xd(skip)]
+                cache: Vec<u8>,
+                #[xd(default, rename = "n")]
+                count: u32,
+            }
+        };
+        let fields = named_fields(&input).unwrap();
+        assert_eq!(fields[0].key, "type");
+        assert!(!fields[1].is_key());
+        assert!(fields[2].attrs.default);
+        assert_eq!(fields[2].key, "n");
+        // The codec derives leave ToMap and FromMap to their own derives.
+        let encode = expand_xd_encode(&input).unwrap().to_string();
+        assert!(encode.contains("XdEncode") && !encode.contains("ToMap"));
+        let decode = expand_xd_decode(&input).unwrap().to_string();
+        assert!(decode.contains("XdDecode") && !decode.contains("FromMap"));
+
+        let input: DeriveInput = syn::parse_quote! {
+            struct T {
+                a: u8,
+                #[xd(rename = "a")]
+                b: u8,
+            }
+        };
+        assert!(expand_to_map(&input).is_err());
+
+        let input: DeriveInput = syn::parse_quote! {
+            struct T {
+                #[xd(extra, skip)]
+                rest: Map,
+            }
+        };
+        assert!(expand_from_map(&input).is_err());
+    }
+}